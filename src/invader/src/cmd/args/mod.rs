@@ -19,6 +19,9 @@ pub struct ArgumentConstraints {
     /// True if an engine is needed.
     pub needs_engine: bool,
 
+    /// True if the engine must not use compressed cache files.
+    pub needs_uncompressed_engine: bool,
+
     /// True if the data dir is needed.
     pub needs_data: bool,
 
@@ -50,6 +53,13 @@ impl ArgumentConstraints {
         s
     }
 
+    /// Set needs_engine and needs_uncompressed_engine to true.
+    pub fn needs_uncompressed_engine(self) -> ArgumentConstraints {
+        let mut s = self.needs_engine();
+        s.needs_uncompressed_engine = true;
+        s
+    }
+
     /// Set needs_data to true.
     pub fn needs_data(self) -> ArgumentConstraints {
         let mut s = self;
@@ -456,6 +466,7 @@ impl ParsedArguments {
                 parsed.engine_target = match parsed.named.get("engine") {
                     None => return Err(ErrorMessage::StaticString(get_compiled_string!("arguments.error_engine_needed"))),
                     Some(n) => match EngineTarget::from_shorthand(&n[0]) {
+                        Some(n) if n.compressed && constraints.needs_uncompressed_engine => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("arguments.error_engine_compressed"), engine=n.name))),
                         Some(n) => Some(n),
                         None => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("arguments.error_engine_invalid"), engine=n[0])))
                     }
@@ -60,4 +60,9 @@ fn argument_parsing() {
     assert_eq!("some arg", result.named.get("an-arg").unwrap()[0]);
     assert!(result.named.get("boring-arg").unwrap().is_empty());
     assert_eq!("another arg", result.named.get("cool-arg").unwrap()[0]);
+
+    // Some verbs cannot use engines with compressed cache files.
+    assert!(ParsedArguments::parse_arguments(&["-e", "xbox-us"], &[], &[], "", "", ArgumentConstraints::new().needs_engine()).unwrap().engine_target.is_some());
+    assert!(ParsedArguments::parse_arguments(&["-e", "xbox-us"], &[], &[], "", "", ArgumentConstraints::new().needs_uncompressed_engine()).is_err());
+    assert!(ParsedArguments::parse_arguments(&["-e", "pc-custom"], &[], &[], "", "", ArgumentConstraints::new().needs_uncompressed_engine()).unwrap().engine_target.is_some());
 }
@@ -3,6 +3,7 @@ use ringhopper_proc::get_compiled_string;
 /// Verbs define different actions that can be called by the driver for an engine module.
 #[derive(Copy, Clone, PartialEq, PartialOrd)]
 pub enum Verb {
+    Animations,
     Archive,
     Bitmap,
     Bludgeon,
@@ -33,6 +34,7 @@ pub enum Verb {
     Scan,
     Script,
     Sound,
+    SoundLooping,
     Strings,
     Strip,
     TagCollection,
@@ -57,6 +59,7 @@ impl VerbInfo {
 }
 
 pub(crate) static ALL_VERBS: &'static [VerbInfo] = &[
+    VerbInfo::new(Verb::Animations, "animations", get_compiled_string!("verb.animations.description")),
     VerbInfo::new(Verb::Archive, "archive", get_compiled_string!("verb.archive.description")),
     VerbInfo::new(Verb::Bitmap, "bitmap", get_compiled_string!("verb.bitmap.description")),
     VerbInfo::new(Verb::Bludgeon, "bludgeon", get_compiled_string!("verb.bludgeon.description")),
@@ -87,6 +90,7 @@ pub(crate) static ALL_VERBS: &'static [VerbInfo] = &[
     VerbInfo::new(Verb::Scan, "scan", get_compiled_string!("verb.scan.description")),
     VerbInfo::new(Verb::Script, "script", get_compiled_string!("verb.script.description")),
     VerbInfo::new(Verb::Sound, "sound", get_compiled_string!("verb.sound.description")),
+    VerbInfo::new(Verb::SoundLooping, "sound-looping", get_compiled_string!("verb.sound-looping.description")),
     VerbInfo::new(Verb::Strings, "strings", get_compiled_string!("verb.strings.description")),
     VerbInfo::new(Verb::Strip, "strip",  get_compiled_string!("verb.strip.description")),
     VerbInfo::new(Verb::TagCollection, "tag-collection", get_compiled_string!("verb.tag-collection.description")),
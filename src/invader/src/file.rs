@@ -1,6 +1,8 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fs::File;
+use ringhopper::engines::h1::{TagFileSerializeFn, TagReference};
 use ringhopper::error::*;
+use ringhopper::file::TagFile;
 use ringhopper_proc::*;
 use std::io::*;
 
@@ -59,6 +61,33 @@ pub fn make_parent_directories(path: &Path) -> ErrorMessageResult<()> {
     make_directories(&path.parent().unwrap())
 }
 
+/// Find the files in a directory with any of the extensions, sorted by file name.
+///
+/// `read_dir_error` makes the error returned if the directory can't be read, so each verb can use its own error string.
+pub fn find_files_with_extensions<F>(directory: &Path, extensions: &[&str], read_dir_error: F) -> ErrorMessageResult<Vec<PathBuf>> where F: Fn(Error) -> ErrorMessage {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for entry in std::fs::read_dir(directory).map_err(&read_dir_error)? {
+        let path = entry.map_err(&read_dir_error)?.path();
+        let extension = path.extension().map(|e| e.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+        if path.is_file() && extensions.contains(&extension.as_str()) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Load the tag if it exists, so a verb that generates it can keep its settings.
+///
+/// Return the tag, if any, and the path to write the generated tag to, which is in the first tags directory if the tag
+/// doesn't exist yet.
+pub fn load_existing_tag<T: TagFileSerializeFn>(tags_dirs: &[&Path], tag_path: &TagReference) -> ErrorMessageResult<(Option<T>, PathBuf)> {
+    match TagFile::from_tag_ref(tags_dirs, tag_path) {
+        Some(t) => Ok((Some(*T::from_tag_file(&read_file(&t.file_path)?)?.data), t.file_path)),
+        None => Ok((None, tags_dirs[0].join(tag_path.get_relative_fs_path())))
+    }
+}
+
 /// Format the size to a human-readable size
 pub fn format_size(length: usize) -> String {
     // Convert to 64-bit float
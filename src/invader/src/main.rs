@@ -13,6 +13,10 @@ extern crate vorbis_rs;
 extern crate xbadpcm;
 extern crate libsamplerate_sys;
 extern crate jxl_oxide;
+#[cfg(feature = "opus")]
+extern crate audiopus;
+#[cfg(feature = "opus")]
+extern crate ogg;
 
 use ringhopper_proc::*;
 
@@ -101,14 +105,28 @@ fn main() -> ExitCode {
 
 fn get_verb_function(verb: Verb) -> Option<VerbFn> {
     match verb {
+        Verb::Animations => Some(animations::animations_verb),
         Verb::Bitmap => Some(bitmap::bitmap_verb),
+        Verb::Bludgeon => Some(bludgeon::bludgeon_verb),
+        Verb::BSP => Some(bsp::bsp_verb),
+        Verb::Build => Some(build::build_verb),
+        Verb::Collision => Some(collision::collision_verb),
         Verb::Convert => Some(convert::convert_verb),
+        Verb::Dependency => Some(dependency::dependency_verb),
+        Verb::Edit => Some(edit::edit_verb),
+        Verb::Extract => Some(extract::extract_verb),
+        Verb::Font => Some(font::font_verb),
+        Verb::GBXModel => Some(model::model_verb),
+        Verb::Info => Some(info::info_verb),
         Verb::Lightmap => Some(lightmap::lightmap_verb),
         Verb::ListEngines => Some(list_engines::list_engines_verb),
+        Verb::Model => Some(model::model_verb),
         Verb::NormalizeLightmaps => Some(normalize_lightmaps::normalize_lightmaps_verb),
         Verb::Recover => Some(recover::recover_verb),
         Verb::RecoverProcessed => Some(recover_processed::recover_processed_verb),
+        Verb::Refactor => Some(refactor::refactor_verb),
         Verb::Sound => Some(sound::sound_verb),
+        Verb::SoundLooping => Some(sound::sound_looping_verb),
         Verb::Script => Some(script::script_verb),
         Verb::Strip => Some(strip::strip_verb),
         Verb::Strings => Some(unicode_strings::unicode_strings_verb),
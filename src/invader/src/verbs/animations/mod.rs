@@ -0,0 +1,52 @@
+use std::path::Path;
+use std::process::ExitCode;
+use crate::cmd::*;
+use crate::file::*;
+use macros::terminal::*;
+use ringhopper::engines::h1::definitions::ModelAnimations;
+use ringhopper::engines::h1::jms::*;
+use ringhopper::engines::h1::*;
+use ringhopper::error::*;
+use ringhopper::types::HALO_DIRECTORY_SEPARATOR;
+use ringhopper_proc::*;
+
+/// Read all animation source files in the directory, sorted by file name.
+fn read_animation_files(animations_dir: &Path) -> ErrorMessageResult<Vec<ModelAnimationJMA>> {
+    let extensions = AnimationSourceType::ALL.map(|t| t.extension());
+    let paths = find_files_with_extensions(animations_dir, &extensions, |error| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.animations.error_reading_animations_directory"), dir=animations_dir.display(), error=error)))?;
+
+    let mut animations: Vec<ModelAnimationJMA> = Vec::with_capacity(paths.len());
+    for path in paths {
+        let source_type = AnimationSourceType::from_extension(&path.extension().unwrap().to_string_lossy()).unwrap();
+        let name = path.file_stem().unwrap().to_string_lossy().to_ascii_lowercase();
+        let jma = JMA::parse_bytes(&read_file(&path)?).map_err(|e| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.animations.error_reading_animation"), file=path.display(), error=e)))?;
+        animations.push(ModelAnimationJMA { name, source_type, jma });
+    }
+
+    Ok(animations)
+}
+
+pub fn animations_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageResult<ExitCode> {
+    let parsed_args = ParsedArguments::parse_arguments(args, &[], &[get_compiled_string!("arguments.specifier.tag_without_group")], executable, verb.get_description(), ArgumentConstraints::new().needs_data().needs_tags().multiple_tags_directories())?;
+
+    let tags_dirs = str_slice_to_path_vec(&parsed_args.named["tags"]);
+    let data = Path::new(&parsed_args.named["data"][0]);
+    let group = TagGroup::ModelAnimations;
+
+    // The tag is named after its directory (e.g. "characters\cyborg" makes "characters\cyborg\cyborg").
+    let directory = TagReference::from_path_and_group(&parsed_args.extra[0], group)?;
+    let directory_path = directory.get_path_without_extension().trim_end_matches(HALO_DIRECTORY_SEPARATOR).to_owned();
+    let name = directory_path.rsplit(HALO_DIRECTORY_SEPARATOR).next().unwrap_or_default();
+    let tag_path = TagReference::from_path_and_group(&format!("{directory_path}{HALO_DIRECTORY_SEPARATOR}{name}"), group)?;
+
+    let animations_dir = data.join(directory_path.replace(HALO_DIRECTORY_SEPARATOR, std::path::MAIN_SEPARATOR_STR)).join("animations");
+    let animations = read_animation_files(&animations_dir)?;
+
+    let (existing, output_path) = load_existing_tag::<ModelAnimations>(&tags_dirs, &tag_path)?;
+    let output = build_model_animations(&animations, existing.as_ref())?.into_tag_file()?;
+    make_parent_directories(&output_path)?;
+    write_file(&output_path, &output)?;
+
+    println_success!(get_compiled_string!("engine.h1.verbs.animations.saved_file"), file=output_path.display());
+    Ok(ExitCode::SUCCESS)
+}
@@ -203,7 +203,7 @@ fn do_single_bitmap(file: &TagFile, log_mutex: super::LogMutex, _available_threa
         return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.bitmap.error_cannot_regenerate_missing_tag"), tag=file.tag_path)))
     }
     else {
-        let mut tag = Bitmap::default();
+        let mut tag = Bitmap::new_with_defaults();
         tag.usage = BitmapUsage::Default;
         tag.encoding_format = BitmapFormat::_32bit;
         is_new_bitmap_tag = true;
@@ -369,7 +369,7 @@ fn do_single_bitmap(file: &TagFile, log_mutex: super::LogMutex, _available_threa
             return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.bitmap.error_exceeded_dimensions"), max=U16_MAX, width=b.width, height=b.height, depth=b.depth)));
         }
 
-        let mut data = BitmapData::default();
+        let mut data = BitmapData::new_with_defaults();
         let data_offset = bitmap_tag.processed_pixel_data.len();
 
         // Determine the best encoding format
@@ -0,0 +1,91 @@
+use ringhopper_proc::*;
+use std::num::NonZeroUsize;
+use std::process::ExitCode;
+use crate::cmd::*;
+use macros::terminal::*;
+use ringhopper::engines::h1::*;
+use ringhopper::engines::h1::definitions::{GBXModel, Model};
+use ringhopper::error::ErrorMessageResult;
+use ringhopper::file::*;
+use crate::file::*;
+
+#[derive(Copy, Clone)]
+struct BludgeonOptions {
+    dry_run: bool,
+    batched: bool
+}
+
+/// Repair a model tag, returning a description of each repair.
+fn repair_model<M: ModelRepair>(model: &mut M) -> ErrorMessageResult<Vec<&'static str>> {
+    let mut repairs = Vec::new();
+    if model.check_for_extraction_bugs().is_err() {
+        model.repair_extraction_bugs()?;
+        repairs.push(get_compiled_string!("engine.h1.verbs.bludgeon.repair_extraction_bugs"));
+    }
+    Ok(repairs)
+}
+
+/// Repair the tag data, returning the repaired tag data and a description of each repair.
+fn repair_tag_data(group: TagGroup, data: &[u8]) -> ErrorMessageResult<(Vec<u8>, Vec<&'static str>)> {
+    match group {
+        TagGroup::GBXModel => {
+            let mut model = *GBXModel::from_tag_file(data)?.data;
+            let repairs = repair_model(&mut model)?;
+            Ok((model.into_tag_file()?, repairs))
+        },
+        TagGroup::Model => {
+            let mut model = *Model::from_tag_file(data)?.data;
+            let mut repairs = repair_model(&mut model)?;
+
+            // Xbox models need compressed vertices. If there are too many nodes, the model can't have them anyway.
+            if let Ok(true) = regenerate_compressed_vertices(&mut model) {
+                repairs.push(get_compiled_string!("engine.h1.verbs.bludgeon.repair_compressed_vertices"));
+            }
+            Ok((model.into_tag_file()?, repairs))
+        },
+        _ => Ok((data.to_owned(), Vec::new()))
+    }
+}
+
+fn bludgeon_tag(path: &TagFile, log_mutex: super::LogMutex, _: NonZeroUsize, options: &BludgeonOptions) -> ErrorMessageResult<bool> {
+    let file_data = read_file(&path.file_path)?;
+    let (final_data, repairs) = repair_tag_data(path.tag_path.get_group(), &file_data)?;
+    let skip = repairs.is_empty();
+
+    if !skip && !options.dry_run {
+        write_file(&path.file_path, &final_data)?;
+    }
+
+    let l = log_mutex.lock();
+    for r in &repairs {
+        if options.dry_run {
+            println!(get_compiled_string!("engine.h1.verbs.bludgeon.would_repair_tag"), tag=path.tag_path, repair=r);
+        }
+        else {
+            println_success!(get_compiled_string!("engine.h1.verbs.bludgeon.repaired_tag"), tag=path.tag_path, repair=r);
+        }
+    }
+    if skip && !options.batched {
+        println!(get_compiled_string!("engine.h1.verbs.bludgeon.skipped_tag"), tag=path.tag_path);
+    }
+    drop(l);
+
+    Ok(!skip)
+}
+
+pub fn bludgeon_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageResult<ExitCode> {
+    let parsed_args = ParsedArguments::parse_arguments(args,
+                                                       &[
+                                                       Argument { long: "dry-run", short: 'n', description: get_compiled_string!("engine.h1.verbs.bludgeon.arguments.dry-run.description"), parameter: None, multiple: false }
+                                                       ],
+                                                       &[get_compiled_string!("arguments.specifier.tag_batch_with_group")],
+                                                       executable,
+                                                       verb.get_description(),
+                                                       ArgumentConstraints::new().needs_tags().uses_threads().multiple_tags_directories())?;
+    let tag_path = &parsed_args.extra[0];
+    let options = BludgeonOptions {
+        dry_run: parsed_args.named.get("dry-run").is_some(),
+        batched: TagFile::uses_batching(tag_path)
+    };
+    Ok(super::do_with_batching_threaded(bludgeon_tag, tag_path, None, &str_slice_to_path_vec(&parsed_args.named["tags"]), parsed_args.threads, options)?.exit_code())
+}
@@ -0,0 +1,61 @@
+use std::path::Path;
+use std::process::ExitCode;
+use crate::cmd::*;
+use crate::file::*;
+use macros::terminal::*;
+use ringhopper::engines::h1::definitions::ScenarioStructureBSP;
+use ringhopper::engines::h1::jms::*;
+use ringhopper::engines::h1::*;
+use ringhopper::error::*;
+use ringhopper::types::HALO_DIRECTORY_SEPARATOR;
+use ringhopper_proc::*;
+use super::model::ShaderResolver;
+
+/// Read all JMS files in the directory, sorted by file name, along with their lowercase file names.
+fn read_jms_files(models_dir: &Path) -> ErrorMessageResult<Vec<(String, JMS)>> {
+    let paths = find_files_with_extensions(models_dir, &["jms"], |error| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.bsp.error_reading_models_directory"), dir=models_dir.display(), error=error)))?;
+
+    let mut jms_files = Vec::with_capacity(paths.len());
+    for path in paths {
+        let name = path.file_stem().unwrap().to_string_lossy().to_ascii_lowercase();
+        let jms = JMS::parse_bytes(&read_file(&path)?).map_err(|e| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.bsp.error_reading_jms"), file=path.display(), error=e)))?;
+        jms_files.push((name, jms));
+    }
+
+    if jms_files.is_empty() {
+        return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.jms.error_no_jms_files")))
+    }
+    Ok(jms_files)
+}
+
+pub fn bsp_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageResult<ExitCode> {
+    let parsed_args = ParsedArguments::parse_arguments(args, &[], &[get_compiled_string!("arguments.specifier.tag_without_group")], executable, verb.get_description(), ArgumentConstraints::new().needs_data().needs_tags().multiple_tags_directories())?;
+
+    let tags_dirs = str_slice_to_path_vec(&parsed_args.named["tags"]);
+    let data = Path::new(&parsed_args.named["data"][0]);
+    let group = TagGroup::ScenarioStructureBSP;
+
+    // Each JMS makes a BSP named after it in the scenario's directory (e.g. "levels\test\models\room.jms" makes
+    // "levels\test\room").
+    let directory = TagReference::from_path_and_group(&parsed_args.extra[0], group)?;
+    let directory_path = directory.get_path_without_extension().trim_end_matches(HALO_DIRECTORY_SEPARATOR).to_owned();
+    let models_dir = data.join(directory_path.replace(HALO_DIRECTORY_SEPARATOR, std::path::MAIN_SEPARATOR_STR)).join("models");
+    let jms_files = read_jms_files(&models_dir)?;
+
+    let shaders = ShaderResolver::new(&tags_dirs, &directory_path)?;
+    for (name, jms) in jms_files {
+        let tag_path = TagReference::from_path_and_group(&format!("{directory_path}{HALO_DIRECTORY_SEPARATOR}{name}"), group)?;
+
+        let (existing, output_path) = load_existing_tag::<ScenarioStructureBSP>(&tags_dirs, &tag_path)?;
+        let bsp = build_scenario_structure_bsp(&jms, existing.as_ref(), |material| Ok(shaders.resolve(material)))
+            .map_err(|e| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.bsp.error_building_bsp"), tag=tag_path, error=e)))?;
+
+        make_parent_directories(&output_path)?;
+        write_file(&output_path, &bsp.into_tag_file()?)?;
+
+        println_success!(get_compiled_string!("engine.h1.verbs.bsp.saved_file"), file=output_path.display());
+        eprintln_warn!(get_compiled_string!("engine.h1.verbs.bsp.warning_not_generated"), tag=tag_path);
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,41 @@
+use ringhopper::engines::h1::{TagGroup, TagReference, build_cache_file, get_scenario_name};
+use ringhopper_proc::*;
+use std::process::ExitCode;
+use std::path::Path;
+use crate::cmd::*;
+use crate::file::*;
+use ringhopper::error::ErrorMessageResult;
+use ringhopper::file::TagFile;
+use macros::terminal::*;
+
+pub fn build_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageResult<ExitCode> {
+    let parsed_args = ParsedArguments::parse_arguments(args, &[], &[get_compiled_string!("arguments.specifier.tag_without_group")], executable, verb.get_description(), ArgumentConstraints::new().needs_tags().needs_maps().needs_uncompressed_engine().multiple_tags_directories())?;
+
+    let tags_dirs = str_slice_to_path_vec(&parsed_args.named["tags"]);
+    let maps_dir = Path::new(&parsed_args.named["maps"][0]);
+    let engine = parsed_args.engine_target.unwrap();
+    let scenario = TagReference::from_path_and_group(&parsed_args.extra[0], TagGroup::Scenario)?;
+
+    let cache_file = build_cache_file(&scenario, engine, &mut |reference| {
+        match TagFile::from_tag_ref(&tags_dirs, reference) {
+            Some(n) => Ok(Some(read_file(&n.file_path)?)),
+            None => Ok(None)
+        }
+    })?;
+
+    let output_path = maps_dir.join(format!("{}.map", get_scenario_name(&scenario)));
+    write_file(&output_path, &cache_file.data)?;
+
+    println!(get_compiled_string!("engine.h1.verbs.build.tag_count"), count=cache_file.tag_count);
+    println!(get_compiled_string!("engine.h1.verbs.build.tag_space"),
+             used=cache_file.tag_space_used as f64 / 1024.0 / 1024.0,
+             limit=cache_file.tag_space_limit as f64 / 1024.0 / 1024.0,
+             percent=cache_file.tag_space_used as f64 / cache_file.tag_space_limit as f64 * 100.0);
+    println!(get_compiled_string!("engine.h1.verbs.build.file_size"),
+             size=cache_file.data.len() as f64 / 1024.0 / 1024.0,
+             limit=cache_file.file_size_limit as f64 / 1024.0 / 1024.0,
+             percent=cache_file.data.len() as f64 / cache_file.file_size_limit as f64 * 100.0);
+    println_success!(get_compiled_string!("engine.h1.verbs.build.saved_file"), file=output_path.display());
+
+    Ok(ExitCode::SUCCESS)
+}
@@ -3,7 +3,7 @@ use std::path::Path;
 use ringhopper::engines::h1::definitions::{TagCollection, TagCollectionTag, UIWidgetCollection};
 use ringhopper::engines::h1::*;
 use crate::cmd::*;
-use ringhopper::types::tag::TagGroupFn;
+use ringhopper::types::tag::{TagBlockFn, TagGroupFn};
 use macros::terminal::*;
 use crate::file::*;
 use ringhopper::error::{ErrorMessage, ErrorMessageResult};
@@ -14,7 +14,7 @@ macro_rules! make_collection_tag {
         let input = $input;
 
         // Put it together
-        let mut collection = <$parser>::default();
+        let mut collection = <$parser>::new_with_defaults();
         for l in input.lines() {
             if l.is_empty() { continue }
             collection.tags.blocks.push(TagCollectionTag {
@@ -64,6 +64,7 @@ mod tests {
     use ringhopper::engines::h1::definitions::*;
     use ringhopper::engines::h1::*;
     use ringhopper::error::ErrorMessageResult;
+    use ringhopper::types::tag::TagBlockFn;
 
     #[test]
     fn test_make_tag_collection_tag() {
@@ -0,0 +1,54 @@
+use std::path::Path;
+use std::process::ExitCode;
+use crate::cmd::*;
+use crate::file::*;
+use macros::terminal::*;
+use ringhopper::engines::h1::definitions::ModelCollisionGeometry;
+use ringhopper::engines::h1::jms::*;
+use ringhopper::engines::h1::*;
+use ringhopper::error::*;
+use ringhopper::types::HALO_DIRECTORY_SEPARATOR;
+use ringhopper_proc::*;
+
+/// Read all JMS files in the directory, sorted by file name.
+fn read_jms_files(physics_dir: &Path) -> ErrorMessageResult<Vec<CollisionPermutationJMS>> {
+    let paths = find_files_with_extensions(physics_dir, &["jms"], |error| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.collision.error_reading_physics_directory"), dir=physics_dir.display(), error=error)))?;
+
+    let mut jms_files: Vec<CollisionPermutationJMS> = Vec::with_capacity(paths.len());
+    for path in paths {
+        let permutation = path.file_stem().unwrap().to_string_lossy().to_ascii_lowercase();
+        if jms_files.iter().any(|j| j.permutation == permutation) {
+            return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.collision.error_duplicate_jms"), permutation=permutation)))
+        }
+
+        let jms = JMS::parse_bytes(&read_file(&path)?).map_err(|e| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.collision.error_reading_jms"), file=path.display(), error=e)))?;
+        jms_files.push(CollisionPermutationJMS { permutation, jms });
+    }
+
+    Ok(jms_files)
+}
+
+pub fn collision_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageResult<ExitCode> {
+    let parsed_args = ParsedArguments::parse_arguments(args, &[], &[get_compiled_string!("arguments.specifier.tag_without_group")], executable, verb.get_description(), ArgumentConstraints::new().needs_data().needs_tags().multiple_tags_directories())?;
+
+    let tags_dirs = str_slice_to_path_vec(&parsed_args.named["tags"]);
+    let data = Path::new(&parsed_args.named["data"][0]);
+    let group = TagGroup::ModelCollisionGeometry;
+
+    // The tag is named after its directory (e.g. "weapons\pistol" makes "weapons\pistol\pistol").
+    let directory = TagReference::from_path_and_group(&parsed_args.extra[0], group)?;
+    let directory_path = directory.get_path_without_extension().trim_end_matches(HALO_DIRECTORY_SEPARATOR).to_owned();
+    let name = directory_path.rsplit(HALO_DIRECTORY_SEPARATOR).next().unwrap_or_default();
+    let tag_path = TagReference::from_path_and_group(&format!("{directory_path}{HALO_DIRECTORY_SEPARATOR}{name}"), group)?;
+
+    let physics_dir = data.join(directory_path.replace(HALO_DIRECTORY_SEPARATOR, std::path::MAIN_SEPARATOR_STR)).join("physics");
+    let jms_files = read_jms_files(&physics_dir)?;
+
+    let (existing, output_path) = load_existing_tag::<ModelCollisionGeometry>(&tags_dirs, &tag_path)?;
+    let output = build_model_collision_geometry(&jms_files, existing.as_ref())?.into_tag_file()?;
+    make_parent_directories(&output_path)?;
+    write_file(&output_path, &output)?;
+
+    println_success!(get_compiled_string!("engine.h1.verbs.collision.saved_file"), file=output_path.display());
+    Ok(ExitCode::SUCCESS)
+}
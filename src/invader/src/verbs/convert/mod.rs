@@ -15,25 +15,25 @@ use std::convert::{From, TryFrom};
 type ConversionFunctionTuple = (TagGroup, fn (&[u8]) -> ErrorMessageResult<Vec<u8>>);
 
 fn convert_object_to_object<T: ObjectSuperFn + TagFileSerializeFn, U: ObjectSuperFn + TagFileSerializeFn + Default>(data: &[u8]) -> ErrorMessageResult<Vec<u8>> {
-    let mut to_object = U::default();
+    let mut to_object = U::new_with_defaults();
     *to_object.get_base_object_mut() = T::from_tag_file(data)?.data.get_base_object().to_owned();
     to_object.into_tag_file()
 }
 
 fn convert_unit_to_unit<T: UnitSuperFn + TagFileSerializeFn, U: UnitSuperFn + TagFileSerializeFn + Default>(data: &[u8]) -> ErrorMessageResult<Vec<u8>> {
-    let mut to_object = U::default();
+    let mut to_object = U::new_with_defaults();
     *to_object.get_base_unit_mut() = T::from_tag_file(data)?.data.get_base_unit().to_owned();
     to_object.into_tag_file()
 }
 
 fn convert_item_to_item<T: ItemSuperFn + TagFileSerializeFn, U: ItemSuperFn + TagFileSerializeFn + Default>(data: &[u8]) -> ErrorMessageResult<Vec<u8>> {
-    let mut to_object = U::default();
+    let mut to_object = U::new_with_defaults();
     *to_object.get_base_item_mut() = T::from_tag_file(data)?.data.get_base_item().to_owned();
     to_object.into_tag_file()
 }
 
 fn convert_device_to_device<T: DeviceSuperFn + TagFileSerializeFn, U: DeviceSuperFn + TagFileSerializeFn + Default>(data: &[u8]) -> ErrorMessageResult<Vec<u8>> {
-    let mut to_object = U::default();
+    let mut to_object = U::new_with_defaults();
     *to_object.get_base_device_mut() = T::from_tag_file(data)?.data.get_base_device().to_owned();
     to_object.into_tag_file()
 }
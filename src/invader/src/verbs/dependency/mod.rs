@@ -0,0 +1,135 @@
+use std::collections::VecDeque;
+use std::path::Path;
+use std::process::ExitCode;
+use crate::cmd::*;
+use crate::file::*;
+use macros::terminal::*;
+use ringhopper::engines::h1::definitions::parse_tag_file;
+use ringhopper::engines::h1::*;
+use ringhopper::error::*;
+use ringhopper::file::*;
+use ringhopper::types::tag::TagGroupFn;
+use ringhopper_proc::*;
+
+/// Get the dependencies of a tag, warning about any references to groups not allowed by their fields.
+fn read_dependencies(tag: &TagFile) -> ErrorMessageResult<Vec<TagReference>> {
+    let dependencies = parse_tag_file(&read_file(&tag.file_path)?)?.data.get_dependencies();
+    let mut references = Vec::with_capacity(dependencies.len());
+    for d in dependencies {
+        if !d.is_group_allowed() {
+            let groups = d.allowed_groups.iter().map(|g| g.as_str()).collect::<Vec<&str>>().join(", ");
+            eprintln_warn!(get_compiled_string!("engine.h1.verbs.dependency.warning_invalid_group"), tag=tag.tag_path, path=d.path, reference=d.reference, groups=groups);
+        }
+        if !references.contains(&d.reference) {
+            references.push(d.reference);
+        }
+    }
+    Ok(references)
+}
+
+/// Find the tags referenced by `tag`, following references of references if `recursive` is set.
+///
+/// Each reference is returned along with whether or not it exists.
+fn find_dependencies(tags_directories: &[&Path], tag: &TagReference, recursive: bool) -> ErrorMessageResult<Vec<(TagReference, bool)>> {
+    let tag_file = match TagFile::from_tag_ref(tags_directories, tag) {
+        Some(n) => n,
+        None => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("general.error_tag_not_found"), tag=tag)))
+    };
+
+    let mut dependencies: Vec<(TagReference, bool)> = Vec::new();
+    let mut queue = VecDeque::from([tag_file]);
+    while let Some(next) = queue.pop_front() {
+        for reference in read_dependencies(&next)? {
+            if reference == *tag || dependencies.iter().any(|(r, _)| *r == reference) {
+                continue;
+            }
+
+            let file = TagFile::from_tag_ref(tags_directories, &reference);
+            dependencies.push((reference, file.is_some()));
+            if let (true, Some(file)) = (recursive, file) {
+                queue.push_back(file);
+            }
+        }
+    }
+
+    Ok(dependencies)
+}
+
+/// Find the tags that reference `tag`, following tags that reference those tags if `recursive` is set.
+fn find_reverse_dependencies(tags_directories: &[&Path], tag: &TagReference, recursive: bool, thread_count: usize) -> ErrorMessageResult<Vec<TagReference>> {
+    // Read the dependencies of every tag in the virtual tags directory.
+    let all_tags = TagFile::from_virtual_tags_directory(tags_directories)?;
+    let chunk_size = all_tags.len().div_ceil(thread_count).max(1);
+    let all_dependencies: Vec<(TagReference, Vec<TagReference>)> = std::thread::scope(|scope| {
+        let threads: Vec<_> = all_tags.chunks(chunk_size).map(|chunk| scope.spawn(move || {
+            let mut results = Vec::with_capacity(chunk.len());
+            for t in chunk {
+                match read_dependencies(t) {
+                    Ok(n) => results.push((t.tag_path.clone(), n)),
+                    Err(e) => eprintln_warn!(get_compiled_string!("engine.h1.verbs.dependency.warning_could_not_read_tag"), tag=t.tag_path, error=e)
+                }
+            }
+            results
+        })).collect();
+        threads.into_iter().flat_map(|t| t.join().unwrap()).collect()
+    });
+
+    let mut dependents: Vec<TagReference> = Vec::new();
+    let mut searched = 0;
+    let mut search = vec![tag.to_owned()];
+    while searched < search.len() {
+        let target = search[searched].clone();
+        searched += 1;
+
+        for (reference, dependencies) in &all_dependencies {
+            if reference == tag || dependents.contains(reference) || !dependencies.contains(&target) {
+                continue;
+            }
+
+            dependents.push(reference.clone());
+            if recursive {
+                search.push(reference.clone());
+            }
+        }
+    }
+
+    dependents.sort_by_key(|t| t.get_path_with_extension());
+    Ok(dependents)
+}
+
+pub fn dependency_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageResult<ExitCode> {
+    let parsed_args = ParsedArguments::parse_arguments(args,
+                                                       &[
+                                                       Argument { long: "recursive", short: 'r', description: get_compiled_string!("engine.h1.verbs.dependency.arguments.recursive.description"), parameter: None, multiple: false },
+                                                       Argument { long: "reverse", short: 'R', description: get_compiled_string!("engine.h1.verbs.dependency.arguments.reverse.description"), parameter: None, multiple: false }
+                                                       ],
+                                                       &[get_compiled_string!("arguments.specifier.tag_with_group")],
+                                                       executable,
+                                                       verb.get_description(),
+                                                       ArgumentConstraints::new().needs_tags().uses_threads().multiple_tags_directories())?;
+
+    let tags_dirs = str_slice_to_path_vec(&parsed_args.named["tags"]);
+    let tag = TagReference::from_full_path(&parsed_args.extra[0])?;
+    let recursive = parsed_args.named.get("recursive").is_some();
+
+    if parsed_args.named.get("reverse").is_some() {
+        for t in find_reverse_dependencies(&tags_dirs, &tag, recursive, parsed_args.threads.get())? {
+            println!("{t}");
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+    else {
+        // Missing tags are errors, since the tag would be broken if it were used.
+        let mut missing = false;
+        for (t, exists) in find_dependencies(&tags_dirs, &tag, recursive)? {
+            if exists {
+                println!("{t}");
+            }
+            else {
+                println_error!(get_compiled_string!("engine.h1.verbs.dependency.missing_tag"), tag=t);
+                missing = true;
+            }
+        }
+        Ok(if missing { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+    }
+}
@@ -0,0 +1,342 @@
+use ringhopper_proc::*;
+use std::num::NonZeroUsize;
+use std::process::ExitCode;
+use crate::cmd::*;
+use macros::terminal::*;
+use ringhopper::error::*;
+use ringhopper::file::*;
+use ringhopper::types::*;
+use crate::file::*;
+use ringhopper::engines::h1::definitions::parse_tag_file;
+
+mod value;
+use self::value::*;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Clone)]
+struct EditOptions {
+    get: Vec<String>,
+    set: Vec<(String, String)>,
+    insert: Vec<String>,
+    delete: Vec<String>,
+    count: Vec<String>,
+    batched: bool
+}
+
+/// Field that a path resolved to.
+enum Target<'a> {
+    /// Any field that is not a block array.
+    Field(TagFieldValue<'a>),
+
+    /// Block array, optionally with an index into it (e.g. `triggers[0]`).
+    ///
+    /// The index is not bounds checked, as inserting at the end of the array is valid.
+    Array(&'a mut dyn ReflexiveFn, Option<usize>),
+
+    /// Bit of a bitfield.
+    Bit(&'a mut dyn TagBitfieldFn, usize)
+}
+
+fn edit_tag(path: &TagFile, log_mutex: super::LogMutex, _: NonZeroUsize, options: &EditOptions) -> ErrorMessageResult<bool> {
+    let file_data = read_file(&path.file_path)?;
+    let mut tag = parse_tag_file(&file_data)?.data;
+    let tag = tag.as_mut();
+
+    for p in &options.insert {
+        let (array, index) = resolve_array(tag, p)?;
+        let index = index.unwrap_or(array.len());
+        check_bounds(p, index, array.len() + 1)?;
+        array.insert_default(index);
+    }
+
+    for p in &options.delete {
+        let (array, index) = resolve_array(tag, p)?;
+        match index {
+            Some(index) => {
+                check_bounds(p, index, array.len())?;
+                array.remove(index);
+            },
+            None => while array.len() > 0 {
+                array.remove(array.len() - 1);
+            }
+        }
+    }
+
+    for (p, v) in &options.set {
+        set_target(resolve(tag, p)?, p, v)?;
+    }
+
+    let mut output = Vec::new();
+    for p in &options.get {
+        get_target(resolve(tag, p)?, p, &mut output)?;
+    }
+    for p in &options.count {
+        let (array, index) = resolve_array(tag, p)?;
+        if index.is_some() {
+            return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.edit.error_not_an_array"), path=p)))
+        }
+        output.push(array.len().to_string());
+    }
+
+    let modified = !options.insert.is_empty() || !options.delete.is_empty() || !options.set.is_empty();
+    if modified {
+        write_file(&path.file_path, &tag.into_tag_file()?)?;
+    }
+
+    let l = log_mutex.lock();
+    for line in output {
+        if options.batched {
+            println!("{tag}: {line}", tag=path.tag_path, line=line);
+        }
+        else {
+            println!("{line}", line=line);
+        }
+    }
+    if modified {
+        println_success!(get_compiled_string!("engine.h1.verbs.edit.edited_tag"), tag=path.tag_path);
+    }
+    drop(l);
+
+    Ok(true)
+}
+
+/// Check that the index of the block array at `path` is less than `count`.
+fn check_bounds(path: &str, index: usize, count: usize) -> ErrorMessageResult<()> {
+    if index >= count {
+        Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.edit.error_index_out_of_bounds"), path=path, count=count)))
+    }
+    else {
+        Ok(())
+    }
+}
+
+/// Split a path segment such as `triggers[0]` into its name and index.
+fn parse_index(segment: &str) -> Option<(&str, usize)> {
+    let (name, index) = segment.strip_suffix(']')?.rsplit_once('[')?;
+    Some((name, index.parse().ok()?))
+}
+
+/// Find the field of the block that matches the path segment.
+///
+/// Fields are matched by their full name first so elements of fixed-size arrays (e.g. `vertices[0]`) can be
+/// accessed, and then by block array name and index.
+fn find_field<'a, B: TagBlockFn + ?Sized>(block: &'a mut B, segment: &str, path: &str) -> ErrorMessageResult<(TagFieldValue<'a>, Option<usize>)> {
+    let not_found = || ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.edit.error_field_not_found"), path=path));
+    let find = |block: &B, name: &str| (0..block.field_count()).find(|i| block.field_at_index(*i).name == name);
+
+    if let Some(i) = find(block, segment) {
+        return Ok((block.field_at_index_mut(i).field, None))
+    }
+
+    let (name, index) = parse_index(segment).ok_or_else(not_found)?;
+    let i = find(block, name).ok_or_else(not_found)?;
+    match block.field_at_index_mut(i).field {
+        field @ TagFieldValue::MutableArray(_) => Ok((field, Some(index))),
+        _ => Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.edit.error_not_an_array"), path=path)))
+    }
+}
+
+/// Resolve a path such as `triggers[0].rounds per second` into the field it refers to.
+///
+/// Bounds fields can be accessed with `lower` and `upper`, and bits of bitfields can be accessed by name.
+fn resolve<'a, B: TagBlockFn + ?Sized>(block: &'a mut B, path: &str) -> ErrorMessageResult<Target<'a>> {
+    let not_found = || ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.edit.error_field_not_found"), path=path));
+
+    let mut segments = path.split('.');
+    let (mut current, mut index) = find_field(block, segments.next().unwrap(), path)?;
+
+    while let Some(segment) = segments.next() {
+        (current, index) = match (current, index) {
+            (TagFieldValue::MutableArray(array), Some(index)) => {
+                check_bounds(path, index, array.len())?;
+                find_field(array.block_at_index_mut(index), segment, path)?
+            },
+            (TagFieldValue::MutableArray(_), None) => {
+                return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.edit.error_index_required"), path=path)))
+            },
+            (TagFieldValue::MutableBlock(block), _) => find_field(block, segment, path)?,
+            (TagFieldValue::MutableBounds(bounds), _) => match segment {
+                "lower" => (TagFieldValue::MutableValue(bounds.get_lower_mut()), None),
+                "upper" => (TagFieldValue::MutableValue(bounds.get_upper_mut()), None),
+                _ => return Err(not_found())
+            },
+            (TagFieldValue::MutableBitfield(bitfield), _) => {
+                let bit = bitfield.get_bits().iter().position(|b| *b == segment).ok_or_else(not_found)?;
+                return match segments.next() {
+                    Some(_) => Err(not_found()),
+                    None => Ok(Target::Bit(bitfield, bit))
+                }
+            },
+            _ => return Err(not_found())
+        };
+    }
+
+    match current {
+        TagFieldValue::MutableArray(array) => Ok(Target::Array(array, index)),
+        field => Ok(Target::Field(field))
+    }
+}
+
+/// Resolve a path that must refer to a block array.
+fn resolve_array<'a, B: TagBlockFn + ?Sized>(block: &'a mut B, path: &str) -> ErrorMessageResult<(&'a mut dyn ReflexiveFn, Option<usize>)> {
+    match resolve(block, path)? {
+        Target::Array(array, index) => Ok((array, index)),
+        _ => Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.edit.error_not_an_array"), path=path)))
+    }
+}
+
+/// Set the target from a string.
+fn set_target(target: Target, path: &str, value: &str) -> ErrorMessageResult<()> {
+    let invalid = || ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.edit.error_invalid_value"), value=value, path=path));
+
+    match target {
+        Target::Field(TagFieldValue::MutableValue(mut field)) => set_value_from_string(field.get_value(), value, path),
+        Target::Field(TagFieldValue::MutableBounds(bounds)) => {
+            // Split the components evenly between both bounds.
+            let components: Vec<&str> = value.split_whitespace().collect();
+            let (lower, upper) = components.split_at(components.len() / 2);
+            if lower.len() != upper.len() {
+                return Err(invalid())
+            }
+            set_value_from_string(bounds.get_lower_mut().get_value(), &lower.join(" "), path)?;
+            set_value_from_string(bounds.get_upper_mut().get_value(), &upper.join(" "), path)
+        },
+        Target::Field(TagFieldValue::MutableEnum(e)) => {
+            let value = value.trim();
+            match e.get_options().iter().position(|o| *o == value) {
+                Some(n) => e.set_value(n as u16),
+                None => e.set_value(value.parse().map_err(|_| invalid())?)
+            }
+        },
+        Target::Field(TagFieldValue::MutableBitfield(bitfield)) => {
+            let bits = bitfield.get_bits();
+            let mut new_bits = vec![false; bits.len()];
+            for name in value.split_whitespace() {
+                new_bits[bits.iter().position(|b| *b == name).ok_or_else(invalid)?] = true;
+            }
+            for (i, b) in new_bits.into_iter().enumerate() {
+                bitfield.set_bit(i, b);
+            }
+            Ok(())
+        },
+        Target::Bit(bitfield, bit) => {
+            bitfield.set_bit(bit, match value.trim() {
+                "true" | "1" => true,
+                "false" | "0" => false,
+                _ => return Err(invalid())
+            });
+            Ok(())
+        },
+        _ => Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.edit.error_unsupported_value"), path=path)))
+    }
+}
+
+/// Get the target as lines of text.
+///
+/// Single values are output as-is, while blocks are output as one `path = value` line per field.
+fn get_target(target: Target, path: &str, output: &mut Vec<String>) -> ErrorMessageResult<()> {
+    match target {
+        Target::Field(field) => match field_to_string(&field) {
+            Some(n) => output.push(n),
+            None => get_field_lines(field, path, output)
+        },
+        Target::Array(array, Some(index)) => {
+            check_bounds(path, index, array.len())?;
+            get_block_lines(array.block_at_index(index), path, output);
+        },
+        Target::Array(array, None) => get_field_lines(TagFieldValue::Array(array), path, output),
+        Target::Bit(bitfield, bit) => output.push(bitfield.get_bit(bit).to_string())
+    }
+    Ok(())
+}
+
+/// Get each field of the block as `path = value` lines.
+fn get_block_lines(block: &dyn TagBlockFn, path: &str, output: &mut Vec<String>) {
+    for i in 0..block.field_count() {
+        let field = block.field_at_index(i);
+        let field_path = format!("{path}.{name}", path=path, name=field.name);
+        match field_to_string(&field.field) {
+            Some(n) => output.push(format!("{field_path} = {n}", field_path=field_path, n=n)),
+            None => get_field_lines(field.field, &field_path, output)
+        }
+    }
+}
+
+/// Get each block of a block or block array as `path = value` lines.
+fn get_field_lines(field: TagFieldValue, path: &str, output: &mut Vec<String>) {
+    match field {
+        TagFieldValue::Array(array) => for i in 0..array.len() {
+            get_block_lines(array.block_at_index(i), &format!("{path}[{i}]", path=path, i=i), output)
+        },
+        TagFieldValue::MutableArray(array) => for i in 0..array.len() {
+            get_block_lines(array.block_at_index(i), &format!("{path}[{i}]", path=path, i=i), output)
+        },
+        TagFieldValue::Block(block) => get_block_lines(block, path, output),
+        TagFieldValue::MutableBlock(block) => get_block_lines(block, path, output),
+        _ => unreachable!()
+    }
+}
+
+/// Convert the field into a string, or return `None` if it is a block or block array.
+fn field_to_string(field: &TagFieldValue) -> Option<String> {
+    let bounds_to_string = |bounds: &dyn BoundsFn| format!("{lower} {upper}", lower=value_to_string(bounds.get_lower().get_value()), upper=value_to_string(bounds.get_upper().get_value()));
+    let enum_to_string = |e: &dyn TagEnumValueFn| match e.get_options().get(e.get_value() as usize) {
+        Some(n) => n.to_string(),
+        None => e.get_value().to_string()
+    };
+    let bitfield_to_string = |bitfield: &dyn TagBitfieldFn| bitfield.get_bits().iter().enumerate().filter(|(i, _)| bitfield.get_bit(*i)).map(|(_, b)| *b).collect::<Vec<&str>>().join(" ");
+
+    match field {
+        TagFieldValue::Value(v) => Some(value_to_string(v.get_value())),
+        TagFieldValue::MutableValue(v) => Some(value_to_string(FieldReference { field: &*v.field }.get_value())),
+        TagFieldValue::Bounds(b) => Some(bounds_to_string(*b)),
+        TagFieldValue::MutableBounds(b) => Some(bounds_to_string(&**b)),
+        TagFieldValue::Enum(e) => Some(enum_to_string(*e)),
+        TagFieldValue::MutableEnum(e) => Some(enum_to_string(&**e)),
+        TagFieldValue::Bitfield(b) => Some(bitfield_to_string(*b)),
+        TagFieldValue::MutableBitfield(b) => Some(bitfield_to_string(&**b)),
+        TagFieldValue::Array(_) | TagFieldValue::MutableArray(_) | TagFieldValue::Block(_) | TagFieldValue::MutableBlock(_) => None
+    }
+}
+
+pub fn edit_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageResult<ExitCode> {
+    let parsed_args = ParsedArguments::parse_arguments(args,
+                                                       &[
+                                                       Argument { long: "get", short: 'g', description: get_compiled_string!("engine.h1.verbs.edit.arguments.get.description"), parameter: Some("path"), multiple: true },
+                                                       Argument { long: "set", short: 's', description: get_compiled_string!("engine.h1.verbs.edit.arguments.set.description"), parameter: Some("path=value"), multiple: true },
+                                                       Argument { long: "insert", short: 'i', description: get_compiled_string!("engine.h1.verbs.edit.arguments.insert.description"), parameter: Some("path"), multiple: true },
+                                                       Argument { long: "delete", short: 'D', description: get_compiled_string!("engine.h1.verbs.edit.arguments.delete.description"), parameter: Some("path"), multiple: true },
+                                                       Argument { long: "count", short: 'c', description: get_compiled_string!("engine.h1.verbs.edit.arguments.count.description"), parameter: Some("path"), multiple: true }
+                                                       ],
+                                                       &[get_compiled_string!("arguments.specifier.tag_batch_with_group")],
+                                                       executable,
+                                                       verb.get_description(),
+                                                       ArgumentConstraints::new().needs_tags().uses_threads().multiple_tags_directories())?;
+
+    let named = |name: &str| parsed_args.named.get(name).cloned().unwrap_or_default();
+    let mut set = Vec::new();
+    for s in named("set") {
+        match s.split_once('=') {
+            Some((path, value)) => set.push((path.to_owned(), value.to_owned())),
+            None => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.edit.error_invalid_set"), argument=s)))
+        }
+    }
+
+    let tag_path = &parsed_args.extra[0];
+    let options = EditOptions {
+        get: named("get"),
+        set,
+        insert: named("insert"),
+        delete: named("delete"),
+        count: named("count"),
+        batched: TagFile::uses_batching(tag_path)
+    };
+
+    if options.get.is_empty() && options.set.is_empty() && options.insert.is_empty() && options.delete.is_empty() && options.count.is_empty() {
+        return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.verbs.edit.error_no_operations")))
+    }
+
+    Ok(super::do_with_batching_threaded(edit_tag, tag_path, None, &str_slice_to_path_vec(&parsed_args.named["tags"]), parsed_args.threads, options)?.exit_code())
+}
@@ -0,0 +1,80 @@
+use ringhopper::engines::h1::definitions::*;
+use super::*;
+
+fn set(weapon: &mut Weapon, path: &str, value: &str) -> ErrorMessageResult<()> {
+    set_target(resolve(weapon, path)?, path, value)
+}
+
+fn get(weapon: &mut Weapon, path: &str) -> ErrorMessageResult<Vec<String>> {
+    let mut output = Vec::new();
+    get_target(resolve(weapon, path)?, path, &mut output)?;
+    Ok(output)
+}
+
+#[test]
+fn test_edit_weapon() {
+    let mut weapon = Weapon::default();
+    assert!(get(&mut weapon, "triggers[0].acceleration time").is_err());
+
+    // Insert at the end and then at the beginning.
+    let (triggers, index) = resolve_array(&mut weapon, "triggers").unwrap();
+    assert_eq!(None, index);
+    triggers.insert_default(0);
+    let (triggers, index) = resolve_array(&mut weapon, "triggers[0]").unwrap();
+    assert_eq!(Some(0), index);
+    triggers.insert_default(0);
+    assert_eq!(2, weapon.triggers.blocks.len());
+
+    // Values
+    set(&mut weapon, "triggers[1].acceleration time", "2.5").unwrap();
+    assert_eq!(2.5, weapon.triggers.blocks[1].acceleration_time);
+    assert_eq!(vec!["2.5"], get(&mut weapon, "triggers[1].acceleration time").unwrap());
+    assert!(set(&mut weapon, "triggers[1].acceleration time", "fast").is_err());
+    assert!(set(&mut weapon, "triggers[1].first person offset", "1 2").is_err());
+    set(&mut weapon, "triggers[1].first person offset", "1 2 3").unwrap();
+    assert_eq!(vec!["1 2 3"], get(&mut weapon, "triggers[1].first person offset").unwrap());
+
+    // Bounds
+    set(&mut weapon, "triggers[0].maximum rate of fire", "1 2").unwrap();
+    set(&mut weapon, "triggers[0].maximum rate of fire.upper", "3").unwrap();
+    assert_eq!(vec!["1 3"], get(&mut weapon, "triggers[0].maximum rate of fire").unwrap());
+    assert!(set(&mut weapon, "triggers[0].maximum rate of fire", "1").is_err());
+
+    // Bitfields
+    set(&mut weapon, "triggers[0].flags.can-fire-with-partial-ammo", "true").unwrap();
+    assert!(weapon.triggers.blocks[0].flags.can_fire_with_partial_ammo);
+    assert_eq!(vec!["can-fire-with-partial-ammo"], get(&mut weapon, "triggers[0].flags").unwrap());
+    set(&mut weapon, "triggers[0].flags", "tracks-fired-projectile analog-rate-of-fire").unwrap();
+    assert!(!weapon.triggers.blocks[0].flags.can_fire_with_partial_ammo);
+    assert_eq!(vec!["true"], get(&mut weapon, "triggers[0].flags.analog-rate-of-fire").unwrap());
+
+    // Enums, indices, and references
+    set(&mut weapon, "triggers[0].prediction type", "instant").unwrap();
+    assert_eq!(WeaponPredictionType::Instant, weapon.triggers.blocks[0].prediction_type);
+    assert!(set(&mut weapon, "triggers[0].prediction type", "sometimes").is_err());
+    set(&mut weapon, "triggers[0].magazine", "1").unwrap();
+    assert_eq!(Some(1), weapon.triggers.blocks[0].magazine);
+    set(&mut weapon, "triggers[0].magazine", "null").unwrap();
+    assert_eq!(None, weapon.triggers.blocks[0].magazine);
+    set(&mut weapon, "triggers[0].projectile", "weapons\\assault rifle\\bullet.projectile").unwrap();
+    assert_eq!(vec!["weapons\\assault rifle\\bullet.projectile"], get(&mut weapon, "triggers[0].projectile").unwrap());
+    assert!(set(&mut weapon, "triggers[0].projectile", "weapons\\assault rifle\\bullet").is_err());
+
+    // Blocks are output as one line per field.
+    let lines = get(&mut weapon, "triggers[1]").unwrap();
+    assert!(lines.contains(&"triggers[1].acceleration time = 2.5".to_owned()));
+    assert_eq!(lines, get(&mut weapon, "triggers").unwrap()[lines.len()..]);
+
+    // Invalid paths
+    assert!(get(&mut weapon, "triggers.acceleration time").is_err());
+    assert!(get(&mut weapon, "triggers[2].acceleration time").is_err());
+    assert!(get(&mut weapon, "triggers[0].acceleration time.upper").is_err());
+    assert!(get(&mut weapon, "triggers[0].nothing").is_err());
+    assert!(resolve_array(&mut weapon, "triggers[0].acceleration time").is_err());
+
+    // Remove
+    let (triggers, _) = resolve_array(&mut weapon, "triggers").unwrap();
+    triggers.remove(0);
+    assert_eq!(1, weapon.triggers.blocks.len());
+    assert_eq!(2.5, weapon.triggers.blocks[0].acceleration_time);
+}
@@ -0,0 +1,132 @@
+use std::str::FromStr;
+use ringhopper::error::*;
+use ringhopper::types::*;
+use ringhopper_proc::*;
+
+/// Convert the value into a string.
+///
+/// Values with multiple components are separated with spaces.
+pub fn value_to_string(value: ValueReference) -> String {
+    match value {
+        ValueReference::Int8(v) => v.to_string(),
+        ValueReference::Int16(v) => v.to_string(),
+        ValueReference::Int32(v) => v.to_string(),
+        ValueReference::UInt8(v) => v.to_string(),
+        ValueReference::UInt16(v) => v.to_string(),
+        ValueReference::UInt32(v) => v.to_string(),
+        ValueReference::Float32(v) => v.to_string(),
+
+        ValueReference::ColorAHSV(v) => join(&[v.a, v.h, v.s, v.v]),
+        ValueReference::ColorARGB(v) => join(&[v.a, v.r, v.g, v.b]),
+        ValueReference::ColorARGBInt(v) => join(&[v.a, v.r, v.g, v.b]),
+        ValueReference::ColorHSV(v) => join(&[v.h, v.s, v.v]),
+        ValueReference::ColorRGB(v) => join(&[v.r, v.g, v.b]),
+        ValueReference::ColorRGBInt(v) => join(&[v.r, v.g, v.b]),
+        ValueReference::Euler2D(v) => join(&[v.y, v.p]),
+        ValueReference::Euler3D(v) => join(&[v.y, v.p, v.r]),
+        ValueReference::Matrix(v) => join(&v.vectors.iter().flat_map(|v| [v.x, v.y, v.z]).collect::<Vec<f32>>()),
+        ValueReference::Plane2D(v) => join(&[v.vector.x, v.vector.y, v.d]),
+        ValueReference::Plane3D(v) => join(&[v.vector.x, v.vector.y, v.vector.z, v.d]),
+        ValueReference::Point2D(v) => join(&[v.x, v.y]),
+        ValueReference::Point2DInt(v) => join(&[v.x, v.y]),
+        ValueReference::Point3D(v) => join(&[v.x, v.y, v.z]),
+        ValueReference::Quaternion(v) => join(&[v.x, v.y, v.z, v.w]),
+        ValueReference::Rectangle(v) => join(&[v.top, v.left, v.bottom, v.right]),
+        ValueReference::String32(v) => v.to_str().to_owned(),
+        ValueReference::Vector2D(v) => join(&[v.x, v.y]),
+        ValueReference::Vector3D(v) => join(&[v.x, v.y, v.z]),
+
+        ValueReference::Data(v) => format!(get_compiled_string!("engine.h1.verbs.edit.data_size"), size=v.len()),
+        ValueReference::Index(v) => match v {
+            Some(n) => n.to_string(),
+            None => "null".to_owned()
+        },
+
+        ValueReference::H1TagReference(v) => match v.is_empty() {
+            true => String::new(),
+            false => v.get_path_with_extension()
+        }
+    }
+}
+
+/// Set the value from a string, using the same format as [`value_to_string`].
+///
+/// Return an [`Err`] if the string is not a valid value for the field at `path`.
+pub fn set_value_from_string(value: ValueReferenceMut, string: &str, path: &str) -> ErrorMessageResult<()> {
+    let invalid = || ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.edit.error_invalid_value"), value=string, path=path));
+
+    macro_rules! parse {
+        ($t:ty) => {
+            string.trim().parse::<$t>().map_err(|_| invalid())?
+        };
+        ($t:ty, $count:expr) => {
+            split::<$t, $count>(string).ok_or_else(invalid)?
+        };
+    }
+
+    match value {
+        ValueReferenceMut::Int8(v) => *v = parse!(i8),
+        ValueReferenceMut::Int16(v) => *v = parse!(i16),
+        ValueReferenceMut::Int32(v) => *v = parse!(i32),
+        ValueReferenceMut::UInt8(v) => *v = parse!(u8),
+        ValueReferenceMut::UInt16(v) => *v = parse!(u16),
+        ValueReferenceMut::UInt32(v) => *v = parse!(u32),
+        ValueReferenceMut::Float32(v) => *v = parse!(f32),
+
+        ValueReferenceMut::ColorAHSV(v) => { let [a, h, s, vv] = parse!(f32, 4); *v = ColorAHSV { a, h, s, v: vv } },
+        ValueReferenceMut::ColorARGB(v) => { let [a, r, g, b] = parse!(f32, 4); *v = ColorARGB { a, r, g, b } },
+        ValueReferenceMut::ColorARGBInt(v) => { let [a, r, g, b] = parse!(u8, 4); *v = ColorARGBInt { a, r, g, b } },
+        ValueReferenceMut::ColorHSV(v) => { let [h, s, vv] = parse!(f32, 3); *v = ColorHSV { h, s, v: vv } },
+        ValueReferenceMut::ColorRGB(v) => { let [r, g, b] = parse!(f32, 3); *v = ColorRGB { r, g, b } },
+        ValueReferenceMut::ColorRGBInt(v) => { let [r, g, b] = parse!(u8, 3); *v = ColorRGBInt { r, g, b } },
+        ValueReferenceMut::Euler2D(v) => { let [y, p] = parse!(f32, 2); *v = Euler2D { y, p } },
+        ValueReferenceMut::Euler3D(v) => { let [y, p, r] = parse!(f32, 3); *v = Euler3D { y, p, r } },
+        ValueReferenceMut::Matrix(v) => {
+            let m = parse!(f32, 9);
+            for (vector, c) in v.vectors.iter_mut().zip(m.chunks(3)) {
+                *vector = Vector3D { x: c[0], y: c[1], z: c[2] };
+            }
+        },
+        ValueReferenceMut::Plane2D(v) => { let [x, y, d] = parse!(f32, 3); *v = Plane2D { vector: Vector2D { x, y }, d } },
+        ValueReferenceMut::Plane3D(v) => { let [x, y, z, d] = parse!(f32, 4); *v = Plane3D { vector: Vector3D { x, y, z }, d } },
+        ValueReferenceMut::Point2D(v) => { let [x, y] = parse!(f32, 2); *v = Point2D { x, y } },
+        ValueReferenceMut::Point2DInt(v) => { let [x, y] = parse!(i16, 2); *v = Point2DInt { x, y } },
+        ValueReferenceMut::Point3D(v) => { let [x, y, z] = parse!(f32, 3); *v = Point3D { x, y, z } },
+        ValueReferenceMut::Quaternion(v) => { let [x, y, z, w] = parse!(f32, 4); *v = Quaternion { x, y, z, w } },
+        ValueReferenceMut::Rectangle(v) => { let [top, left, bottom, right] = parse!(i16, 4); *v = Rectangle { top, left, bottom, right } },
+        ValueReferenceMut::String32(v) => *v = String32::from_str(string)?,
+        ValueReferenceMut::Vector2D(v) => { let [x, y] = parse!(f32, 2); *v = Vector2D { x, y } },
+        ValueReferenceMut::Vector3D(v) => { let [x, y, z] = parse!(f32, 3); *v = Vector3D { x, y, z } },
+
+        ValueReferenceMut::Data(_) => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.edit.error_unsupported_value"), path=path))),
+        ValueReferenceMut::Index(v) => *v = match string.trim() {
+            "null" => None,
+            _ => Some(parse!(u16)).filter(|i| *i != 0xFFFF)
+        },
+
+        ValueReferenceMut::H1TagReference(v) => match string.trim() {
+            "" => v.set_path_without_extension("")?,
+            s => v.set_full_path(s)?
+        }
+    }
+
+    Ok(())
+}
+
+/// Join the components with spaces.
+fn join<T: ToString>(components: &[T]) -> String {
+    components.iter().map(ToString::to_string).collect::<Vec<String>>().join(" ")
+}
+
+/// Split the string by whitespace into exactly `N` components.
+fn split<T: FromStr + Copy + Default, const N: usize>(string: &str) -> Option<[T; N]> {
+    let mut components = [T::default(); N];
+    let mut iterator = string.split_whitespace();
+    for c in &mut components {
+        *c = iterator.next()?.parse().ok()?;
+    }
+    match iterator.next() {
+        Some(_) => None,
+        None => Some(components)
+    }
+}
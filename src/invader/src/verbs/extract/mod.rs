@@ -0,0 +1,86 @@
+use ringhopper::engines::h1::{CacheFile, ResourceMap, ResourceMapType, extract_tag};
+use ringhopper_proc::*;
+use std::process::ExitCode;
+use std::path::Path;
+use crate::cmd::*;
+use crate::file::*;
+use ringhopper::error::{ErrorMessage, ErrorMessageResult};
+use macros::terminal::*;
+
+pub fn extract_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageResult<ExitCode> {
+    let parsed_args = ParsedArguments::parse_arguments(args,
+                                                       &[
+                                                       Argument { long: "search", short: 's', description: get_compiled_string!("engine.h1.verbs.extract.arguments.search.description"), parameter: Some("tag*.group"), multiple: true }
+                                                       ],
+                                                       &[get_compiled_string!("arguments.specifier.cache_file")],
+                                                       executable,
+                                                       verb.get_description(),
+                                                       ArgumentConstraints::new().needs_tags()
+                                                                                 .needs_maps()
+                                                                                 .can_overwrite())?;
+
+    let tags_dir = Path::new(&parsed_args.named["tags"][0]);
+    let maps_dir = Path::new(&parsed_args.named["maps"][0]);
+    let overwrite = parsed_args.named.get("overwrite").is_some();
+
+    let mut cache_file = CacheFile::from_data(read_file(Path::new(&parsed_args.extra[0]))?)?;
+
+    // Resource maps are optional, as only tags that use them need them.
+    for resource_map_type in [ResourceMapType::Bitmaps, ResourceMapType::Sounds, ResourceMapType::Loc] {
+        let resource_map_path = maps_dir.join(resource_map_type.file_name());
+        if resource_map_path.is_file() {
+            cache_file.add_resource_map(ResourceMap::from_data(read_file(&resource_map_path)?)?);
+        }
+    }
+
+    // Extract everything if no search was given.
+    let patterns: Vec<String> = match parsed_args.named.get("search") {
+        Some(n) => n.iter().map(|p| p.replace('/', "\\")).collect(),
+        None => vec!["*".to_owned()]
+    };
+    let tags: Vec<_> = cache_file.tags().iter().filter(|t| patterns.iter().any(|p| t.matches_pattern(p))).cloned().collect();
+    if tags.is_empty() {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.extract.error_no_tags_found"), patterns=patterns.join(", "))));
+    }
+
+    let mut success = 0usize;
+    let mut skipped = 0usize;
+    let mut errors = 0usize;
+
+    for tag in &tags {
+        let output_path = tags_dir.join(tag.get_relative_fs_path());
+        if output_path.exists() && !overwrite {
+            println!(get_compiled_string!("engine.h1.verbs.extract.skipped_tag"), tag=tag);
+            skipped += 1;
+            continue;
+        }
+
+        let result = extract_tag(&cache_file, tag).and_then(|data| {
+            make_parent_directories(&output_path)?;
+            write_file(&output_path, &data)
+        });
+
+        match result {
+            Ok(_) => {
+                println_success!(get_compiled_string!("engine.h1.verbs.extract.extracted_tag"), tag=tag);
+                success += 1;
+            },
+            Err(e) => {
+                eprintln_error_pre!(get_compiled_string!("engine.h1.verbs.extract.error_extracting_tag"), tag=tag, error=e);
+                errors += 1;
+            }
+        }
+    }
+
+    if skipped > 0 {
+        println!(get_compiled_string!("engine.h1.verbs.extract.skipped_count"), skipped=skipped);
+    }
+    if errors > 0 {
+        println_warn!(get_compiled_string!("engine.h1.verbs.extract.extracted_count_with_errors"), success=success, errors=errors);
+        Ok(ExitCode::FAILURE)
+    }
+    else {
+        println_success!(get_compiled_string!("engine.h1.verbs.extract.extracted_count"), success=success);
+        Ok(ExitCode::SUCCESS)
+    }
+}
@@ -0,0 +1,145 @@
+use std::path::Path;
+use std::process::ExitCode;
+use crate::cmd::*;
+use crate::file::*;
+use macros::terminal::*;
+use ringhopper::engines::h1::definitions::{Font, FontCharacter};
+use ringhopper::engines::h1::*;
+use ringhopper::error::*;
+use ringhopper::types::TagBlockFn;
+use ringhopper_proc::*;
+
+/// Default size of characters in pixels.
+const DEFAULT_FONT_SIZE: f32 = 14.0;
+
+/// Default range of characters to rasterize, which is printable ASCII.
+const DEFAULT_CHARACTER_RANGE: (u16, u16) = (0x20, 0x7E);
+
+/// Extensions of font files that can be read, in order of preference.
+const FONT_FILE_EXTENSIONS: [&str; 2] = ["ttf", "otf"];
+
+struct FontOptions {
+    size: f32,
+    first_character: u16,
+    last_character: u16
+}
+
+/// Convert a pixel measurement into a font tag field, clamping it to the range of the field.
+fn font_measurement(value: f32) -> i16 {
+    value.clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+/// Rasterize the characters of a TrueType or OpenType font into a font tag.
+///
+/// Font tags cannot store kerning pairs, so each character is spaced by its advance width, and its side bearing is
+/// stored as the origin of its bitmap. The flags and sub-font references of the existing tag are kept, if any, and new
+/// tags start with the default values of the font definition.
+fn make_font(font_data: &[u8], font_path: &Path, options: &FontOptions, existing: Option<Font>) -> ErrorMessageResult<Font> {
+    let font = fontdue::Font::from_bytes(font_data, fontdue::FontSettings { scale: options.size, ..Default::default() })
+        .map_err(|e| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.font.error_reading_font"), file=font_path.display(), error=e)))?;
+    let line_metrics = font.horizontal_line_metrics(options.size)
+        .ok_or_else(|| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.font.error_reading_font"), file=font_path.display(), error="no horizontal metrics")))?;
+
+    let mut tag = existing.unwrap_or_else(Font::new_with_defaults);
+    tag.ascending_height = font_measurement(line_metrics.ascent.ceil());
+    tag.descending_height = font_measurement((-line_metrics.descent).ceil());
+    tag.leading_height = font_measurement(line_metrics.line_gap.round());
+    tag.characters.blocks.clear();
+    tag.pixels.clear();
+
+    for code in options.first_character..=options.last_character {
+        // Skip surrogates and characters the font does not have.
+        let character = match char::from_u32(code as u32) {
+            Some(n) if font.lookup_glyph_index(n) != 0 => n,
+            _ => continue
+        };
+
+        // Pixels are stored as one byte of intensity each, and the origin is the top left of the bitmap relative to the
+        // baseline.
+        let (metrics, pixels) = font.rasterize(character, options.size);
+        let pixels_offset = i32::try_from(tag.pixels.len()).map_err(|_| ErrorMessage::StaticString(get_compiled_string!("engine.h1.types.serialize.error_architecture_limit_exceeded")))?;
+        tag.characters.blocks.push(FontCharacter {
+            character: code,
+            character_width: font_measurement(metrics.advance_width.round()),
+            bitmap_width: font_measurement(metrics.width as f32),
+            bitmap_height: font_measurement(metrics.height as f32),
+            bitmap_origin_x: font_measurement(-metrics.xmin as f32),
+            bitmap_origin_y: font_measurement((metrics.ymin + metrics.height as i32) as f32),
+            pixels_offset,
+            ..Default::default()
+        });
+        tag.pixels.extend_from_slice(&pixels);
+    }
+
+    if tag.characters.blocks.is_empty() {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.font.error_no_characters"), file=font_path.display(), first=options.first_character, last=options.last_character)));
+    }
+
+    tag.generate_character_tables();
+    Ok(tag)
+}
+
+/// Parse a character code, which can be decimal or hexadecimal with a `0x` prefix.
+fn parse_character_code(code: &str) -> Option<u16> {
+    match code.strip_prefix("0x").or_else(|| code.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => code.parse().ok()
+    }
+}
+
+pub fn font_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageResult<ExitCode> {
+    let parsed_args = ParsedArguments::parse_arguments(args,
+                                                       &[
+                                                           Argument { long: "font-size", short: 's', description: get_compiled_string!("engine.h1.verbs.font.arguments.font_size"), parameter: Some("px"), multiple: false },
+                                                           Argument { long: "characters", short: 'c', description: get_compiled_string!("engine.h1.verbs.font.arguments.characters"), parameter: Some("first-last"), multiple: false },
+                                                           Argument { long: "bold", short: 'b', description: get_compiled_string!("engine.h1.verbs.font.arguments.bold"), parameter: Some("tag"), multiple: false },
+                                                           Argument { long: "italic", short: 'i', description: get_compiled_string!("engine.h1.verbs.font.arguments.italic"), parameter: Some("tag"), multiple: false },
+                                                           Argument { long: "condense", short: 'C', description: get_compiled_string!("engine.h1.verbs.font.arguments.condense"), parameter: Some("tag"), multiple: false },
+                                                           Argument { long: "underline", short: 'u', description: get_compiled_string!("engine.h1.verbs.font.arguments.underline"), parameter: Some("tag"), multiple: false },
+                                                       ],
+                                                       &[get_compiled_string!("arguments.specifier.tag_without_group")],
+                                                       executable,
+                                                       verb.get_description(),
+                                                       ArgumentConstraints::new().needs_data().needs_tags().multiple_tags_directories())?;
+
+    let tags_dirs = str_slice_to_path_vec(&parsed_args.named["tags"]);
+    let data = Path::new(&parsed_args.named["data"][0]);
+    let group = TagGroup::Font;
+    let tag_path = TagReference::from_path_and_group(&parsed_args.extra[0], group)?;
+
+    let size = match parsed_args.named.get("font-size") {
+        Some(v) => v[0].parse::<f32>().ok().filter(|s| s.is_finite() && *s > 0.0)
+                       .ok_or_else(|| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.font.error_bad_font_size"), value=v[0])))?,
+        None => DEFAULT_FONT_SIZE
+    };
+    let (first_character, last_character) = match parsed_args.named.get("characters") {
+        Some(v) => v[0].split_once('-')
+                       .and_then(|(first, last)| Some((parse_character_code(first)?, parse_character_code(last)?)))
+                       .filter(|(first, last)| first <= last)
+                       .ok_or_else(|| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.font.error_bad_character_range"), value=v[0])))?,
+        None => DEFAULT_CHARACTER_RANGE
+    };
+    let options = FontOptions { size, first_character, last_character };
+
+    // The font file is named after the tag.
+    let font_path_base = data.join(tag_path.get_relative_fs_path());
+    let font_path = FONT_FILE_EXTENSIONS.iter()
+                                        .map(|e| font_path_base.with_extension(e))
+                                        .find(|p| p.is_file())
+                                        .ok_or_else(|| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.font.error_cannot_find_font_file"), file=font_path_base.with_extension(FONT_FILE_EXTENSIONS[0]).display())))?;
+
+    let (existing, output_path) = load_existing_tag::<Font>(&tags_dirs, &tag_path)?;
+    let mut font = make_font(&read_file(&font_path)?, &font_path, &options, existing)?;
+    for (argument, reference) in [("bold", &mut font.bold), ("italic", &mut font.italic), ("condense", &mut font.condense), ("underline", &mut font.underline)] {
+        if let Some(v) = parsed_args.named.get(argument) {
+            *reference = TagReference::from_path_and_group(&v[0], group)?;
+        }
+    }
+
+    let output = font.into_tag_file()?;
+    make_parent_directories(&output_path)?;
+    write_file(&output_path, &output)?;
+
+    println_success!(get_compiled_string!("engine.h1.verbs.font.saved_file"), file=output_path.display());
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,120 @@
+use ringhopper::engines::h1::CacheFile;
+use ringhopper::engines::h1::definitions::ScenarioType;
+use ringhopper::types::TagEnumFn;
+use ringhopper::types::tag::TagGroupFn;
+use ringhopper_proc::*;
+use std::collections::BTreeMap;
+use std::process::ExitCode;
+use std::path::Path;
+use crate::cmd::*;
+use crate::file::*;
+use ringhopper::error::ErrorMessageResult;
+
+#[derive(Copy, Clone, PartialEq)]
+enum InfoFormat {
+    Text,
+    KeyValue
+}
+
+pub fn info_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageResult<ExitCode> {
+    let parsed_args = ParsedArguments::parse_arguments(args,
+                                                       &[
+                                                       Argument { long: "format", short: 'f', description: get_compiled_string!("engine.h1.verbs.info.arguments.format.description"), parameter: Some("format"), multiple: false }
+                                                       ],
+                                                       &[get_compiled_string!("arguments.specifier.cache_file")],
+                                                       executable,
+                                                       verb.get_description(),
+                                                       ArgumentConstraints::new())?;
+
+    let format = parsed_args.parse_set("format", &[("text", InfoFormat::Text), ("key-value", InfoFormat::KeyValue)])?.unwrap_or(InfoFormat::Text);
+    let data = read_file(Path::new(&parsed_args.extra[0]))?;
+
+    // Compressed cache files can't be read past their header without being decompressed.
+    let (header, engine, engine_exact_match) = CacheFile::read_header(&data)?;
+    let map_type = ScenarioType::options()[header.map_type.into_u16() as usize];
+    let file_size = data.len();
+    let file_size_limit = engine.max_cache_file_size(header.map_type);
+    let cache_file = if engine.compressed { None } else { Some(CacheFile::from_data(data)?) };
+
+    let mut group_counts = BTreeMap::new();
+    if let Some(cache_file) = &cache_file {
+        for tag in cache_file.tags() {
+            *group_counts.entry(tag.get_group().as_str()).or_insert(0usize) += 1;
+        }
+    }
+
+    match format {
+        InfoFormat::Text => {
+            println!(get_compiled_string!("engine.h1.verbs.info.scenario"), scenario=header.name, map_type=map_type);
+            if engine_exact_match {
+                println!(get_compiled_string!("engine.h1.verbs.info.engine"), engine=engine.name);
+            }
+            else {
+                println!(get_compiled_string!("engine.h1.verbs.info.engine_fallback"), engine=engine.name, build=header.build);
+            }
+            println!(get_compiled_string!("engine.h1.verbs.info.compressed"), compressed=if engine.compressed { "yes" } else { "no" });
+            println!(get_compiled_string!("engine.h1.verbs.info.crc32"), crc32=header.crc32);
+
+            let cache_file = match &cache_file {
+                Some(n) => n,
+                None => {
+                    println!(get_compiled_string!("engine.h1.verbs.info.file_size"),
+                             size=header.decompressed_file_size as f64 / 1024.0 / 1024.0,
+                             limit=file_size_limit as f64 / 1024.0 / 1024.0,
+                             percent=header.decompressed_file_size as f64 / file_size_limit as f64 * 100.0);
+                    println!(get_compiled_string!("engine.h1.verbs.info.compressed_file_size"), size=file_size as f64 / 1024.0 / 1024.0);
+                    return Ok(ExitCode::SUCCESS)
+                }
+            };
+
+            let crc32 = cache_file.calculate_crc32();
+            if crc32 != header.crc32 {
+                println!(get_compiled_string!("engine.h1.verbs.info.crc32_mismatch"), crc32=crc32);
+            }
+            let tag_space_used = cache_file.tag_space_used();
+            println!(get_compiled_string!("engine.h1.verbs.info.tag_space"),
+                     used=tag_space_used as f64 / 1024.0 / 1024.0,
+                     limit=engine.max_tag_space as f64 / 1024.0 / 1024.0,
+                     percent=tag_space_used as f64 / engine.max_tag_space as f64 * 100.0);
+            println!(get_compiled_string!("engine.h1.verbs.info.file_size"),
+                     size=file_size as f64 / 1024.0 / 1024.0,
+                     limit=file_size_limit as f64 / 1024.0 / 1024.0,
+                     percent=file_size as f64 / file_size_limit as f64 * 100.0);
+            println!(get_compiled_string!("engine.h1.verbs.info.tag_count"), count=cache_file.tags().len());
+            for (group, count) in &group_counts {
+                println!("    {group}: {count}", group=group, count=count);
+            }
+        },
+        InfoFormat::KeyValue => {
+            println!("scenario={}", header.name);
+            println!("map_type={}", map_type);
+            println!("engine={}", engine.shorthand.unwrap_or(engine.name));
+            println!("engine_exact_match={}", engine_exact_match);
+            println!("build={}", header.build);
+            println!("compressed={}", engine.compressed);
+            println!("crc32=0x{:08X}", header.crc32);
+
+            let cache_file = match &cache_file {
+                Some(n) => n,
+                None => {
+                    println!("file_size={}", header.decompressed_file_size);
+                    println!("file_size_limit={}", file_size_limit);
+                    println!("compressed_file_size={}", file_size);
+                    return Ok(ExitCode::SUCCESS)
+                }
+            };
+
+            println!("crc32_calculated=0x{:08X}", cache_file.calculate_crc32());
+            println!("tag_space_used={}", cache_file.tag_space_used());
+            println!("tag_space_limit={}", engine.max_tag_space);
+            println!("file_size={}", file_size);
+            println!("file_size_limit={}", file_size_limit);
+            println!("tag_count={}", cache_file.tags().len());
+            for (group, count) in &group_counts {
+                println!("tag_count.{}={}", group, count);
+            }
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
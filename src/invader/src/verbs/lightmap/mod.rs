@@ -1,12 +1,13 @@
-use ringhopper::bitmap::{ColorPlateBuildBitmap, build_color_plate, BitmapEncoding, ColorPlateOptions, ColorPlateInputType};
-use ringhopper::engines::h1::definitions::{Scenario, ScenarioStructureBSP, ScenarioStructureBSPMaterialUncompressedRenderedVertex, ScenarioStructureBSPMaterialCompressedRenderedVertex, ScenarioStructureBSPMaterialCompressedLightmapVertex, ScenarioStructureBSPMaterialUncompressedLightmapVertex, BitmapType, Bitmap, BitmapFormat, BitmapGroupSequence, BitmapData, BitmapDataType, BitmapDataFormat, BitmapUsage};
-use ringhopper::types::{ColorARGBInt, String32, Reflexive, TagGroupFn, HALO_DIRECTORY_SEPARATOR};
+use ringhopper::bitmap::{ColorPlateBuildBitmap, build_color_plate, BitmapEncoding};
+use ringhopper::engines::h1::definitions::{Scenario, ScenarioStructureBSP, ScenarioStructureBSPLightmap, ScenarioStructureBSPMaterial, ScenarioStructureBSPMaterialUncompressedRenderedVertex, ScenarioStructureBSPMaterialCompressedRenderedVertex, ScenarioStructureBSPMaterialCompressedLightmapVertex, ScenarioStructureBSPMaterialUncompressedLightmapVertex, ScenarioStructureBSPSurface, BitmapType, Bitmap, BitmapFormat, BitmapGroupSequence, BitmapData, BitmapDataType, BitmapDataFormat, BitmapUsage, Shader, ShaderEnvironment, ShaderModel, ShaderTransparentChicago, ShaderTransparentChicagoExtended, ShaderTransparentGeneric, ShaderTransparentGlass, ShaderTransparentMeter, ShaderTransparentPlasma, ShaderTransparentWater, Sky};
+use ringhopper::types::{ColorARGBInt, ColorRGB, Point2D, Point3D, Vector3D, String32, Reflexive, TagBlockFn, TagGroupFn, HALO_DIRECTORY_SEPARATOR};
 use ringhopper_proc::*;
+use std::collections::HashMap;
 use std::num::NonZeroUsize;
 use std::path::{PathBuf, Path};
 use std::process::ExitCode;
 use crate::cmd::*;
-use ringhopper::engines::h1::{TagGroup, TagFileSerializeFn, TagSerialize};
+use ringhopper::engines::h1::{TagGroup, TagReference, TagFileSerializeFn, TagSerialize, ModelCompression, ShaderSuperFn};
 use ringhopper::error::ErrorMessage;
 use ringhopper::file::TagFile;
 use crate::file::*;
@@ -14,19 +15,29 @@ use macros::terminal::*;
 use ringhopper::error::ErrorMessageResult;
 use flate2::{Compress, FlushCompress};
 
+mod radiosity;
+use self::radiosity::*;
+
+#[cfg(test)]
+mod tests;
+
 #[derive(Clone)]
 struct LightmapOptions {
     fullbright: bool,
     bsp: Option<Vec<String>>,
     tags_dirs: Vec<PathBuf>,
-    batching: bool
+    batching: bool,
+    samples: usize,
+    bounces: usize
 }
 
 pub fn lightmap_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageResult<ExitCode> {
     let parsed_args = ParsedArguments::parse_arguments(args,
                                                        &[
-                                                           Argument { long: "fullbright", short: 'f', description: "Render lightmaps as fullbright.", parameter: None, multiple: false },
-                                                           Argument { long: "bsp", short: 'b', description: "Choose a BSP by name to bake.", parameter: Some("bsp-name"), multiple: true },
+                                                           Argument { long: "fullbright", short: 'f', description: get_compiled_string!("engine.h1.verbs.lightmap.arguments.fullbright"), parameter: None, multiple: false },
+                                                           Argument { long: "bsp", short: 'b', description: get_compiled_string!("engine.h1.verbs.lightmap.arguments.bsp"), parameter: Some("bsp-name"), multiple: true },
+                                                           Argument { long: "samples", short: 's', description: get_compiled_string!("engine.h1.verbs.lightmap.arguments.samples"), parameter: Some("count"), multiple: false },
+                                                           Argument { long: "bounces", short: 'B', description: get_compiled_string!("engine.h1.verbs.lightmap.arguments.bounces"), parameter: Some("count"), multiple: false },
                                                        ],
                                                        &[get_compiled_string!("arguments.specifier.tag_batch_without_group")],
                                                        executable,
@@ -40,27 +51,30 @@ pub fn lightmap_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessa
     let all_tags = str_slice_to_path_vec(&parsed_args.named["tags"]);
     let all_tags = all_tags.iter().map(|f| (*f).to_owned());
 
+    let parse_count = |argument: &str, default: usize| match parsed_args.named.get(argument) {
+        Some(v) => v[0].parse::<usize>().map_err(|_| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.lightmap.error_bad_count"), argument=argument, value=v[0]))),
+        None => Ok(default)
+    };
+
     let options = LightmapOptions {
         fullbright: parsed_args.named.contains_key("fullbright"),
         bsp: parsed_args.named.get("bsp").map(|f| f.to_owned()),
         tags_dirs: all_tags.collect(),
-        batching: TagFile::uses_batching(tag_path)
+        batching: TagFile::uses_batching(tag_path),
+        samples: parse_count("samples", 64)?.max(1),
+        bounces: parse_count("bounces", 3)?
     };
 
-    if !options.fullbright {
-        return Err(ErrorMessage::StaticString("Lightmapping without --fullbright is not implemented yet"));
-    }
-
     Ok(super::do_with_batching_threaded(lightmap_scenario, &tag_path, Some(TagGroup::Scenario), &str_slice_to_path_vec(&parsed_args.named["tags"]), parsed_args.threads, options)?.exit_code())
 }
 
-fn lightmap_scenario(tag_file: &TagFile, log_mutex: super::LogMutex, _: NonZeroUsize, options: &LightmapOptions) -> ErrorMessageResult<bool> {
+fn lightmap_scenario(tag_file: &TagFile, log_mutex: super::LogMutex, available_threads: NonZeroUsize, options: &LightmapOptions) -> ErrorMessageResult<bool> {
     let scenario_tag = *Scenario::from_tag_file(&read_file(&tag_file.file_path)?)?.data;
 
     let tags_dirs: Vec<&Path> = options.tags_dirs.iter().map(|f| f.as_path()).collect();
 
     let mut bsps_baked = 0;
-    for b in scenario_tag.structure_bsps {
+    for b in &scenario_tag.structure_bsps {
         if let Some(base_name) = &options.bsp {
             if !base_name.contains(&b.structure_bsp.get_path_without_extension().rsplit(HALO_DIRECTORY_SEPARATOR).next().unwrap().to_owned()) {
                 continue
@@ -72,6 +86,9 @@ fn lightmap_scenario(tag_file: &TagFile, log_mutex: super::LogMutex, _: NonZeroU
         if options.fullbright {
             fullbright_bsp_tag(&bsp_tag_file, &log_mutex)?;
         }
+        else {
+            radiosity_bsp_tag(&bsp_tag_file, &scenario_tag, &tags_dirs, available_threads, options, &log_mutex)?;
+        }
     }
 
     let l = log_mutex.lock();
@@ -86,10 +103,40 @@ fn lightmap_scenario(tag_file: &TagFile, log_mutex: super::LogMutex, _: NonZeroU
     Ok(bsps_baked > 0)
 }
 
+/// Return true if the lightmap has any surfaces that can be lightmapped.
+///
+/// Some "lightmaps" only contain transparent shaders, which are not lightmapped.
+fn is_lightmap_lit(lightmap: &ScenarioStructureBSPLightmap) -> bool {
+    lightmap.materials.blocks.iter().any(|m| matches!(m.shader.get_group(), TagGroup::ShaderEnvironment | TagGroup::ShaderModel))
+}
+
+/// Check the rendered vertices of a material and remove any lightmap vertices after them.
+fn trim_rendered_vertices(mat: &mut ScenarioStructureBSPMaterial, mati: usize, lmi: usize) -> ErrorMessageResult<()> {
+    let vertex_count = mat.rendered_vertices_count as usize;
+    if mat.uncompressed_vertices.is_empty() {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.lightmap.error_uncompressed_vertices_missing"), material=mati, lightmap=lmi)));
+    }
+    if mat.compressed_vertices.is_empty() {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.lightmap.error_compressed_vertices_missing"), material=mati, lightmap=lmi)));
+    }
+
+    // Trim down to size
+    let rendered_size_uncompressed = vertex_count * ScenarioStructureBSPMaterialUncompressedRenderedVertex::tag_size();
+    let rendered_size_compressed = vertex_count * ScenarioStructureBSPMaterialCompressedRenderedVertex::tag_size();
+    if mat.uncompressed_vertices.len() < rendered_size_uncompressed {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.lightmap.error_uncompressed_vertices_corrupt"), material=mati, lightmap=lmi)));
+    }
+    if mat.compressed_vertices.len() < rendered_size_compressed {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.lightmap.error_compressed_vertices_corrupt"), material=mati, lightmap=lmi)));
+    }
+    mat.uncompressed_vertices.resize(rendered_size_uncompressed, 0);
+    mat.compressed_vertices.resize(rendered_size_compressed, 0);
+
+    Ok(())
+}
+
 fn fullbright_bsp_tag(tag_file: &TagFile, log_mutex: &super::LogMutex) -> ErrorMessageResult<()> {
     let mut tag = *ScenarioStructureBSP::from_tag_file(&read_file(&tag_file.file_path)?)?.data;
-    tag.lightmaps_bitmap.set_group(TagGroup::Bitmap);
-    tag.lightmaps_bitmap.set_path_without_extension(tag_file.tag_path.get_path_without_extension())?;
 
     // Prepare lightmap vertices lengths
     let uncompressed_lightmap_vertex_len = ScenarioStructureBSPMaterialUncompressedLightmapVertex::tag_size();
@@ -98,20 +145,7 @@ fn fullbright_bsp_tag(tag_file: &TagFile, log_mutex: &super::LogMutex) -> ErrorM
     let mut last_lightmap_index = 0usize;
     for lmi in 0..tag.lightmaps.blocks.len() {
         let lm = &mut tag.lightmaps[lmi];
-
-        // Some "lightmaps" do not actually contain lightable surfaces
-        let mut has_non_transparent_shaders = false;
-        for m in &lm.materials {
-            match m.shader.get_group() {
-                TagGroup::ShaderEnvironment | TagGroup::ShaderModel => {
-                    has_non_transparent_shaders = true;
-                    break;
-                },
-                _ => ()
-            }
-        }
-
-        if !has_non_transparent_shaders {
+        if !is_lightmap_lit(lm) {
             continue;
         }
 
@@ -124,50 +158,221 @@ fn fullbright_bsp_tag(tag_file: &TagFile, log_mutex: &super::LogMutex) -> ErrorM
             if vertex_count == 0 {
                 continue;
             }
-            if mat.uncompressed_vertices.is_empty() {
-                return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.lightmap.error_uncompressed_vertices_missing"), material=mati, lightmap=lmi)));
+            trim_rendered_vertices(mat, mati, lmi)?;
+
+            // Add in new lightmap vertices
+            let rendered_size_uncompressed = mat.uncompressed_vertices.len();
+            let rendered_size_compressed = mat.compressed_vertices.len();
+            mat.uncompressed_vertices.resize(vertex_count * uncompressed_lightmap_vertex_len + rendered_size_uncompressed, 0u8);
+            mat.compressed_vertices.resize(vertex_count * compressed_lightmap_vertex_len + rendered_size_compressed, 0u8);
+
+            // Set lightmap vertex count to indicate we rendered this
+            mat.lightmap_vertices_count = vertex_count as u32;
+        }
+    }
+
+    // Every lightmap gets a white page
+    let white = ColorPlateBuildBitmap { width: 4, height: 4, pixel_data: vec![ColorARGBInt { a: 255, r: 255, g: 255, b: 255 }; 4*4] };
+    write_lightmaps(tag_file, tag, vec![white; last_lightmap_index + 1], log_mutex)
+}
+
+/// Read the lighting of a sky, if the sky exists.
+fn read_radiosity_sky(tags_dirs: &[&Path], reference: &TagReference) -> ErrorMessageResult<Option<RadiositySky>> {
+    let sky_tag_file = match TagFile::from_tag_ref(tags_dirs, reference) {
+        Some(n) => n,
+        None => return Ok(None)
+    };
+    let sky = *Sky::from_tag_file(&read_file(&sky_tag_file.file_path)?)?.data;
+
+    let scale_color = |color: &ColorRGB, power: f32| ColorRGB { r: color.r * power, g: color.g * power, b: color.b * power };
+    Ok(Some(RadiositySky {
+        ambient: scale_color(&sky.outdoor_ambient_radiosity_color, sky.outdoor_ambient_radiosity_power),
+        lights: sky.lights.blocks.iter().map(|l| RadiositySkyLight {
+            direction: Vector3D { x: l.direction.p.cos() * l.direction.y.cos(), y: l.direction.p.cos() * l.direction.y.sin(), z: l.direction.p.sin() },
+            color: scale_color(&l.color, l.power)
+        }).collect()
+    }))
+}
+
+/// Read the base shader of a shader tag, if the shader exists.
+fn read_base_shader(tags_dirs: &[&Path], reference: &TagReference) -> ErrorMessageResult<Option<Shader>> {
+    let shader_tag_file = match TagFile::from_tag_ref(tags_dirs, reference) {
+        Some(n) => n,
+        None => return Ok(None)
+    };
+    let data = read_file(&shader_tag_file.file_path)?;
+
+    macro_rules! read_shader {
+        ($($group:ident),*) => {
+            match reference.get_group() {
+                $(TagGroup::$group => Ok(Some($group::from_tag_file(&data)?.data.get_base_shader().clone())),)*
+                _ => Ok(None)
+            }
+        }
+    }
+
+    read_shader!(ShaderEnvironment, ShaderModel, ShaderTransparentChicago, ShaderTransparentChicagoExtended, ShaderTransparentGeneric, ShaderTransparentGlass, ShaderTransparentMeter, ShaderTransparentPlasma, ShaderTransparentWater)
+}
+
+fn radiosity_bsp_tag(tag_file: &TagFile, scenario: &Scenario, tags_dirs: &[&Path], threads: NonZeroUsize, options: &LightmapOptions, log_mutex: &super::LogMutex) -> ErrorMessageResult<()> {
+    let mut tag = *ScenarioStructureBSP::from_tag_file(&read_file(&tag_file.file_path)?)?.data;
+
+    let skies = scenario.skies.blocks.iter().map(|s| read_radiosity_sky(tags_dirs, &s.sky)).collect::<ErrorMessageResult<Vec<_>>>()?;
+
+    // Surfaces use the sky of their cluster
+    let mut surface_skies: Vec<Option<usize>> = vec![None; tag.surfaces.blocks.len()];
+    for cluster in &tag.clusters {
+        for s in &cluster.surface_indices {
+            if let Some(sky) = usize::try_from(s.index).ok().and_then(|i| surface_skies.get_mut(i)) {
+                *sky = cluster.sky.map(|n| n as usize);
+            }
+        }
+    }
+
+    // Gather the surfaces of each lightmap
+    let mut shaders: HashMap<String, Option<Shader>> = HashMap::new();
+    let mut lit_lightmaps = Vec::new();
+    let mut radiosity_lightmaps = Vec::new();
+    for lmi in 0..tag.lightmaps.blocks.len() {
+        let lm = &mut tag.lightmaps[lmi];
+        if !is_lightmap_lit(lm) {
+            continue;
+        }
+
+        let mut materials = Vec::with_capacity(lm.materials.blocks.len());
+        for mati in 0..lm.materials.blocks.len() {
+            let mat = &mut lm.materials.blocks[mati];
+            let path = mat.shader.get_path_with_extension();
+            if !shaders.contains_key(&path) {
+                shaders.insert(path.clone(), read_base_shader(tags_dirs, &mat.shader)?);
             }
-            if mat.compressed_vertices.is_empty() {
-                return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.lightmap.error_compressed_vertices_missing"), material=mati, lightmap=lmi)));
+            let (emission, tint) = match &shaders[&path] {
+                Some(s) => (ColorRGB { r: s.color_of_emitted_light.r * s.power, g: s.color_of_emitted_light.g * s.power, b: s.color_of_emitted_light.b * s.power }, s.tint_color),
+                None => (ColorRGB::default(), ColorRGB::default())
+            };
+
+            let mut material = RadiosityMaterial {
+                vertices: Vec::new(),
+                triangles: Vec::new(),
+                skies: Vec::new(),
+                emission,
+                tint,
+                opaque: matches!(mat.shader.get_group(), TagGroup::ShaderEnvironment | TagGroup::ShaderModel)
+            };
+
+            let vertex_count = mat.rendered_vertices_count as usize;
+            if vertex_count > 0 {
+                trim_rendered_vertices(mat, mati, lmi)?;
+
+                // Vertex data is stored in little endian
+                let float_at = |vertex: &[u8], i: usize| f32::from_le_bytes(vertex[i * 4..i * 4 + 4].try_into().unwrap());
+                material.vertices = mat.uncompressed_vertices.chunks(ScenarioStructureBSPMaterialUncompressedRenderedVertex::tag_size()).map(|v| RadiosityVertex {
+                    position: Point3D { x: float_at(v, 0), y: float_at(v, 1), z: float_at(v, 2) },
+                    normal: Vector3D { x: float_at(v, 3), y: float_at(v, 4), z: float_at(v, 5) }
+                }).collect();
+
+                let surfaces_corrupt = || ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.lightmap.error_surfaces_corrupt"), material=mati, lightmap=lmi));
+                let first_surface = usize::try_from(mat.surfaces).map_err(|_| surfaces_corrupt())?;
+                let surface_count = usize::try_from(mat.surface_count).map_err(|_| surfaces_corrupt())?;
+                let surfaces = tag.surfaces.blocks.get(first_surface..first_surface + surface_count).ok_or_else(surfaces_corrupt)?;
+                for s in surfaces {
+                    let triangle = match [s.vertex0_index, s.vertex1_index, s.vertex2_index] {
+                        [Some(a), Some(b), Some(c)] if [a, b, c].iter().all(|v| (*v as usize) < vertex_count) => [a, b, c],
+                        _ => return Err(surfaces_corrupt())
+                    };
+                    material.triangles.push(triangle);
+                }
+                material.skies = surface_skies[first_surface..first_surface + surface_count].to_vec();
             }
 
-            // Trim down to size
-            let rendered_size_uncompressed = vertex_count * ScenarioStructureBSPMaterialUncompressedRenderedVertex::tag_size();
-            let rendered_size_compressed = vertex_count * ScenarioStructureBSPMaterialCompressedRenderedVertex::tag_size();
-            if mat.uncompressed_vertices.len() < rendered_size_uncompressed {
-                return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.lightmap.error_uncompressed_vertices_corrupt"), material=mati, lightmap=lmi)));
+            materials.push(material);
+        }
+
+        lit_lightmaps.push(lmi);
+        radiosity_lightmaps.push(materials);
+    }
+
+    let baked = bake_radiosity(&radiosity_lightmaps, &skies, &RadiositySettings { samples: options.samples, bounces: options.bounces, threads });
+
+    // Replace the vertices and surfaces with the baked ones
+    let mut pages = Vec::with_capacity(baked.len());
+    for (page, (lmi, baked_lightmap)) in lit_lightmaps.into_iter().zip(baked).enumerate() {
+        let lm = &mut tag.lightmaps[lmi];
+        lm.bitmap = Some(page as u16);
+
+        for (mati, (mat, baked_material)) in lm.materials.blocks.iter_mut().zip(baked_lightmap.materials).enumerate() {
+            if baked_material.vertices.is_empty() {
+                continue;
             }
-            if mat.compressed_vertices.len() < rendered_size_compressed {
-                return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.lightmap.error_compressed_vertices_corrupt"), material=mati, lightmap=lmi)));
+            let vertex_count = baked_material.vertices.len();
+            if vertex_count > u16::MAX as usize {
+                return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.lightmap.error_too_many_vertices"), material=mati, lightmap=lmi)));
             }
-            mat.uncompressed_vertices.resize(rendered_size_uncompressed, 0);
-            mat.compressed_vertices.resize(rendered_size_compressed, 0);
 
-            // Add in new lightmap vertices
-            mat.uncompressed_vertices.resize(vertex_count * uncompressed_lightmap_vertex_len + rendered_size_uncompressed, 0u8);
-            mat.compressed_vertices.resize(vertex_count * compressed_lightmap_vertex_len + rendered_size_compressed, 0u8);
+            let uncompressed_rendered_len = ScenarioStructureBSPMaterialUncompressedRenderedVertex::tag_size();
+            let compressed_rendered_len = ScenarioStructureBSPMaterialCompressedRenderedVertex::tag_size();
+            let mut uncompressed_vertices = Vec::with_capacity(vertex_count * (uncompressed_rendered_len + ScenarioStructureBSPMaterialUncompressedLightmapVertex::tag_size()));
+            let mut compressed_vertices = Vec::with_capacity(vertex_count * (compressed_rendered_len + ScenarioStructureBSPMaterialCompressedLightmapVertex::tag_size()));
 
-            // Set lightmap vertex count to indicate we rendered this
+            // Vertices on the edges of charts are duplicated
+            for v in &baked_material.vertices {
+                let source = v.source as usize;
+                uncompressed_vertices.extend_from_slice(&mat.uncompressed_vertices[source * uncompressed_rendered_len..(source + 1) * uncompressed_rendered_len]);
+                compressed_vertices.extend_from_slice(&mat.compressed_vertices[source * compressed_rendered_len..(source + 1) * compressed_rendered_len]);
+            }
+
+            for v in &baked_material.vertices {
+                let vertex = ScenarioStructureBSPMaterialUncompressedLightmapVertex { normal: v.normal, texture_coords: Point2D { x: v.texture_coords.x, y: v.texture_coords.y } };
+                for f in [vertex.normal.x, vertex.normal.y, vertex.normal.z, vertex.texture_coords.x, vertex.texture_coords.y] {
+                    uncompressed_vertices.extend_from_slice(&f.to_le_bytes());
+                }
+
+                let compressed = vertex.compress();
+                compressed_vertices.extend_from_slice(&compressed.normal.to_le_bytes());
+                compressed_vertices.extend_from_slice(&compressed.texture_coordinate_x.to_le_bytes());
+                compressed_vertices.extend_from_slice(&compressed.texture_coordinate_y.to_le_bytes());
+            }
+
+            let first_surface = mat.surfaces as usize;
+            for (i, t) in baked_material.triangles.iter().enumerate() {
+                let [a, b, c] = t.map(|v| Some(v as u16));
+                tag.surfaces.blocks[first_surface + i] = ScenarioStructureBSPSurface { vertex0_index: a, vertex1_index: b, vertex2_index: c };
+            }
+
+            mat.uncompressed_vertices = uncompressed_vertices;
+            mat.compressed_vertices = compressed_vertices;
+            mat.rendered_vertices_count = vertex_count as u32;
             mat.lightmap_vertices_count = vertex_count as u32;
         }
+
+        pages.push(ColorPlateBuildBitmap { width: baked_lightmap.width, height: baked_lightmap.height, pixel_data: baked_lightmap.pixels });
+    }
+
+    // A BSP with nothing to light still needs a lightmap bitmap
+    if pages.is_empty() {
+        pages.push(ColorPlateBuildBitmap { width: 4, height: 4, pixel_data: vec![ColorARGBInt { a: 255, r: 255, g: 255, b: 255 }; 4*4] });
     }
 
+    write_lightmaps(tag_file, tag, pages, log_mutex)
+}
+
+/// Write a bitmap tag with one page for each lightmap next to the BSP tag, and then write the BSP tag.
+fn write_lightmaps(tag_file: &TagFile, mut tag: ScenarioStructureBSP, pages: Vec<ColorPlateBuildBitmap>, log_mutex: &super::LogMutex) -> ErrorMessageResult<()> {
+    tag.lightmaps_bitmap.set_group(TagGroup::Bitmap);
+    tag.lightmaps_bitmap.set_path_without_extension(tag_file.tag_path.get_path_without_extension())?;
+
     // Create the bitmap tag
-    let lightmap_count = last_lightmap_index + 1;
-    let bitmap_data = vec![ColorARGBInt { a: 255, r: 255, g: 255, b: 255 }; 4*4];
-    let bitmap_data_16bit = BitmapEncoding::R5G6B5.encode(&bitmap_data, 4, 4, 1, 1, 0, false);
-    let lightmap_bitmap_data = vec![vec![ColorPlateBuildBitmap { width: 4, height: 4, pixel_data: bitmap_data.clone() }; 1]; lightmap_count];
+    let lightmap_count = pages.len();
+    let lightmap_bitmap_data: Vec<Vec<ColorPlateBuildBitmap>> = pages.into_iter().map(|p| vec![p]).collect();
     let (color_plate_pixel_data, color_plate_width, color_plate_height) = build_color_plate(BitmapType::_2dTextures, &lightmap_bitmap_data, true, BitmapEncoding::A8R8G8B8)?;
-    let mut options = ColorPlateOptions::default();
-    options.input_type = ColorPlateInputType::TwoDimensionalTextures;
-    let mut bitmap_tag = Bitmap::default();
+    let mut bitmap_tag = Bitmap::new_with_defaults();
     bitmap_tag._type = BitmapType::_2dTextures;
     bitmap_tag.usage = BitmapUsage::LightMap;
     bitmap_tag.encoding_format = BitmapFormat::_16bit;
     bitmap_tag.bitmap_group_sequence.blocks.reserve_exact(lightmap_count);
     bitmap_tag.bitmap_data.blocks.reserve_exact(lightmap_count);
 
-    for l in 0..lightmap_count {
+    for (l, page) in lightmap_bitmap_data.iter().map(|p| &p[0]).enumerate() {
         bitmap_tag.bitmap_group_sequence.blocks.push(BitmapGroupSequence {
             name: String32::from_str(&format!("lightmap_{}", l))?,
             first_bitmap_index: Some(l as u16),
@@ -175,14 +380,15 @@ fn fullbright_bsp_tag(tag_file: &TagFile, log_mutex: &super::LogMutex) -> ErrorM
             sprites: Reflexive::default()
         });
 
-        let mut bitmap_data = BitmapData::default();
+        let bitmap_data_16bit = BitmapEncoding::R5G6B5.encode(&page.pixel_data, page.width, page.height, 1, 1, 0, false);
+        let mut bitmap_data = BitmapData::new_with_defaults();
         bitmap_data.bitmap_class = TagGroup::Bitmap.as_fourcc();
-        bitmap_data.width = 4;
-        bitmap_data.height = 4;
+        bitmap_data.width = page.width as u16;
+        bitmap_data.height = page.height as u16;
         bitmap_data.depth = 1;
         bitmap_data._type = BitmapDataType::_2dTexture;
         bitmap_data.format = BitmapDataFormat::R5G6B5;
-        bitmap_data.flags.power_of_two_dimensions = true;
+        bitmap_data.flags.power_of_two_dimensions = page.width.is_power_of_two() && page.height.is_power_of_two();
         bitmap_data.pixel_data_offset = bitmap_tag.processed_pixel_data.len() as u32;
         bitmap_data.pixel_data_size = bitmap_data_16bit.len() as u32;
         bitmap_tag.bitmap_data.blocks.push(bitmap_data);
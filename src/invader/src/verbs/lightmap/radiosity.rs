@@ -0,0 +1,892 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use ringhopper::types::{ColorARGBInt, ColorRGB, Point2D, Point3D, Vector3D};
+
+/// Number of texels per world unit that lightmaps are baked at if every chart fits on one page.
+const TEXELS_PER_WORLD_UNIT: f32 = 16.0;
+
+/// Texel density below which pages are allowed to exceed [`MAX_PAGE_SIZE`].
+const MIN_TEXELS_PER_WORLD_UNIT: f32 = 0.25;
+
+/// Largest width or height of a lightmap page.
+const MAX_PAGE_SIZE: usize = 1024;
+
+/// Smallest width or height of a lightmap page.
+const MIN_PAGE_SIZE: usize = 4;
+
+/// Texels of padding around each chart so filtering does not bleed light between charts.
+const CHART_PADDING: usize = 2;
+
+/// Distance that samples are moved off of their surface so rays do not hit it.
+const SURFACE_OFFSET: f32 = 0.0005;
+
+/// Fraction of light reflected by a surface with a white radiosity tint.
+const SURFACE_REFLECTANCE: f32 = 0.5;
+
+/// Maximum number of triangles in a BVH leaf.
+const BVH_LEAF_SIZE: usize = 4;
+
+/// Distance that projected triangles can overlap by without being put in separate charts.
+const OVERLAP_EPSILON: f32 = 0.0005;
+
+type Vec3 = [f32; 3];
+
+fn add(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn mul(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] * b[0], a[1] * b[1], a[2] * b[2]]
+}
+
+fn scale(a: Vec3, by: f32) -> Vec3 {
+    [a[0] * by, a[1] * by, a[2] * by]
+}
+
+fn dot(a: Vec3, b: Vec3) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn length(a: Vec3) -> f32 {
+    dot(a, a).sqrt()
+}
+
+fn normalize(a: Vec3) -> Option<Vec3> {
+    let l = length(a);
+    if l > 0.0 && l.is_finite() { Some(scale(a, 1.0 / l)) } else { None }
+}
+
+fn luminance(c: Vec3) -> f32 {
+    0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2]
+}
+
+fn from_point(p: &Point3D) -> Vec3 {
+    [p.x, p.y, p.z]
+}
+
+fn from_vector(v: &Vector3D) -> Vec3 {
+    [v.x, v.y, v.z]
+}
+
+fn from_color(c: &ColorRGB) -> Vec3 {
+    [c.r, c.g, c.b]
+}
+
+/// Vertex of a surface being lit.
+#[derive(Clone, Debug)]
+pub struct RadiosityVertex {
+    /// Position in world units.
+    pub position: Point3D,
+
+    /// Normal of the side of the surface that is lit.
+    pub normal: Vector3D
+}
+
+/// Surfaces sharing a shader in a lightmap.
+#[derive(Clone, Debug)]
+pub struct RadiosityMaterial {
+    /// Vertices of the material.
+    pub vertices: Vec<RadiosityVertex>,
+
+    /// Triangles of the material.
+    pub triangles: Vec<[u16; 3]>,
+
+    /// Index of the sky of the cluster of each triangle, if any.
+    pub skies: Vec<Option<usize>>,
+
+    /// Light emitted by the shader.
+    pub emission: ColorRGB,
+
+    /// Radiosity tint of the shader. Black is treated as white.
+    pub tint: ColorRGB,
+
+    /// Opaque surfaces cast shadows and reflect light. Other surfaces are only lit.
+    pub opaque: bool
+}
+
+/// Light of a sky coming from one direction.
+#[derive(Clone, Debug)]
+pub struct RadiositySkyLight {
+    /// Direction towards the light.
+    pub direction: Vector3D,
+
+    /// Color of the light, multiplied by its power.
+    pub color: ColorRGB
+}
+
+/// Lighting of a sky seen by the clusters that use it.
+#[derive(Clone, Debug, Default)]
+pub struct RadiositySky {
+    /// Color of the light coming from the sky in every direction, multiplied by its power.
+    pub ambient: ColorRGB,
+
+    /// Lights of the sky.
+    pub lights: Vec<RadiositySkyLight>
+}
+
+/// Settings for baking lightmaps.
+#[derive(Clone, Debug)]
+pub struct RadiositySettings {
+    /// Number of rays traced from each texel per bounce.
+    pub samples: usize,
+
+    /// Number of times light is reflected between surfaces.
+    pub bounces: usize,
+
+    /// Number of threads to trace rays on.
+    pub threads: NonZeroUsize
+}
+
+/// Lightmap vertex made from a vertex of a [`RadiosityMaterial`].
+#[derive(Clone, Debug)]
+pub struct BakedVertex {
+    /// Index of the vertex of the material this was made from.
+    ///
+    /// Vertices that lie on the edge of more than one chart are used more than once.
+    pub source: u16,
+
+    /// Direction that most of the light at the vertex comes from.
+    pub normal: Vector3D,
+
+    /// Coordinates of the vertex on the lightmap page.
+    pub texture_coords: Point2D
+}
+
+/// Material with lightmap vertices.
+#[derive(Clone, Debug, Default)]
+pub struct BakedMaterial {
+    /// Vertices of the material.
+    pub vertices: Vec<BakedVertex>,
+
+    /// Triangles of the material in the same order as the [`RadiosityMaterial`], indexing [`BakedMaterial::vertices`].
+    pub triangles: Vec<[u32; 3]>
+}
+
+/// Baked lightmap page and its materials.
+#[derive(Clone, Debug)]
+pub struct BakedLightmap {
+    /// Width of the page in pixels.
+    pub width: usize,
+
+    /// Height of the page in pixels.
+    pub height: usize,
+
+    /// Pixels of the page.
+    pub pixels: Vec<ColorARGBInt>,
+
+    /// Materials in the same order as the lightmap that was baked.
+    pub materials: Vec<BakedMaterial>
+}
+
+/// Connected triangles of a material facing the same axis, which are projected onto that axis without overlapping.
+struct Chart {
+    material: usize,
+    triangles: Vec<usize>,
+    vertices: Vec<u16>,
+    projected: Vec<[f32; 2]>,
+    min: [f32; 2],
+    max: [f32; 2],
+
+    /// Index of each material vertex in `vertices`.
+    vertex_indices: HashMap<u16, usize>,
+
+    /// Triangle that each vertex was first added by.
+    vertex_triangles: Vec<usize>
+}
+
+impl Chart {
+    fn new(material: usize) -> Chart {
+        Chart {
+            material,
+            triangles: Vec::new(),
+            vertices: Vec::new(),
+            projected: Vec::new(),
+            min: [f32::INFINITY; 2],
+            max: [f32::NEG_INFINITY; 2],
+            vertex_indices: HashMap::new(),
+            vertex_triangles: Vec::new()
+        }
+    }
+
+    fn add_triangle(&mut self, index: usize, triangle: &[u16; 3], projected: &[[f32; 2]; 3]) {
+        self.triangles.push(index);
+        for (&v, &p) in triangle.iter().zip(projected) {
+            if self.vertex_indices.contains_key(&v) {
+                continue
+            }
+            for (a, p) in p.iter().enumerate() {
+                self.min[a] = self.min[a].min(*p);
+                self.max[a] = self.max[a].max(*p);
+            }
+            self.vertex_indices.insert(v, self.vertices.len());
+            self.vertices.push(v);
+            self.projected.push(p);
+            self.vertex_triangles.push(index);
+        }
+    }
+
+    fn size(&self, density: f32) -> (usize, usize) {
+        let extent = |a: usize| ((self.max[a] - self.min[a]) * density).floor() as usize + 1 + CHART_PADDING * 2;
+        (extent(0), extent(1))
+    }
+}
+
+/// Point on a surface that light is gathered at.
+struct Sample {
+    origin: Vec3,
+    normal: Vec3,
+    sky: Option<usize>,
+    emission: Vec3,
+    reflectance: Vec3
+}
+
+/// Triangle that blocks light.
+struct Occluder {
+    position: Vec3,
+    edges: [Vec3; 2],
+    facing: Vec3,
+    lightmap: usize,
+    texels: [[f32; 2]; 3]
+}
+
+struct Hit {
+    occluder: usize,
+    u: f32,
+    v: f32
+}
+
+struct BvhNode {
+    min: Vec3,
+    max: Vec3,
+    start: usize,
+    count: usize,
+    right: usize
+}
+
+/// Bounding volume hierarchy of the occluders.
+struct Bvh {
+    nodes: Vec<BvhNode>,
+    order: Vec<usize>
+}
+
+impl Bvh {
+    fn new(occluders: &[Occluder]) -> Bvh {
+        let mut bvh = Bvh { nodes: Vec::new(), order: (0..occluders.len()).collect() };
+        let corners: Vec<[Vec3; 3]> = occluders.iter().map(|o| [o.position, add(o.position, o.edges[0]), add(o.position, o.edges[1])]).collect();
+        if !occluders.is_empty() {
+            bvh.build(&corners, 0, occluders.len());
+        }
+        bvh
+    }
+
+    fn build(&mut self, corners: &[[Vec3; 3]], start: usize, end: usize) -> usize {
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        let mut centroid_min = [f32::INFINITY; 3];
+        let mut centroid_max = [f32::NEG_INFINITY; 3];
+        let centroid = |i: usize| scale(add(add(corners[i][0], corners[i][1]), corners[i][2]), 1.0 / 3.0);
+        for &i in &self.order[start..end] {
+            for c in &corners[i] {
+                for a in 0..3 {
+                    min[a] = min[a].min(c[a]);
+                    max[a] = max[a].max(c[a]);
+                }
+            }
+            let c = centroid(i);
+            for a in 0..3 {
+                centroid_min[a] = centroid_min[a].min(c[a]);
+                centroid_max[a] = centroid_max[a].max(c[a]);
+            }
+        }
+
+        let node = self.nodes.len();
+        self.nodes.push(BvhNode { min, max, start, count: end - start, right: 0 });
+        if end - start <= BVH_LEAF_SIZE {
+            return node
+        }
+
+        // Split at the median along the longest axis of the centroids.
+        let axis = (0..3).max_by(|a, b| (centroid_max[*a] - centroid_min[*a]).total_cmp(&(centroid_max[*b] - centroid_min[*b]))).unwrap();
+        self.order[start..end].sort_by(|a, b| centroid(*a)[axis].total_cmp(&centroid(*b)[axis]).then(a.cmp(b)));
+        let middle = (start + end) / 2;
+
+        self.nodes[node].count = 0;
+        self.build(corners, start, middle);
+        let right = self.build(corners, middle, end);
+        self.nodes[node].right = right;
+        node
+    }
+
+    /// Find the closest occluder hit by the ray, or any occluder if `any` is set.
+    fn trace(&self, occluders: &[Occluder], origin: Vec3, direction: Vec3, any: bool) -> Option<Hit> {
+        if self.nodes.is_empty() {
+            return None
+        }
+
+        let inverse = direction.map(|d| 1.0 / d);
+        let mut closest = f32::INFINITY;
+        let mut hit = None;
+        let mut stack = vec![0usize];
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+
+            // Slab test against the bounding box.
+            let mut near = 0.0f32;
+            let mut far = closest;
+            for a in 0..3 {
+                let t0 = (node.min[a] - origin[a]) * inverse[a];
+                let t1 = (node.max[a] - origin[a]) * inverse[a];
+                near = near.max(t0.min(t1));
+                far = far.min(t0.max(t1));
+            }
+            if near > far || far.is_nan() {
+                continue
+            }
+
+            if node.count == 0 {
+                stack.push(node.right);
+                stack.push(n + 1);
+                continue
+            }
+
+            for &o in &self.order[node.start..node.start + node.count] {
+                if let Some((t, u, v)) = intersect(&occluders[o], origin, direction) {
+                    if t < closest {
+                        closest = t;
+                        hit = Some(Hit { occluder: o, u, v });
+                        if any {
+                            return hit
+                        }
+                    }
+                }
+            }
+        }
+        hit
+    }
+}
+
+/// Intersect a ray with both sides of a triangle, returning the distance and barycentric coordinates of the hit.
+fn intersect(occluder: &Occluder, origin: Vec3, direction: Vec3) -> Option<(f32, f32, f32)> {
+    let [e1, e2] = occluder.edges;
+    let p = cross(direction, e2);
+    let determinant = dot(e1, p);
+    if determinant.abs() < 1e-12 {
+        return None
+    }
+    let inverse = 1.0 / determinant;
+    let t = sub(origin, occluder.position);
+    let u = dot(t, p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None
+    }
+    let q = cross(t, e1);
+    let v = dot(direction, q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None
+    }
+    let distance = dot(e2, q) * inverse;
+    if distance > SURFACE_OFFSET * 0.01 { Some((distance, u, v)) } else { None }
+}
+
+/// Deterministic pseudorandom number generator.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    fn next_f32(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// Get `count` cosine-weighted directions in the hemisphere around the normal.
+///
+/// Directions follow a low discrepancy sequence that is randomly offset for each sample, so the same sample always
+/// gets the same directions.
+fn hemisphere_directions(normal: Vec3, sample: usize, count: usize) -> Vec<Vec3> {
+    let mut random = SplitMix64(sample as u64);
+    let offset = [random.next_f32(), random.next_f32()];
+
+    let helper = if normal[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+    let tangent = normalize(cross(helper, normal)).unwrap();
+    let bitangent = cross(normal, tangent);
+
+    (0..count).map(|i| {
+        let u = (offset[0] + i as f32 * 0.754_877_7).fract();
+        let v = (offset[1] + i as f32 * 0.569_840_3).fract();
+        let r = v.sqrt();
+        let phi = std::f32::consts::TAU * u;
+        let z = (1.0 - v).max(0.0).sqrt();
+        add(add(scale(tangent, r * phi.cos()), scale(bitangent, r * phi.sin())), scale(normal, z))
+    }).collect()
+}
+
+/// Run `function` for each index on up to `threads` threads, returning the results in order.
+fn parallel_map<T: Send, F: Fn(usize) -> T + Sync>(count: usize, threads: NonZeroUsize, function: F) -> Vec<T> {
+    let chunk_size = count.div_ceil(threads.get()).max(1);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..count).step_by(chunk_size).map(|start| {
+            let function = &function;
+            scope.spawn(move || (start..(start + chunk_size).min(count)).map(function).collect::<Vec<T>>())
+        }).collect();
+        handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+    })
+}
+
+/// Get the normal of the side of a triangle that is lit, using its vertex normals to choose the side.
+fn facing_normal(material: &RadiosityMaterial, triangle: &[u16; 3]) -> Vec3 {
+    let [a, b, c] = triangle.map(|v| &material.vertices[v as usize]);
+    let vertex_normal = add(add(from_vector(&a.normal), from_vector(&b.normal)), from_vector(&c.normal));
+    match normalize(cross(sub(from_point(&b.position), from_point(&a.position)), sub(from_point(&c.position), from_point(&a.position)))) {
+        Some(n) if dot(n, vertex_normal) < 0.0 => scale(n, -1.0),
+        Some(n) => n,
+        None => normalize(vertex_normal).unwrap_or([0.0, 0.0, 1.0])
+    }
+}
+
+/// Get the point that rays are traced from for a point on a triangle.
+///
+/// Points on the edges of a triangle can lie on the surfaces next to it, so the point is also moved towards the middle
+/// of the triangle.
+fn sample_origin(position: Vec3, centroid: Vec3, facing: Vec3) -> Vec3 {
+    let inward = normalize(sub(centroid, position)).map(|d| scale(d, SURFACE_OFFSET * 2.0)).unwrap_or_default();
+    add(add(position, inward), scale(facing, SURFACE_OFFSET))
+}
+
+/// Triangles of a chart in each cell of a grid.
+type ChartGrid = HashMap<(i64, i64), Vec<usize>>;
+
+/// Check if two projected triangles overlap, not counting edges and vertices that touch.
+fn triangles_overlap(a: &[[f32; 2]; 3], b: &[[f32; 2]; 3]) -> bool {
+    // The triangles don't overlap if the line through an edge of either of them separates them.
+    let separated_by_edge = |triangle: &[[f32; 2]; 3]| (0..3).any(|e| {
+        let (p, q) = (triangle[e], triangle[(e + 1) % 3]);
+        let length = (q[0] - p[0]).hypot(q[1] - p[1]);
+        if length <= 0.0 {
+            return false
+        }
+        let axis = [(p[1] - q[1]) / length, (q[0] - p[0]) / length];
+        let range = |t: &[[f32; 2]; 3]| t.iter().map(|p| p[0] * axis[0] + p[1] * axis[1]).fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), d| (min.min(d), max.max(d)));
+        let ((a_min, a_max), (b_min, b_max)) = (range(a), range(b));
+        a_max <= b_min + OVERLAP_EPSILON || b_max <= a_min + OVERLAP_EPSILON
+    });
+    !separated_by_edge(a) && !separated_by_edge(b)
+}
+
+/// Split a material into charts of connected triangles facing the same axis.
+///
+/// Connected triangles can still overlap when they are projected onto the axis, such as where the surface folds over
+/// itself, and they would share texels. These are put in separate charts.
+fn make_charts(material_index: usize, material: &RadiosityMaterial) -> Vec<Chart> {
+    let axes: Vec<usize> = material.triangles.iter().map(|t| {
+        let normal = facing_normal(material, t);
+        let axis = (0..3).max_by(|a, b| normal[*a].abs().total_cmp(&normal[*b].abs()).then(b.cmp(a))).unwrap();
+        axis * 2 + (normal[axis] < 0.0) as usize
+    }).collect();
+    let projected: Vec<[[f32; 2]; 3]> = material.triangles.iter().zip(&axes).map(|(t, axis)| {
+        let (u_axis, v_axis) = match axis / 2 {
+            0 => (1, 2),
+            1 => (0, 2),
+            _ => (0, 1)
+        };
+        t.map(|v| {
+            let p = from_point(&material.vertices[v as usize].position);
+            [p[u_axis], p[v_axis]]
+        })
+    }).collect();
+
+    // Join triangles that share a vertex and face the same axis.
+    let mut parents: Vec<usize> = (0..material.triangles.len()).collect();
+    fn root(parents: &mut [usize], mut i: usize) -> usize {
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+    let mut first_triangle: HashMap<(u16, usize), usize> = HashMap::new();
+    for (t, triangle) in material.triangles.iter().enumerate() {
+        for &v in triangle {
+            let other = *first_triangle.entry((v, axes[t])).or_insert(t);
+            let (a, b) = (root(&mut parents, other), root(&mut parents, t));
+            if a != b {
+                parents[a.max(b)] = a.min(b);
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of_root: HashMap<usize, usize> = HashMap::new();
+    for t in 0..material.triangles.len() {
+        let r = root(&mut parents, t);
+        let group = *group_of_root.entry(r).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(t);
+    }
+
+    // Put each triangle of a group in the first chart it doesn't overlap, using a grid to find the triangles it might
+    // overlap.
+    let bounds = |t: usize| {
+        let [a, b, c] = projected[t];
+        ([a[0].min(b[0]).min(c[0]), a[1].min(b[1]).min(c[1])], [a[0].max(b[0]).max(c[0]), a[1].max(b[1]).max(c[1])])
+    };
+    let mut charts: Vec<Chart> = Vec::new();
+    for group in groups {
+        let (mut min, mut max, mut total_extent) = ([f32::INFINITY; 2], [f32::NEG_INFINITY; 2], 0.0);
+        for &t in &group {
+            let (t_min, t_max) = bounds(t);
+            for a in 0..2 {
+                min[a] = min[a].min(t_min[a]);
+                max[a] = max[a].max(t_max[a]);
+            }
+            total_extent += (t_max[0] - t_min[0]).max(t_max[1] - t_min[1]);
+        }
+        let cell_size = (total_extent / group.len() as f32).max((max[0] - min[0]).max(max[1] - min[1]) / 256.0);
+        let cell_size = if cell_size > 0.0 { cell_size } else { 1.0 };
+        let cells = |t: usize| {
+            let (t_min, t_max) = bounds(t);
+            let [x0, y0, x1, y1] = [t_min[0], t_min[1], t_max[0], t_max[1]].map(|c| (c / cell_size).floor() as i64);
+            (x0..=x1).flat_map(move |x| (y0..=y1).map(move |y| (x, y)))
+        };
+
+        let mut group_charts: Vec<(Chart, ChartGrid)> = Vec::new();
+        for t in group {
+            let chart = group_charts.iter().position(|(_, grid)| {
+                !cells(t).any(|c| grid.get(&c).is_some_and(|others| others.iter().any(|o| triangles_overlap(&projected[t], &projected[*o]))))
+            }).unwrap_or_else(|| {
+                group_charts.push((Chart::new(material_index), HashMap::new()));
+                group_charts.len() - 1
+            });
+            let (chart, grid) = &mut group_charts[chart];
+            chart.add_triangle(t, &material.triangles[t], &projected[t]);
+            for c in cells(t) {
+                grid.entry(c).or_default().push(t);
+            }
+        }
+        charts.extend(group_charts.into_iter().map(|(chart, _)| chart));
+    }
+
+    charts
+}
+
+/// Width and height of a page and the origin of each chart on it.
+type PackedPage = (usize, usize, Vec<(usize, usize)>);
+
+/// Pack charts of the given sizes onto shelves of a page.
+///
+/// If `limit` is set, pages are no wider or taller than [`MAX_PAGE_SIZE`].
+fn pack_charts(sizes: &[(usize, usize)], limit: bool) -> Option<PackedPage> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|a, b| sizes[*b].1.cmp(&sizes[*a].1).then(sizes[*b].0.cmp(&sizes[*a].0)).then(a.cmp(b)));
+
+    let area: usize = sizes.iter().map(|(w, h)| w * h).sum();
+    let widest = sizes.iter().map(|s| s.0).max().unwrap_or(0);
+    let mut width = ((area as f64).sqrt().ceil() as usize).max(widest).max(MIN_PAGE_SIZE).next_power_of_two();
+
+    loop {
+        if limit && width > MAX_PAGE_SIZE {
+            return None
+        }
+
+        let mut origins = vec![(0, 0); sizes.len()];
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+        for &c in &order {
+            let (w, h) = sizes[c];
+            if x + w > width {
+                x = 0;
+                y += shelf_height;
+                shelf_height = 0;
+            }
+            origins[c] = (x, y);
+            x += w;
+            shelf_height = shelf_height.max(h);
+        }
+        let height = (y + shelf_height).max(MIN_PAGE_SIZE).next_power_of_two();
+        if height <= width {
+            return Some((width, height, origins))
+        }
+        width *= 2;
+    }
+}
+
+/// Lightmap page being baked.
+struct Page {
+    width: usize,
+    height: usize,
+    texels: Vec<Option<usize>>
+}
+
+impl Page {
+    /// Get the sample at the texel containing the coordinates.
+    fn sample_at(&self, coordinates: [f32; 2]) -> Option<usize> {
+        let x = (coordinates[0].max(0.0) as usize).min(self.width - 1);
+        let y = (coordinates[1].max(0.0) as usize).min(self.height - 1);
+        self.texels[x + y * self.width]
+    }
+
+    /// Fill empty texels with neighboring values.
+    fn dilate<T: Clone>(&self, values: &mut [Option<T>], passes: usize) {
+        for _ in 0..passes {
+            let previous = values.to_vec();
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    if previous[x + y * self.width].is_some() {
+                        continue
+                    }
+                    let neighbor = [(0isize, -1isize), (-1, 0), (1, 0), (0, 1), (-1, -1), (1, -1), (-1, 1), (1, 1)].iter().find_map(|(dx, dy)| {
+                        let (nx, ny) = (x as isize + dx, y as isize + dy);
+                        if nx < 0 || ny < 0 || nx >= self.width as isize || ny >= self.height as isize {
+                            return None
+                        }
+                        previous[nx as usize + ny as usize * self.width].clone()
+                    });
+                    values[x + y * self.width] = neighbor;
+                }
+            }
+        }
+    }
+}
+
+/// Bake lightmaps for the materials of each lightmap.
+///
+/// Each lightmap gets one page. Its materials are split into charts that are projected onto the axis they face and
+/// packed onto the page, and then light is gathered at every texel by tracing rays from it. Rays that miss every
+/// opaque surface see the ambient light of the sky of the texel's cluster, and light is bounced between surfaces
+/// `settings.bounces` times. Each texel is traced on its own with its own random sequence, so the result does not
+/// depend on the number of threads.
+pub fn bake_radiosity(lightmaps: &[Vec<RadiosityMaterial>], skies: &[Option<RadiositySky>], settings: &RadiositySettings) -> Vec<BakedLightmap> {
+    let mut baked: Vec<BakedLightmap> = Vec::with_capacity(lightmaps.len());
+    let mut pages: Vec<Page> = Vec::with_capacity(lightmaps.len());
+    let mut samples: Vec<Sample> = Vec::new();
+    let mut vertex_samples: Vec<Vec<Vec<usize>>> = Vec::with_capacity(lightmaps.len());
+    let mut occluders: Vec<Occluder> = Vec::new();
+
+    for (l, materials) in lightmaps.iter().enumerate() {
+        let charts: Vec<Chart> = materials.iter().enumerate().flat_map(|(m, material)| make_charts(m, material)).collect();
+
+        // Lower the density until everything fits on one page.
+        let mut density = TEXELS_PER_WORLD_UNIT;
+        let (width, height, origins) = loop {
+            let sizes: Vec<(usize, usize)> = charts.iter().map(|c| c.size(density)).collect();
+            let limit = density >= MIN_TEXELS_PER_WORLD_UNIT;
+            if let Some(p) = pack_charts(&sizes, limit) {
+                break p
+            }
+            density *= 0.5;
+        };
+
+        let mut page = Page { width, height, texels: vec![None; width * height] };
+        let mut baked_materials = vec![BakedMaterial::default(); materials.len()];
+        let mut material_vertex_samples: Vec<Vec<usize>> = vec![Vec::new(); materials.len()];
+        for m in 0..materials.len() {
+            baked_materials[m].triangles = vec![[0; 3]; materials[m].triangles.len()];
+        }
+
+        for (chart, origin) in charts.iter().zip(origins) {
+            let material = &materials[chart.material];
+            let baked_material = &mut baked_materials[chart.material];
+            let reflectance = match from_color(&material.tint) {
+                [0.0, 0.0, 0.0] => [1.0; 3],
+                c => c
+            };
+            let reflectance = if material.opaque { scale(reflectance, SURFACE_REFLECTANCE) } else { [0.0; 3] };
+            let emission = from_color(&material.emission);
+
+            // Place the chart's vertices on the page.
+            let first_vertex = baked_material.vertices.len();
+            let texels: Vec<[f32; 2]> = chart.projected.iter().map(|p| [
+                (origin.0 + CHART_PADDING) as f32 + (p[0] - chart.min[0]) * density + 0.5,
+                (origin.1 + CHART_PADDING) as f32 + (p[1] - chart.min[1]) * density + 0.5
+            ]).collect();
+            for (v, t) in chart.vertices.iter().zip(&texels) {
+                baked_material.vertices.push(BakedVertex {
+                    source: *v,
+                    normal: material.vertices[*v as usize].normal,
+                    texture_coords: Point2D { x: t[0] / width as f32, y: t[1] / height as f32 }
+                });
+            }
+            let local = |v: u16| chart.vertex_indices[&v];
+
+            for &t in &chart.triangles {
+                let triangle = &material.triangles[t];
+                let indices = triangle.map(local);
+                baked_material.triangles[t] = indices.map(|i| (first_vertex + i) as u32);
+
+                let positions = triangle.map(|v| from_point(&material.vertices[v as usize].position));
+                let normals = triangle.map(|v| from_vector(&material.vertices[v as usize].normal));
+                let triangle_texels = indices.map(|i| texels[i]);
+                let facing = facing_normal(material, triangle);
+                let centroid = scale(add(add(positions[0], positions[1]), positions[2]), 1.0 / 3.0);
+                let sky = material.skies.get(t).copied().flatten();
+
+                if material.opaque {
+                    occluders.push(Occluder {
+                        position: positions[0],
+                        edges: [sub(positions[1], positions[0]), sub(positions[2], positions[0])],
+                        facing,
+                        lightmap: l,
+                        texels: triangle_texels
+                    });
+                }
+
+                // Make a sample at every texel whose center is inside the triangle.
+                let [a, b, c] = triangle_texels;
+                let area = (b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1]);
+                if area.abs() < 1e-9 {
+                    continue
+                }
+                let min_x = a[0].min(b[0]).min(c[0]).floor().max(0.0) as usize;
+                let min_y = a[1].min(b[1]).min(c[1]).floor().max(0.0) as usize;
+                let max_x = (a[0].max(b[0]).max(c[0]).floor() as usize).min(width - 1);
+                let max_y = (a[1].max(b[1]).max(c[1]).floor() as usize).min(height - 1);
+                for y in min_y..=max_y {
+                    for x in min_x..=max_x {
+                        if page.texels[x + y * width].is_some() {
+                            continue
+                        }
+                        let p = [x as f32 + 0.5, y as f32 + 0.5];
+                        let w1 = ((p[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (p[1] - a[1])) / area;
+                        let w2 = ((b[0] - a[0]) * (p[1] - a[1]) - (p[0] - a[0]) * (b[1] - a[1])) / area;
+                        let w0 = 1.0 - w1 - w2;
+                        if w0 < -1e-4 || w1 < -1e-4 || w2 < -1e-4 {
+                            continue
+                        }
+                        let position = add(add(scale(positions[0], w0), scale(positions[1], w1)), scale(positions[2], w2));
+                        let normal = add(add(scale(normals[0], w0), scale(normals[1], w1)), scale(normals[2], w2));
+                        let normal = normalize(normal).filter(|n| dot(*n, facing) > 0.0).unwrap_or(facing);
+                        page.texels[x + y * width] = Some(samples.len());
+                        samples.push(Sample { origin: sample_origin(position, centroid, facing), normal, sky, emission, reflectance });
+                    }
+                }
+            }
+
+            // Make a sample at every vertex for finding the direction of its light.
+            for (i, &v) in chart.vertices.iter().enumerate() {
+                let vertex = &material.vertices[v as usize];
+                let triangle = chart.vertex_triangles[i];
+                let facing = facing_normal(material, &material.triangles[triangle]);
+                let centroid = scale(material.triangles[triangle].iter().fold([0.0; 3], |c, n| add(c, from_point(&material.vertices[*n as usize].position))), 1.0 / 3.0);
+                let sky = material.skies.get(triangle).copied().flatten();
+                let normal = normalize(from_vector(&vertex.normal)).filter(|n| dot(*n, facing) > 0.0).unwrap_or(facing);
+                let texel = texels[i].map(|t| t as usize);
+                let texel = texel[0].min(width - 1) + texel[1].min(height - 1) * width;
+                if page.texels[texel].is_none() {
+                    page.texels[texel] = Some(samples.len());
+                }
+                material_vertex_samples[chart.material].push(samples.len());
+                samples.push(Sample { origin: sample_origin(from_point(&vertex.position), centroid, facing), normal, sky, emission, reflectance });
+            }
+        }
+
+        let mut texels = page.texels.clone();
+        page.dilate(&mut texels, CHART_PADDING);
+        page.texels = texels;
+
+        baked.push(BakedLightmap { width, height, pixels: Vec::new(), materials: baked_materials });
+        pages.push(page);
+        vertex_samples.push(material_vertex_samples);
+    }
+
+    let bvh = Bvh::new(&occluders);
+    let trace = |origin: Vec3, direction: Vec3| bvh.trace(&occluders, origin, direction, false);
+
+    // Light coming straight from the sky lights.
+    let direct: Vec<(Vec3, Vec3)> = parallel_map(samples.len(), settings.threads, |s| {
+        let sample = &samples[s];
+        let mut light = [0.0; 3];
+        let mut direction = [0.0; 3];
+        if let Some(Some(sky)) = sample.sky.and_then(|s| skies.get(s)) {
+            for l in &sky.lights {
+                let Some(to_light) = normalize(from_vector(&l.direction)) else { continue };
+                let intensity = dot(sample.normal, to_light);
+                if intensity <= 0.0 || bvh.trace(&occluders, sample.origin, to_light, true).is_some() {
+                    continue
+                }
+                let color = scale(from_color(&l.color), intensity);
+                light = add(light, color);
+                direction = add(direction, scale(to_light, luminance(color)));
+            }
+        }
+        (light, direction)
+    });
+
+    // Gather light, bouncing it off of the surfaces that were lit in the previous pass.
+    let mut outgoing: Vec<Vec3> = samples.iter().map(|s| s.emission).collect();
+    let mut gathered: Vec<(Vec3, Vec3)> = Vec::new();
+    let ray_count = settings.samples.max(1);
+    for _ in 0..=settings.bounces {
+        gathered = parallel_map(samples.len(), settings.threads, |s| {
+            let sample = &samples[s];
+            let ambient = match sample.sky.and_then(|s| skies.get(s)) {
+                Some(Some(sky)) => from_color(&sky.ambient),
+                _ => [0.0; 3]
+            };
+
+            let mut light = [0.0; 3];
+            let mut direction = direct[s].1;
+            for ray in hemisphere_directions(sample.normal, s, ray_count) {
+                let incoming = match trace(sample.origin, ray) {
+                    None => ambient,
+                    Some(hit) => {
+                        let occluder = &occluders[hit.occluder];
+                        if dot(ray, occluder.facing) > 0.0 {
+                            continue
+                        }
+                        let [a, b, c] = occluder.texels;
+                        let w0 = 1.0 - hit.u - hit.v;
+                        let texel = [
+                            a[0] * w0 + b[0] * hit.u + c[0] * hit.v,
+                            a[1] * w0 + b[1] * hit.u + c[1] * hit.v
+                        ];
+                        match pages[occluder.lightmap].sample_at(texel) {
+                            Some(hit_sample) => outgoing[hit_sample],
+                            None => continue
+                        }
+                    }
+                };
+                let incoming = scale(incoming, 1.0 / ray_count as f32);
+                light = add(light, incoming);
+                direction = add(direction, scale(ray, luminance(incoming)));
+            }
+            (add(light, direct[s].0), direction)
+        });
+
+        outgoing = samples.iter().zip(&gathered).map(|(s, (light, _))| add(s.emission, mul(s.reflectance, *light))).collect();
+    }
+
+    // Write the pages and the light directions of the vertices.
+    for (l, (baked, page)) in baked.iter_mut().zip(&pages).enumerate() {
+        let mut colors: Vec<Option<Vec3>> = page.texels.iter().map(|t| t.map(|s| add(gathered[s].0, samples[s].emission))).collect();
+        page.dilate(&mut colors, CHART_PADDING);
+        baked.pixels = colors.into_iter().map(|c| {
+            let [r, g, b] = c.unwrap_or_default().map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+            ColorARGBInt { a: 255, r, g, b }
+        }).collect();
+
+        for (material, material_samples) in baked.materials.iter_mut().zip(&vertex_samples[l]) {
+            for (vertex, &s) in material.vertices.iter_mut().zip(material_samples) {
+                if let Some(n) = normalize(gathered[s].1) {
+                    vertex.normal = Vector3D { x: n[0], y: n[1], z: n[2] };
+                }
+            }
+        }
+    }
+
+    baked
+}
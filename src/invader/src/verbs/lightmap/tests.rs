@@ -0,0 +1,134 @@
+use std::num::NonZeroUsize;
+use ringhopper::types::{ColorRGB, Point3D, Vector3D};
+use super::radiosity::*;
+
+/// Add a quad with the given corners, wound counterclockwise when seen from the side its normal faces.
+fn add_quad(material: &mut RadiosityMaterial, corners: [[f32; 3]; 4], normal: [f32; 3]) {
+    let first = material.vertices.len() as u16;
+    for [x, y, z] in corners {
+        material.vertices.push(RadiosityVertex { position: Point3D { x, y, z }, normal: Vector3D { x: normal[0], y: normal[1], z: normal[2] } });
+    }
+    material.triangles.push([first, first + 1, first + 2]);
+    material.triangles.push([first, first + 2, first + 3]);
+    material.skies.push(Some(0));
+    material.skies.push(Some(0));
+}
+
+fn empty_material(emission: ColorRGB) -> RadiosityMaterial {
+    RadiosityMaterial { vertices: Vec::new(), triangles: Vec::new(), skies: Vec::new(), emission, tint: ColorRGB::default(), opaque: true }
+}
+
+/// Make a closed unit box with its faces lit from the inside. The ceiling emits `ceiling_emission`.
+fn make_box(ceiling_emission: ColorRGB) -> Vec<RadiosityMaterial> {
+    let mut walls = empty_material(ColorRGB::default());
+    add_quad(&mut walls, [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]], [0.0, 0.0, 1.0]);
+    add_quad(&mut walls, [[0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 1.0], [1.0, 0.0, 0.0]], [0.0, 1.0, 0.0]);
+    add_quad(&mut walls, [[0.0, 1.0, 0.0], [1.0, 1.0, 0.0], [1.0, 1.0, 1.0], [0.0, 1.0, 1.0]], [0.0, -1.0, 0.0]);
+    add_quad(&mut walls, [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 1.0, 1.0], [0.0, 0.0, 1.0]], [1.0, 0.0, 0.0]);
+    add_quad(&mut walls, [[1.0, 0.0, 0.0], [1.0, 0.0, 1.0], [1.0, 1.0, 1.0], [1.0, 1.0, 0.0]], [-1.0, 0.0, 0.0]);
+
+    let mut ceiling = empty_material(ceiling_emission);
+    add_quad(&mut ceiling, [[0.0, 0.0, 1.0], [0.0, 1.0, 1.0], [1.0, 1.0, 1.0], [1.0, 0.0, 1.0]], [0.0, 0.0, -1.0]);
+
+    vec![walls, ceiling]
+}
+
+fn settings(threads: usize) -> RadiositySettings {
+    RadiositySettings { samples: 16, bounces: 2, threads: NonZeroUsize::new(threads).unwrap() }
+}
+
+fn sunny_sky() -> Vec<Option<RadiositySky>> {
+    vec![Some(RadiositySky {
+        ambient: ColorRGB { r: 0.25, g: 0.25, b: 0.25 },
+        lights: vec![RadiositySkyLight { direction: Vector3D { x: 0.0, y: 0.0, z: 1.0 }, color: ColorRGB { r: 0.5, g: 0.25, b: 0.0 } }]
+    })]
+}
+
+#[test]
+fn test_bake_radiosity_sunlit_floor() {
+    let mut floor = empty_material(ColorRGB::default());
+    add_quad(&mut floor, [[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [2.0, 2.0, 0.0], [0.0, 2.0, 0.0]], [0.0, 0.0, 1.0]);
+
+    let baked = bake_radiosity(&[vec![floor]], &sunny_sky(), &settings(1));
+    assert_eq!(1, baked.len());
+
+    let lightmap = &baked[0];
+    assert!(lightmap.width.is_power_of_two() && lightmap.height.is_power_of_two());
+    assert_eq!(lightmap.width * lightmap.height, lightmap.pixels.len());
+
+    // Nothing blocks the sky, so every texel sees all of the ambient light and the light straight above.
+    let material = &lightmap.materials[0];
+    for v in &material.vertices {
+        assert!((0.0..=1.0).contains(&v.texture_coords.x) && (0.0..=1.0).contains(&v.texture_coords.y));
+        let x = (v.texture_coords.x * lightmap.width as f32) as usize;
+        let y = (v.texture_coords.y * lightmap.height as f32) as usize;
+        let pixel = lightmap.pixels[x + y * lightmap.width];
+        assert_eq!((191, 128, 64), (pixel.r, pixel.g, pixel.b));
+
+        // The light mostly comes from above.
+        assert!(v.normal.z > 0.9);
+    }
+    assert_eq!(2, material.triangles.len());
+}
+
+#[test]
+fn test_bake_radiosity_closed_box() {
+    // No light gets into a closed box.
+    let baked = bake_radiosity(&[make_box(ColorRGB::default())], &sunny_sky(), &settings(1));
+    assert!(baked[0].pixels.iter().all(|p| p.r == 0 && p.g == 0 && p.b == 0));
+
+    // An emissive ceiling lights the floor, and light bounces off of the walls.
+    let baked = bake_radiosity(&[make_box(ColorRGB { r: 1.0, g: 1.0, b: 1.0 })], &sunny_sky(), &settings(1));
+    let lightmap = &baked[0];
+    let walls = &lightmap.materials[0];
+    let floor_vertex = &walls.vertices[walls.triangles[0][0] as usize];
+    let x = (floor_vertex.texture_coords.x * lightmap.width as f32) as usize;
+    let y = (floor_vertex.texture_coords.y * lightmap.height as f32) as usize;
+    let pixel = lightmap.pixels[x + y * lightmap.width];
+    assert!(pixel.r > 0 && pixel.r == pixel.g && pixel.g == pixel.b);
+
+    // Each face is its own chart, so the vertices on the edges of the box are duplicated.
+    assert_eq!(20, walls.vertices.len());
+    assert_eq!(10, walls.triangles.len());
+}
+
+#[test]
+fn test_bake_radiosity_is_deterministic() {
+    let mut room = make_box(ColorRGB { r: 0.5, g: 0.75, b: 1.0 });
+    let mut floor = empty_material(ColorRGB::default());
+    add_quad(&mut floor, [[-2.0, -2.0, -1.0], [3.0, -2.0, -1.0], [3.0, 3.0, -1.0], [-2.0, 3.0, -1.0]], [0.0, 0.0, 1.0]);
+    room.push(floor);
+
+    let single = bake_radiosity(&[room.clone()], &sunny_sky(), &settings(1));
+    let multiple = bake_radiosity(&[room], &sunny_sky(), &settings(3));
+    assert_eq!(single[0].pixels, multiple[0].pixels);
+    for (a, b) in single[0].materials.iter().zip(&multiple[0].materials) {
+        assert_eq!(a.triangles, b.triangles);
+        for (va, vb) in a.vertices.iter().zip(&b.vertices) {
+            assert_eq!((va.source, va.normal, va.texture_coords), (vb.source, vb.normal, vb.texture_coords));
+        }
+    }
+}
+
+#[test]
+fn test_bake_radiosity_folded_surface() {
+    // A ramp folds back over the floor from its corner, so they overlap when projected onto the floor.
+    let mut folded = empty_material(ColorRGB::default());
+    add_quad(&mut folded, [[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [2.0, 2.0, 0.0], [0.0, 2.0, 0.0]], [0.0, 0.0, 1.0]);
+    add_quad(&mut folded, [[0.0, 0.0, 0.0], [2.0, 0.0, 0.5], [2.0, 2.0, 0.5], [0.0, 2.0, 0.5]], [0.0, 0.0, 1.0]);
+    folded.triangles[2][0] = 0;
+    folded.triangles[3][0] = 0;
+
+    let baked = bake_radiosity(&[vec![folded]], &sunny_sky(), &settings(1));
+    let lightmap = &baked[0];
+    let pixel = |source: u16| {
+        let v = lightmap.materials[0].vertices.iter().find(|v| v.source == source).unwrap();
+        let x = (v.texture_coords.x * lightmap.width as f32) as usize;
+        let y = (v.texture_coords.y * lightmap.height as f32) as usize;
+        let pixel = lightmap.pixels[x + y * lightmap.width];
+        pixel.r as u32 + pixel.g as u32 + pixel.b as u32
+    };
+
+    // The ramp shades the floor under it, so they can't share texels.
+    assert!(pixel(2) < pixel(6));
+}
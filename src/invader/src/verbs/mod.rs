@@ -12,14 +12,26 @@ use ringhopper::engines::h1::TagGroup;
 use ringhopper::error::*;
 use ringhopper::file::TagFile;
 
+pub mod animations;
 pub mod bitmap;
+pub mod bludgeon;
+pub mod bsp;
+pub mod build;
 pub mod collection;
+pub mod collision;
 pub mod convert;
+pub mod dependency;
+pub mod edit;
+pub mod extract;
+pub mod font;
+pub mod info;
 pub mod lightmap;
 pub mod list_engines;
+pub mod model;
 pub mod normalize_lightmaps;
 pub mod recover;
 pub mod recover_processed;
+pub mod refactor;
 pub mod script;
 pub mod sound;
 pub mod strip;
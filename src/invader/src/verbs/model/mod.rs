@@ -0,0 +1,125 @@
+use std::path::Path;
+use std::process::ExitCode;
+use crate::cmd::*;
+use crate::file::*;
+use macros::terminal::*;
+use ringhopper::engines::h1::definitions::{GBXModel, Model};
+use ringhopper::engines::h1::jms::*;
+use ringhopper::engines::h1::*;
+use ringhopper::error::*;
+use ringhopper::file::*;
+use ringhopper::types::HALO_DIRECTORY_SEPARATOR;
+use ringhopper_proc::*;
+
+/// Read all JMS files in the directory, sorted by file name.
+///
+/// glTF and OBJ files are also read. The permutations of their meshes are used if they are named, and the name of the
+/// file otherwise.
+fn read_jms_files(models_dir: &Path) -> ErrorMessageResult<Vec<ModelPermutationJMS>> {
+    let paths = find_files_with_extensions(models_dir, &["jms", "gltf", "glb", "obj"], |error| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.model.error_reading_models_directory"), dir=models_dir.display(), error=error)))?;
+
+    let mut jms_files: Vec<ModelPermutationJMS> = Vec::with_capacity(paths.len());
+    for path in paths {
+        let file_name = path.file_stem().unwrap().to_string_lossy().to_ascii_lowercase();
+        let data = read_file(&path)?;
+        let imported = match path.extension().unwrap().to_string_lossy().to_ascii_lowercase().as_str() {
+            "jms" => JMS::parse_bytes(&data).map(|jms| vec![ImportedJMS { permutation: None, jms }]),
+            "obj" => JMS::from_obj(&data),
+            _ => JMS::from_gltf(&data)
+        }.map_err(|e| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.model.error_reading_jms"), file=path.display(), error=e)))?;
+
+        for ImportedJMS { permutation, jms } in imported {
+            let name = permutation.map(|p| p.to_ascii_lowercase()).unwrap_or_else(|| file_name.clone());
+            let (permutation, lod) = ModelLevelOfDetail::split_file_name(&name);
+            if jms_files.iter().any(|j| j.permutation == permutation && j.lod == lod) {
+                return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.model.error_duplicate_jms"), permutation=permutation, lod=lod.suffix())))
+            }
+            jms_files.push(ModelPermutationJMS { permutation: permutation.to_owned(), lod, jms });
+        }
+    }
+
+    Ok(jms_files)
+}
+
+/// Find a shader for each material name.
+///
+/// Shaders in the shaders directory of the model (or scenario) are preferred, followed by shaders in its directory,
+/// followed by any other shaders. If there are multiple matches, the first one sorted by path is used.
+pub struct ShaderResolver {
+    shaders: Vec<TagReference>,
+    model_directory: String
+}
+
+impl ShaderResolver {
+    pub fn new(tags_directories: &[&Path], model_directory: &str) -> ErrorMessageResult<ShaderResolver> {
+        let mut shaders: Vec<TagReference> = TagFile::from_virtual_tags_directory(tags_directories)?
+            .into_iter()
+            .map(|t| t.tag_path)
+            .filter(|t| t.get_group().supergroup() == TagGroup::Shader)
+            .collect();
+        shaders.sort_by_key(|s| s.get_path_with_extension());
+        Ok(ShaderResolver { shaders, model_directory: format!("{model_directory}{HALO_DIRECTORY_SEPARATOR}") })
+    }
+
+    pub fn resolve(&self, name: &str) -> Option<TagReference> {
+        let shaders_directory = format!("{}shaders{HALO_DIRECTORY_SEPARATOR}", self.model_directory);
+        let matches: Vec<&TagReference> = self.shaders.iter().filter(|s| {
+            let path = s.get_path_without_extension();
+            path.rsplit(HALO_DIRECTORY_SEPARATOR).next() == Some(name)
+        }).collect();
+
+        matches.iter().find(|s| s.get_path_without_extension().starts_with(&shaders_directory))
+            .or_else(|| matches.iter().find(|s| s.get_path_without_extension().starts_with(&self.model_directory)))
+            .or_else(|| matches.first())
+            .map(|s| (*s).clone())
+    }
+}
+
+pub fn model_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageResult<ExitCode> {
+    let parsed_args = ParsedArguments::parse_arguments(args, &[], &[get_compiled_string!("arguments.specifier.tag_without_group")], executable, verb.get_description(), ArgumentConstraints::new().needs_data().needs_tags().multiple_tags_directories())?;
+
+    let tags_dirs = str_slice_to_path_vec(&parsed_args.named["tags"]);
+    let data = Path::new(&parsed_args.named["data"][0]);
+    let group = match *verb {
+        Verb::Model => TagGroup::Model,
+        Verb::GBXModel => TagGroup::GBXModel,
+        _ => unreachable!()
+    };
+
+    // The model tag is named after its directory (e.g. "weapons\pistol" makes "weapons\pistol\pistol").
+    let directory = TagReference::from_path_and_group(&parsed_args.extra[0], group)?;
+    let directory_path = directory.get_path_without_extension().trim_end_matches(HALO_DIRECTORY_SEPARATOR).to_owned();
+    let name = directory_path.rsplit(HALO_DIRECTORY_SEPARATOR).next().unwrap_or_default();
+    let tag_path = TagReference::from_path_and_group(&format!("{directory_path}{HALO_DIRECTORY_SEPARATOR}{name}"), group)?;
+
+    let models_dir = data.join(directory_path.replace(HALO_DIRECTORY_SEPARATOR, std::path::MAIN_SEPARATOR_STR)).join("models");
+    let jms_files = read_jms_files(&models_dir)?;
+
+    let (existing, output_path) = match group {
+        TagGroup::Model => {
+            let (existing, output_path) = load_existing_tag::<Model>(&tags_dirs, &tag_path)?;
+            (existing.map(GBXModel::from), output_path)
+        },
+        _ => load_existing_tag::<GBXModel>(&tags_dirs, &tag_path)?
+    };
+
+    let shaders = ShaderResolver::new(&tags_dirs, &directory_path)?;
+    let model = build_gbxmodel(&jms_files, existing.as_ref(), |material| Ok(shaders.resolve(material)))?;
+
+    let output = match group {
+        TagGroup::Model => {
+            let mut model = Model::try_from(model)?;
+            if let Err(e) = regenerate_compressed_vertices(&mut model) {
+                eprintln_warn!("{}", e);
+            }
+            model.into_tag_file()?
+        },
+        _ => model.into_tag_file()?
+    };
+
+    make_parent_directories(&output_path)?;
+    write_file(&output_path, &output)?;
+
+    println_success!(get_compiled_string!("engine.h1.verbs.model.saved_file"), file=output_path.display());
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,58 @@
+use ringhopper::error::*;
+use ringhopper::file::TagFile;
+use ringhopper::engines::h1::definitions::*;
+use ringhopper::engines::h1::TagFileSerializeFn;
+use ringhopper::engines::h1::jms::*;
+use ringhopper_proc::*;
+use std::path::Path;
+use crate::file::*;
+use super::RecoverResult;
+
+pub fn recover_animations(tag_data: &[u8], tag_file: &TagFile, data_dir: &Path, overwrite: bool) -> ErrorMessageResult<RecoverResult> {
+    let tag = *ModelAnimations::from_tag_file(tag_data)?.data;
+
+    if tag.animations.blocks.is_empty() {
+        return Ok(RecoverResult::NoSourceData);
+    }
+
+    // Let's get the path to the animations dir
+    let animations_dir_path = match (|| {
+        let mut file = data_dir.join(tag_file.tag_path.to_string());
+        file.set_extension("");
+        let file_name = file.file_name()?;
+
+        let parent = file.parent()?;
+        let parent_file_name = parent.file_name()?;
+
+        if parent_file_name != file_name {
+            None
+        }
+        else {
+            Some(parent.join("animations"))
+        }
+    })() {
+        Some(n) => n,
+        None => return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.verbs.recover.error_parent_dir_incorrect")))
+    };
+
+    // Recover everything first so nothing is written if any animation can't be recovered.
+    let mut animations = Vec::with_capacity(tag.animations.blocks.len());
+    for i in 0..tag.animations.blocks.len() {
+        animations.push(recover_animation(&tag, i)?);
+    }
+
+    let mut result = RecoverResult::DataAlreadyExists;
+    make_directories(&animations_dir_path)?;
+
+    for a in animations {
+        let path = animations_dir_path.join(format!("{}.{}", a.name, a.source_type.extension()));
+        if !overwrite && path.exists() {
+            continue;
+        }
+
+        write_file(&path, &a.jma.into_bytes())?;
+        result = RecoverResult::Recovered;
+    }
+
+    Ok(result)
+}
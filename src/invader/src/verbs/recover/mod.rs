@@ -10,6 +10,7 @@ use macros::terminal::*;
 use ringhopper::error::ErrorMessageResult;
 use std::path::*;
 
+mod animations;
 mod bitmap;
 mod hud_message_text;
 mod model;
@@ -28,6 +29,7 @@ const RECOVER_FUNCTION_GROUPS: &'static [(TagGroup, fn (tag_data: &[u8], tag_fil
     (TagGroup::GBXModel, model::recover_gbxmodels),
     (TagGroup::HUDMessageText, hud_message_text::recover_hud_messages),
     (TagGroup::Model, model::recover_models),
+    (TagGroup::ModelAnimations, animations::recover_animations),
     (TagGroup::Scenario, scenario::recover_scripts),
     (TagGroup::StringList, string_list::recover_string_list),
     (TagGroup::TagCollection, tag_collection::recover_tag_collection),
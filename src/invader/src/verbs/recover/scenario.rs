@@ -2,6 +2,7 @@ use ringhopper::error::*;
 use ringhopper::file::TagFile;
 use ringhopper::engines::h1::definitions::*;
 use ringhopper::engines::h1::TagFileSerializeFn;
+use ringhopper::engines::h1::ScriptDecompiler;
 use ringhopper::types::ReflexiveFn;
 use std::path::Path;
 use ringhopper_proc::*;
@@ -12,7 +13,7 @@ pub fn recover_scripts(tag_data: &[u8], tag_file: &TagFile, data_dir: &Path, ove
     let tag = Scenario::from_tag_file(tag_data)?.data;
 
     if tag.source_files.blocks.is_empty() {
-        return Ok(RecoverResult::NoSourceData);
+        return recover_decompiled_scripts(&tag, tag_file, data_dir, overwrite);
     }
 
     // Check if we have duplicate source files of the same name.
@@ -63,3 +64,28 @@ pub fn recover_scripts(tag_data: &[u8], tag_file: &TagFile, data_dir: &Path, ove
         false => Ok(RecoverResult::DataAlreadyExists)
     }
 }
+
+/// Decompile the scripts of scenarios without source files, such as ones extracted from cache files.
+///
+/// Since the compiled scripts cannot be split back into their source files, they are written to one file named after
+/// the scenario.
+fn recover_decompiled_scripts(tag: &Scenario, tag_file: &TagFile, data_dir: &Path, overwrite: bool) -> ErrorMessageResult<RecoverResult> {
+    if tag.scripts.blocks.is_empty() && tag.globals.blocks.is_empty() {
+        return Ok(RecoverResult::NoSourceData);
+    }
+
+    let tag_path = data_dir.join(tag_file.tag_path.to_string());
+    let output_dir = tag_path.parent().unwrap().join("scripts");
+    let mut path = output_dir.join(tag_path.file_name().unwrap());
+    path.set_extension("hsc");
+
+    if !overwrite && path.is_file() {
+        return Ok(RecoverResult::DataAlreadyExists);
+    }
+
+    let source = tag.decompile_scripts()?;
+    make_directories(&output_dir)?;
+    write_file(&path, source.as_bytes())?;
+
+    Ok(RecoverResult::Recovered)
+}
@@ -0,0 +1,103 @@
+use ringhopper::error::*;
+use ringhopper::file::TagFile;
+use ringhopper::engines::h1::definitions::*;
+use ringhopper::engines::h1::TagFileSerializeFn;
+use ringhopper::engines::h1::TagSerialize;
+use ringhopper::engines::h1::jms::*;
+use ringhopper_proc::*;
+use ringhopper::types::*;
+use std::path::Path;
+use crate::file::*;
+use std::convert::TryFrom;
+use super::RecoverProcessedResult;
+
+/// Make a JMS from the rendered geometry of the BSP.
+///
+/// The JMS has one node and region, and a material for each shader. Collision-only geometry is not included.
+fn make_jms(bsp: &ScenarioStructureBSP) -> ErrorMessageResult<JMS> {
+    let mut jms = JMS {
+        nodes: vec![Node { name: "frame".to_owned(), rotation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 }, ..Default::default() }],
+        regions: vec![Region { name: "unnamed".to_owned() }],
+        ..Default::default()
+    };
+
+    for (lmi, lightmap) in bsp.lightmaps.blocks.iter().enumerate() {
+        for (mati, mat) in lightmap.materials.blocks.iter().enumerate() {
+            let vertex_count = mat.rendered_vertices_count as usize;
+            if vertex_count == 0 {
+                continue;
+            }
+
+            let vertices_corrupt = || ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.recover-processed.error_bsp_vertices_corrupt"), material=mati, lightmap=lmi));
+            let vertex_size = ScenarioStructureBSPMaterialUncompressedRenderedVertex::tag_size();
+            let vertex_data = mat.uncompressed_vertices.get(..vertex_count * vertex_size).ok_or_else(vertices_corrupt)?;
+
+            let surfaces_corrupt = || ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.recover-processed.error_bsp_surfaces_corrupt"), material=mati, lightmap=lmi));
+            let first_surface = usize::try_from(mat.surfaces).map_err(|_| surfaces_corrupt())?;
+            let surface_count = usize::try_from(mat.surface_count).map_err(|_| surfaces_corrupt())?;
+            let surfaces = bsp.surfaces.blocks.get(first_surface..first_surface + surface_count).ok_or_else(surfaces_corrupt)?;
+
+            // Materials are named after the shader, like the shaders of recovered models.
+            let shader_path = mat.shader.get_path_without_extension();
+            let mut shader_name = match shader_path.rfind(HALO_DIRECTORY_SEPARATOR) {
+                Some(n) => shader_path.split_at(n+1).1.to_owned(),
+                None => shader_path.to_owned()
+            };
+            let permutation = mat.shader_permutation.unwrap_or(0);
+            if permutation > 0 {
+                shader_name += &permutation.to_string();
+            }
+            let shader = match jms.materials.iter().position(|m| m.name == shader_name) {
+                Some(n) => n,
+                None => {
+                    jms.materials.push(Material { name: shader_name, tif_path: "<none>".to_owned() });
+                    jms.materials.len() - 1
+                }
+            };
+            let shader = Some(u16::try_from(shader).map_err(|_| ErrorMessage::StaticString(get_compiled_string!("engine.h1.verbs.recover-processed.error_bsp_too_many_materials")))?);
+
+            // Vertex data is stored in little endian
+            let first_vertex = jms.vertices.len() as u32;
+            let float_at = |vertex: &[u8], i: usize| f32::from_le_bytes(vertex[i * 4..i * 4 + 4].try_into().unwrap());
+            jms.vertices.extend(vertex_data.chunks(vertex_size).map(|v| Vertex {
+                node0: Some(0),
+                position: Point3D { x: float_at(v, 0), y: float_at(v, 1), z: float_at(v, 2) },
+                normal: Vector3D { x: float_at(v, 3), y: float_at(v, 4), z: float_at(v, 5) },
+                node1: None,
+                node1_weight: 0.0,
+                texture_coordinates: Point3D { x: float_at(v, 12), y: float_at(v, 13), z: 0.0 }
+            }));
+
+            // Surfaces are wound in the opposite order of JMS triangles.
+            for s in surfaces {
+                let (a, b, c) = match [s.vertex0_index, s.vertex1_index, s.vertex2_index] {
+                    [Some(a), Some(b), Some(c)] if [a, b, c].iter().all(|v| (*v as usize) < vertex_count) => (a as u32, b as u32, c as u32),
+                    _ => return Err(surfaces_corrupt())
+                };
+                jms.triangles.push(Triangle { region: Some(0), shader, vertices: (first_vertex + a, first_vertex + c, first_vertex + b) });
+            }
+        }
+    }
+
+    Ok(jms)
+}
+
+pub fn recover_processed_scenario_structure_bsps(tag_data: &[u8], tag_file: &TagFile, data_dir: &Path, options: &super::RecoverProcessedOptions) -> ErrorMessageResult<RecoverProcessedResult> {
+    let bsp = *ScenarioStructureBSP::from_tag_file(tag_data)?.data;
+
+    // BSPs are read from the models directory next to the tag, named after the tag.
+    let tag_path = data_dir.join(tag_file.tag_path.get_relative_fs_path());
+    let models_dir_path = tag_path.parent().unwrap().join("models");
+    let mut path = models_dir_path.join(tag_path.file_name().unwrap());
+    path.set_extension(options.model_format.extension());
+
+    if !options.overwrite && path.exists() {
+        return Ok(RecoverProcessedResult::DataAlreadyExists);
+    }
+
+    let jms = make_jms(&bsp)?;
+    make_directories(&models_dir_path)?;
+    write_file(&path, &options.model_format.write_jms(&jms)?)?;
+
+    Ok(RecoverProcessedResult::Recovered)
+}
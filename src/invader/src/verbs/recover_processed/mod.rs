@@ -3,6 +3,7 @@ use std::num::NonZeroUsize;
 use std::process::ExitCode;
 use crate::cmd::*;
 use ringhopper::engines::h1::TagGroup;
+use ringhopper::engines::h1::jms::JMS;
 use ringhopper::error::ErrorMessage;
 use ringhopper::file::TagFile;
 use crate::file::*;
@@ -11,7 +12,9 @@ use ringhopper::error::ErrorMessageResult;
 use std::path::*;
 
 mod bitmap;
+mod bsp;
 mod model;
+mod sound;
 
 pub enum RecoverProcessedResult {
     Recovered,
@@ -23,14 +26,72 @@ const RECOVER_PROCESSED_FUNCTION_GROUPS: &'static [(TagGroup, fn (tag_data: &[u8
     (TagGroup::Bitmap, bitmap::recover_processed_bitmaps),
     (TagGroup::GBXModel, model::recover_processed_gbxmodels),
     (TagGroup::Model, model::recover_processed_models),
+    (TagGroup::ScenarioStructureBSP, bsp::recover_processed_scenario_structure_bsps),
+    (TagGroup::Sound, sound::recover_processed_sounds),
 ];
 
+/// Format that recovered models and BSPs are written in.
+#[derive(Copy, Clone, PartialEq)]
+pub enum ModelFormat {
+    Jms,
+    Gltf,
+    Obj
+}
+
+impl ModelFormat {
+    /// Get the file extension for the format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ModelFormat::Jms => "jms",
+            ModelFormat::Gltf => "glb",
+            ModelFormat::Obj => "obj"
+        }
+    }
+
+    /// Write the JMS in the format.
+    pub fn write_jms(self, jms: &JMS) -> ErrorMessageResult<Vec<u8>> {
+        match self {
+            ModelFormat::Jms => Ok(jms.into_bytes()),
+            ModelFormat::Gltf => jms.into_gltf(),
+            ModelFormat::Obj => jms.into_obj()
+        }
+    }
+}
+
+/// Format that recovered sounds are written in.
+#[derive(Copy, Clone, PartialEq)]
+pub enum SoundDataFormat {
+    Wav,
+    Flac
+}
+
+impl SoundDataFormat {
+    /// Get the file extension for the format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            SoundDataFormat::Wav => "wav",
+            SoundDataFormat::Flac => "flac"
+        }
+    }
+
+    /// Encode interleaved 16-bit samples in the format.
+    pub fn encode(self, samples: &[i16], channel_count: usize, sample_rate: u32) -> ErrorMessageResult<Vec<u8>> {
+        match self {
+            SoundDataFormat::Wav => ringhopper::sound::write_wav(samples, channel_count, sample_rate),
+            SoundDataFormat::Flac => ringhopper::sound::encode_flac(samples, channel_count, sample_rate)
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct RecoverProcessedOptions {
     pub batching: bool,
+    pub bsps: bool,
     pub force: bool,
     pub overwrite: bool,
     pub force_plate: bool,
+    pub model_format: ModelFormat,
+    pub sound_format: SoundDataFormat,
     pub data_dir: PathBuf
 }
 
@@ -38,8 +99,11 @@ fn recover_processed_tag(tag_file: &TagFile, log_mutex: super::LogMutex, _availa
     let group = tag_file.tag_path.get_group();
     let file_data = read_file(&tag_file.file_path)?;
 
+    // BSPs are only recovered with --bsps.
+    let bsps_skipped = group == TagGroup::ScenarioStructureBSP && !options.bsps;
+
     for fg in RECOVER_PROCESSED_FUNCTION_GROUPS {
-        if fg.0 == group {
+        if fg.0 == group && !bsps_skipped {
             let result = fg.1(&file_data, tag_file, &options.data_dir, options)?;
             let skipped;
 
@@ -79,8 +143,11 @@ fn recover_processed_tag(tag_file: &TagFile, log_mutex: super::LogMutex, _availa
 pub fn recover_processed_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageResult<ExitCode> {
     let parsed_args = ParsedArguments::parse_arguments(args,
                                                        &[
+                                                       Argument { long: "bsps", short: 'B', description: get_compiled_string!("engine.h1.verbs.recover-processed.arguments.bsps.description"), parameter: None, multiple: false },
                                                        Argument { long: "force", short: 'f', description: get_compiled_string!("engine.h1.verbs.recover-processed.arguments.force.description"), parameter: None, multiple: false },
-                                                       Argument { long: "force-plate", short: 'P', description: get_compiled_string!("engine.h1.verbs.recover-processed.arguments.force-plate.description"), parameter: None, multiple: false }
+                                                       Argument { long: "force-plate", short: 'P', description: get_compiled_string!("engine.h1.verbs.recover-processed.arguments.force-plate.description"), parameter: None, multiple: false },
+                                                       Argument { long: "format", short: 'F', description: get_compiled_string!("engine.h1.verbs.recover-processed.arguments.format.description"), parameter: Some("format"), multiple: false },
+                                                       Argument { long: "sound-format", short: 'S', description: get_compiled_string!("engine.h1.verbs.recover-processed.arguments.sound-format.description"), parameter: Some("format"), multiple: false }
                                                        ],
                                                        &[get_compiled_string!("arguments.specifier.tag_batch_with_group")],
                                                        executable,
@@ -92,8 +159,11 @@ pub fn recover_processed_verb(verb: &Verb, args: &[&str], executable: &str) -> E
 
     let tag_path = &parsed_args.extra[0];
     let options = RecoverProcessedOptions {
+        bsps: parsed_args.named.contains_key("bsps"),
         force: parsed_args.named.contains_key("force"),
         force_plate: parsed_args.named.contains_key("force-plate"),
+        model_format: parsed_args.parse_set("format", &[("jms", ModelFormat::Jms), ("gltf", ModelFormat::Gltf), ("obj", ModelFormat::Obj)])?.unwrap_or(ModelFormat::Jms),
+        sound_format: parsed_args.parse_set("sound-format", &[("wav", SoundDataFormat::Wav), ("flac", SoundDataFormat::Flac)])?.unwrap_or(SoundDataFormat::Wav),
         batching: TagFile::uses_batching(tag_path),
         overwrite: parsed_args.named.get("overwrite").is_some(),
         data_dir: Path::new(&parsed_args.named["data"][0]).to_owned()
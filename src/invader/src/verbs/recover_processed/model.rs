@@ -239,7 +239,7 @@ fn make_jms(model: &Model, permutation: &str, lod: usize) -> ErrorMessageResult<
     Ok(Some(JMS { node_list_checksum, markers, materials, vertices, triangles, nodes, regions }))
 }
 
-pub fn recover_jms(model: Model, tag_file: &TagFile, data_dir: &Path, overwrite: bool) -> ErrorMessageResult<RecoverProcessedResult> {
+pub fn recover_jms(model: Model, tag_file: &TagFile, data_dir: &Path, options: &super::RecoverProcessedOptions) -> ErrorMessageResult<RecoverProcessedResult> {
     // Let's get the path to the models dir
     let models_dir_path = match (|| {
         let mut file = data_dir.join(tag_file.tag_path.to_string());
@@ -307,12 +307,12 @@ pub fn recover_jms(model: Model, tag_file: &TagFile, data_dir: &Path, overwrite:
                 _ => unreachable!()
             };
 
-            let path = models_dir_path.join(format!("{p} {suffix}.jms"));
-            if !overwrite && path.exists() {
+            let path = models_dir_path.join(format!("{p} {suffix}.{extension}", extension=options.model_format.extension()));
+            if !options.overwrite && path.exists() {
                 continue;
             }
 
-            write_file(&path, &jms.into_bytes())?;
+            write_file(&path, &options.model_format.write_jms(jms)?)?;
             result = RecoverProcessedResult::Recovered;
         }
     }
@@ -322,10 +322,10 @@ pub fn recover_jms(model: Model, tag_file: &TagFile, data_dir: &Path, overwrite:
 
 pub fn recover_processed_models(tag_data: &[u8], tag_file: &TagFile, data_dir: &Path, options: &super::RecoverProcessedOptions) -> ErrorMessageResult<RecoverProcessedResult> {
     let model = *Model::from_tag_file(tag_data)?.data;
-    recover_jms(model, tag_file, data_dir, options.overwrite)
+    recover_jms(model, tag_file, data_dir, options)
 }
 
 pub fn recover_processed_gbxmodels(tag_data: &[u8], tag_file: &TagFile, data_dir: &Path, options: &super::RecoverProcessedOptions) -> ErrorMessageResult<RecoverProcessedResult> {
     let model = Model::try_from(*GBXModel::from_tag_file(tag_data)?.data)?;
-    recover_jms(model, tag_file, data_dir, options.overwrite)
+    recover_jms(model, tag_file, data_dir, options)
 }
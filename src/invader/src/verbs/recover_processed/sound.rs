@@ -0,0 +1,183 @@
+use ringhopper::error::*;
+use ringhopper::file::TagFile;
+use ringhopper::engines::h1::definitions::*;
+use ringhopper::engines::h1::TagFileSerializeFn;
+use ringhopper::sound::*;
+use ringhopper_proc::*;
+use ringhopper::types::*;
+use std::path::Path;
+use crate::file::*;
+use super::RecoverProcessedResult;
+
+/// Decode an Ogg Vorbis stream into interleaved samples.
+fn decode_ogg_vorbis(data: &[u8]) -> ErrorMessageResult<Vec<i16>> {
+    use symphonia::core::audio::SampleBuffer;
+    use symphonia::core::codecs::DecoderOptions;
+    use symphonia::core::formats::FormatOptions;
+    use symphonia::core::meta::MetadataOptions;
+    use symphonia::core::probe::Hint;
+    use symphonia::core::io::*;
+
+    let cursor = std::io::Cursor::new(data.to_owned());
+    let stream = MediaSourceStream::new(Box::new(cursor), MediaSourceStreamOptions::default());
+    let mut hint = Hint::new();
+    hint.with_extension("ogg");
+
+    let mut r = symphonia::default::get_probe().format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+                                               .map_err(|e| ErrorMessage::AllocatedString(e.to_string()))?;
+    let track = r.format.default_track().ok_or(ErrorMessage::StaticString(get_compiled_string!("engine.h1.verbs.recover-processed.error_sound_no_vorbis_stream")))?.to_owned();
+    let mut decoder = symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())
+                                                      .map_err(|e| ErrorMessage::AllocatedString(e.to_string()))?;
+
+    let mut samples = Vec::new();
+    while let Ok(packet) = r.format.next_packet() {
+        if packet.track_id() != track.id {
+            continue;
+        }
+        let audio_buf = decoder.decode(&packet).map_err(|e| ErrorMessage::AllocatedString(e.to_string()))?;
+        let mut sample_buf = SampleBuffer::<i16>::new(audio_buf.capacity() as u64, *audio_buf.spec());
+        sample_buf.copy_interleaved_ref(audio_buf);
+        samples.extend_from_slice(sample_buf.samples());
+    }
+
+    Ok(samples)
+}
+
+/// Decode the samples of a permutation into interleaved samples.
+fn decode_permutation(permutation: &SoundPermutation, channel_count: usize) -> ErrorMessageResult<Vec<i16>> {
+    let data = permutation.samples.as_slice();
+    match permutation.format {
+        // PCM is stored in big endian
+        SoundFormat::Pcm => Ok(data.chunks_exact(2).map(|s| i16::from_be_bytes([s[0], s[1]])).collect()),
+
+        // Xbox ADPCM is IMA ADPCM with blocks of a fixed size, so both are decoded the same way.
+        SoundFormat::XboxAdpcm | SoundFormat::ImaAdpcm => decode_ima_adpcm(data, channel_count),
+
+        SoundFormat::OggVorbis => decode_ogg_vorbis(data)
+    }
+}
+
+/// Decode a permutation along with each permutation it was split into.
+fn decode_split_permutation(pitch_range: &SoundPitchRange, pitch_range_index: usize, permutation_index: usize, channel_count: usize) -> ErrorMessageResult<Vec<i16>> {
+    let permutation_count = pitch_range.permutations.len();
+    let mut visited = vec![false; permutation_count];
+    let mut samples = Vec::new();
+    let mut next = Some(permutation_index);
+
+    while let Some(index) = next {
+        if visited[index] {
+            return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.recover-processed.error_sound_permutation_loop"), permutation=permutation_index, pitch_range=pitch_range_index)));
+        }
+        visited[index] = true;
+
+        let permutation = &pitch_range.permutations[index];
+        let decoded = decode_permutation(permutation, channel_count).map_err(|e| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.recover-processed.error_sound_cannot_decode"), permutation=index, pitch_range=pitch_range_index, error=e)))?;
+        samples.extend_from_slice(&decoded);
+
+        next = match permutation.next_permutation_index {
+            Some(n) if (n as usize) < permutation_count => Some(n as usize),
+            Some(n) => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.recover-processed.error_sound_invalid_next_permutation"), permutation=index, pitch_range=pitch_range_index, index=n, count=permutation_count))),
+            None => None
+        };
+    }
+
+    Ok(samples)
+}
+
+/// Get the indices of the permutations that are not continuations of split permutations.
+fn actual_permutations(pitch_range: &SoundPitchRange) -> Vec<usize> {
+    let permutation_count = pitch_range.permutations.len();
+    let actual_count = pitch_range.actual_permutation_count as usize;
+    if actual_count > 0 && actual_count <= permutation_count {
+        return (0..actual_count).collect();
+    }
+
+    // Without a valid count, any permutation that is not continued from another one is an actual permutation.
+    let mut is_continuation = vec![false; permutation_count];
+    for p in &pitch_range.permutations {
+        if let Some(n) = p.next_permutation_index.filter(|n| (*n as usize) < permutation_count) {
+            is_continuation[n as usize] = true;
+        }
+    }
+    (0..permutation_count).filter(|i| !is_continuation[*i]).collect()
+}
+
+/// Get the name of a pitch range or permutation as a file name, using its index if it is unnamed.
+fn file_name(name: &str, index: usize, used_names: &mut Vec<String>) -> ErrorMessageResult<String> {
+    let name = if name.is_empty() { index.to_string() } else { name.to_owned() };
+    if used_names.iter().any(|n| n.eq_ignore_ascii_case(&name)) {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.recover-processed.error_sound_duplicate_name"), name=name)));
+    }
+    used_names.push(name.clone());
+    Ok(name)
+}
+
+pub fn recover_processed_sounds(tag_data: &[u8], tag_file: &TagFile, data_dir: &Path, options: &super::RecoverProcessedOptions) -> ErrorMessageResult<RecoverProcessedResult> {
+    let sound = *Sound::from_tag_file(tag_data)?.data;
+
+    // Sounds are read from a directory named after the tag.
+    let mut sound_dir = data_dir.join(tag_file.tag_path.get_relative_fs_path());
+    sound_dir.set_extension("");
+
+    if !options.overwrite && sound_dir.exists() {
+        return Ok(RecoverProcessedResult::DataAlreadyExists);
+    }
+
+    let channel_count = match sound.channel_count {
+        SoundChannelCount::Mono => 1,
+        SoundChannelCount::Stereo => 2
+    };
+    let sample_rate = match sound.sample_rate {
+        SoundSampleRate::_22050Hz => 22050,
+        SoundSampleRate::_44100Hz => 44100
+    };
+
+    // A single pitch range named "default" is stored as files in the directory, and otherwise each pitch range is a
+    // directory of files.
+    let pitch_ranges_are_dirs = !(sound.pitch_ranges.len() == 1 && sound.pitch_ranges[0].name.to_str() == "default");
+
+    let mut files = Vec::new();
+    let mut pitch_range_names = Vec::new();
+    for (pri, pitch_range) in sound.pitch_ranges.blocks.iter().enumerate() {
+        let pitch_range_dir = if pitch_ranges_are_dirs {
+            sound_dir.join(file_name(pitch_range.name.to_str(), pri, &mut pitch_range_names)?)
+        }
+        else {
+            sound_dir.clone()
+        };
+
+        // Pitch ranges encoded in a different format than the tag get a format file so they are encoded the same way.
+        let permutations = actual_permutations(pitch_range);
+        if let Some(first) = permutations.first().map(|p| pitch_range.permutations[*p].format) {
+            if first != sound.format && permutations.iter().all(|p| pitch_range.permutations[*p].format == first) {
+                files.push((pitch_range_dir.join(crate::verbs::sound::PITCH_RANGE_FORMAT_FILE_NAME), first.as_str().as_bytes().to_owned()));
+            }
+        }
+
+        let mut permutation_names = Vec::new();
+        for pei in permutations {
+            let name = file_name(pitch_range.permutations[pei].name.to_str(), pei, &mut permutation_names)?;
+            let samples = decode_split_permutation(pitch_range, pri, pei, channel_count)?;
+
+            let path = pitch_range_dir.join(format!("{name}.{extension}", extension=options.sound_format.extension()));
+            files.push((path, options.sound_format.encode(&samples, channel_count, sample_rate)?));
+
+            // Subtitles are stored in a text file next to the permutation.
+            let subtitle_data = &pitch_range.permutations[pei].subtitle_data;
+            if !subtitle_data.is_empty() {
+                files.push((pitch_range_dir.join(format!("{name}.txt")), decode_subtitle_data(subtitle_data)?.into_bytes()));
+            }
+        }
+    }
+
+    if files.is_empty() {
+        return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.verbs.recover-processed.error_sound_no_permutations")));
+    }
+
+    for (path, data) in files {
+        make_directories(path.parent().unwrap())?;
+        write_file(&path, &data)?;
+    }
+
+    Ok(RecoverProcessedResult::Recovered)
+}
@@ -0,0 +1,201 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use crate::cmd::*;
+use crate::file::*;
+use macros::terminal::*;
+use ringhopper::engines::h1::definitions::{parse_tag_file, Scenario};
+use ringhopper::engines::h1::*;
+use ringhopper::error::*;
+use ringhopper::file::*;
+use ringhopper::types::{HALO_DIRECTORY_SEPARATOR, TagBlockFn, TagFieldValue, ValueReferenceMut};
+use ringhopper_proc::*;
+
+/// Tags or directories being moved.
+enum Refactor {
+    /// Move a single tag.
+    Tag(TagReference, TagReference),
+
+    /// Move every tag in a directory, including subdirectories.
+    Directory(String, String)
+}
+
+impl Refactor {
+    /// Get the new reference for the tag, or `None` if the tag is not being moved.
+    fn get_new_reference(&self, reference: &TagReference) -> Option<TagReference> {
+        match self {
+            Refactor::Tag(from, to) => match reference == from {
+                true => Some(to.clone()),
+                false => None
+            },
+            Refactor::Directory(from, to) => {
+                let remainder = reference.get_path_without_extension().strip_prefix(from.as_str())?.strip_prefix(HALO_DIRECTORY_SEPARATOR)?;
+                TagReference::from_path_and_group(&format!("{to}{HALO_DIRECTORY_SEPARATOR}{remainder}"), reference.get_group()).ok()
+            }
+        }
+    }
+}
+
+/// Replace each reference in the block that is being moved, returning the number of references replaced.
+fn replace_references<B: TagBlockFn + ?Sized>(block: &mut B, refactor: &Refactor) -> usize {
+    let mut count = 0;
+    for i in 0..block.field_count() {
+        match block.field_at_index_mut(i).field {
+            TagFieldValue::MutableValue(mut v) => if let ValueReferenceMut::H1TagReference(reference) = v.get_value() {
+                if let Some(new_reference) = refactor.get_new_reference(reference) {
+                    *reference = new_reference;
+                    count += 1;
+                }
+            },
+            TagFieldValue::MutableBlock(b) => count += replace_references(b, refactor),
+            TagFieldValue::MutableArray(a) => for b in 0..a.len() {
+                count += replace_references(a.block_at_index_mut(b), refactor);
+            },
+            _ => ()
+        }
+    }
+    count
+}
+
+/// Replace references in the tag data, returning the new tag data and the number of references replaced.
+fn refactor_tag(tag: &TagFile, data: &[u8], refactor: &Refactor, moves: &[(TagFile, TagReference)]) -> ErrorMessageResult<(Vec<u8>, usize)> {
+    if tag.tag_path.get_group() != TagGroup::Scenario {
+        let mut tag_data = parse_tag_file(data)?.data;
+        let count = replace_references(tag_data.as_mut(), refactor);
+        return Ok((tag_data.into_tag_file()?, count))
+    }
+
+    // Scripts refer to tags by path, so these need to be updated, too. The references array contains the tags used by
+    // compiled scripts, and any tags that are moved are checked in case the scripts are not compiled.
+    let mut scenario = Scenario::from_tag_file(data)?.data;
+    let mut script_references: Vec<(TagReference, TagReference)> = Vec::new();
+    let used_in_scripts = scenario.references.blocks.iter().map(|r| &r.reference);
+    let moved = moves.iter().map(|(t, _)| &t.tag_path);
+    for reference in used_in_scripts.chain(moved) {
+        if let Some(new_reference) = refactor.get_new_reference(reference) {
+            if !script_references.iter().any(|(r, _)| r == reference) {
+                script_references.push((reference.clone(), new_reference));
+            }
+        }
+    }
+
+    let mut count = 0;
+    for (from, to) in &script_references {
+        count += scenario.replace_script_references(from, to)?;
+    }
+    count += replace_references(scenario.as_mut(), refactor);
+    Ok((scenario.into_tag_file()?, count))
+}
+
+pub fn refactor_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageResult<ExitCode> {
+    let parsed_args = ParsedArguments::parse_arguments(args,
+                                                       &[
+                                                       Argument { long: "move", short: 'M', description: get_compiled_string!("engine.h1.verbs.refactor.arguments.move.description"), parameter: None, multiple: false },
+                                                       Argument { long: "dry-run", short: 'n', description: get_compiled_string!("engine.h1.verbs.refactor.arguments.dry-run.description"), parameter: None, multiple: false }
+                                                       ],
+                                                       &[get_compiled_string!("arguments.specifier.from_tag_or_directory"), get_compiled_string!("arguments.specifier.to_tag_or_directory")],
+                                                       executable,
+                                                       verb.get_description(),
+                                                       ArgumentConstraints::new().needs_tags().multiple_tags_directories())?;
+
+    let tags_dirs = str_slice_to_path_vec(&parsed_args.named["tags"]);
+    let move_tags = parsed_args.named.get("move").is_some();
+    let dry_run = parsed_args.named.get("dry-run").is_some();
+    let (from, to) = (parsed_args.extra[0].as_str(), parsed_args.extra[1].as_str());
+
+    // If it has a tag group extension, it's a tag. Otherwise, it's a directory.
+    let refactor = match TagReference::from_full_path(from) {
+        Ok(from_reference) => {
+            let to_reference = TagReference::from_full_path(to)?;
+            if from_reference.get_group() != to_reference.get_group() {
+                return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.refactor.error_group_mismatch"), from=from_reference, to=to_reference)))
+            }
+            Refactor::Tag(from_reference, to_reference)
+        },
+        Err(_) => {
+            let normalize = |path: &str| TagReference::from_path_and_group(path, TagGroup::_None).map(|r| r.get_path_without_extension().trim_end_matches(HALO_DIRECTORY_SEPARATOR).to_owned());
+            let from_directory = normalize(from)?;
+            if from_directory.is_empty() {
+                return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.verbs.refactor.error_root_directory")))
+            }
+            Refactor::Directory(from_directory, normalize(to)?)
+        }
+    };
+
+    // Find everything we're moving, and make sure we aren't overwriting anything.
+    let mut all_tags: Vec<(&Path, Vec<TagFile>, Vec<(TagFile, TagReference)>)> = Vec::with_capacity(tags_dirs.len());
+    for dir in &tags_dirs {
+        let tags = TagFile::from_virtual_tags_directory(&[dir])?;
+        let mut moves = Vec::new();
+        for t in &tags {
+            if let Some(new_reference) = refactor.get_new_reference(&t.tag_path) {
+                if move_tags && tags.iter().any(|t| t.tag_path == new_reference) {
+                    return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.refactor.error_destination_exists"), from=t.tag_path, to=new_reference)))
+                }
+                moves.push((t.clone(), new_reference));
+            }
+        }
+        all_tags.push((dir, tags, moves));
+    }
+
+    if move_tags && all_tags.iter().all(|(_, _, moves)| moves.is_empty()) {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.refactor.error_nothing_to_move"), path=from)))
+    }
+
+    // Update references in every tags directory, since any of them can refer to the tags being moved.
+    let mut updated = 0usize;
+    let mut errors = 0usize;
+    for (_, tags, moves) in &all_tags {
+        for t in tags {
+            let (new_data, count) = match read_file(&t.file_path).and_then(|data| refactor_tag(t, &data, &refactor, moves)) {
+                Ok(n) => n,
+                Err(e) => {
+                    eprintln_error!(get_compiled_string!("engine.h1.verbs.refactor.error_updating_tag"), tag=t.tag_path, error=e);
+                    errors += 1;
+                    continue;
+                }
+            };
+            if count == 0 {
+                continue;
+            }
+
+            if dry_run {
+                println!(get_compiled_string!("engine.h1.verbs.refactor.would_update_tag"), tag=t.tag_path, count=count);
+            }
+            else {
+                write_file(&t.file_path, &new_data)?;
+                println_success!(get_compiled_string!("engine.h1.verbs.refactor.updated_tag"), tag=t.tag_path, count=count);
+            }
+            updated += 1;
+        }
+    }
+
+    // Lastly, move the tags.
+    if move_tags {
+        for (dir, _, moves) in &all_tags {
+            for (t, new_reference) in moves {
+                let new_path: PathBuf = dir.join(new_reference.get_relative_fs_path());
+                if dry_run {
+                    println!(get_compiled_string!("engine.h1.verbs.refactor.would_move_tag"), from=t.tag_path, to=new_reference);
+                    continue;
+                }
+
+                make_parent_directories(&new_path)?;
+                std::fs::rename(&t.file_path, &new_path).map_err(|e| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.refactor.error_moving_tag"), from=t.file_path.display(), to=new_path.display(), error=e)))?;
+                println_success!(get_compiled_string!("engine.h1.verbs.refactor.moved_tag"), from=t.tag_path, to=new_reference);
+            }
+        }
+    }
+
+    if errors > 0 {
+        eprintln_warn!(get_compiled_string!("engine.h1.verbs.refactor.updated_count_with_errors"), count=updated, errors=errors);
+        Ok(ExitCode::FAILURE)
+    }
+    else if dry_run {
+        println!(get_compiled_string!("engine.h1.verbs.refactor.would_update_count"), count=updated);
+        Ok(ExitCode::SUCCESS)
+    }
+    else {
+        println!(get_compiled_string!("engine.h1.verbs.refactor.updated_count"), count=updated);
+        Ok(ExitCode::SUCCESS)
+    }
+}
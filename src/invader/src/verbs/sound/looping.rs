@@ -0,0 +1,219 @@
+use std::num::NonZeroUsize;
+use std::process::ExitCode;
+use std::path::*;
+use macros::println_success;
+use ringhopper::engines::h1::definitions::{SoundLooping, SoundLoopingDetail, SoundLoopingTrack};
+use ringhopper::error::{ErrorMessageResult, ErrorMessage};
+use ringhopper::file::*;
+use ringhopper::types::{Bounds, TagBlockFn};
+use ringhopper::engines::h1::*;
+use ringhopper_proc::*;
+use crate::file::*;
+use crate::*;
+use super::{GeneratedSound, SoundOptions, SOUND_ARGUMENTS};
+
+/// Name of the directory in a sound_looping tag's data directory that contains the detail sounds.
+const DETAIL_DIRECTORY: &str = "detail";
+
+/// Prefix of the directories in a sound_looping tag's data directory that contain the tracks (e.g. `track0`).
+const TRACK_DIRECTORY_PREFIX: &str = "track";
+
+/// Names of the sound directories in a track's directory, in the order they are referenced by the track.
+const TRACK_SOUND_DIRECTORIES: [&str; 5] = ["in", "loop", "out", "alternate_loop", "alternate_out"];
+
+/// Maximum number of tracks in a sound_looping tag.
+const TRACK_LIMIT: usize = 4;
+
+/// Maximum number of detail sounds in a sound_looping tag.
+const DETAIL_SOUND_LIMIT: usize = 32;
+
+/// Length of the fades in seconds for new tracks without a start or end sound.
+const DEFAULT_FADE_DURATION: f32 = 1.0;
+
+/// Shortest time in seconds between new detail sounds playing.
+const MINIMUM_DETAIL_PERIOD: f64 = 5.0;
+
+pub fn sound_looping_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageResult<ExitCode> {
+    let parsed_args = ParsedArguments::parse_arguments(args, SOUND_ARGUMENTS, &[get_compiled_string!("arguments.specifier.tag_batch_without_group")], executable, verb.get_description(), ArgumentConstraints::new().needs_tags().needs_data().uses_threads())?;
+    let tag_path = &parsed_args.extra[0];
+    let mut options = super::parse_sound_options(&parsed_args)?;
+
+    // Only show which sound tags were saved rather than each of their permutations.
+    options.batched = true;
+
+    let result = crate::verbs::do_with_batching_threaded(do_single_sound_looping, tag_path, Some(TagGroup::SoundLooping), &str_slice_to_path_vec(&parsed_args.named["tags"]), parsed_args.threads, options)?;
+    Ok(result.exit_code())
+}
+
+/// Get the names and paths of the directories in a directory, sorted by name.
+fn get_directories(directory: &Path) -> ErrorMessageResult<Vec<(String, PathBuf)>> {
+    let iterating_error = |e| ErrorMessage::AllocatedString(format!(get_compiled_string!("file.error_iterating_directory"), path=directory.to_string_lossy(), error=e));
+
+    let mut directories = Vec::new();
+    for d in std::fs::read_dir(directory).map_err(iterating_error)? {
+        let path = d.map_err(iterating_error)?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let name = path.file_name()
+                       .and_then(|n| n.to_str())
+                       .ok_or_else(|| ErrorMessage::AllocatedString(format!(get_compiled_string!("file.error_non_utf8_path"), path=path.to_string_lossy())))?
+                       .to_owned();
+        directories.push((name, path));
+    }
+    directories.sort();
+    Ok(directories)
+}
+
+/// Make a detail sound that plays every few times its length.
+///
+/// Detail sounds that are louder than the loop of the first track are made as loud as it.
+fn new_detail_sound(sound: TagReference, generated: &GeneratedSound, loop_loudness: Option<f64>) -> SoundLoopingDetail {
+    let mut detail = SoundLoopingDetail::new_with_defaults();
+    detail.sound = sound;
+
+    let lower = (generated.longest_permutation * 2.0).max(MINIMUM_DETAIL_PERIOD);
+    detail.random_period_bounds = Bounds { lower: lower as f32, upper: (lower * 3.0) as f32 };
+
+    if let (Some(loop_loudness), Some(loudness)) = (loop_loudness, generated.loudness) {
+        detail.gain = 10.0f64.powf((loop_loudness - loudness) / 20.0).min(1.0) as f32;
+    }
+
+    detail
+}
+
+fn do_single_sound_looping(tag: &TagFile, log_mutex: crate::verbs::LogMutex, available_threads: NonZeroUsize, options: &SoundOptions) -> ErrorMessageResult<bool> {
+    let available_threads = available_threads.get();
+
+    let mut data = options.data_dir.join(tag.tag_path.get_relative_fs_path());
+    data.set_extension("");
+    if !data.is_dir() {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound.error_cannot_find_dir"), dir=data.to_string_lossy())))
+    }
+
+    let unexpected_directory = |dir: &Path| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound-looping.error_unexpected_directory"), dir=dir.to_string_lossy()));
+
+    // Find the tracks and detail sounds.
+    let mut track_dirs = Vec::new();
+    let mut detail_dirs = Vec::new();
+    for (name, path) in get_directories(&data)? {
+        if name.eq_ignore_ascii_case(DETAIL_DIRECTORY) {
+            detail_dirs = get_directories(&path)?.into_iter().map(|(detail_name, _)| [name.clone(), detail_name]).collect();
+        }
+        else if name.to_ascii_lowercase().starts_with(TRACK_DIRECTORY_PREFIX) {
+            // Check every track before generating anything.
+            let mut sound_dirs: [Option<String>; TRACK_SOUND_DIRECTORIES.len()] = Default::default();
+            for (sound_name, sound_path) in get_directories(&path)? {
+                let index = TRACK_SOUND_DIRECTORIES.iter().position(|n| n.eq_ignore_ascii_case(&sound_name)).ok_or_else(|| unexpected_directory(&sound_path))?;
+                sound_dirs[index] = Some(sound_name);
+            }
+            if sound_dirs[1].is_none() {
+                return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound-looping.error_no_loop"), dir=path.to_string_lossy())));
+            }
+            track_dirs.push((name, sound_dirs));
+        }
+        else {
+            return Err(unexpected_directory(&path));
+        }
+    }
+
+    if track_dirs.is_empty() {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound-looping.error_no_tracks"), dir=data.to_string_lossy())));
+    }
+    if track_dirs.len() > TRACK_LIMIT {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound-looping.error_too_many_tracks"), dir=data.to_string_lossy(), count=track_dirs.len(), limit=TRACK_LIMIT)));
+    }
+    if detail_dirs.len() > DETAIL_SOUND_LIMIT {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound-looping.error_too_many_detail_sounds"), dir=data.to_string_lossy(), count=detail_dirs.len(), limit=DETAIL_SOUND_LIMIT)));
+    }
+
+    // Sound tags are generated next to the sound_looping tag in a directory named after it, mirroring the data directory.
+    let tags_dir = tag.file_path.ancestors().nth(tag.tag_path.get_relative_fs_path().components().count()).unwrap_or(Path::new(""));
+    let generate = |relative_path: &[String]| -> ErrorMessageResult<(TagReference, GeneratedSound)> {
+        let path = std::iter::once(tag.tag_path.get_path_without_extension()).chain(relative_path.iter().map(|p| p.as_str())).collect::<Vec<&str>>().join("\\");
+        let tag_path = TagReference::from_path_and_group(&path, TagGroup::Sound)?;
+        let sound_tag = TagFile { file_path: tags_dir.join(tag_path.get_relative_fs_path()), tag_path };
+        let sound_data = relative_path.iter().fold(data.clone(), |d, p| d.join(p));
+
+        let generated = super::generate_sound(&sound_tag, &sound_data, &log_mutex, available_threads, options)
+                              .map_err(|e| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound-looping.error_cannot_generate_sound"), tag=sound_tag.tag_path, error=e)))?;
+        Ok((sound_tag.tag_path, generated))
+    };
+
+    // Load the tag if it exists so its settings are kept.
+    let mut sound_looping = if tag.file_path.is_file() {
+        *SoundLooping::from_tag_file(&read_file(&tag.file_path)?)?.data
+    }
+    else {
+        SoundLooping::new_with_defaults()
+    };
+
+    // Generate the tracks.
+    let mut tracks = Vec::with_capacity(track_dirs.len());
+    let mut loop_loudness = None;
+    for (ti, (track_name, sound_dirs)) in track_dirs.iter().enumerate() {
+        let mut sounds: [Option<TagReference>; TRACK_SOUND_DIRECTORIES.len()] = Default::default();
+        for (index, name) in sound_dirs.iter().enumerate() {
+            let name = match name {
+                Some(n) => n,
+                None => continue
+            };
+            let (reference, generated) = generate(&[track_name.to_owned(), name.to_owned()])?;
+
+            // Detail sounds are compared with the loop of the first track.
+            if ti == 0 && index == 1 {
+                loop_loudness = generated.loudness;
+            }
+            sounds[index] = Some(reference);
+        }
+        let [start, _loop, end, alternate_loop, alternate_end] = sounds;
+
+        // New tracks fade in and out if they have no start and end sounds.
+        let mut track = match sound_looping.tracks.blocks.get(ti) {
+            Some(n) => n.clone(),
+            None => {
+                let mut track = SoundLoopingTrack::new_with_defaults();
+                if start.is_none() {
+                    track.flags.fade_in_at_start = true;
+                    track.fade_in_duration = DEFAULT_FADE_DURATION;
+                }
+                if end.is_none() {
+                    track.flags.fade_out_at_stop = true;
+                    track.fade_out_duration = DEFAULT_FADE_DURATION;
+                }
+                track
+            }
+        };
+
+        for (field, reference) in [(&mut track.start, start), (&mut track._loop, _loop), (&mut track.end, end), (&mut track.alternate_loop, alternate_loop), (&mut track.alternate_end, alternate_end)] {
+            match reference {
+                Some(n) => *field = n,
+                None => field.set_path_without_extension("")?
+            }
+        }
+        tracks.push(track);
+    }
+
+    // Generate the detail sounds.
+    let mut detail_sounds = Vec::with_capacity(detail_dirs.len());
+    for relative_path in &detail_dirs {
+        let (reference, generated) = generate(relative_path)?;
+        let detail = match sound_looping.detail_sounds.blocks.iter().find(|d| d.sound == reference) {
+            Some(n) => n.clone(),
+            None => new_detail_sound(reference, &generated, loop_loudness)
+        };
+        detail_sounds.push(detail);
+    }
+
+    sound_looping.tracks.blocks = tracks;
+    sound_looping.detail_sounds.blocks = detail_sounds;
+
+    make_parent_directories(&tag.file_path)?;
+    write_file(&tag.file_path, &sound_looping.into_tag_file()?)?;
+
+    let l = log_mutex.lock().unwrap();
+    println_success!(get_compiled_string!("engine.h1.verbs.unicode-strings.saved_file"), file=tag.tag_path);
+    drop(l);
+
+    Ok(true)
+}
@@ -7,14 +7,23 @@ use ringhopper::engines::h1::definitions::{SoundClass, SoundFormat, Sound, Sound
 use ringhopper::error::{ErrorMessageResult, ErrorMessage};
 use ringhopper::file::*;
 use ringhopper::types::*;
+use ringhopper::sound::{MouthDataAnalysis, MouthDataOptions};
 use ringhopper::engines::h1::*;
 use ringhopper_proc::*;
 use vorbis_rs::VorbisBitrateManagementStrategy;
 use crate::file::*;
 use crate::*;
 
+mod looping;
+#[cfg(feature = "opus")]
+mod opus;
 mod util;
 
+pub use self::looping::sound_looping_verb;
+
+/// Name of the file in a pitch range's directory that sets the format of its permutations (e.g. `xbox-adpcm`).
+pub const PITCH_RANGE_FORMAT_FILE_NAME: &str = "format";
+
 #[derive(Clone)]
 struct SoundOptions {
     batched: bool,
@@ -25,23 +34,45 @@ struct SoundOptions {
     adpcm_block_size: Option<bool>,
     compression_level: VorbisBitrateManagementStrategy,
     class: Option<SoundClass>,
-    format: Option<SoundFormat>
+    format: Option<SoundFormat>,
+    normalize: Option<f32>,
+    normalize_scope: util::NormalizeScope,
+    normalize_gain: util::NormalizeGain,
+    mouth_data: MouthDataOptions,
+    report: bool
 }
 
-pub fn sound_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageResult<ExitCode> {
-    let parsed_args = ParsedArguments::parse_arguments(args, &[
-        Argument { long: "channel-count", short: 'C', description: get_compiled_string!("engine.h1.verbs.sound.arguments.channel-count.description"), parameter: Some("mono/stereo"), multiple: false },
-        Argument { long: "class", short: 'c', description: get_compiled_string!("engine.h1.verbs.sound.arguments.class.description"), parameter: Some("class"), multiple: false },
-        Argument { long: "compression-level", short: 'L', description: get_compiled_string!("engine.h1.verbs.sound.arguments.compression-level.description"), parameter: Some("level"), multiple: false },
-        Argument { long: "format", short: 'f', description: get_compiled_string!("engine.h1.verbs.sound.arguments.format.description"), parameter: Some("format"), multiple: false },
-        Argument { long: "sample-rate", short: 'R', description: get_compiled_string!("engine.h1.verbs.sound.arguments.sample-rate.description"), parameter: Some("Hz"), multiple: false },
-        Argument { long: "split", short: 'S', description: get_compiled_string!("engine.h1.verbs.sound.arguments.split.description"), parameter: Some("on/off"), multiple: false },
-        Argument { long: "fit-to-adpcm-block-size", short: 'A', description: get_compiled_string!("engine.h1.verbs.sound.arguments.fit-to-adpcm-block-size.description"), parameter: Some("on/off"), multiple: false },
-    ], &[get_compiled_string!("arguments.specifier.tag_batch_without_group")], executable, verb.get_description(), ArgumentConstraints::new().needs_tags().needs_data().uses_threads())?;
+/// Arguments shared by the sound and sound-looping verbs.
+const SOUND_ARGUMENTS: &[Argument] = &[
+    Argument { long: "channel-count", short: 'C', description: get_compiled_string!("engine.h1.verbs.sound.arguments.channel-count.description"), parameter: Some("mono/stereo"), multiple: false },
+    Argument { long: "class", short: 'c', description: get_compiled_string!("engine.h1.verbs.sound.arguments.class.description"), parameter: Some("class"), multiple: false },
+    Argument { long: "compression-level", short: 'L', description: get_compiled_string!("engine.h1.verbs.sound.arguments.compression-level.description"), parameter: Some("level"), multiple: false },
+    Argument { long: "format", short: 'f', description: get_compiled_string!("engine.h1.verbs.sound.arguments.format.description"), parameter: Some("format"), multiple: false },
+    Argument { long: "sample-rate", short: 'R', description: get_compiled_string!("engine.h1.verbs.sound.arguments.sample-rate.description"), parameter: Some("Hz"), multiple: false },
+    Argument { long: "split", short: 'S', description: get_compiled_string!("engine.h1.verbs.sound.arguments.split.description"), parameter: Some("on/off"), multiple: false },
+    Argument { long: "fit-to-adpcm-block-size", short: 'A', description: get_compiled_string!("engine.h1.verbs.sound.arguments.fit-to-adpcm-block-size.description"), parameter: Some("on/off"), multiple: false },
+    Argument { long: "normalize", short: 'N', description: get_compiled_string!("engine.h1.verbs.sound.arguments.normalize.description"), parameter: Some("LUFS"), multiple: false },
+    Argument { long: "normalize-scope", short: 'n', description: get_compiled_string!("engine.h1.verbs.sound.arguments.normalize-scope.description"), parameter: Some("scope"), multiple: false },
+    Argument { long: "normalize-gain", short: 'g', description: get_compiled_string!("engine.h1.verbs.sound.arguments.normalize-gain.description"), parameter: Some("gain"), multiple: false },
+    Argument { long: "mouth-data", short: 'M', description: get_compiled_string!("engine.h1.verbs.sound.arguments.mouth-data.description"), parameter: Some("analysis"), multiple: false },
+    Argument { long: "mouth-smoothing", short: 's', description: get_compiled_string!("engine.h1.verbs.sound.arguments.mouth-smoothing.description"), parameter: Some("amount"), multiple: false }
+];
 
+pub fn sound_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageResult<ExitCode> {
+    let mut arguments = SOUND_ARGUMENTS.to_vec();
+    arguments.push(Argument { long: "report", short: 'r', description: get_compiled_string!("engine.h1.verbs.sound.arguments.report.description"), parameter: None, multiple: false });
+    let parsed_args = ParsedArguments::parse_arguments(args, &arguments, &[get_compiled_string!("arguments.specifier.tag_batch_without_group")], executable, verb.get_description(), ArgumentConstraints::new().needs_tags().needs_data().uses_threads())?;
     let tag_path = &parsed_args.extra[0];
-    let options = SoundOptions {
-        batched: TagFile::uses_batching(tag_path),
+    let options = parse_sound_options(&parsed_args)?;
+
+    let result = super::do_with_batching_threaded(do_single_sound, &tag_path, Some(TagGroup::Sound), &str_slice_to_path_vec(&parsed_args.named["tags"]), parsed_args.threads, options)?;
+    Ok(result.exit_code())
+}
+
+/// Parse the arguments in [`SOUND_ARGUMENTS`] into options for generating sound tags.
+fn parse_sound_options(parsed_args: &ParsedArguments) -> ErrorMessageResult<SoundOptions> {
+    Ok(SoundOptions {
+        batched: TagFile::uses_batching(&parsed_args.extra[0]),
         data_dir: Path::new(&parsed_args.named["data"][0]).to_owned(),
         split: parsed_args.parse_bool_on_off("split")?,
         compression_level: match parsed_args.named.get("compression-level") {
@@ -74,17 +105,57 @@ pub fn sound_verb(verb: &Verb, args: &[&str], executable: &str) -> ErrorMessageR
         format: parsed_args.parse_enum("format")?,
         channel_count: parsed_args.parse_set("channel-count", &[("stereo", Some(2)), ("mono", Some(1)), ("auto", None)])?,
         sample_rate: parsed_args.parse_set("sample-rate", &[("22050", Some(22050)), ("44100", Some(44100)), ("auto", None)])?,
-        adpcm_block_size: parsed_args.parse_bool_on_off("fit-to-adpcm-block-size")?
-    };
-
-    let result = super::do_with_batching_threaded(do_single_sound, &tag_path, Some(TagGroup::Sound), &str_slice_to_path_vec(&parsed_args.named["tags"]), parsed_args.threads, options)?;
-    Ok(result.exit_code())
+        adpcm_block_size: parsed_args.parse_bool_on_off("fit-to-adpcm-block-size")?,
+        normalize: match parsed_args.parse_f32("normalize")? {
+            Some(n) if !(-70.0..=0.0).contains(&n) => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound.error_bad_normalize_loudness"), value=n))),
+            n => n
+        },
+        normalize_scope: parsed_args.parse_set("normalize-scope", &[("permutation", util::NormalizeScope::Permutation), ("sound", util::NormalizeScope::Sound)])?.unwrap_or(util::NormalizeScope::Permutation),
+        normalize_gain: parsed_args.parse_set("normalize-gain", &[("samples", util::NormalizeGain::Samples), ("tag", util::NormalizeGain::Tag)])?.unwrap_or(util::NormalizeGain::Samples),
+        mouth_data: MouthDataOptions {
+            analysis: parsed_args.parse_set("mouth-data", &[("amplitude", MouthDataAnalysis::Amplitude), ("speech", MouthDataAnalysis::Speech)])?.unwrap_or_default(),
+            smoothing: match parsed_args.parse_f32("mouth-smoothing")? {
+                Some(n) if !(0.0..1.0).contains(&n) => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound.error_bad_mouth_smoothing"), value=n))),
+                Some(n) => n as f64,
+                None => MouthDataOptions::default().smoothing
+            }
+        },
+        report: parsed_args.named.contains_key("report")
+    })
 }
 
 fn do_single_sound(tag: &TagFile, log_mutex: super::LogMutex, available_threads: NonZeroUsize, options: &SoundOptions) -> ErrorMessageResult<bool> {
+    let data = options.data_dir.join(tag.tag_path.get_path_without_extension());
+    if !data.is_dir() {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound.error_cannot_find_dir"), dir=data.to_string_lossy())))
+    }
+
+    // Only print the levels if we just want a report
+    if options.report {
+        let pitch_ranges = util::load_data_dir(&data)?;
+        let l = log_mutex.lock().unwrap();
+        util::print_levels(&pitch_ranges, &tag.tag_path);
+        drop(l);
+        return Ok(true);
+    }
+
+    generate_sound(tag, &data, &log_mutex, available_threads.get(), options)?;
+    Ok(true)
+}
+
+/// Length and loudness of a generated sound tag.
+struct GeneratedSound {
+    /// Length of the longest permutation in seconds.
+    longest_permutation: f64,
+
+    /// Integrated loudness of all permutations with their gain applied in LUFS, or [`None`] if it is too quiet to measure.
+    loudness: Option<f64>
+}
+
+/// Generate a sound tag from a data directory.
+fn generate_sound(tag: &TagFile, data: &Path, log_mutex: &super::LogMutex, available_threads: usize, options: &SoundOptions) -> ErrorMessageResult<GeneratedSound> {
     let default_channel_count: Option<usize>;
     let default_sample_rate: Option<u32>;
-    let available_threads = available_threads.get();
 
     // Load our sounds
     let mut sound_tag = if tag.file_path.is_file() {
@@ -99,7 +170,7 @@ fn do_single_sound(tag: &TagFile, log_mutex: super::LogMutex, available_threads:
         }
         default_channel_count = options.channel_count.unwrap_or(None);
         default_sample_rate = options.sample_rate.unwrap_or(None);
-        Sound::default()
+        Sound::new_with_defaults()
     };
 
     sound_tag.flags.split_long_sound_into_permutations = options.split.unwrap_or(sound_tag.flags.split_long_sound_into_permutations);
@@ -107,12 +178,7 @@ fn do_single_sound(tag: &TagFile, log_mutex: super::LogMutex, available_threads:
     sound_tag.format = options.format.unwrap_or(sound_tag.format);
     sound_tag.sound_class = options.class.unwrap_or(sound_tag.sound_class);
 
-    let data = options.data_dir.join(tag.tag_path.get_path_without_extension());
-    if !data.is_dir() {
-        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound.error_cannot_find_dir"), dir=data.to_string_lossy())))
-    }
-
-    let mut pitch_ranges = util::load_data_dir(&data)?;
+    let mut pitch_ranges = util::load_data_dir(data)?;
 
     // Determine our sample/channel count
     let (highest_sample_rate, highest_channel_count) = util::get_best_sample_rate_and_channel_count(&pitch_ranges)?;
@@ -131,6 +197,11 @@ fn do_single_sound(tag: &TagFile, log_mutex: super::LogMutex, available_threads:
                         if pe.name == pe_tag.name.to_str() {
                             pe.gain = pe_tag.gain;
                             pe.skip_fraction = pe_tag.skip_fraction;
+
+                            // Keep the subtitles if there is no subtitle file.
+                            if pe.subtitle_data.is_empty() {
+                                pe.subtitle_data = pe_tag.subtitle_data.clone();
+                            }
                             break;
                         }
                     }
@@ -140,6 +211,11 @@ fn do_single_sound(tag: &TagFile, log_mutex: super::LogMutex, available_threads:
         }
     }
 
+    // Normalize loudness
+    if let Some(target) = options.normalize {
+        util::normalize(&mut pitch_ranges, target as f64, options.normalize_scope, options.normalize_gain);
+    }
+
     let generates_mouth_data = match sound_tag.sound_class {
         SoundClass::UnitDialog | SoundClass::ScriptedDialogPlayer | SoundClass::ScriptedDialogOther | SoundClass::ScriptedDialogForceUnspatialized => true,
         _ => false
@@ -147,6 +223,9 @@ fn do_single_sound(tag: &TagFile, log_mutex: super::LogMutex, available_threads:
 
     let pitch_range_count = pitch_ranges.len();
 
+    // Pitch ranges can override the format of the sound tag.
+    let pitch_range_formats: Vec<SoundFormat> = pitch_ranges.iter().map(|p| p.format.unwrap_or(sound_tag.format)).collect();
+
     // Have an array of indices indicating the next permutation to process.
     //
     // If size == permutation count for the pitch range, move to the next one.
@@ -160,16 +239,18 @@ fn do_single_sound(tag: &TagFile, log_mutex: super::LogMutex, available_threads:
         let pr_array = pitch_ranges.clone();
         let best_sample_rate = best_sample_rate.clone();
         let best_channel_count = best_channel_count.clone();
-        let sound_tag_format = sound_tag.format.clone();
+        let pitch_range_formats = pitch_range_formats.clone();
         let split = split.clone();
         let fit_to_adpcm_blocksize = sound_tag.flags.fit_to_adpcm_blocksize.clone();
         let compression_level = options.compression_level.clone();
+        let mouth_data_options = options.mouth_data;
         let count_array = permutation_count_per_pitch_range.clone();
         let available_threads = available_threads;
 
         threads.push(std::thread::spawn(move || -> ErrorMessageResult<()> {
             for pri in 0..pitch_range_count {
                 let permutation_count = count_array[pri];
+                let format = pitch_range_formats[pri];
                 loop {
                     // Get the next permutation we can process
                     let mut nptp = nptp_array.lock().unwrap();
@@ -203,7 +284,7 @@ fn do_single_sound(tag: &TagFile, log_mutex: super::LogMutex, available_threads:
                     }
 
                     // Fit to Xbox ADPCM block size
-                    if sound_tag_format == SoundFormat::XboxAdpcm && fit_to_adpcm_blocksize {
+                    if format == SoundFormat::XboxAdpcm && fit_to_adpcm_blocksize {
                         let alignment = 64 * best_channel_count;
                         let disparity = pe.samples.len() % alignment;
                         if disparity != 0 {
@@ -221,11 +302,11 @@ fn do_single_sound(tag: &TagFile, log_mutex: super::LogMutex, available_threads:
 
                     // Generate mouth data
                     if generates_mouth_data {
-                        pe.generate_mouth_data()
+                        pe.generate_mouth_data(&mouth_data_options)
                     }
 
                     // Encode
-                    pe.encode(sound_tag_format, split, compression_level, available_threads)?;
+                    pe.encode(format, split, compression_level, available_threads)?;
 
                     // Move it back
                     let mut pra = pr_array.lock().unwrap();
@@ -247,8 +328,8 @@ fn do_single_sound(tag: &TagFile, log_mutex: super::LogMutex, available_threads:
     sound_tag.sample_rate = match best_sample_rate { 44100 => SoundSampleRate::_44100Hz, 22050 => SoundSampleRate::_22050Hz, _ => unreachable!() };
     sound_tag.channel_count = match best_channel_count { 1 => SoundChannelCount::Mono, 2 => SoundChannelCount::Stereo, _ => unreachable!() };
 
-    for pr in &mut *pitch_ranges {
-        let mut pitch_range = SoundPitchRange::default();
+    for (pr, format) in pitch_ranges.iter_mut().zip(pitch_range_formats) {
+        let mut pitch_range = SoundPitchRange::new_with_defaults();
         pitch_range.natural_pitch = pr.natural_pitch;
         pitch_range.bend_bounds = pr.pitch_bounds;
         pitch_range.name = String32::from_str(&pr.name)?;
@@ -285,18 +366,20 @@ fn do_single_sound(tag: &TagFile, log_mutex: super::LogMutex, available_threads:
         pitch_range.actual_permutation_count = pr.permutations.len() as u16;
 
         let make_permutation = |pe: &util::Sound| -> ErrorMessageResult<SoundPermutation> {
-            let mut permutation = SoundPermutation::default();
+            let mut permutation = SoundPermutation::new_with_defaults();
             permutation.name = String32::from_str(&pe.name)?;
             permutation.gain = pe.gain;
             permutation.skip_fraction = pe.skip_fraction;
-            permutation.format = sound_tag.format;
+            permutation.format = format;
             Ok(permutation)
         };
 
         if split {
             // First initialize our permutations.
             for i in 0..pr.permutations.len() {
-                pitch_range.permutations.blocks.push(make_permutation(&pr.permutations[i])?);
+                let mut permutation = make_permutation(&pr.permutations[i])?;
+                permutation.subtitle_data = pr.permutations[i].subtitle_data.clone();
+                pitch_range.permutations.blocks.push(permutation);
             }
 
             // Next initialize our sub-permutations and write our samples.
@@ -337,6 +420,7 @@ fn do_single_sound(tag: &TagFile, log_mutex: super::LogMutex, available_threads:
                 permutation.samples.append(samples);
                 permutation.buffer_size = *buffer_size as u32;
                 permutation.mouth_data.append(&mut pe.mouth_data);
+                permutation.subtitle_data.append(&mut pe.subtitle_data);
                 pitch_range.permutations.blocks.push(permutation);
             }
         }
@@ -411,6 +495,9 @@ fn do_single_sound(tag: &TagFile, log_mutex: super::LogMutex, available_threads:
     println_success!(get_compiled_string!("engine.h1.verbs.unicode-strings.saved_file"), file=tag.tag_path);
     drop(l);
 
-    Ok(true)
+    Ok(GeneratedSound {
+        longest_permutation: pitch_ranges.iter().flat_map(|p| p.permutations.iter()).map(|p| p.samples.len() as f64 / (p.channels * p.sample_rate as usize) as f64).fold(0.0, f64::max),
+        loudness: util::measure_loudness(&pitch_ranges)
+    })
 }
 
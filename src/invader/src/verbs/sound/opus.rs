@@ -0,0 +1,83 @@
+use std::path::Path;
+use audiopus::{Channels, MutSignals, SampleRate};
+use audiopus::coder::Decoder;
+use audiopus::packet::Packet;
+use ringhopper::error::*;
+use ringhopper_proc::*;
+
+/// Sample rate that Opus streams are decoded at.
+pub const OPUS_SAMPLE_RATE: u32 = 48000;
+
+/// Maximum number of samples per channel in an Opus packet (120 ms).
+const OPUS_MAX_PACKET_SAMPLES: usize = 5760;
+
+/// Decode an Ogg Opus file into interleaved samples, returning the samples and channel count.
+///
+/// Only the first logical stream is decoded. The pre-skip and output gain in the header are applied, and the end of the
+/// stream is trimmed to its final granule position.
+pub fn decode_ogg_opus(data: Vec<u8>, path: &Path) -> ErrorMessageResult<(Vec<i16>, usize)> {
+    let cannot_decode = |e: &dyn std::fmt::Display| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound.error_cannot_decode"), file=path.to_string_lossy(), e=e.to_string()));
+    let bad_header = || ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound.error_opus_bad_header"), file=path.to_string_lossy()));
+
+    let mut reader = ogg::PacketReader::new(std::io::Cursor::new(data));
+    let mut next_packet = || reader.read_packet().map_err(|e| cannot_decode(&e));
+
+    // The identification header is "OpusHead", version, channel count, pre-skip, input sample rate, output gain, and
+    // channel mapping family, all in little endian.
+    let header = next_packet()?.ok_or_else(bad_header)?;
+    let serial = header.stream_serial();
+    let header = header.data;
+    if header.len() < 19 || &header[0..8] != b"OpusHead" || header[8] >> 4 != 0 {
+        return Err(bad_header());
+    }
+    let channels = header[9] as usize;
+    let pre_skip = u16::from_le_bytes([header[10], header[11]]) as usize;
+    let output_gain = i16::from_le_bytes([header[16], header[17]]);
+    let mapping_family = header[18];
+
+    // Only mono and stereo streams without a channel mapping table can be decoded.
+    let opus_channels = match (channels, mapping_family) {
+        (1, 0) => Channels::Mono,
+        (2, 0) => Channels::Stereo,
+        _ => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound.error_bad_channel_count"), file=path.to_string_lossy(), channels=channels)))
+    };
+
+    let mut decoder = Decoder::new(SampleRate::Hz48000, opus_channels).map_err(|e| cannot_decode(&e))?;
+    decoder.set_gain(output_gain as i32).map_err(|e| cannot_decode(&e))?;
+
+    let mut samples = Vec::new();
+    let mut buffer = vec![0i16; OPUS_MAX_PACKET_SAMPLES * channels];
+    let mut final_granule_position = None;
+    let mut packet_index = 0usize;
+    while let Some(packet) = next_packet()? {
+        if packet.stream_serial() != serial {
+            continue;
+        }
+
+        // The comment header comes after the identification header.
+        packet_index += 1;
+        if packet_index == 1 {
+            continue;
+        }
+
+        let input = Packet::try_from(packet.data.as_slice()).map_err(|e| cannot_decode(&e))?;
+        let output = MutSignals::try_from(buffer.as_mut_slice()).map_err(|e| cannot_decode(&e))?;
+        let decoded = decoder.decode(Some(input), output, false).map_err(|e| cannot_decode(&e))?;
+        samples.extend_from_slice(&buffer[..decoded * channels]);
+
+        if packet.last_in_page() {
+            final_granule_position = Some(packet.absgp_page() as usize);
+        }
+        if packet.last_in_stream() {
+            break;
+        }
+    }
+
+    // Granule positions include the pre-skip.
+    if let Some(end) = final_granule_position {
+        samples.truncate(end.saturating_mul(channels));
+    }
+    samples.drain(..(pre_skip * channels).min(samples.len()));
+
+    Ok((samples, channels))
+}
@@ -1,4 +1,5 @@
 use ringhopper::{error::ErrorMessageResult, types::Bounds, engines::h1::definitions::SoundFormat};
+use ringhopper::sound::{LoudnessMeter, MouthDataOptions, encode_subtitle_data, measure_levels, parse_srt};
 use libsamplerate_sys::*;
 
 use vorbis_rs::*;
@@ -68,6 +69,9 @@ pub struct Sound {
     /// Mouth data
     pub mouth_data: Vec<u8>,
 
+    /// Subtitle data to set in tag data
+    pub subtitle_data: Vec<u8>,
+
     /// Encoded samples and their required buffer sizes.
     pub encoded_samples: Vec<(Vec<u8>, usize)>,
 
@@ -105,13 +109,29 @@ impl Sound {
                             .ok_or_else(|| ErrorMessage::AllocatedString(format!(get_compiled_string!("file.error_non_utf8_path"),path=path.to_string_lossy())))?
                             .to_owned();
 
+        let extension = path.extension().unwrap().to_str().unwrap().to_ascii_lowercase();
+
+        // Opus isn't supported by symphonia, so it is decoded with libopus instead if it is enabled.
+        #[cfg(not(feature = "opus"))]
+        if extension == "opus" {
+            return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound.error_opus_not_enabled"), file=path.to_string_lossy())));
+        }
+        #[cfg(feature = "opus")]
+        if extension == "opus" {
+            let (samples, channels) = super::opus::decode_ogg_opus(read_file(&path)?, &path)?;
+            if samples.is_empty() {
+                return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound.error_cannot_decode_no_samples"), file=path.to_string_lossy())));
+            }
+            let sample_rate = super::opus::OPUS_SAMPLE_RATE;
+            return Ok(Sound { name, sample_rate, channels, samples, path, gain: 0.0, skip_fraction: 0.0, mouth_data: Vec::new(), subtitle_data: Vec::new(), original_channels: channels, original_sample_rate: sample_rate, original_bits_per_sample: BitsPerSample::Unknown, original_codec: "opus", encoded_samples: Vec::new() });
+        }
+
         let codecs = symphonia::default::get_codecs();
         let probe = symphonia::default::get_probe();
         let cursor = std::io::Cursor::new(read_file(&path)?);
         let stream = MediaSourceStream::new(Box::new(cursor), MediaSourceStreamOptions::default());
 
         let mut hint = Hint::new();
-        let extension = path.extension().unwrap().to_str().unwrap().to_ascii_lowercase();
         hint.with_extension(&extension);
 
         let mut r = probe.format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
@@ -179,7 +199,7 @@ impl Sound {
             return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound.error_cannot_decode_no_samples"), file=path.to_string_lossy())));
         }
 
-        Ok(Sound { name, sample_rate, channels, samples, path, gain: 0.0, skip_fraction: 0.0, mouth_data: Vec::new(), original_channels: channels, original_sample_rate: sample_rate, original_bits_per_sample: bps, original_codec, encoded_samples: Vec::new() })
+        Ok(Sound { name, sample_rate, channels, samples, path, gain: 0.0, skip_fraction: 0.0, mouth_data: Vec::new(), subtitle_data: Vec::new(), original_channels: channels, original_sample_rate: sample_rate, original_bits_per_sample: bps, original_codec, encoded_samples: Vec::new() })
     }
 
     /// Encode the sound to the given format.
@@ -254,47 +274,40 @@ impl Sound {
     }
 
     /// Generate mouth data.
-    pub fn generate_mouth_data(&mut self) {
-        // Basically, take the sample rate, multiply by channel count, divide by tick rate (30 Hz), and round the result
-        let samples_per_tick = ((self.sample_rate as usize * self.channels as usize) as f64 / 30.0).round() as usize;
-        let sample_count = self.samples.len();
-
-        // Generate samples, adding an extra tick for incomplete ticks
-        let tick_count = (sample_count + samples_per_tick - 1) / samples_per_tick;
-        let mut mouth_data_floats = Vec::with_capacity(tick_count);
-        let mut max: f32 = 0.0;
-        let mut min: f32 = 1.0;
-        let mut total: f32 = 0.0;
-        for i in (0..self.samples.len()).step_by(samples_per_tick) {
-            let tick_start = i;
-            let tick_end = i.saturating_add(samples_per_tick).min(self.samples.len());
-            let mut tick_total = 0.0;
-            for i in tick_start..tick_end {
-                let s = convert_pcm_16_to_f32(self.samples[i]).abs();
-                tick_total += s;
-            }
+    pub fn generate_mouth_data(&mut self, options: &MouthDataOptions) {
+        self.mouth_data = ringhopper::sound::generate_mouth_data(&self.samples, self.channels, self.sample_rate, options);
+    }
 
-            let s = tick_total / ((tick_end - tick_start) as f32);
-            min = min.min(s);
-            max = max.max(s);
-            total += s;
-            mouth_data_floats.push(s);
-        }
+    /// Load subtitles from a text or SRT file with the same name as the sound file, if one exists.
+    fn load_subtitles(&mut self) -> ErrorMessageResult<()> {
+        let txt_path = self.path.with_extension("txt");
+        let srt_path = self.path.with_extension("srt");
+        let path = match (txt_path.is_file(), srt_path.is_file()) {
+            (false, false) => return Ok(()),
+            (true, true) => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound.error_ambiguous_subtitles"), permutation=self.name, txt=txt_path.to_string_lossy(), srt=srt_path.to_string_lossy()))),
+            (true, false) => txt_path,
+            (false, true) => srt_path
+        };
 
-        let max = max;
-        let min = min;
-        let average = total / (mouth_data_floats.len() as f32);
-        let range = (max + average) / 2.0 - min;
+        let bad_subtitles = |e: ErrorMessage| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound.error_bad_subtitles"), file=path.to_string_lossy(), error=e));
+        let contents = read_file(&path)?;
+        let contents = std::str::from_utf8(&contents).map_err(|_| bad_subtitles(ErrorMessage::StaticString(get_compiled_string!("engine.types.error_string_not_valid_utf8"))))?;
 
-        // Do nothing if there's no range
-        if range == 0.0 {
-            self.mouth_data = mouth_data_floats.into_iter().map(|f| (f * (u8::MAX as f32) + 0.5) as u8).collect();
+        let text = if path.extension().is_some_and(|e| e == "srt") {
+            // Only the text can be stored, but cues that start after the sound ends are likely from the wrong file.
+            let length = self.samples.len() as f64 / (self.channels * self.sample_rate as usize) as f64;
+            let cues = parse_srt(contents).map_err(bad_subtitles)?;
+            if let Some(cue) = cues.iter().find(|c| c.start > length) {
+                return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound.error_subtitle_after_end"), file=path.to_string_lossy(), start=cue.start, length=length)));
+            }
+            cues.into_iter().map(|c| c.text).collect::<Vec<String>>().join("\n")
         }
-
-        // Otherwise write it
         else {
-            self.mouth_data = mouth_data_floats.into_iter().map(|f| (((f - min) / range).clamp(0.0, 1.0) * (u8::MAX as f32) + 0.5) as u8).collect();
-        }
+            contents.to_owned()
+        };
+
+        self.subtitle_data = encode_subtitle_data(&text).map_err(bad_subtitles)?;
+        Ok(())
     }
 }
 
@@ -314,6 +327,9 @@ pub struct PitchRange {
 
     /// Default pitch bounds to set in the tag.
     pub pitch_bounds: Bounds<f32>,
+
+    /// Format of the permutations if set by the pitch range's format file, overriding the format of the sound tag.
+    pub format: Option<SoundFormat>,
 }
 
 impl PitchRange {
@@ -326,7 +342,7 @@ impl PitchRange {
             else if let Some(n) = f.extension() {
                 match n.to_str() {
                     None => false,
-                    Some(n) => n.eq_ignore_ascii_case("wav") || n.eq_ignore_ascii_case("flac")
+                    Some(n) => n.eq_ignore_ascii_case("wav") || n.eq_ignore_ascii_case("flac") || n.eq_ignore_ascii_case("opus")
                 }
             }
             else {
@@ -340,12 +356,135 @@ impl PitchRange {
         // Add all permutations
         let mut permutations = Vec::with_capacity(input_files.len());
         for i in input_files {
-            permutations.push(Sound::new(i)?);
+            let mut sound = Sound::new(i)?;
+            sound.load_subtitles()?;
+            permutations.push(sound);
         }
-        Ok(PitchRange { name, path, permutations, natural_pitch: f32::default(), pitch_bounds: Bounds::default() })
+        // Read the format file if present.
+        let format_path = path.join(super::PITCH_RANGE_FORMAT_FILE_NAME);
+        let format = if format_path.is_file() {
+            let bad_format_file = |e: ErrorMessage| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.verbs.sound.error_bad_format_file"), file=format_path.to_string_lossy(), error=e));
+            let contents = read_file(&format_path)?;
+            let contents = std::str::from_utf8(&contents).map_err(|_| bad_format_file(ErrorMessage::StaticString(get_compiled_string!("engine.types.error_string_not_valid_utf8"))))?;
+            Some(crate::from_str(contents.trim()).map_err(bad_format_file)?)
+        }
+        else {
+            None
+        };
+
+        Ok(PitchRange { name, path, permutations, natural_pitch: f32::default(), pitch_bounds: Bounds::default(), format })
     }
 }
 
+/// Which permutations are measured together when normalizing loudness.
+#[derive(Copy, Clone, PartialEq)]
+pub enum NormalizeScope {
+    /// Each permutation is normalized on its own.
+    Permutation,
+
+    /// All permutations are normalized together, keeping the differences in loudness between them.
+    Sound
+}
+
+/// What normalizing loudness changes.
+#[derive(Copy, Clone, PartialEq)]
+pub enum NormalizeGain {
+    /// Amplify the samples, limited so that they do not clip.
+    Samples,
+
+    /// Set the gain of each permutation in the tag, which can only make it quieter.
+    Tag
+}
+
+/// Normalize the integrated loudness of the permutations to the target loudness in LUFS.
+///
+/// Permutations that are too quiet to measure are left alone.
+pub fn normalize(pitch_ranges: &mut [PitchRange], target: f64, scope: NormalizeScope, gain: NormalizeGain) {
+    let permutations = pitch_ranges.iter_mut().flat_map(|p| p.permutations.iter_mut());
+    let groups: Vec<Vec<&mut Sound>> = match scope {
+        NormalizeScope::Permutation => permutations.map(|p| vec![p]).collect(),
+        NormalizeScope::Sound => vec![permutations.collect()]
+    };
+
+    for mut group in groups {
+        let mut meter = LoudnessMeter::new();
+        for pe in &group {
+            meter.add_samples(&pe.samples, pe.channels, pe.sample_rate);
+        }
+        let loudness = match meter.integrated_loudness() {
+            Some(n) => n,
+            None => continue
+        };
+        let mut linear_gain = 10.0f64.powf((target - loudness) / 20.0);
+
+        match gain {
+            NormalizeGain::Samples => {
+                let peak = group.iter().flat_map(|pe| pe.samples.iter()).map(|s| (*s as i32).unsigned_abs()).max().unwrap_or(0);
+                if peak > 0 {
+                    linear_gain = linear_gain.min(i16::MAX as f64 / peak as f64);
+                }
+                for pe in &mut group {
+                    for s in &mut pe.samples {
+                        *s = (*s as f64 * linear_gain).round().clamp(i16::MIN as f64, i16::MAX as f64) as i16;
+                    }
+                }
+            },
+            NormalizeGain::Tag => {
+                for pe in &mut group {
+                    pe.gain = linear_gain.min(1.0) as f32;
+                }
+            }
+        }
+    }
+}
+
+/// Measure the integrated loudness of all permutations together in LUFS with each permutation's gain applied.
+pub fn measure_loudness(pitch_ranges: &[PitchRange]) -> Option<f64> {
+    let mut meter = LoudnessMeter::new();
+    for pe in pitch_ranges.iter().flat_map(|p| p.permutations.iter()) {
+        // A gain of zero is the default gain of 1.
+        if pe.gain == 0.0 || pe.gain == 1.0 {
+            meter.add_samples(&pe.samples, pe.channels, pe.sample_rate);
+        }
+        else {
+            let samples: Vec<i16> = pe.samples.iter().map(|s| (*s as f32 * pe.gain).round() as i16).collect();
+            meter.add_samples(&samples, pe.channels, pe.sample_rate);
+        }
+    }
+    meter.integrated_loudness()
+}
+
+/// Print the peak, RMS, and loudness of each permutation and of the whole sound.
+pub fn print_levels(pitch_ranges: &[PitchRange], tag_path: &impl Display) {
+    let format_loudness = |loudness: Option<f64>| match loudness {
+        Some(n) => format!("{n:.1} LUFS"),
+        None => "N/A".to_owned()
+    };
+
+    let mut meter = LoudnessMeter::new();
+    for (pi, pitch_range) in pitch_ranges.iter().enumerate() {
+        println!(get_compiled_string!("engine.h1.verbs.sound.output_pitch_range_header"),
+                 pitch_range_index=pi,
+                 pitch_range_name=pitch_range.name,
+                 permutation_count=pitch_range.permutations.len());
+
+        for (pm, permutation) in pitch_range.permutations.iter().enumerate() {
+            let levels = measure_levels(&permutation.samples, permutation.channels, permutation.sample_rate);
+            meter.add_samples(&permutation.samples, permutation.channels, permutation.sample_rate);
+            println!(get_compiled_string!("engine.h1.verbs.sound.report_permutation"),
+                     permutation_index=pm,
+                     permutation_name=permutation.name,
+                     peak=levels.peak,
+                     rms=levels.rms,
+                     loudness=format_loudness(levels.loudness));
+        }
+
+        println!();
+    }
+
+    println!(get_compiled_string!("engine.h1.verbs.sound.report_sound"), tag=tag_path, loudness=format_loudness(meter.integrated_loudness()));
+}
+
 /// Resample the given samples to the new sample rate.
 pub fn resample(samples: &[i16], channel_count: usize, ratio: f64) -> ErrorMessageResult<Vec<i16>> {
     // Calculate the ratio
@@ -654,6 +793,6 @@ fn encode_block(samples_to_encode: &[i16], channels: NonZeroU8, sample_rate: Non
             return Ok((v, 0));
         }
 
-        SoundFormat::ImaAdpcm => return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.verbs.sound.error_ima_adpcm_not_supported"))),
+        SoundFormat::ImaAdpcm => Ok((ringhopper::sound::encode_ima_adpcm(samples_to_encode, channels.get() as usize), 0))
     }
 }
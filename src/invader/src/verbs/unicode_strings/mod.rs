@@ -3,7 +3,7 @@ use std::path::Path;
 use ringhopper::engines::h1::definitions::{UnicodeStringList, UnicodeStringListString};
 use ringhopper::engines::h1::*;
 use crate::cmd::*;
-use ringhopper::types::tag::TagGroupFn;
+use ringhopper::types::tag::{TagBlockFn, TagGroupFn};
 use macros::terminal::*;
 use crate::file::*;
 use ringhopper::error::{ErrorMessage, ErrorMessageResult};
@@ -95,7 +95,7 @@ fn make_string_list(file_data: &[u8], data_path: &Path, group: TagGroup) -> Erro
         return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.verbs.unicode-strings.error_missing_end_string")));
     }
 
-    let mut list = UnicodeStringList::default();
+    let mut list = UnicodeStringList::new_with_defaults();
     match group {
         TagGroup::StringList => {
             for s in strings {
@@ -45,6 +45,183 @@ fn format_tag_field_name(string: &str) -> String {
     }
 }
 
+// Generate code to check a field against the minimum, maximum, non_null, and bounds limits of its definition.
+fn generate_validation_code(field: &Value, field_expression: &str, field_display_name: &str) -> String {
+    let field_type = field.get("type").unwrap().as_str().unwrap();
+    let bounds = field.get("bounds").unwrap_or(&Value::Bool(false)).as_bool().unwrap();
+    let non_null = field.get("non_null").unwrap_or(&Value::Bool(false)).as_bool().unwrap();
+    let minimum = field.get("minimum");
+    let maximum = field.get("maximum");
+
+    let push_error = |display_name: &str, string: &str, arguments: String| {
+        format!("errors.push(ErrorMessage::AllocatedString(format!(get_compiled_string!(\"engine.h1.types.validate.{string}\"), path=tag_field_path(path, {display_name:?}), {arguments})));")
+    };
+
+    let mut code = String::new();
+    match field_type {
+        "Reflexive" => {
+            if let Some(minimum) = minimum {
+                code += &format!("if {field_expression}.blocks.len() < {minimum} {{ {} }}", push_error(field_display_name, "error_too_few_blocks", format!("count={field_expression}.blocks.len(), minimum={minimum}")));
+            }
+
+            // Some limits depend on the engine.
+            let maximum = match maximum {
+                Some(Value::Object(limits)) => {
+                    let mut engine_limits = String::new();
+                    for (engine, limit) in limits {
+                        if engine != "default" && engine != "extended" {
+                            engine_limits += &format!("Some({engine:?}) => {limit},");
+                        }
+                    }
+                    Some(format!("match engine.and_then(|e| e.shorthand) {{ {engine_limits} _ => {} }}", limits.get("default").unwrap()))
+                },
+                Some(n) => Some(n.to_string()),
+                None => None
+            };
+            if let Some(maximum) = maximum {
+                code += &format!("let maximum: usize = {maximum}; if {field_expression}.blocks.len() > maximum {{ {} }}", push_error(field_display_name, "error_too_many_blocks", format!("count={field_expression}.blocks.len(), maximum=maximum")));
+            }
+
+            code += &format!("{field_expression}.validate_fields(&tag_field_path(path, {field_display_name:?}), engine, errors);");
+        },
+        "Data" => {
+            if let Some(maximum) = maximum {
+                code += &format!("if {field_expression}.len() > {maximum} {{ {} }}", push_error(field_display_name, "error_data_too_large", format!("size={field_expression}.len(), maximum={maximum}")));
+            }
+        },
+        "TagReference" if non_null => {
+            code += &format!("if {field_expression}.is_empty() {{ {} }}", push_error(field_display_name, "error_null", String::new()));
+        },
+        "String32" if non_null => {
+            code += &format!("if {field_expression}.to_str().is_empty() {{ {} }}", push_error(field_display_name, "error_null", String::new()));
+        },
+        _ => {
+            // Check each bound separately.
+            let values: &[(String, String)] = &match bounds {
+                true => vec![(format!("{field_expression}.lower"), format!("{field_display_name}.lower")), (format!("{field_expression}.upper"), format!("{field_display_name}.upper"))],
+                false => vec![(field_expression.to_owned(), field_display_name.to_owned())]
+            };
+            for (value, display_name) in values {
+                if let Some(minimum) = minimum {
+                    code += &format!("if ({value} as f64) < {minimum}f64 {{ {} }}", push_error(display_name, "error_value_below_minimum", format!("value={value}, minimum={minimum}")));
+                }
+                if let Some(maximum) = maximum {
+                    code += &format!("if ({value} as f64) > {maximum}f64 {{ {} }}", push_error(display_name, "error_value_above_maximum", format!("value={value}, maximum={maximum}")));
+                }
+            }
+
+            if bounds {
+                code += &format!("if !{field_expression}.is_normal() {{ {} }}", push_error(field_display_name, "error_bounds_inverted", format!("lower={field_expression}.lower, upper={field_expression}.upper")));
+            }
+        }
+    }
+
+    code
+}
+
+// Generate an expression for the default value of a field, if its definition has one.
+fn generate_default_value(field: &Value) -> Option<String> {
+    let field_type = field.get("type").unwrap().as_str().unwrap();
+    let bounds = field.get("bounds").unwrap_or(&Value::Bool(false)).as_bool().unwrap();
+    let default = field.get("default")?;
+
+    let primitive_type = match field_type {
+        "int8" => "i8",
+        "int16" => "i16",
+        "int32" => "i32",
+        "uint8" => "u8",
+        "uint16" => "u16",
+        "uint32" => "u32",
+        "float" | "Angle" | "Fraction" | "ColorRGB" | "ColorARGB" => "f32",
+        n => panic!("default values are not supported for {n}")
+    };
+
+    let components = match default {
+        Value::Array(array) => array.iter().map(|v| format!("{v}{primitive_type}")).collect::<Vec<String>>(),
+        n => vec![format!("{n}{primitive_type}")]
+    };
+
+    let component_names: &[&str] = match (field_type, bounds) {
+        ("ColorRGB", _) => &["r", "g", "b"],
+        ("ColorARGB", _) => &["a", "r", "g", "b"],
+        (_, true) => &["lower", "upper"],
+        (_, false) => return Some(components[0].to_owned())
+    };
+    assert_eq!(component_names.len(), components.len(), "default value for {field_type} has the wrong number of components");
+
+    let type_name = match bounds {
+        true => "Bounds",
+        false => field_type
+    };
+    let fields = component_names.iter().zip(components).map(|(name, value)| format!("{name}: {value}")).collect::<Vec<String>>().join(", ");
+    Some(format!("{type_name} {{ {fields} }}"))
+}
+
+// Generate code to collect the tag references of a field.
+fn generate_dependency_code(field: &Value, field_expression: &str, field_display_name: &str) -> String {
+    match field.get("type").unwrap().as_str().unwrap() {
+        "Reflexive" => format!("{field_expression}.get_dependencies_fields(&tag_field_path(path, {field_display_name:?}), dependencies);"),
+        "TagReference" => {
+            let groups = expand_reference_groups(field.get("groups").unwrap().as_array().unwrap());
+            let allowed_groups = match groups == ["*"] {
+                true => String::new(),
+                false => groups.iter().map(|g| format!("TagGroup::{}", tag_group_extension_to_struct(g))).collect::<Vec<String>>().join(", ")
+            };
+            format!("if !{field_expression}.is_empty() {{ dependencies.push(TagDependency {{ path: tag_field_path(path, {field_display_name:?}), reference: {field_expression}.clone(), allowed_groups: &[{allowed_groups}] }}); }}")
+        },
+        _ => String::new()
+    }
+}
+
+// Expand the groups a tag reference allows into their tag group extensions (e.g. "unit" -> "biped", "vehicle").
+//
+// Return ["*"] if all groups are allowed.
+fn expand_reference_groups(groups: &[Value]) -> Vec<String> {
+    let mut expanded = Vec::<String>::new();
+
+    for g in groups {
+        let appended_group: &'static [&'static str] = match g.as_str().unwrap() {
+            "unit"   => &["biped", "vehicle"],
+            "item"   => &["weapon", "equipment", "garbage"],
+            "device" => &["device_machine", "device_light_fixture", "device_control"],
+            "object" => &["biped", "vehicle",
+                          "weapon", "equipment", "garbage",
+                          "scenery",
+                          "device_machine", "device_light_fixture", "device_control",
+                          "placeholder",
+                          "sound_scenery"],
+
+            "model"  => &["gbxmodel", "model"],
+
+            "shader" => &[
+                "shader_environment",
+                "shader_model",
+                "shader_transparent_chicago_extended",
+                "shader_transparent_chicago",
+                "shader_transparent_generic",
+                "shader_transparent_glass",
+                "shader_transparent_meter",
+                "shader_transparent_plasma",
+                "shader_transparent_water"
+            ],
+
+            n => {
+                expanded.push(n.to_owned());
+                &[]
+            }
+        };
+
+        expanded.reserve(appended_group.len());
+        for &i in appended_group {
+            expanded.push(i.to_owned());
+        }
+    }
+
+    expanded.sort();
+    expanded.dedup();
+    expanded
+}
+
 /// Load the definitions json files.
 #[proc_macro]
 pub fn load_definition_json_def(_: TokenStream) -> TokenStream {
@@ -197,6 +374,26 @@ pub fn load_definition_json_def(_: TokenStream) -> TokenStream {
                         {object_name}::from_u16(u16::from_tag_cached(data, at, struct_end)?)
                     }}
                 }}").parse::<TokenStream>().unwrap());
+
+                stream.extend(format!("
+                impl CacheSerialize for {object_name} {{
+                    fn into_cache(&self, writer: &mut CacheTagWriter, at: usize) -> ErrorMessageResult<()> {{
+                        self.into_u16().into_cache(writer, at)
+                    }}
+                    fn from_cache(reader: &CacheTagReader, data: &[u8], at: usize) -> ErrorMessageResult<Self> {{
+                        {object_name}::from_u16(u16::from_cache(reader, data, at)?)
+                    }}
+                }}").parse::<TokenStream>().unwrap());
+
+                stream.extend(format!("
+                impl TagFieldFn for {object_name} {{
+                    fn as_field_value(&self) -> TagFieldValue {{
+                        TagFieldValue::Enum(self)
+                    }}
+                    fn as_field_value_mut(&mut self) -> TagFieldValue {{
+                        TagFieldValue::MutableEnum(self)
+                    }}
+                }}").parse::<TokenStream>().unwrap());
             }
             else if object_type == "bitfield" {
                 let width = object.get("width").unwrap().as_u64().unwrap();
@@ -205,6 +402,11 @@ pub fn load_definition_json_def(_: TokenStream) -> TokenStream {
                 let mut into_uint_code = String::new();
                 let mut from_uint_code = String::new();
 
+                let mut bit_names = String::new();
+                let mut get_bit_code = String::new();
+                let mut set_bit_code = String::new();
+                let mut bit_index = 0usize;
+
                 let mut current_value = 1u32;
                 let mut tag_mask = 0xFFFFFFFFu32;
                 let mut value_mask = 0u32;
@@ -235,6 +437,10 @@ pub fn load_definition_json_def(_: TokenStream) -> TokenStream {
                     value_mask |= current_value;
 
                     into_uint_code += &format!("return_value |= {current_value}u{width} * (self.{name} as u{width});");
+                    bit_names += &format!("{:?},", o.get("name").unwrap().as_str().unwrap().replace(" ", "-").replace("'", "").to_lowercase());
+                    get_bit_code += &format!("{bit_index} => self.{name},");
+                    set_bit_code += &format!("{bit_index} => self.{name} = value,");
+                    bit_index += 1;
                     from_uint_code += &format!("{name}: (input_value & {current_value}u{width}) != 0,");
 
                     current_value <<= 1;
@@ -280,6 +486,44 @@ pub fn load_definition_json_def(_: TokenStream) -> TokenStream {
                     }}
                 }}");
                 stream.extend(parsing_code.parse::<TokenStream>().unwrap());
+
+                // Cache files store every bit, including cache only bits.
+                stream.extend(format!("
+                impl CacheSerialize for {object_name} {{
+                    fn into_cache(&self, writer: &mut CacheTagWriter, at: usize) -> ErrorMessageResult<()> {{
+                        self.into_u{width}().into_cache(writer, at)
+                    }}
+                    fn from_cache(reader: &CacheTagReader, data: &[u8], at: usize) -> ErrorMessageResult<Self> {{
+                        Ok({object_name}::from_u{width}(u{width}::from_cache(reader, data, at)?))
+                    }}
+                }}").parse::<TokenStream>().unwrap());
+
+                stream.extend(format!("
+                impl TagBitfieldFn for {object_name} {{
+                    fn get_bits(&self) -> &'static [&'static str] {{
+                        &[{bit_names}]
+                    }}
+                    fn get_bit(&self, index: usize) -> bool {{
+                        match index {{
+                            {get_bit_code}
+                            _ => panic!(\"bit index {{index}} is out of bounds\")
+                        }}
+                    }}
+                    fn set_bit(&mut self, index: usize, value: bool) {{
+                        match index {{
+                            {set_bit_code}
+                            _ => panic!(\"bit index {{index}} is out of bounds\")
+                        }}
+                    }}
+                }}
+                impl TagFieldFn for {object_name} {{
+                    fn as_field_value(&self) -> TagFieldValue {{
+                        TagFieldValue::Bitfield(self)
+                    }}
+                    fn as_field_value_mut(&mut self) -> TagFieldValue {{
+                        TagFieldValue::MutableBitfield(self)
+                    }}
+                }}").parse::<TokenStream>().unwrap());
             }
             else if object_type == "struct" {
                 // Check if we implement copy
@@ -298,18 +542,33 @@ pub fn load_definition_json_def(_: TokenStream) -> TokenStream {
                 // If we inherit anything, handle that too
                 let mut from_tag_code;
                 let mut into_tag_code;
+                let mut into_cache_code;
+                let mut from_cache_code;
+                let mut reflect_code = String::new();
+                let mut reflect_mut_code = String::new();
+                let mut reflect_count = 0usize;
+                let mut validate_code = String::new();
+                let mut defaults_code = String::new();
+                let mut dependencies_code = String::new();
                 match object.get("inherits") {
                     Some(n) => {
                         implements_copy = false; // can't determine this
+                        validate_code += "self.base_struct.validate_fields(path, engine, errors);";
+                        dependencies_code += "self.base_struct.get_dependencies_fields(path, dependencies);";
 
                         let inherited_object = n.as_str().unwrap();
+                        defaults_code += &format!("new_object.base_struct = {inherited_object}::new_with_defaults();");
                         all_fields_defined += &format!("pub base_struct: {inherited_object},");
                         from_tag_code = format!("new_object.base_struct = {inherited_object}::from_tag(data, at, struct_end, cursor)?; let mut local_cursor = at + {inherited_object}::tag_size();");
                         into_tag_code = format!("self.base_struct.into_tag(data, at, struct_end)?; let mut local_cursor = at + {inherited_object}::tag_size();");
+                        into_cache_code = format!("self.base_struct.into_cache(writer, at)?; let mut local_cursor = at + {inherited_object}::tag_size();");
+                        from_cache_code = format!("new_object.base_struct = {inherited_object}::from_cache(reader, data, at)?; let mut local_cursor = at + {inherited_object}::tag_size();");
                     },
                     None => {
                         from_tag_code = format!("let mut local_cursor = at;");
                         into_tag_code = format!("let mut local_cursor = at;");
+                        into_cache_code = format!("let mut local_cursor = at;");
+                        from_cache_code = format!("let mut local_cursor = at;");
                     }
                 }
 
@@ -321,6 +580,8 @@ pub fn load_definition_json_def(_: TokenStream) -> TokenStream {
                         let cursor_increment = format!("local_cursor += {};", f.get("size").unwrap().as_u64().unwrap());
                         from_tag_code += &cursor_increment;
                         into_tag_code += &cursor_increment;
+                        into_cache_code += &cursor_increment;
+                        from_cache_code += &cursor_increment;
                         continue
                     }
 
@@ -381,6 +642,14 @@ pub fn load_definition_json_def(_: TokenStream) -> TokenStream {
                     // Is this cache only?
                     let cache_only = f.get("cache_only").unwrap_or(&Value::Bool(false)).as_bool().unwrap();
 
+                    // Is this stripped from cache files or stored outside of tag data?
+                    let non_cached = f.get("non_cached").unwrap_or(&Value::Bool(false)).as_bool().unwrap();
+                    let file_offset = f.get("file_offset").unwrap_or(&Value::Bool(false)).as_bool().unwrap();
+                    let external_file_offset = match f.get("external_file_offset") {
+                        Some(n) => format!("Some(\"{}\")", n.as_str().unwrap()),
+                        None => "None".to_owned()
+                    };
+
                     let mut doc = f.get("comment").unwrap_or(&Value::String(String::new())).as_str().unwrap().to_owned();
 
                     // Write the serialization code
@@ -407,11 +676,57 @@ pub fn load_definition_json_def(_: TokenStream) -> TokenStream {
                             }
                         }
 
+                        // Non-cached fields are left zeroed out in cache files, and file offset data is written outside of the tag data.
+                        if file_offset {
+                            into_cache_code += &format!("writer.write_file_data(&self.{field_name_written}{type_suffix}, local_cursor)?;");
+                            from_cache_code += &format!("new_object.{field_name_written}{type_suffix} = reader.read_file_data(data, local_cursor, {external_file_offset})?;");
+                        }
+                        else if !non_cached {
+                            into_cache_code += &format!("self.{field_name_written}{type_suffix}.into_cache(writer, local_cursor)?;");
+                            from_cache_code += &format!("new_object.{field_name_written}{type_suffix} = {field_type_written_expression}::from_cache(reader, data, local_cursor)?;");
+                        }
+
                         let cursor_increment = format!("local_cursor += {field_type_written_expression}::tag_size();");
                         from_tag_code += &cursor_increment;
                         into_tag_code += &cursor_increment;
+                        into_cache_code += &cursor_increment;
+                        from_cache_code += &cursor_increment;
                     };
 
+                    // Each element of an array is its own field when enumerated at runtime.
+                    let field_description = f.get("description").or(f.get("comment")).unwrap_or(&Value::String(String::new())).as_str().unwrap().to_owned();
+                    for i in 0..count {
+                        let (field_expression, field_display_name) = match count {
+                            1 => (format!("self.{field_name_written}"), field_name.to_owned()),
+                            _ => (format!("self.{field_name_written}[{i}]"), format!("{field_name}[{i}]"))
+                        };
+                        reflect_code += &format!("{reflect_count} => TagField {{ field: {field_expression}.as_field_value(), name: {field_display_name:?}, comment: {field_description:?} }},");
+                        reflect_mut_code += &format!("{reflect_count} => TagField {{ field: {field_expression}.as_field_value_mut(), name: {field_display_name:?}, comment: {field_description:?} }},");
+                        reflect_count += 1;
+                    }
+
+                    // Check the limits and collect the references of each element. Cache only fields are not saved in tag files, so they are skipped.
+                    if !cache_only {
+                        for i in 0..count {
+                            let (field_expression, field_display_name) = match count {
+                                1 => (format!("self.{field_name_written}"), field_name.to_owned()),
+                                _ => (format!("self.{field_name_written}[{i}]"), format!("{field_name}[{i}]"))
+                            };
+                            validate_code += &generate_validation_code(f, &field_expression, &field_display_name);
+                            dependencies_code += &generate_dependency_code(f, &field_expression, &field_display_name);
+                        }
+                    }
+
+                    // Set the default value of each element.
+                    if let Some(default) = generate_default_value(f) {
+                        for i in 0..count {
+                            match count {
+                                1 => defaults_code += &format!("new_object.{field_name_written} = {default};"),
+                                _ => defaults_code += &format!("new_object.{field_name_written}[{i}] = {default};")
+                            }
+                        }
+                    }
+
                     // One object, not an array
                     if count == 1 {
                         write_serialization_code("");
@@ -430,48 +745,7 @@ pub fn load_definition_json_def(_: TokenStream) -> TokenStream {
                         }
 
                         let groups_arr = f.get("groups").unwrap().as_array().unwrap();
-                        let mut groups = Vec::<String>::new();
-
-                        for g in groups_arr {
-                            let appended_group: &'static [&'static str] = match g.as_str().unwrap() {
-                                "unit"   => &["biped", "vehicle"],
-                                "item"   => &["weapon", "equipment", "garbage"],
-                                "device" => &["device_machine", "device_light_fixture", "device_control"],
-                                "object" => &["biped", "vehicle",
-                                              "weapon", "equipment", "garbage",
-                                              "scenery",
-                                              "device_machine", "device_light_fixture", "device_control",
-                                              "placeholder",
-                                              "sound_scenery"],
-
-                                "model"  => &["gbxmodel", "model"],
-
-                                "shader" => &[
-                                    "shader_environment",
-                                    "shader_model",
-                                    "shader_transparent_chicago_extended",
-                                    "shader_transparent_chicago",
-                                    "shader_transparent_generic",
-                                    "shader_transparent_glass",
-                                    "shader_transparent_meter",
-                                    "shader_transparent_plasma",
-                                    "shader_transparent_water"
-                                ],
-
-                                n => {
-                                    groups.push(n.to_owned());
-                                    &[]
-                                }
-                            };
-
-                            groups.reserve(appended_group.len());
-                            for &i in appended_group {
-                                groups.push(i.to_owned());
-                            }
-                        }
-
-                        groups.dedup();
-                        groups.sort();
+                        let groups = expand_reference_groups(groups_arr);
 
                         if groups == ["*"] {
                             doc += &format!("Allowed groups: All\n");
@@ -507,7 +781,9 @@ pub fn load_definition_json_def(_: TokenStream) -> TokenStream {
 
                         // Default the group
                         if !cache_only {
-                            from_tag_code += &format!("if new_object.{field_name_written}.get_group() == TagGroup::_None {{ new_object.{field_name_written}.set_group(TagGroup::{default_group}); }}");
+                            let default_group_code = format!("if new_object.{field_name_written}.get_group() == TagGroup::_None {{ new_object.{field_name_written}.set_group(TagGroup::{default_group}); }}");
+                            from_tag_code += &default_group_code;
+                            from_cache_code += &default_group_code;
                         }
                     }
 
@@ -525,11 +801,60 @@ pub fn load_definition_json_def(_: TokenStream) -> TokenStream {
                 stream.extend(format!("#[derive(Default{}, Clone, PartialEq)] pub struct {object_name} {{ {all_fields_defined} }}", match implements_copy { true => ", Copy", false => "" } ).parse::<TokenStream>().unwrap());
 
                 // Define parsing it too
+                // Inherited fields come first.
+                let (base_field_count, base_field_code, base_field_mut_code) = match object.get("inherits") {
+                    Some(_) => (
+                        "self.base_struct.field_count()",
+                        "if index < self.base_struct.field_count() { return self.base_struct.field_at_index(index) }",
+                        "if index < self.base_struct.field_count() { return self.base_struct.field_at_index_mut(index) }"
+                    ),
+                    None => ("0", "", "")
+                };
                 stream.extend(format!("
                 impl TagBlockFn for {object_name} {{
-                    fn field_count(&self) -> usize {{ todo!() }}
-                    fn field_at_index(&self, _: usize) -> TagField {{ todo!() }}
-                    fn field_at_index_mut(&mut self, _: usize) -> TagField{{ todo!() }}
+                    fn field_count(&self) -> usize {{
+                        {base_field_count} + {reflect_count}
+                    }}
+                    fn field_at_index(&self, index: usize) -> TagField {{
+                        {base_field_code}
+                        match index - ({base_field_count}) {{
+                            {reflect_code}
+                            _ => panic!(\"field index {{index}} is out of bounds\")
+                        }}
+                    }}
+                    fn field_at_index_mut(&mut self, index: usize) -> TagField {{
+                        {base_field_mut_code}
+                        match index - ({base_field_count}) {{
+                            {reflect_mut_code}
+                            _ => panic!(\"field index {{index}} is out of bounds\")
+                        }}
+                    }}
+                    #[allow(unused_mut, clippy::excessive_precision)]
+                    fn new_with_defaults() -> Self {{
+                        let mut new_object = {object_name}::default();
+                        {defaults_code}
+                        new_object
+                    }}
+                }}
+                impl TagFieldFn for {object_name} {{
+                    fn as_field_value(&self) -> TagFieldValue {{
+                        TagFieldValue::Block(self)
+                    }}
+                    fn as_field_value_mut(&mut self) -> TagFieldValue {{
+                        TagFieldValue::MutableBlock(self)
+                    }}
+                }}
+                impl TagValidate for {object_name} {{
+                    #[allow(unused_variables)]
+                    fn validate_fields(&self, path: &str, engine: Option<&EngineTarget>, errors: &mut Vec<ErrorMessage>) {{
+                        {validate_code}
+                    }}
+                }}
+                impl TagDependencies for {object_name} {{
+                    #[allow(unused_variables)]
+                    fn get_dependencies_fields(&self, path: &str, dependencies: &mut Vec<TagDependency>) {{
+                        {dependencies_code}
+                    }}
                 }}").parse::<TokenStream>().unwrap());
 
                 // Next serializing code
@@ -551,12 +876,29 @@ pub fn load_definition_json_def(_: TokenStream) -> TokenStream {
                     }}
                 }}");
                 stream.extend(parsing_code.parse::<TokenStream>().unwrap());
+
+                // And cache serializing code
+                stream.extend(format!("
+                impl CacheSerialize for {object_name} {{
+                    fn into_cache(&self, writer: &mut CacheTagWriter, at: usize) -> ErrorMessageResult<()> {{
+                        {into_cache_code}
+                        debug_assert_eq!(at + {tag_size}, local_cursor, \"Size for {object_name} is wrong\");
+                        Ok(())
+                    }}
+                    fn from_cache(reader: &CacheTagReader, data: &[u8], at: usize) -> ErrorMessageResult<{object_name}> {{
+                        let mut new_object = {object_name}::default();
+                        {from_cache_code}
+                        debug_assert_eq!(at + {tag_size}, local_cursor, \"Size for {object_name} is wrong\");
+                        Ok(new_object)
+                    }}
+                }}").parse::<TokenStream>().unwrap());
             }
         }
     }
 
     // Write functions for reading tags with TagFileSerializeFn
     let mut group_read_match_block = String::new();
+    let mut group_read_cache_match_block = String::new();
     for group in group_to_struct {
         stream.extend(format!("impl TagFileSerializeFn for {group} {{
             fn from_tag_file(data: &[u8]) -> ErrorMessageResult<ParsedTagFile<Self>> {{
@@ -571,8 +913,22 @@ pub fn load_definition_json_def(_: TokenStream) -> TokenStream {
             fn into_tag_file(&self) -> ErrorMessageResult<Vec<u8>> {{
                 ParsedTagFile::into_tag(self, TagGroup::{group})
             }}
+            fn into_cache_tag(&self, writer: &mut CacheTagWriter) -> ErrorMessageResult<usize> {{
+                let offset = writer.allocate({group}::tag_size())?;
+                self.into_cache(writer, offset)?;
+                Ok(offset)
+            }}
+            fn from_cache_tag(cache_file: &CacheFile, reference: &TagReference) -> ErrorMessageResult<Self> {{
+                if reference.get_group() != TagGroup::{group} {{
+                    return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!(\"engine.h1.types.tag.header.error_reason_wrong_group\"), group_expected=\"{group}\", group_actual=reference.get_group().as_str())))
+                }}
+                let (reader, address) = cache_file.get_tag_reader(reference)?;
+                {group}::from_cache(&reader, reader.read(address, {group}::tag_size())?, 0)
+            }}
         }}").parse::<TokenStream>());
 
+        group_read_cache_match_block += &format!("TagGroup::{group} => Ok(Box::new({group}::from_cache_tag(cache_file, reference)?)),");
+
         group_read_match_block += &format!("TagGroup::{group} => {{
             let tag_file = {group}::from_tag_file(data)?;
             Ok(ParsedTagFile {{
@@ -594,6 +950,17 @@ pub fn load_definition_json_def(_: TokenStream) -> TokenStream {
         }}
     }}").parse::<TokenStream>());
 
+    stream.extend(format!("
+        /// Generic function for parsing a tag in a cache file for when knowing the tag group is not required.
+        ///
+        /// Returns an error if the tag could not be parsed.
+        pub fn parse_cache_tag(cache_file: &CacheFile, reference: &TagReference) -> ErrorMessageResult<Box<dyn TagFileSerializeFn>> {{
+        match reference.get_group() {{
+            {group_read_cache_match_block}
+            n => Err(ErrorMessage::AllocatedString(format!(get_compiled_string!(\"engine.h1.types.tag.header.error_reason_unparsable_group\"), group=n.as_str())))
+        }}
+    }}").parse::<TokenStream>());
+
     stream
 }
 
@@ -756,6 +1123,12 @@ pub fn load_target_json_def(_: TokenStream) -> TokenStream {
         let max_script_nodes = parse_int_value(get_value("max_script_nodes", &filename, &jsons).unwrap());
         let cache_file_version = parse_int_value(get_value("cache_file_version", &filename, &jsons).unwrap());
         let bsps_occupy_tag_space = get_value("bsps_occupy_tag_space", filename, &jsons).unwrap_or(Value::Bool(false)).as_bool().unwrap();
+        let compressed = get_value("compressed", filename, &jsons).unwrap_or(Value::Bool(false)).as_bool().unwrap();
+        let header_layout = match get_value("header_layout", filename, &jsons).as_ref().map(|v| v.as_str().unwrap()).unwrap_or("standard") {
+            "standard" => "HeaderLayout::Standard",
+            "gearbox-demo" => "HeaderLayout::GearboxDemo",
+            n => panic!("Unknown header layout {}", n)
+        };
         let max_tag_space = parse_int_value(get_value("max_tag_space", &filename, &jsons).unwrap());
 
         let max_cache_file_size = get_value("max_cache_file_size", filename, &jsons).unwrap().as_object().unwrap().to_owned();
@@ -793,6 +1166,8 @@ pub fn load_target_json_def(_: TokenStream) -> TokenStream {
                 max_script_nodes: {max_script_nodes},
                 cache_file_version: {cache_file_version},
                 bsps_occupy_tag_space: {bsps_occupy_tag_space},
+                compressed: {compressed},
+                header_layout: {header_layout},
                 max_tag_space: {max_tag_space},
                 max_cache_file_size_user_interface: {max_cache_file_size_user_interface},
                 max_cache_file_size_singleplayer: {max_cache_file_size_singleplayer},
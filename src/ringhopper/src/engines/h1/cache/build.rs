@@ -0,0 +1,580 @@
+use crate::error::*;
+use crate::types::{String32, HALO_DIRECTORY_SEPARATOR};
+use crate::types::tag::TagGroupFn;
+use crate::engines::h1::*;
+use crate::engines::h1::definitions::*;
+use ringhopper_proc::*;
+use rat_in_a_tube::CompileTarget;
+use std::collections::HashSet;
+
+/// Size of the header at the start of BSP data.
+const BSP_HEADER_LEN: usize = 0x18;
+
+/// Size of an uncompressed rendered vertex in BSP data.
+const BSP_UNCOMPRESSED_RENDERED_VERTEX_LEN: u32 = 56;
+
+/// Number of game ticks per second, which some rates are converted to when they are built.
+pub(super) const TICK_RATE: f32 = 30.0;
+
+/// Tag with an object definition, which has to be preprocessed when built.
+pub(super) trait ObjectTag {
+    fn object_mut(&mut self) -> &mut Object;
+}
+
+macro_rules! impl_object_tag {
+    ($($group:ty => $($base:ident).+;)*) => {
+        $(
+            impl ObjectTag for $group {
+                fn object_mut(&mut self) -> &mut Object {
+                    &mut self.$($base).+
+                }
+            }
+        )*
+    };
+}
+
+impl_object_tag! {
+    Biped => base_struct.base_struct;
+    Vehicle => base_struct.base_struct;
+    Weapon => base_struct.base_struct;
+    Equipment => base_struct.base_struct;
+    Garbage => base_struct.base_struct;
+    DeviceMachine => base_struct.base_struct;
+    DeviceControl => base_struct.base_struct;
+    DeviceLightFixture => base_struct.base_struct;
+    Projectile => base_struct;
+    Scenery => base_struct.base_struct;
+    Placeholder => base_struct.base_struct;
+    SoundScenery => base_struct.base_struct;
+}
+
+/// Cache file that was built with [`build_cache_file`].
+pub struct BuiltCacheFile {
+    /// Cache file data.
+    pub data: Vec<u8>,
+
+    /// Type of the scenario.
+    pub map_type: ScenarioType,
+
+    /// Number of tags in the cache file.
+    pub tag_count: usize,
+
+    /// Tag space used by the tag data and BSPs, in bytes.
+    pub tag_space_used: usize,
+
+    /// Maximum tag space for the engine, in bytes.
+    pub tag_space_limit: usize,
+
+    /// Maximum cache file size for the engine, in bytes.
+    pub file_size_limit: usize
+}
+
+/// Get the name of the scenario, which is the last part of its path, that cache files are named after.
+pub fn get_scenario_name(scenario_path: &TagReference) -> &str {
+    let path = scenario_path.get_path_without_extension();
+    match path.rfind(HALO_DIRECTORY_SEPARATOR) {
+        Some(n) => &path[n + 1..],
+        None => path
+    }
+}
+
+/// Build a cache file from a scenario tag for the given engine.
+///
+/// `load_tag` is called to read a tag file, returning `None` if the tag does not exist.
+pub fn build_cache_file(scenario_path: &TagReference, engine: &EngineTarget, load_tag: &mut dyn FnMut(&TagReference) -> ErrorMessageResult<Option<Vec<u8>>>) -> ErrorMessageResult<BuiltCacheFile> {
+    // Compressed (Xbox) cache files also need compressed vertices and a different tag data header.
+    if engine.compressed {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.cache.error_target_unsupported"), engine=engine.name)));
+    }
+
+    let mut load = |reference: &TagReference| -> ErrorMessageResult<Vec<u8>> {
+        match load_tag(reference)? {
+            Some(n) => Ok(n),
+            None => Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("general.error_tag_not_found"), tag=reference)))
+        }
+    };
+
+    let wrap_error = |reference: &TagReference, error: ErrorMessage| {
+        ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.cache.error_compiling_tag"), tag=reference, error=error))
+    };
+
+    // Load the scenario first, since its type determines what tags are required.
+    let mut scenario = *Scenario::from_tag_file(&load(scenario_path)?).map_err(|e| wrap_error(scenario_path, e))?.data;
+    let map_type = scenario._type;
+
+    let mut index = CacheTagIndex::default();
+    index.get_or_insert(scenario_path);
+
+    let required_tags = &engine.required_tags;
+    let required_tags_for_type = match map_type {
+        ScenarioType::Singleplayer => required_tags.singleplayer,
+        ScenarioType::Multiplayer => required_tags.multiplayer,
+        ScenarioType::UserInterface => required_tags.user_interface
+    };
+    for path in required_tags.all.iter().chain(required_tags_for_type.iter()) {
+        index.get_or_insert(&TagReference::from_full_path(path)?);
+    }
+
+    let base_address = engine.base_memory_address.get_preferred_address();
+    let overflow = || ErrorMessage::StaticString(get_compiled_string!("engine.h1.types.serialize.error_architecture_limit_exceeded"));
+
+    // Compile BSPs first, as these are placed right after the header and the scenario needs to know where they are.
+    let mut bsp_tags = HashSet::new();
+    let mut bsps = Vec::new();
+    let mut bsp_file_offset = CACHE_FILE_HEADER_LEN;
+    for scenario_bsp in &mut scenario.structure_bsps {
+        let reference = scenario_bsp.structure_bsp.clone();
+        if reference.is_empty() {
+            continue;
+        }
+
+        let writer = compile_bsp(&load(&reference)?, engine).map_err(|e| wrap_error(&reference, e))?;
+        let bsp_size = writer.data().len();
+        let bsp_address = (base_address as usize).checked_add(engine.max_tag_space)
+                                                 .and_then(|n| n.checked_sub(bsp_size))
+                                                 .and_then(|n| u32::try_from(n).ok());
+        let bsp_address = match bsp_address {
+            Some(n) => n,
+            None => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.cache.error_tag_space_exceeded"), size=bsp_size, limit=engine.max_tag_space)))
+        };
+
+        scenario_bsp.bsp_start = u32::try_from(bsp_file_offset).map_err(|_| overflow())?;
+        scenario_bsp.bsp_size = bsp_size as u32;
+        scenario_bsp.bsp_address = bsp_address;
+
+        for (_, r) in writer.tag_references() {
+            index.get_or_insert(r);
+        }
+        bsp_tags.insert(index.get_or_insert(&reference));
+
+        bsp_file_offset = bsp_file_offset.checked_add(bsp_size).ok_or_else(overflow)?;
+        bsps.push((bsp_address, writer));
+    }
+    let raw_data_offset = bsp_file_offset;
+
+    // Compile the rest of the tags, adding their dependencies to the end of the index as we go.
+    let mut writer = CacheTagWriter::new();
+    let mut raw_data = Vec::new();
+    let mut model_data = ModelData::default();
+    let mut tag_offsets = Vec::new();
+    let mut references_indexed = 0;
+    let mut scenario = Some(scenario);
+
+    while tag_offsets.len() < index.len() {
+        let tag_index = tag_offsets.len();
+        let reference = index.tags()[tag_index].clone();
+
+        // BSPs referenced by the scenario are not part of the tag data.
+        if bsp_tags.contains(&tag_index) {
+            tag_offsets.push(None);
+            continue;
+        }
+
+        let offset = (|| -> ErrorMessageResult<usize> {
+            let tag_id = tag_id_from_index(tag_index);
+
+            if tag_index == 0 {
+                let mut scenario = scenario.take().unwrap();
+                convert_script_syntax_data(&mut scenario, &mut index)?;
+                return scenario.into_cache_tag(&mut writer);
+            }
+
+            let data = load(&reference)?;
+            match reference.get_group() {
+                TagGroup::Bitmap => {
+                    let mut bitmap = Bitmap::from_tag_file(&data)?.data;
+                    preprocess_bitmap(&mut bitmap, tag_id, &mut raw_data, raw_data_offset)?;
+                    bitmap.into_cache_tag(&mut writer)
+                },
+                TagGroup::Sound => {
+                    let mut sound = Sound::from_tag_file(&data)?.data;
+                    preprocess_sound(&mut sound, tag_id);
+                    sound.into_cache_tag(&mut writer)
+                },
+                TagGroup::Font => {
+                    let mut font = Font::from_tag_file(&data)?.data;
+                    preprocess_font(&mut font);
+                    font.into_cache_tag(&mut writer)
+                },
+                TagGroup::GBXModel => {
+                    let mut model = GBXModel::from_tag_file(&data)?.data;
+                    for geometry in &mut model.geometries {
+                        for part in &mut geometry.parts {
+                            model_data.add_part(&mut part.base_struct)?;
+                        }
+                    }
+                    model.into_cache_tag(&mut writer)
+                },
+                TagGroup::Model => {
+                    let mut model = Model::from_tag_file(&data)?.data;
+                    for geometry in &mut model.geometries {
+                        for part in &mut geometry.parts {
+                            model_data.add_part(part)?;
+                        }
+                    }
+                    model.into_cache_tag(&mut writer)
+                },
+                TagGroup::Projectile => {
+                    let mut projectile = Projectile::from_tag_file(&data)?.data;
+                    preprocess_projectile(&mut projectile);
+                    preprocess_object(projectile.object_mut());
+                    projectile.into_cache_tag(&mut writer)
+                },
+                TagGroup::Biped => build_object_tag::<Biped>(&data, &mut writer),
+                TagGroup::Vehicle => build_object_tag::<Vehicle>(&data, &mut writer),
+                TagGroup::Weapon => build_object_tag::<Weapon>(&data, &mut writer),
+                TagGroup::Equipment => build_object_tag::<Equipment>(&data, &mut writer),
+                TagGroup::Garbage => build_object_tag::<Garbage>(&data, &mut writer),
+                TagGroup::DeviceMachine => build_object_tag::<DeviceMachine>(&data, &mut writer),
+                TagGroup::DeviceControl => build_object_tag::<DeviceControl>(&data, &mut writer),
+                TagGroup::DeviceLightFixture => build_object_tag::<DeviceLightFixture>(&data, &mut writer),
+                TagGroup::Scenery => build_object_tag::<Scenery>(&data, &mut writer),
+                TagGroup::Placeholder => build_object_tag::<Placeholder>(&data, &mut writer),
+                TagGroup::SoundScenery => build_object_tag::<SoundScenery>(&data, &mut writer),
+                _ => parse_tag_file(&data)?.data.into_cache_tag(&mut writer)
+            }
+        })().map_err(|e| wrap_error(&reference, e))?;
+
+        tag_offsets.push(Some(offset));
+
+        for (_, r) in &writer.tag_references()[references_indexed..] {
+            index.get_or_insert(r);
+        }
+        references_indexed = writer.tag_references().len();
+    }
+
+    // Tag IDs can only hold 16-bit indices.
+    let tag_count = index.len();
+    let max_tag_count = u16::MAX as usize;
+    if tag_count > max_tag_count {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.cache.error_tag_count_exceeded"), count=tag_count, limit=max_tag_count)));
+    }
+
+    // Tag paths go right after the tag array, and the tag structs go after that.
+    let tag_array_size = tag_count * CACHE_TAG_ARRAY_ENTRY_LEN;
+    let mut tag_paths = Vec::new();
+    let mut tag_path_addresses = Vec::with_capacity(tag_count);
+    let tag_paths_address = base_address as usize + CACHE_TAG_DATA_HEADER_LEN + tag_array_size;
+    for t in index.tags() {
+        tag_path_addresses.push(u32::try_from(tag_paths_address + tag_paths.len()).map_err(|_| overflow())?);
+        tag_paths.extend_from_slice(t.get_path_without_extension().as_bytes());
+        tag_paths.push(0);
+    }
+    tag_paths.resize((tag_paths.len() + 3) & !3, 0);
+
+    let tag_structs_address = u32::try_from(tag_paths_address + tag_paths.len()).map_err(|_| overflow())?;
+    let file_offset = raw_data_offset + raw_data.len();
+    let (tag_structs, file_data) = writer.finish(tag_structs_address, file_offset, &index, &tag_path_addresses)?;
+    raw_data.extend_from_slice(&file_data);
+
+    let mut bsp_data = Vec::with_capacity(bsps.len());
+    let mut largest_bsp = 0;
+    let mut total_bsp_size = 0;
+    for (address, bsp) in bsps {
+        let file_offset = raw_data_offset + raw_data.len();
+        let (data, file_data) = bsp.finish(address, file_offset, &index, &tag_path_addresses)?;
+        raw_data.extend_from_slice(&file_data);
+        largest_bsp = largest_bsp.max(data.len());
+        total_bsp_size += data.len();
+        bsp_data.push(data);
+    }
+
+    // Put it all together
+    let model_data_offset = raw_data_offset + raw_data.len();
+    let vertex_size = model_data.vertices.len();
+    let model_data_size = vertex_size + model_data.indices.len();
+    let tag_data_offset = model_data_offset + model_data_size;
+
+    let mut tag_data = CacheTagDataHeader {
+        tag_array_address: base_address + CACHE_TAG_DATA_HEADER_LEN as u32,
+        scenario_tag_id: tag_id_from_index(0),
+        checksum: 0,
+        tag_count: tag_count as u32,
+        model_part_count: model_data.part_count as u32,
+        model_data_file_offset: u32::try_from(model_data_offset).map_err(|_| overflow())?,
+        vertex_size: vertex_size as u32,
+        model_data_size: model_data_size as u32
+    }.into_bytes()?;
+
+    let tag_array_offset = tag_data.len();
+    tag_data.resize(tag_array_offset + tag_array_size, 0);
+    for i in 0..tag_count {
+        let group = index.tags()[i].get_group();
+        let secondary_group = group.supergroup();
+        let tertiary_group = secondary_group.supergroup();
+        let fourcc_or_null = |group: TagGroup| if group == TagGroup::_None { 0xFFFFFFFF } else { group.as_fourcc() };
+
+        let entry_offset = tag_array_offset + i * CACHE_TAG_ARRAY_ENTRY_LEN;
+        CacheTagArrayEntry {
+            primary_group: group.as_fourcc(),
+            secondary_group: fourcc_or_null(secondary_group),
+            tertiary_group: fourcc_or_null(tertiary_group),
+            tag_id: tag_id_from_index(i),
+            path_address: tag_path_addresses[i],
+            data_address: match tag_offsets[i] {
+                Some(n) => tag_structs_address + n as u32,
+                None => 0
+            },
+            external: false
+        }.into_bytes(&mut tag_data[entry_offset..entry_offset + CACHE_TAG_ARRAY_ENTRY_LEN])?;
+    }
+    tag_data.extend_from_slice(&tag_paths);
+    tag_data.extend_from_slice(&tag_structs);
+
+    // Check if everything fits.
+    let tag_space_used = tag_data.len() + if engine.bsps_occupy_tag_space { total_bsp_size } else { largest_bsp };
+    if tag_space_used > engine.max_tag_space {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.cache.error_tag_space_exceeded"), size=tag_space_used, limit=engine.max_tag_space)));
+    }
+
+    let file_size = tag_data_offset + tag_data.len();
+    let file_size_limit = engine.max_cache_file_size(map_type);
+    if file_size > file_size_limit {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.cache.error_file_size_exceeded"), size=file_size, limit=file_size_limit)));
+    }
+
+    // The checksum covers the BSPs, model data, and tag data.
+    let mut crc32 = u32::MAX;
+    for b in &bsp_data {
+        crc32 = crate::crc::crc32_with_init(crc32, b);
+    }
+    crc32 = crate::crc::crc32_with_init(crc32, &model_data.vertices);
+    crc32 = crate::crc::crc32_with_init(crc32, &model_data.indices);
+    crc32 = crate::crc::crc32_with_init(crc32, &tag_data);
+
+    let header = CacheFileHeader {
+        cache_file_version: engine.cache_file_version,
+        decompressed_file_size: file_size as u32,
+        compressed_padding: 0,
+        tag_data_offset: tag_data_offset as u32,
+        tag_data_size: tag_data.len() as u32,
+        name: String32::from_str(get_scenario_name(scenario_path))?,
+        build: String32::from_str(engine.build.unwrap_or(""))?,
+        map_type,
+        crc32
+    };
+
+    let mut data = header.into_bytes(engine.header_layout)?;
+    data.reserve(file_size - data.len());
+    for b in &bsp_data {
+        data.extend_from_slice(b);
+    }
+    data.extend_from_slice(&raw_data);
+    data.extend_from_slice(&model_data.vertices);
+    data.extend_from_slice(&model_data.indices);
+    data.extend_from_slice(&tag_data);
+    debug_assert_eq!(data.len(), file_size);
+
+    Ok(BuiltCacheFile {
+        data,
+        map_type,
+        tag_count,
+        tag_space_used,
+        tag_space_limit: engine.max_tag_space,
+        file_size_limit
+    })
+}
+
+/// Compile a BSP tag into its own buffer, starting with the BSP header.
+fn compile_bsp(data: &[u8], engine: &EngineTarget) -> ErrorMessageResult<CacheTagWriter> {
+    let mut bsp = ScenarioStructureBSP::from_tag_file(data)?.data;
+
+    // Rendered vertices and lightmap vertices are stored one after the other in the uncompressed vertices.
+    let mut lightmap_material_count = 0u32;
+    for lightmap in &mut bsp.lightmaps {
+        for material in &mut lightmap.materials {
+            material.rendered_vertices_type = VertexType::StructureBspUncompressedRenderedVertices;
+            material.rendered_vertices_offset = 0;
+            material.lightmap_vertices_type = VertexType::StructureBspUncompressedLightmapVertices;
+            material.lightmap_vertices_offset = material.rendered_vertices_count.saturating_mul(BSP_UNCOMPRESSED_RENDERED_VERTEX_LEN);
+            lightmap_material_count += 1;
+        }
+    }
+
+    let mut writer = CacheTagWriter::new();
+    let header_offset = writer.allocate(BSP_HEADER_LEN)?;
+    let bsp_offset = bsp.into_cache_tag(&mut writer)?;
+    writer.write_pointer(header_offset, bsp_offset)?;
+
+    // MCC stores the lightmap vertices differently, so the material count isn't in the header.
+    if !matches!(engine.script_compile_target, CompileTarget::HaloCEA) {
+        lightmap_material_count.into_cache(&mut writer, header_offset + 0x4)?;
+        lightmap_material_count.into_cache(&mut writer, header_offset + 0xC)?;
+    }
+    TagGroup::ScenarioStructureBSP.as_fourcc().into_cache(&mut writer, header_offset + 0x14)?;
+
+    Ok(writer)
+}
+
+/// Convert the script syntax data from big endian to little endian.
+///
+/// Nodes that refer to tags are given the tag IDs of those tags, adding them to the index if needed.
+fn convert_script_syntax_data(scenario: &mut Scenario, index: &mut CacheTagIndex) -> ErrorMessageResult<()> {
+    let syntax_data = &scenario.script_syntax_data;
+    let table_size = ScenarioScriptNodeTable::tag_size();
+    let node_size = ScenarioScriptNode::tag_size();
+    if syntax_data.len() < table_size {
+        return Ok(())
+    }
+
+    let table = ScenarioScriptNodeTable::from_tag(syntax_data, 0, table_size, &mut table_size.clone())?;
+    let node_count = (syntax_data.len() - table_size) / node_size;
+    let mut nodes = Vec::with_capacity(node_count);
+    for i in 0..node_count {
+        let node_offset = table_size + i * node_size;
+        let node_end = node_offset + node_size;
+        let mut node = ScenarioScriptNode::from_tag(syntax_data, node_offset, node_end, &mut node_end.clone())?;
+        if let Some(reference) = get_script_node_tag(scenario, &node, i)? {
+            node.data = ScenarioScriptNodeValue { id: tag_id_from_index(index.get_or_insert(&reference)) };
+        }
+        nodes.push(node);
+    }
+
+    let mut new_syntax_data = into_cache_standalone(&[table])?;
+    new_syntax_data.extend_from_slice(&into_cache_standalone(&nodes)?);
+    new_syntax_data.resize(syntax_data.len(), 0);
+    scenario.script_syntax_data = new_syntax_data;
+
+    Ok(())
+}
+
+/// Get the tag that a script node refers to, or `None` if it does not refer to one.
+///
+/// The tag is looked up in the scenario's references, since the node only has the path and object definitions can be
+/// of any object group.
+fn get_script_node_tag(scenario: &Scenario, node: &ScenarioScriptNode, node_index: usize) -> ErrorMessageResult<Option<TagReference>> {
+    // Globals refer to tags by the global's name, and "none" is stored as a null tag ID.
+    if !node.flags.is_primitive || node.flags.is_global || !SCRIPT_TAG_VALUE_TYPES.contains(&node._type) || unsafe { node.data.unsigned_long_int } == u32::MAX {
+        return Ok(None)
+    }
+
+    let offset = node.string_offset as usize;
+    let path = match scenario.script_string_data.get(offset..) {
+        Some(n) => &n[..n.iter().position(|&c| c == 0).unwrap_or(n.len())],
+        None => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.cache.error_script_invalid_string"), node=node_index, offset=offset)))
+    };
+
+    scenario.references
+            .blocks
+            .iter()
+            .map(|r| &r.reference)
+            .find(|r| script_value_type_refers_to_group(node._type, r.get_group()) && script_token_matches_path(path, r.get_path_without_extension()))
+            .map(|r| Some(r.clone()))
+            .ok_or_else(|| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.cache.error_script_tag_not_referenced"), tag=String::from_utf8_lossy(path))))
+}
+
+/// Move the bitmap's pixel data into the cache file's raw data, which starts at `file_offset` of the cache file.
+fn preprocess_bitmap(bitmap: &mut Bitmap, tag_id: TagID, raw_data: &mut Vec<u8>, file_offset: usize) -> ErrorMessageResult<()> {
+    let pixel_data = &bitmap.processed_pixel_data;
+    let mut offsets: Vec<usize> = bitmap.bitmap_data.blocks.iter().map(|b| b.pixel_data_offset as usize).collect();
+    offsets.sort();
+
+    for (i, data) in bitmap.bitmap_data.blocks.iter_mut().enumerate() {
+        // Each bitmap data's pixels end where the next one starts.
+        let start = data.pixel_data_offset as usize;
+        let end = offsets.iter().copied().find(|o| *o > start).unwrap_or(pixel_data.len());
+        if start > pixel_data.len() || end > pixel_data.len() {
+            return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.cache.error_bitmap_data_out_of_bounds"), bitmap_data=i)));
+        }
+
+        let new_offset = file_offset + raw_data.len();
+        raw_data.extend_from_slice(&pixel_data[start..end]);
+
+        data.pixel_data_offset = u32::try_from(new_offset).map_err(|_| ErrorMessage::StaticString(get_compiled_string!("engine.h1.types.serialize.error_architecture_limit_exceeded")))?;
+        data.pixel_data_size = (end - start) as u32;
+        data.bitmap_tag_id = tag_id;
+        data.pointer = 0;
+    }
+
+    Ok(())
+}
+
+/// Set the cache only fields of the font.
+fn preprocess_font(font: &mut Font) {
+    font.leading_width = ((font.ascending_height as i32 + font.descending_height as i32) / 5) as i16;
+    font.generate_character_tables();
+}
+
+/// Build an object tag that has no other preprocessing.
+fn build_object_tag<T: TagFileSerializeFn + ObjectTag>(data: &[u8], writer: &mut CacheTagWriter) -> ErrorMessageResult<usize> {
+    let mut tag = T::from_tag_file(data)?.data;
+    preprocess_object(tag.object_mut());
+    tag.into_cache_tag(writer)
+}
+
+/// Accumulate the weights of the object's change color permutations, which is how they are stored in cache files.
+fn preprocess_object(object: &mut Object) {
+    for change_color in &mut object.change_colors {
+        let mut total = 0.0;
+        for permutation in &mut change_color.permutations {
+            total += permutation.weight;
+            permutation.weight = total;
+        }
+    }
+}
+
+/// Convert the projectile's velocities from world units per second to world units per tick.
+fn preprocess_projectile(projectile: &mut Projectile) {
+    projectile.initial_velocity /= TICK_RATE;
+    projectile.final_velocity /= TICK_RATE;
+}
+
+/// Set the cache only fields of the sound, and convert its maximum bend from per second to per tick.
+fn preprocess_sound(sound: &mut Sound, tag_id: TagID) {
+    sound.maximum_bend_per_second = sound.maximum_bend_per_second.powf(1.0 / TICK_RATE);
+    sound.unknown_ffffffff_0 = 0xFFFFFFFF;
+    sound.unknown_ffffffff_1 = 0xFFFFFFFF;
+
+    for pitch_range in &mut sound.pitch_ranges {
+        pitch_range.playback_rate = if pitch_range.natural_pitch == 0.0 { 1.0 } else { 1.0 / pitch_range.natural_pitch };
+        pitch_range.unknown_ffffffff_0 = 0xFFFFFFFF;
+        pitch_range.unknown_ffffffff_1 = 0xFFFFFFFF;
+
+        for permutation in &mut pitch_range.permutations {
+            permutation.samples_pointer = 0;
+            permutation.tag_id_0 = tag_id;
+            permutation.tag_id_1 = tag_id;
+        }
+    }
+}
+
+/// Model vertices and indices stored outside of tag data.
+#[derive(Default)]
+struct ModelData {
+    vertices: Vec<u8>,
+    indices: Vec<u8>,
+    part_count: usize
+}
+
+impl ModelData {
+    /// Move the part's vertices and triangles into the model data.
+    fn add_part(&mut self, part: &mut ModelGeometryPart) -> ErrorMessageResult<()> {
+        let overflow = || ErrorMessage::StaticString(get_compiled_string!("engine.h1.types.serialize.error_architecture_limit_exceeded"));
+
+        // Triangles are stored as a triangle strip, where trailing null indices pad the last triangle.
+        let mut strip: Vec<Index> = Vec::with_capacity(part.triangles.blocks.len() * 3);
+        for t in &part.triangles {
+            strip.push(t.vertex0_index);
+            strip.push(t.vertex1_index);
+            strip.push(t.vertex2_index);
+        }
+        while strip.last() == Some(&None) {
+            strip.pop();
+        }
+
+        part.vertex_type = VertexType::ModelUncompressed;
+        part.vertex_count = part.uncompressed_vertices.blocks.len() as u32;
+        part.vertex_offset = u32::try_from(self.vertices.len()).map_err(|_| overflow())?;
+        part.vertex_pointer = 0;
+        self.vertices.extend_from_slice(&into_cache_standalone(&part.uncompressed_vertices.blocks)?);
+
+        part.triangle_buffer_type = TriangleBufferType::TriangleStrip;
+        part.triangle_count = strip.len().saturating_sub(2) as u32;
+        part.triangle_offset = u32::try_from(self.indices.len()).map_err(|_| overflow())?;
+        part.triangle_offset_2 = part.triangle_offset;
+        self.indices.extend_from_slice(&into_cache_standalone(&strip)?);
+
+        self.part_count += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+use crate::error::*;
+use crate::engines::h1::*;
+use crate::engines::h1::definitions::*;
+use ringhopper_proc::*;
+use super::build::{ObjectTag, TICK_RATE};
+
+/// Extract the tag from the cache file, returning tag file data.
+///
+/// Data that was moved outside of tag data when the cache file was built, such as model vertices and bitmap pixel
+/// data, is recovered, and cache-only fields are dropped. Values that were converted when the cache file was built,
+/// such as projectile velocities, sound bends, and object change color weights, are converted back.
+pub fn extract_tag(cache_file: &CacheFile, reference: &TagReference) -> ErrorMessageResult<Vec<u8>> {
+    match reference.get_group() {
+        TagGroup::Bitmap => {
+            let mut bitmap = cache_file.get_tag::<Bitmap>(reference)?;
+            extract_bitmap_pixel_data(cache_file, reference, &mut bitmap)?;
+            bitmap.into_tag_file()
+        },
+        TagGroup::GBXModel => {
+            let mut model = cache_file.get_tag::<GBXModel>(reference)?;
+            for geometry in &mut model.geometries {
+                for part in &mut geometry.parts {
+                    extract_model_part_data(cache_file, &mut part.base_struct)?;
+                }
+            }
+
+            // Gearbox models are always rendered with uncompressed vertices.
+            for geometry in &mut model.geometries {
+                for part in &mut geometry.parts {
+                    let part = &mut part.base_struct;
+                    if part.uncompressed_vertices.blocks.is_empty() {
+                        part.uncompressed_vertices.blocks = part.compressed_vertices.blocks.iter().map(ModelVertexUncompressed::decompress).collect();
+                    }
+                    part.compressed_vertices.blocks.clear();
+                }
+            }
+
+            model.into_tag_file()
+        },
+        TagGroup::Model => {
+            let mut model = cache_file.get_tag::<Model>(reference)?;
+            for geometry in &mut model.geometries {
+                for part in &mut geometry.parts {
+                    extract_model_part_data(cache_file, part)?;
+                }
+            }
+            regenerate_uncompressed_vertices(&mut model);
+            model.into_tag_file()
+        },
+        TagGroup::Scenario => {
+            let mut scenario = cache_file.get_tag::<Scenario>(reference)?;
+            extract_script_syntax_data(cache_file, &mut scenario)?;
+            scenario.into_tag_file()
+        },
+        TagGroup::Sound => {
+            let mut sound = cache_file.get_tag::<Sound>(reference)?;
+            reprocess_sound(&mut sound);
+            sound.into_tag_file()
+        },
+        TagGroup::Projectile => {
+            let mut projectile = cache_file.get_tag::<Projectile>(reference)?;
+            reprocess_projectile(&mut projectile);
+            reprocess_object(projectile.object_mut());
+            projectile.into_tag_file()
+        },
+        TagGroup::Biped => extract_object_tag::<Biped>(cache_file, reference),
+        TagGroup::Vehicle => extract_object_tag::<Vehicle>(cache_file, reference),
+        TagGroup::Weapon => extract_object_tag::<Weapon>(cache_file, reference),
+        TagGroup::Equipment => extract_object_tag::<Equipment>(cache_file, reference),
+        TagGroup::Garbage => extract_object_tag::<Garbage>(cache_file, reference),
+        TagGroup::DeviceMachine => extract_object_tag::<DeviceMachine>(cache_file, reference),
+        TagGroup::DeviceControl => extract_object_tag::<DeviceControl>(cache_file, reference),
+        TagGroup::DeviceLightFixture => extract_object_tag::<DeviceLightFixture>(cache_file, reference),
+        TagGroup::Scenery => extract_object_tag::<Scenery>(cache_file, reference),
+        TagGroup::Placeholder => extract_object_tag::<Placeholder>(cache_file, reference),
+        TagGroup::SoundScenery => extract_object_tag::<SoundScenery>(cache_file, reference),
+        _ => cache_file.parse_tag(reference)?.into_tag_file()
+    }
+}
+
+/// Extract an object tag that has no other reprocessing.
+fn extract_object_tag<T: TagFileSerializeFn + ObjectTag>(cache_file: &CacheFile, reference: &TagReference) -> ErrorMessageResult<Vec<u8>> {
+    let mut tag = cache_file.get_tag::<T>(reference)?;
+    reprocess_object(tag.object_mut());
+    tag.into_tag_file()
+}
+
+/// Turn the accumulated weights of the object's change color permutations back into the weight of each permutation.
+fn reprocess_object(object: &mut Object) {
+    for change_color in &mut object.change_colors {
+        let mut previous = 0.0;
+        for permutation in &mut change_color.permutations {
+            let total = permutation.weight;
+            permutation.weight = total - previous;
+            previous = total;
+        }
+    }
+}
+
+/// Convert the projectile's velocities from world units per tick back to world units per second.
+fn reprocess_projectile(projectile: &mut Projectile) {
+    projectile.initial_velocity *= TICK_RATE;
+    projectile.final_velocity *= TICK_RATE;
+}
+
+/// Convert the sound's maximum bend from per tick back to per second.
+fn reprocess_sound(sound: &mut Sound) {
+    sound.maximum_bend_per_second = sound.maximum_bend_per_second.powf(TICK_RATE);
+}
+
+/// Read the pixel data of each bitmap data back into the processed pixel data.
+fn extract_bitmap_pixel_data(cache_file: &CacheFile, reference: &TagReference, bitmap: &mut Bitmap) -> ErrorMessageResult<()> {
+    let (reader, _) = cache_file.get_tag_reader(reference)?;
+    let mut processed_pixel_data = Vec::new();
+
+    for data in &mut bitmap.bitmap_data {
+        let offset = data.pixel_data_offset as usize;
+        let size = data.pixel_data_size as usize;
+        let pixels = if data.flags.external {
+            reader.read_resource_file(ResourceMapType::Bitmaps.file_name(), offset, size)?
+        }
+        else {
+            reader.read_file(offset, size)?
+        };
+
+        data.pixel_data_offset = u32::try_from(processed_pixel_data.len()).map_err(|_| ErrorMessage::StaticString(get_compiled_string!("engine.h1.types.serialize.error_architecture_limit_exceeded")))?;
+        processed_pixel_data.extend_from_slice(pixels);
+    }
+
+    bitmap.processed_pixel_data = processed_pixel_data;
+    Ok(())
+}
+
+/// Read the vertices and triangles of the model part from the model data of the cache file.
+fn extract_model_part_data(cache_file: &CacheFile, part: &mut ModelGeometryPart) -> ErrorMessageResult<()> {
+    let tag_data_header = cache_file.tag_data_header();
+    let model_data_offset = tag_data_header.model_data_file_offset as usize;
+    let reader = CacheTagReader::new(&[], 0, cache_file.data(), cache_file.tags());
+
+    // Read the vertices.
+    let vertex_count = part.vertex_count as usize;
+    let vertex_offset = model_data_offset + part.vertex_offset as usize;
+    match part.vertex_type {
+        VertexType::ModelCompressed => {
+            let vertex_size = ModelVertexCompressed::tag_size();
+            let vertex_data = reader.read_file(vertex_offset, vertex_count.saturating_mul(vertex_size))?;
+            part.compressed_vertices.blocks = (0..vertex_count).map(|i| ModelVertexCompressed::from_cache(&reader, vertex_data, i * vertex_size)).collect::<ErrorMessageResult<Vec<_>>>()?;
+        },
+        _ => {
+            let vertex_size = ModelVertexUncompressed::tag_size();
+            let vertex_data = reader.read_file(vertex_offset, vertex_count.saturating_mul(vertex_size))?;
+            part.uncompressed_vertices.blocks = (0..vertex_count).map(|i| ModelVertexUncompressed::from_cache(&reader, vertex_data, i * vertex_size)).collect::<ErrorMessageResult<Vec<_>>>()?;
+        }
+    }
+
+    // Read the indices. Triangle strips have two more indices than triangles.
+    let triangle_count = part.triangle_count as usize;
+    let index_count = match part.triangle_buffer_type {
+        TriangleBufferType::TriangleList => triangle_count.saturating_mul(3),
+        TriangleBufferType::TriangleStrip => triangle_count.saturating_add(2)
+    };
+    let index_offset = model_data_offset + tag_data_header.vertex_size as usize + part.triangle_offset as usize;
+    let index_data = reader.read_file(index_offset, index_count.saturating_mul(Index::tag_size()))?;
+    let mut indices = (0..index_count).map(|i| Index::from_cache(&reader, index_data, i * Index::tag_size())).collect::<ErrorMessageResult<Vec<_>>>()?;
+
+    // Pad the last triangle with null indices.
+    while indices.len() % 3 != 0 {
+        indices.push(None);
+    }
+    part.triangles.blocks = indices.chunks(3).map(|t| ModelTriangle { vertex0_index: t[0], vertex1_index: t[1], vertex2_index: t[2] }).collect();
+
+    Ok(())
+}
+
+/// Convert the script syntax data from little endian back to big endian.
+fn extract_script_syntax_data(cache_file: &CacheFile, scenario: &mut Scenario) -> ErrorMessageResult<()> {
+    let syntax_data = &scenario.script_syntax_data;
+    let table_size = ScenarioScriptNodeTable::tag_size();
+    let node_size = ScenarioScriptNode::tag_size();
+    if syntax_data.len() < table_size {
+        return Ok(())
+    }
+
+    let reader = CacheTagReader::new(&[], 0, cache_file.data(), cache_file.tags());
+    let mut new_syntax_data = vec![0u8; syntax_data.len()];
+    ScenarioScriptNodeTable::from_cache(&reader, syntax_data, 0)?.into_tag(&mut new_syntax_data, 0, table_size)?;
+
+    let node_count = (syntax_data.len() - table_size) / node_size;
+    for i in 0..node_count {
+        let node_offset = table_size + i * node_size;
+        ScenarioScriptNode::from_cache(&reader, syntax_data, node_offset)?.into_tag(&mut new_syntax_data, node_offset, node_offset + node_size)?;
+    }
+
+    scenario.script_syntax_data = new_syntax_data;
+    Ok(())
+}
@@ -0,0 +1,304 @@
+use crate::error::*;
+use crate::types::tag::TagGroupFn;
+use crate::engines::h1::*;
+use crate::engines::h1::definitions::Scenario;
+use ringhopper_proc::*;
+
+/// BSP data stored outside of tag data.
+#[derive(Clone, Copy)]
+struct CacheFileBSP {
+    tag_id: TagID,
+    start: usize,
+    size: usize,
+    address: Pointer
+}
+
+/// Cache file that was loaded into memory.
+pub struct CacheFile {
+    data: Vec<u8>,
+    header: CacheFileHeader,
+    tag_data_header: CacheTagDataHeader,
+    engine: &'static EngineTarget,
+    engine_exact_match: bool,
+    base_address: Pointer,
+    tag_data_offset: usize,
+    tag_data_size: usize,
+    tags: Vec<TagReference>,
+    tag_array: Vec<CacheTagArrayEntry>,
+    bsps: Vec<CacheFileBSP>,
+    resource_maps: Vec<ResourceMap>
+}
+
+impl CacheFile {
+    /// Parse the header of the cache file, detecting the engine it was built for.
+    ///
+    /// The bool will be `true` if the engine is an exact match. Headers are not compressed, so unlike
+    /// [`CacheFile::from_data`], this also works on compressed cache files.
+    pub fn read_header(data: &[u8]) -> ErrorMessageResult<(CacheFileHeader, &'static EngineTarget, bool)> {
+        let layout = match CacheFileHeader::detect_layout(data) {
+            Some(n) => n,
+            None => return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.cache.error_not_a_cache_file")))
+        };
+        let header = CacheFileHeader::from_bytes(data, layout)?;
+
+        match EngineTarget::from_cache_file_metadata(header.cache_file_version, header.build.to_str()) {
+            Some((engine, engine_exact_match)) => Ok((header, engine, engine_exact_match)),
+            None => Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.cache.error_unknown_engine"), version=header.cache_file_version, build=header.build)))
+        }
+    }
+
+    /// Parse the cache file, detecting the engine it was built for.
+    pub fn from_data(data: Vec<u8>) -> ErrorMessageResult<CacheFile> {
+        let (header, engine, engine_exact_match) = CacheFile::read_header(&data)?;
+        if engine.compressed {
+            return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.cache.error_target_unsupported"), engine=engine.name)));
+        }
+
+        // Find the tag data.
+        let tag_data_offset = header.tag_data_offset as usize;
+        let tag_data_size = header.tag_data_size as usize;
+        let tag_data = match tag_data_offset.checked_add(tag_data_size) {
+            Some(end) if end <= data.len() => &data[tag_data_offset..end],
+            _ => return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.cache.error_corrupt_cache_file")))
+        };
+        let tag_data_header = CacheTagDataHeader::from_bytes(tag_data)?;
+
+        // Inferred addresses are only known by where the tag array is, since it comes right after the tag data header.
+        let base_address = match engine.base_memory_address {
+            BaseMemoryAddressType::Fixed(n) => n,
+            BaseMemoryAddressType::Inferred(_) => match tag_data_header.tag_array_address.checked_sub(CACHE_TAG_DATA_HEADER_LEN as u32) {
+                Some(n) => n,
+                None => return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.cache.error_corrupt_cache_file")))
+            }
+        };
+
+        // Read the tag array.
+        let tag_count = tag_data_header.tag_count as usize;
+        let reader = CacheTagReader::new(tag_data, base_address, &data, &[]);
+        let tag_array_size = match tag_count.checked_mul(CACHE_TAG_ARRAY_ENTRY_LEN) {
+            Some(n) => n,
+            None => return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.types.serialize.error_architecture_limit_exceeded")))
+        };
+        let tag_array_data = reader.read(tag_data_header.tag_array_address, tag_array_size)?;
+
+        let mut tag_array = Vec::with_capacity(tag_count);
+        let mut tags = Vec::with_capacity(tag_count);
+        for i in 0..tag_count {
+            let offset = i * CACHE_TAG_ARRAY_ENTRY_LEN;
+            let entry = CacheTagArrayEntry::from_bytes(&tag_array_data[offset..offset + CACHE_TAG_ARRAY_ENTRY_LEN])?;
+            let group = match TagGroup::from_fourcc(entry.primary_group) {
+                Some(n) => n,
+                None => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.types.serialize.error_fourcc_invalid"), fourcc=entry.primary_group)))
+            };
+            tags.push(TagReference::from_path_and_group(reader.read_string(entry.path_address)?, group)?);
+            tag_array.push(entry);
+        }
+
+        let mut cache_file = CacheFile {
+            header,
+            tag_data_header,
+            engine,
+            engine_exact_match,
+            base_address,
+            tag_data_offset,
+            tag_data_size,
+            tags,
+            tag_array,
+            bsps: Vec::new(),
+            resource_maps: Vec::new(),
+            data
+        };
+
+        // BSPs are only found through the scenario tag.
+        let scenario_reference = match cache_file.get_tag_from_id(cache_file.tag_data_header.scenario_tag_id)? {
+            Some(n) if n.get_group() == TagGroup::Scenario => n.clone(),
+            _ => return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.cache.error_corrupt_cache_file")))
+        };
+        let scenario = cache_file.get_tag::<Scenario>(&scenario_reference)?;
+        for bsp in &scenario.structure_bsps {
+            let tag_id = match cache_file.tags.iter().position(|t| t == &bsp.structure_bsp) {
+                Some(n) => tag_id_from_index(n),
+                None => continue
+            };
+            cache_file.bsps.push(CacheFileBSP {
+                tag_id,
+                start: bsp.bsp_start as usize,
+                size: bsp.bsp_size as usize,
+                address: bsp.bsp_address
+            });
+        }
+
+        Ok(cache_file)
+    }
+
+    /// Get the cache file data.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Get the cache file header.
+    pub fn header(&self) -> &CacheFileHeader {
+        &self.header
+    }
+
+    /// Get the tag data header.
+    pub fn tag_data_header(&self) -> &CacheTagDataHeader {
+        &self.tag_data_header
+    }
+
+    /// Get the engine the cache file was built for.
+    pub fn engine(&self) -> &'static EngineTarget {
+        self.engine
+    }
+
+    /// Return `true` if the build string of the cache file matched the engine exactly rather than falling back.
+    pub fn engine_is_exact_match(&self) -> bool {
+        self.engine_exact_match
+    }
+
+    /// Get the address the tag data is loaded at.
+    pub fn base_address(&self) -> Pointer {
+        self.base_address
+    }
+
+    /// Get the amount of tag space used by tag data and BSPs.
+    ///
+    /// Only the largest BSP counts towards tag space unless BSPs occupy tag space for the engine.
+    pub fn tag_space_used(&self) -> usize {
+        let bsp_space = if self.engine.bsps_occupy_tag_space {
+            self.bsps.iter().map(|b| b.size).sum()
+        }
+        else {
+            self.bsps.iter().map(|b| b.size).max().unwrap_or(0)
+        };
+        self.tag_data_size + bsp_space
+    }
+
+    /// Get the maximum cache file size for the scenario type of the cache file.
+    pub fn max_cache_file_size(&self) -> usize {
+        self.engine.max_cache_file_size(self.header.map_type)
+    }
+
+    /// Calculate the CRC32 of the cache file from the BSPs, model data, and tag data.
+    ///
+    /// This will match the CRC32 in the header if the cache file was not modified after it was built.
+    pub fn calculate_crc32(&self) -> u32 {
+        let get_data = |start: usize, size: usize| -> &[u8] {
+            start.checked_add(size).and_then(|end| self.data.get(start..end)).unwrap_or(&[])
+        };
+
+        let mut crc32 = u32::MAX;
+        for bsp in &self.bsps {
+            crc32 = crate::crc::crc32_with_init(crc32, get_data(bsp.start, bsp.size));
+        }
+
+        let model_data_offset = self.tag_data_header.model_data_file_offset as usize;
+        let vertex_size = self.tag_data_header.vertex_size as usize;
+        let model_data_size = self.tag_data_header.model_data_size as usize;
+        crc32 = crate::crc::crc32_with_init(crc32, get_data(model_data_offset, vertex_size));
+        crc32 = crate::crc::crc32_with_init(crc32, get_data(model_data_offset.saturating_add(vertex_size), model_data_size.saturating_sub(vertex_size)));
+
+        crate::crc::crc32_with_init(crc32, get_data(self.tag_data_offset, self.tag_data_size))
+    }
+
+    /// Get all tags in the order of the tag array.
+    pub fn tags(&self) -> &[TagReference] {
+        &self.tags
+    }
+
+    /// Get all entries of the tag array.
+    pub fn tag_array(&self) -> &[CacheTagArrayEntry] {
+        &self.tag_array
+    }
+
+    /// Add a resource map for reading tags and data stored outside of the cache file.
+    ///
+    /// This replaces any resource map of the same type that was previously added.
+    pub fn add_resource_map(&mut self, resource_map: ResourceMap) {
+        self.resource_maps.retain(|m| m.resource_type() != resource_map.resource_type());
+        self.resource_maps.push(resource_map);
+    }
+
+    /// Get all resource maps that were added.
+    pub fn resource_maps(&self) -> &[ResourceMap] {
+        &self.resource_maps
+    }
+
+    /// Get the tag referred to by the tag ID, or `None` if the tag ID is null.
+    pub fn get_tag_from_id(&self, id: TagID) -> ErrorMessageResult<Option<&TagReference>> {
+        self.tag_data_reader().get_tag(id)
+    }
+
+    /// Get the index of the tag in the tag array, or `None` if it is not in the cache file.
+    pub fn get_tag_index(&self, reference: &TagReference) -> Option<usize> {
+        self.tags.iter().position(|t| t == reference)
+    }
+
+    /// Get a reader for the memory region the tag is in as well as the address of the tag struct.
+    pub fn get_tag_reader(&self, reference: &TagReference) -> ErrorMessageResult<(CacheTagReader<'_>, Pointer)> {
+        let index = match self.get_tag_index(reference) {
+            Some(n) => n,
+            None => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("general.error_tag_not_found"), tag=reference)))
+        };
+
+        // BSPs are loaded at their own address, with the address of the BSP struct at the start of the BSP header.
+        if reference.get_group() == TagGroup::ScenarioStructureBSP {
+            let tag_id = tag_id_from_index(index);
+            let bsp = match self.bsps.iter().find(|b| b.tag_id == tag_id) {
+                Some(n) => n,
+                None => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.cache.error_bsp_not_loaded"), tag=reference)))
+            };
+            let bsp_data = match bsp.start.checked_add(bsp.size) {
+                Some(end) if end <= self.data.len() => &self.data[bsp.start..end],
+                _ => return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.cache.error_corrupt_cache_file")))
+            };
+            let reader = CacheTagReader::new(bsp_data, bsp.address, &self.data, &self.tags).with_resource_maps(&self.resource_maps);
+            let address = u32::from_tag_cached(bsp_data, 0x0, 0x4)?;
+            return Ok((reader, address))
+        }
+
+        // External tags are stored in a resource map, loaded at address 0.
+        let entry = &self.tag_array[index];
+        if entry.external {
+            let resource_map_type = match reference.get_group() {
+                TagGroup::Bitmap => ResourceMapType::Bitmaps,
+                TagGroup::Sound => ResourceMapType::Sounds,
+                _ => ResourceMapType::Loc
+            };
+            let resource_map = match self.resource_maps.iter().find(|m| m.resource_type() == resource_map_type) {
+                Some(n) => n,
+                None => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.cache.error_external_tag"), tag=reference, map=resource_map_type.file_name())))
+            };
+
+            // Sounds are looked up by path, as their index is not reliable.
+            let resource_data = match resource_map_type {
+                ResourceMapType::Sounds => resource_map.find_resource_data(reference.get_path_without_extension()),
+                _ => resource_map.get_resource_data(entry.data_address as usize)
+            };
+            let resource_data = match resource_data {
+                Some(n) => n,
+                None => return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.cache.error_corrupt_resource_map")))
+            };
+
+            let reader = CacheTagReader::new(resource_data, 0, &self.data, &self.tags).with_resource_maps(&self.resource_maps);
+            return Ok((reader, 0))
+        }
+
+        Ok((self.tag_data_reader(), entry.data_address))
+    }
+
+    /// Get the tag as the given tag struct.
+    pub fn get_tag<T: TagFileSerializeFn>(&self, reference: &TagReference) -> ErrorMessageResult<T> {
+        T::from_cache_tag(self, reference)
+    }
+
+    /// Get the tag as a tag struct of its group.
+    pub fn parse_tag(&self, reference: &TagReference) -> ErrorMessageResult<Box<dyn TagFileSerializeFn>> {
+        crate::engines::h1::definitions::parse_cache_tag(self, reference)
+    }
+
+    fn tag_data_reader(&self) -> CacheTagReader<'_> {
+        let tag_data = &self.data[self.tag_data_offset..self.tag_data_offset + self.tag_data_size];
+        CacheTagReader::new(tag_data, self.base_address, &self.data, &self.tags).with_resource_maps(&self.resource_maps)
+    }
+}
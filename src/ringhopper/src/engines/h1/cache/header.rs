@@ -0,0 +1,273 @@
+use crate::error::*;
+use crate::types::*;
+use crate::engines::h1::{TagSerialize, HeaderLayout, TagID, Pointer};
+use crate::engines::h1::definitions::ScenarioType;
+use ringhopper_proc::*;
+
+/// Size of the cache file header.
+pub const CACHE_FILE_HEADER_LEN: usize = 0x800;
+
+/// FourCC "head" used for the start of standard cache file headers.
+pub const HEAD_FOURCC: u32 = 0x68656164;
+
+/// FourCC "foot" used for the end of standard cache file headers.
+pub const FOOT_FOURCC: u32 = 0x666F6F74;
+
+/// FourCC "Ehed" used for the start of Gearbox demo cache file headers.
+pub const GEARBOX_DEMO_HEAD_FOURCC: u32 = 0x45686564;
+
+/// FourCC "Gfot" used for the end of Gearbox demo cache file headers.
+pub const GEARBOX_DEMO_FOOT_FOURCC: u32 = 0x47666F74;
+
+/// FourCC "tags" used for the end of tag data headers.
+pub const TAGS_FOURCC: u32 = 0x74616773;
+
+/// Size of the tag data header for PC cache files.
+pub const CACHE_TAG_DATA_HEADER_LEN: usize = 0x28;
+
+/// Size of an entry in the tag array.
+pub const CACHE_TAG_ARRAY_ENTRY_LEN: usize = 0x20;
+
+/// Header of a cache file.
+#[derive(Clone, Default)]
+pub struct CacheFileHeader {
+    /// Version of the cache file. See [`EngineTarget::cache_file_version`](crate::engines::h1::EngineTarget::cache_file_version).
+    pub cache_file_version: u32,
+
+    /// Size of the cache file when decompressed.
+    pub decompressed_file_size: u32,
+
+    /// Padding after the compressed data. Only used on compressed cache files.
+    pub compressed_padding: u32,
+
+    /// Offset of the tag data in the cache file.
+    pub tag_data_offset: u32,
+
+    /// Size of the tag data.
+    pub tag_data_size: u32,
+
+    /// Name of the scenario.
+    pub name: String32,
+
+    /// Build of the engine the cache file was built for.
+    pub build: String32,
+
+    /// Type of the scenario.
+    pub map_type: ScenarioType,
+
+    /// CRC32 of the cache file.
+    pub crc32: u32
+}
+
+impl CacheFileHeader {
+    /// Serialize the header into bytes using the given layout.
+    pub fn into_bytes(&self, layout: HeaderLayout) -> ErrorMessageResult<Vec<u8>> {
+        let mut data = vec![0u8; CACHE_FILE_HEADER_LEN];
+        let end = CACHE_FILE_HEADER_LEN;
+
+        match layout {
+            HeaderLayout::Standard => {
+                HEAD_FOURCC.into_tag_cached(&mut data, 0x0, end)?;
+                self.cache_file_version.into_tag_cached(&mut data, 0x4, end)?;
+                self.decompressed_file_size.into_tag_cached(&mut data, 0x8, end)?;
+                self.compressed_padding.into_tag_cached(&mut data, 0xC, end)?;
+                self.tag_data_offset.into_tag_cached(&mut data, 0x10, end)?;
+                self.tag_data_size.into_tag_cached(&mut data, 0x14, end)?;
+                data[0x20..0x40].copy_from_slice(&self.name.bytes);
+                data[0x40..0x60].copy_from_slice(&self.build.bytes);
+                (self.map_type as u16).into_tag_cached(&mut data, 0x60, end)?;
+                self.crc32.into_tag_cached(&mut data, 0x64, end)?;
+                FOOT_FOURCC.into_tag_cached(&mut data, 0x7FC, end)?;
+            },
+            HeaderLayout::GearboxDemo => {
+                (self.map_type as u16).into_tag_cached(&mut data, 0x2, end)?;
+                GEARBOX_DEMO_HEAD_FOURCC.into_tag_cached(&mut data, 0x2C0, end)?;
+                self.tag_data_size.into_tag_cached(&mut data, 0x2C4, end)?;
+                data[0x2C8..0x2E8].copy_from_slice(&self.build.bytes);
+                self.cache_file_version.into_tag_cached(&mut data, 0x588, end)?;
+                data[0x58C..0x5AC].copy_from_slice(&self.name.bytes);
+                self.crc32.into_tag_cached(&mut data, 0x5B0, end)?;
+                self.decompressed_file_size.into_tag_cached(&mut data, 0x5E8, end)?;
+                self.tag_data_offset.into_tag_cached(&mut data, 0x5EC, end)?;
+                GEARBOX_DEMO_FOOT_FOURCC.into_tag_cached(&mut data, 0x7FC, end)?;
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Detect the layout of the header, returning `None` if it is not a cache file header.
+    pub fn detect_layout(data: &[u8]) -> Option<HeaderLayout> {
+        let end = CACHE_FILE_HEADER_LEN;
+        if data.len() < end {
+            return None
+        }
+
+        let fourcc_at = |at: usize| u32::from_tag_cached(data, at, end).unwrap();
+        if fourcc_at(0x0) == HEAD_FOURCC && fourcc_at(0x7FC) == FOOT_FOURCC {
+            Some(HeaderLayout::Standard)
+        }
+        else if fourcc_at(0x2C0) == GEARBOX_DEMO_HEAD_FOURCC && fourcc_at(0x7FC) == GEARBOX_DEMO_FOOT_FOURCC {
+            Some(HeaderLayout::GearboxDemo)
+        }
+        else {
+            None
+        }
+    }
+
+    /// Deserialize the header from bytes using the given layout.
+    pub fn from_bytes(data: &[u8], layout: HeaderLayout) -> ErrorMessageResult<CacheFileHeader> {
+        let end = CACHE_FILE_HEADER_LEN;
+        let string_at = |at: usize| match data.get(at..at + 32) {
+            Some(n) => String32::from_bytes(n.try_into().unwrap()),
+            None => Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.cache.error_corrupt_cache_file")))
+        };
+
+        match layout {
+            HeaderLayout::Standard => Ok(CacheFileHeader {
+                cache_file_version: u32::from_tag_cached(data, 0x4, end)?,
+                decompressed_file_size: u32::from_tag_cached(data, 0x8, end)?,
+                compressed_padding: u32::from_tag_cached(data, 0xC, end)?,
+                tag_data_offset: u32::from_tag_cached(data, 0x10, end)?,
+                tag_data_size: u32::from_tag_cached(data, 0x14, end)?,
+                name: string_at(0x20)?,
+                build: string_at(0x40)?,
+                map_type: ScenarioType::from_u16(u16::from_tag_cached(data, 0x60, end)?)?,
+                crc32: u32::from_tag_cached(data, 0x64, end)?
+            }),
+            HeaderLayout::GearboxDemo => Ok(CacheFileHeader {
+                cache_file_version: u32::from_tag_cached(data, 0x588, end)?,
+                decompressed_file_size: u32::from_tag_cached(data, 0x5E8, end)?,
+                compressed_padding: 0,
+                tag_data_offset: u32::from_tag_cached(data, 0x5EC, end)?,
+                tag_data_size: u32::from_tag_cached(data, 0x2C4, end)?,
+                name: string_at(0x58C)?,
+                build: string_at(0x2C8)?,
+                map_type: ScenarioType::from_u16(u16::from_tag_cached(data, 0x2, end)?)?,
+                crc32: u32::from_tag_cached(data, 0x5B0, end)?
+            })
+        }
+    }
+}
+
+/// Header at the start of the tag data of a PC cache file.
+#[derive(Clone, Default)]
+pub struct CacheTagDataHeader {
+    /// Address of the tag array.
+    pub tag_array_address: Pointer,
+
+    /// Tag ID of the scenario tag.
+    pub scenario_tag_id: TagID,
+
+    /// Checksum of the tag data. Unread.
+    pub checksum: u32,
+
+    /// Number of tags in the tag array.
+    pub tag_count: u32,
+
+    /// Number of model parts in the model data.
+    pub model_part_count: u32,
+
+    /// Offset of the model data in the cache file.
+    pub model_data_file_offset: u32,
+
+    /// Size of the vertex data in the model data. Indices are stored after this.
+    pub vertex_size: u32,
+
+    /// Size of the model data.
+    pub model_data_size: u32
+}
+
+impl CacheTagDataHeader {
+    /// Serialize the header into bytes.
+    pub fn into_bytes(&self) -> ErrorMessageResult<Vec<u8>> {
+        let mut data = vec![0u8; CACHE_TAG_DATA_HEADER_LEN];
+        let end = CACHE_TAG_DATA_HEADER_LEN;
+
+        self.tag_array_address.into_tag_cached(&mut data, 0x0, end)?;
+        self.scenario_tag_id.into_tag_cached(&mut data, 0x4, end)?;
+        self.checksum.into_tag_cached(&mut data, 0x8, end)?;
+        self.tag_count.into_tag_cached(&mut data, 0xC, end)?;
+        self.model_part_count.into_tag_cached(&mut data, 0x10, end)?;
+        self.model_data_file_offset.into_tag_cached(&mut data, 0x14, end)?;
+        self.model_part_count.into_tag_cached(&mut data, 0x18, end)?;
+        self.vertex_size.into_tag_cached(&mut data, 0x1C, end)?;
+        self.model_data_size.into_tag_cached(&mut data, 0x20, end)?;
+        TAGS_FOURCC.into_tag_cached(&mut data, 0x24, end)?;
+
+        Ok(data)
+    }
+
+    /// Deserialize the header from bytes.
+    pub fn from_bytes(data: &[u8]) -> ErrorMessageResult<CacheTagDataHeader> {
+        let end = CACHE_TAG_DATA_HEADER_LEN;
+        if u32::from_tag_cached(data, 0x24, end)? != TAGS_FOURCC {
+            return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.cache.error_corrupt_cache_file")))
+        }
+
+        Ok(CacheTagDataHeader {
+            tag_array_address: u32::from_tag_cached(data, 0x0, end)?,
+            scenario_tag_id: u32::from_tag_cached(data, 0x4, end)?,
+            checksum: u32::from_tag_cached(data, 0x8, end)?,
+            tag_count: u32::from_tag_cached(data, 0xC, end)?,
+            model_part_count: u32::from_tag_cached(data, 0x10, end)?,
+            model_data_file_offset: u32::from_tag_cached(data, 0x14, end)?,
+            vertex_size: u32::from_tag_cached(data, 0x1C, end)?,
+            model_data_size: u32::from_tag_cached(data, 0x20, end)?
+        })
+    }
+}
+
+/// Entry in the tag array of a cache file.
+#[derive(Clone, Default)]
+pub struct CacheTagArrayEntry {
+    /// FourCC of the tag's group.
+    pub primary_group: u32,
+
+    /// FourCC of the group the tag's group inherits, or 0xFFFFFFFF if none.
+    pub secondary_group: u32,
+
+    /// FourCC of the group the secondary group inherits, or 0xFFFFFFFF if none.
+    pub tertiary_group: u32,
+
+    /// Tag ID of the tag.
+    pub tag_id: TagID,
+
+    /// Address of the tag's path.
+    pub path_address: Pointer,
+
+    /// Address of the tag's data, or an index in a resource map if external.
+    pub data_address: Pointer,
+
+    /// The tag's data is in a resource map.
+    pub external: bool
+}
+
+impl CacheTagArrayEntry {
+    /// Serialize the entry into the given slice.
+    pub fn into_bytes(&self, data: &mut [u8]) -> ErrorMessageResult<()> {
+        let end = CACHE_TAG_ARRAY_ENTRY_LEN;
+        self.primary_group.into_tag_cached(data, 0x0, end)?;
+        self.secondary_group.into_tag_cached(data, 0x4, end)?;
+        self.tertiary_group.into_tag_cached(data, 0x8, end)?;
+        self.tag_id.into_tag_cached(data, 0xC, end)?;
+        self.path_address.into_tag_cached(data, 0x10, end)?;
+        self.data_address.into_tag_cached(data, 0x14, end)?;
+        (self.external as u32).into_tag_cached(data, 0x18, end)?;
+        Ok(())
+    }
+
+    /// Deserialize the entry from the given slice.
+    pub fn from_bytes(data: &[u8]) -> ErrorMessageResult<CacheTagArrayEntry> {
+        let end = CACHE_TAG_ARRAY_ENTRY_LEN;
+        Ok(CacheTagArrayEntry {
+            primary_group: u32::from_tag_cached(data, 0x0, end)?,
+            secondary_group: u32::from_tag_cached(data, 0x4, end)?,
+            tertiary_group: u32::from_tag_cached(data, 0x8, end)?,
+            tag_id: u32::from_tag_cached(data, 0xC, end)?,
+            path_address: u32::from_tag_cached(data, 0x10, end)?,
+            data_address: u32::from_tag_cached(data, 0x14, end)?,
+            external: u32::from_tag_cached(data, 0x18, end)? != 0
+        })
+    }
+}
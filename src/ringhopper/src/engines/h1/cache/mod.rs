@@ -0,0 +1,22 @@
+//! Cache file functionality for Halo: Combat Evolved.
+
+#[cfg(test)]
+mod tests;
+
+mod serialize;
+pub use self::serialize::*;
+
+mod header;
+pub use self::header::*;
+
+mod build;
+pub use self::build::*;
+
+mod resource;
+pub use self::resource::*;
+
+mod extract;
+pub use self::extract::*;
+
+mod file;
+pub use self::file::*;
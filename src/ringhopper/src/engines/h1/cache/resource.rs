@@ -0,0 +1,133 @@
+use crate::error::*;
+use crate::engines::h1::TagSerialize;
+use ringhopper_proc::*;
+
+/// Length of the resource map header in bytes.
+pub const RESOURCE_MAP_HEADER_LEN: usize = 0x10;
+
+/// Length of a resource entry in bytes.
+pub const RESOURCE_MAP_ENTRY_LEN: usize = 0xC;
+
+/// Type of resource map.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ResourceMapType {
+    /// Bitmap tags and pixel data (`bitmaps.map`)
+    Bitmaps,
+
+    /// Sound tags and sample data (`sounds.map`)
+    Sounds,
+
+    /// Font, unicode string list, and hud message text tags (`loc.map`)
+    Loc
+}
+
+impl ResourceMapType {
+    /// Get the resource map type from the value in the header.
+    pub fn from_u32(value: u32) -> Option<ResourceMapType> {
+        match value {
+            1 => Some(ResourceMapType::Bitmaps),
+            2 => Some(ResourceMapType::Sounds),
+            3 => Some(ResourceMapType::Loc),
+            _ => None
+        }
+    }
+
+    /// Get the file name of the resource map.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ResourceMapType::Bitmaps => "bitmaps.map",
+            ResourceMapType::Sounds => "sounds.map",
+            ResourceMapType::Loc => "loc.map"
+        }
+    }
+}
+
+/// Resource entry in a resource map.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Resource {
+    /// Path of the resource.
+    pub path: String,
+
+    /// Offset of the resource data in the file.
+    pub data_offset: usize,
+
+    /// Size of the resource data in bytes.
+    pub size: usize
+}
+
+/// Resource map that was loaded into memory.
+pub struct ResourceMap {
+    data: Vec<u8>,
+    resource_type: ResourceMapType,
+    resources: Vec<Resource>
+}
+
+impl ResourceMap {
+    /// Parse the resource map.
+    pub fn from_data(data: Vec<u8>) -> ErrorMessageResult<ResourceMap> {
+        let corrupt = || ErrorMessage::StaticString(get_compiled_string!("engine.h1.cache.error_corrupt_resource_map"));
+
+        if data.len() < RESOURCE_MAP_HEADER_LEN {
+            return Err(corrupt())
+        }
+
+        let resource_type = ResourceMapType::from_u32(u32::from_tag_cached(&data, 0x0, 0x4)?).ok_or_else(corrupt)?;
+        let paths_offset = u32::from_tag_cached(&data, 0x4, 0x8)? as usize;
+        let resources_offset = u32::from_tag_cached(&data, 0x8, 0xC)? as usize;
+        let resource_count = u32::from_tag_cached(&data, 0xC, 0x10)? as usize;
+
+        let resources_end = resource_count.checked_mul(RESOURCE_MAP_ENTRY_LEN).and_then(|n| n.checked_add(resources_offset)).ok_or_else(corrupt)?;
+        if resources_end > data.len() || paths_offset > data.len() {
+            return Err(corrupt())
+        }
+
+        let mut resources = Vec::with_capacity(resource_count);
+        for i in 0..resource_count {
+            let entry = resources_offset + i * RESOURCE_MAP_ENTRY_LEN;
+            let path_offset = paths_offset.checked_add(u32::from_tag_cached(&data, entry, entry + 0x4)? as usize).ok_or_else(corrupt)?;
+            let size = u32::from_tag_cached(&data, entry + 0x4, entry + 0x8)? as usize;
+            let data_offset = u32::from_tag_cached(&data, entry + 0x8, entry + 0xC)? as usize;
+
+            match data_offset.checked_add(size) {
+                Some(end) if end <= data.len() => (),
+                _ => return Err(corrupt())
+            }
+
+            let path_data = data.get(path_offset..).ok_or_else(corrupt)?;
+            let path_length = path_data.iter().position(|b| *b == 0).ok_or_else(corrupt)?;
+            let path = std::str::from_utf8(&path_data[..path_length]).map_err(|_| corrupt())?.to_owned();
+
+            resources.push(Resource { path, data_offset, size });
+        }
+
+        Ok(ResourceMap { data, resource_type, resources })
+    }
+
+    /// Get the type of resource map.
+    pub fn resource_type(&self) -> ResourceMapType {
+        self.resource_type
+    }
+
+    /// Get all resources in the resource map.
+    pub fn resources(&self) -> &[Resource] {
+        &self.resources
+    }
+
+    /// Get the data of the resource at the index, or `None` if it is out of bounds.
+    pub fn get_resource_data(&self, index: usize) -> Option<&[u8]> {
+        self.resources.get(index).map(|r| &self.data[r.data_offset..r.data_offset + r.size])
+    }
+
+    /// Get the data of the resource with the path, or `None` if it is not present.
+    pub fn find_resource_data(&self, path: &str) -> Option<&[u8]> {
+        self.resources.iter().position(|r| r.path == path).and_then(|i| self.get_resource_data(i))
+    }
+
+    /// Read `size` bytes at `offset` of the resource map.
+    pub fn read(&self, offset: usize, size: usize) -> ErrorMessageResult<&[u8]> {
+        match offset.checked_add(size) {
+            Some(end) if end <= self.data.len() => Ok(&self.data[offset..end]),
+            _ => Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.cache.error_corrupt_resource_map")))
+        }
+    }
+}
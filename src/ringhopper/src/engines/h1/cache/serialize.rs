@@ -0,0 +1,550 @@
+use crate::error::*;
+use crate::types::*;
+use crate::types::tag::{TagBlockFn, TagGroupFn};
+use crate::engines::h1::{ResourceMap, TagSerialize, TagGroup, TagReference, TagID, Pointer, Index, ScenarioScriptNodeValue, tag_size_instance};
+use ringhopper_proc::*;
+use std::collections::HashMap;
+
+/// Tags that are to be compiled into a cache file, in the order of the tag array.
+#[derive(Default)]
+pub struct CacheTagIndex {
+    tags: Vec<TagReference>,
+    lookup: HashMap<String, usize>
+}
+
+impl CacheTagIndex {
+    /// Get the index of the tag, adding it to the end of the index if it is not present.
+    pub fn get_or_insert(&mut self, reference: &TagReference) -> usize {
+        let key = reference.get_path_with_extension();
+        match self.lookup.get(&key) {
+            Some(n) => *n,
+            None => {
+                let index = self.tags.len();
+                self.tags.push(reference.clone());
+                self.lookup.insert(key, index);
+                index
+            }
+        }
+    }
+
+    /// Get the index of the tag or `None` if it is not present.
+    pub fn get(&self, reference: &TagReference) -> Option<usize> {
+        self.lookup.get(&reference.get_path_with_extension()).copied()
+    }
+
+    /// Get all tags in the index.
+    pub fn tags(&self) -> &[TagReference] {
+        &self.tags
+    }
+
+    /// Get the number of tags in the index.
+    pub fn len(&self) -> usize {
+        self.tags.len()
+    }
+
+    /// Return `true` if no tags are in the index.
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+}
+
+/// Get the tag ID of the tag at the given index of the tag array.
+pub fn tag_id_from_index(index: usize) -> TagID {
+    let index = index as u32 & 0xFFFF;
+    (((0xE741 + index) & 0xFFFF) << 16) | index
+}
+
+/// Get the index of the tag in the tag array from a tag ID, or `None` if the tag ID is null.
+pub fn tag_index_from_id(id: TagID) -> Option<usize> {
+    match id {
+        0xFFFFFFFF => None,
+        n => Some((n & 0xFFFF) as usize)
+    }
+}
+
+/// Buffer for writing tag data in cache format.
+///
+/// Pointers and tag references are resolved when the data is finished, as the final address and tag array are not
+/// known while tags are being serialized.
+#[derive(Default)]
+pub struct CacheTagWriter {
+    data: Vec<u8>,
+    pointers: Vec<usize>,
+    tag_references: Vec<(usize, TagReference)>,
+    file_data: Vec<u8>,
+    file_offsets: Vec<usize>
+}
+
+impl CacheTagWriter {
+    /// Create a new, empty writer.
+    pub fn new() -> CacheTagWriter {
+        CacheTagWriter::default()
+    }
+
+    /// Get the data written so far.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Get the data written so far as mutable.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Get all tag references written so far.
+    pub fn tag_references(&self) -> &[(usize, TagReference)] {
+        &self.tag_references
+    }
+
+    /// Allocate zeroed out data at the end of the buffer, aligned to 32 bits, returning the offset of the new data.
+    pub fn allocate(&mut self, size: usize) -> ErrorMessageResult<usize> {
+        let offset = self.data.len().checked_add(3).map(|n| n & !3);
+        let end = offset.and_then(|o| o.checked_add(size));
+        match (offset, end) {
+            (Some(offset), Some(end)) => {
+                self.data.resize(end, 0);
+                Ok(offset)
+            },
+            _ => Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.types.serialize.error_architecture_limit_exceeded")))
+        }
+    }
+
+    /// Write a pointer at `at` that points to `offset` in the buffer.
+    pub fn write_pointer(&mut self, at: usize, offset: usize) -> ErrorMessageResult<()> {
+        (offset as Pointer).into_cache(self, at)?;
+        self.pointers.push(at);
+        Ok(())
+    }
+
+    /// Write the tag ID and path address of the tag reference at `at` once the data is finished.
+    pub fn write_tag_reference(&mut self, at: usize, reference: &TagReference) {
+        self.tag_references.push((at, reference.clone()));
+    }
+
+    /// Append the data to the data stored outside of tag data and write a data field at `at` referring to it.
+    pub fn write_file_data(&mut self, data: &Data, at: usize) -> ErrorMessageResult<()> {
+        let size = data.len();
+        let limit = crate::engines::h1::MAX_ARRAY_LENGTH;
+        if size > limit {
+            return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.types.serialize.error_byte_array_limit_exceeded"), size=size, limit=limit)));
+        }
+
+        let offset = self.file_data.len();
+        self.file_data.extend_from_slice(data);
+        (size as u32).into_cache(self, at)?;
+        (offset as u32).into_cache(self, at + 0x8)?;
+        self.file_offsets.push(at + 0x8);
+        Ok(())
+    }
+
+    /// Finish writing the data, returning the tag data and the data stored outside of tag data.
+    ///
+    /// `address` is the address the data is loaded at, `file_offset` is the offset in the cache file the file data will
+    /// be placed, and `tag_path_addresses` are the addresses of the paths of each tag in `index`.
+    pub fn finish(mut self, address: Pointer, file_offset: usize, index: &CacheTagIndex, tag_path_addresses: &[Pointer]) -> ErrorMessageResult<(Vec<u8>, Vec<u8>)> {
+        let overflow = || ErrorMessage::StaticString(get_compiled_string!("engine.h1.types.serialize.error_architecture_limit_exceeded"));
+
+        for p in std::mem::take(&mut self.pointers) {
+            let offset = u32::from_tag_cached(&self.data, p, p + 4)?;
+            let pointer = address.checked_add(offset).ok_or_else(overflow)?;
+            pointer.into_cache(&mut self, p)?;
+        }
+
+        for p in std::mem::take(&mut self.file_offsets) {
+            let offset = u32::from_tag_cached(&self.data, p, p + 4)? as usize;
+            let new_offset: u32 = offset.checked_add(file_offset).and_then(|n| n.try_into().ok()).ok_or_else(overflow)?;
+            new_offset.into_cache(&mut self, p)?;
+        }
+
+        for (p, reference) in std::mem::take(&mut self.tag_references) {
+            let tag_index = match index.get(&reference) {
+                Some(n) => n,
+                None => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("general.error_tag_not_found"), tag=reference)))
+            };
+            tag_path_addresses[tag_index].into_cache(&mut self, p + 0x4)?;
+            tag_id_from_index(tag_index).into_cache(&mut self, p + 0xC)?;
+        }
+
+        Ok((self.data, self.file_data))
+    }
+}
+
+/// Memory region of a cache file for reading tag data in cache format.
+///
+/// Pointers are resolved against the address the region is loaded at, and tag IDs are resolved against the tag array.
+#[derive(Clone, Copy)]
+pub struct CacheTagReader<'a> {
+    memory: &'a [u8],
+    address: Pointer,
+    file: &'a [u8],
+    tags: &'a [TagReference],
+    resource_maps: &'a [ResourceMap]
+}
+
+impl<'a> CacheTagReader<'a> {
+    /// Create a reader for `memory` loaded at `address`.
+    ///
+    /// `file` is the entire cache file for reading data stored outside of tag data, and `tags` is every tag in the tag
+    /// array in order.
+    pub fn new(memory: &'a [u8], address: Pointer, file: &'a [u8], tags: &'a [TagReference]) -> CacheTagReader<'a> {
+        CacheTagReader { memory, address, file, tags, resource_maps: &[] }
+    }
+
+    /// Use `resource_maps` for reading data stored in external resource maps.
+    pub fn with_resource_maps(self, resource_maps: &'a [ResourceMap]) -> CacheTagReader<'a> {
+        CacheTagReader { resource_maps, ..self }
+    }
+
+    /// Read `size` bytes at `address`.
+    pub fn read(&self, address: Pointer, size: usize) -> ErrorMessageResult<&'a [u8]> {
+        let offset = self.offset_of(address)?;
+        match offset.checked_add(size) {
+            Some(end) if end <= self.memory.len() => Ok(&self.memory[offset..end]),
+            _ => Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.cache.error_corrupt_cache_file")))
+        }
+    }
+
+    /// Read a null-terminated UTF-8 string at `address`.
+    pub fn read_string(&self, address: Pointer) -> ErrorMessageResult<&'a str> {
+        let data = &self.memory[self.offset_of(address)?..];
+        let length = match data.iter().position(|b| *b == 0) {
+            Some(n) => n,
+            None => return Err(ErrorMessage::StaticString(get_compiled_string!("engine.types.error_string_not_null_terminated")))
+        };
+        match std::str::from_utf8(&data[..length]) {
+            Ok(n) => Ok(n),
+            Err(_) => Err(ErrorMessage::StaticString(get_compiled_string!("engine.types.error_string_not_valid_utf8")))
+        }
+    }
+
+    /// Read `size` bytes at `offset` of the cache file.
+    pub fn read_file(&self, offset: usize, size: usize) -> ErrorMessageResult<&'a [u8]> {
+        match offset.checked_add(size) {
+            Some(end) if end <= self.file.len() => Ok(&self.file[offset..end]),
+            _ => Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.cache.error_corrupt_cache_file")))
+        }
+    }
+
+    /// Read `size` bytes at `offset` of the resource map with the file name `resource_map`.
+    pub fn read_resource_file(&self, resource_map: &str, offset: usize, size: usize) -> ErrorMessageResult<&'a [u8]> {
+        match self.resource_maps.iter().find(|m| m.resource_type().file_name() == resource_map) {
+            Some(n) => n.read(offset, size),
+            None => Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.cache.error_resource_map_not_loaded"), map=resource_map)))
+        }
+    }
+
+    /// Read a data field at `at` that refers to data stored outside of tag data.
+    ///
+    /// If the data is flagged as external, it is read from `external_file` instead of the cache file.
+    pub fn read_file_data(&self, data: &[u8], at: usize, external_file: Option<&str>) -> ErrorMessageResult<Data> {
+        let size = u32::from_tag_cached(data, at, at + 0x14)? as usize;
+        let flags = u32::from_tag_cached(data, at + 0x4, at + 0x14)?;
+        let offset = u32::from_tag_cached(data, at + 0x8, at + 0x14)? as usize;
+        match external_file {
+            Some(n) if (flags & 1) != 0 => Ok(self.read_resource_file(n, offset, size)?.to_owned()),
+            _ => Ok(self.read_file(offset, size)?.to_owned())
+        }
+    }
+
+    /// Get the tag referred to by the tag ID, or `None` if the tag ID is null.
+    pub fn get_tag(&self, id: TagID) -> ErrorMessageResult<Option<&'a TagReference>> {
+        let index = match tag_index_from_id(id) {
+            Some(n) => n,
+            None => return Ok(None)
+        };
+        match self.tags.get(index) {
+            Some(n) if tag_id_from_index(index) == id => Ok(Some(n)),
+            _ => Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.cache.error_invalid_tag_id"), id=id)))
+        }
+    }
+
+    fn offset_of(&self, address: Pointer) -> ErrorMessageResult<usize> {
+        match address.checked_sub(self.address) {
+            Some(n) if (n as usize) <= self.memory.len() => Ok(n as usize),
+            _ => Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.cache.error_corrupt_cache_file")))
+        }
+    }
+}
+
+/// Serialization implementation for tags in cache format (little endian).
+pub trait CacheSerialize {
+    /// Serialize the data into cache format at `at`, returning an error on failure (except for out-of-bounds and allocation errors which will panic).
+    ///
+    /// The data at `at` must already be allocated with [`CacheTagWriter::allocate`].
+    fn into_cache(&self, writer: &mut CacheTagWriter, at: usize) -> ErrorMessageResult<()>;
+
+    /// Deserialize the data from cache format at `at` of `data`, returning an error on failure.
+    ///
+    /// Pointers and tag IDs are resolved with `reader`.
+    fn from_cache(reader: &CacheTagReader, data: &[u8], at: usize) -> ErrorMessageResult<Self> where Self: Sized;
+}
+
+macro_rules! cache_serialize_for_primitive {
+    ($t:ty) => {
+        impl CacheSerialize for $t {
+            fn into_cache(&self, writer: &mut CacheTagWriter, at: usize) -> ErrorMessageResult<()> {
+                self.into_tag_cached(writer.data_mut(), at, at + std::mem::size_of::<$t>())
+            }
+            fn from_cache(_: &CacheTagReader, data: &[u8], at: usize) -> ErrorMessageResult<Self> {
+                <$t>::from_tag_cached(data, at, at + std::mem::size_of::<$t>())
+            }
+        }
+    };
+}
+
+cache_serialize_for_primitive!(i8);
+cache_serialize_for_primitive!(i16);
+cache_serialize_for_primitive!(i32);
+cache_serialize_for_primitive!(u8);
+cache_serialize_for_primitive!(u16);
+cache_serialize_for_primitive!(u32);
+cache_serialize_for_primitive!(f32);
+
+macro_rules! cache_serialize_for_struct {
+    ($t:ty, $($fields:tt), +) => {
+        impl CacheSerialize for $t {
+            fn into_cache(&self, writer: &mut CacheTagWriter, at: usize) -> ErrorMessageResult<()> {
+                let mut at = at;
+                $(
+                    self.$fields.into_cache(writer, at)?;
+                    at += tag_size_instance(&self.$fields);
+                )+
+                let _ = at;
+                Ok(())
+            }
+            fn from_cache(reader: &CacheTagReader, data: &[u8], at: usize) -> ErrorMessageResult<Self> {
+                let mut new_object = <$t>::default();
+                let mut at = at;
+                $(
+                    new_object.$fields = CacheSerialize::from_cache(reader, data, at)?;
+                    at += tag_size_instance(&new_object.$fields);
+                )+
+                let _ = at;
+                Ok(new_object)
+            }
+        }
+    }
+}
+
+cache_serialize_for_struct!(ColorAHSV, a, h, s, v);
+cache_serialize_for_struct!(ColorARGB, a, r, g, b);
+cache_serialize_for_struct!(ColorHSV, h, s, v);
+cache_serialize_for_struct!(ColorRGB, r, g, b);
+cache_serialize_for_struct!(Euler2D, y, p);
+cache_serialize_for_struct!(Euler3D, y, p, r);
+cache_serialize_for_struct!(Plane2D, vector, d);
+cache_serialize_for_struct!(Plane3D, vector, d);
+cache_serialize_for_struct!(Point2D, x, y);
+cache_serialize_for_struct!(Point2DInt, x, y);
+cache_serialize_for_struct!(Point3D, x, y, z);
+cache_serialize_for_struct!(Quaternion, x, y, z, w);
+cache_serialize_for_struct!(Rectangle, top, left, bottom, right);
+cache_serialize_for_struct!(Vector2D, x, y);
+cache_serialize_for_struct!(Vector3D, x, y, z);
+
+impl<T: TagSerialize + CacheSerialize> CacheSerialize for Bounds<T> {
+    fn into_cache(&self, writer: &mut CacheTagWriter, at: usize) -> ErrorMessageResult<()> {
+        self.lower.into_cache(writer, at)?;
+        self.upper.into_cache(writer, at + T::tag_size())
+    }
+    fn from_cache(reader: &CacheTagReader, data: &[u8], at: usize) -> ErrorMessageResult<Self> {
+        Ok(Bounds {
+            lower: T::from_cache(reader, data, at)?,
+            upper: T::from_cache(reader, data, at + T::tag_size())?
+        })
+    }
+}
+
+impl CacheSerialize for ColorARGBInt {
+    fn into_cache(&self, writer: &mut CacheTagWriter, at: usize) -> ErrorMessageResult<()> {
+        self.to_a8r8g8b8().into_cache(writer, at)
+    }
+    fn from_cache(reader: &CacheTagReader, data: &[u8], at: usize) -> ErrorMessageResult<Self> {
+        Ok(ColorARGBInt::from_a8r8g8b8(u32::from_cache(reader, data, at)?))
+    }
+}
+
+impl CacheSerialize for ColorRGBInt {
+    fn into_cache(&self, writer: &mut CacheTagWriter, at: usize) -> ErrorMessageResult<()> {
+        ColorARGBInt::from(*self).to_a8r8g8b8().into_cache(writer, at)
+    }
+    fn from_cache(reader: &CacheTagReader, data: &[u8], at: usize) -> ErrorMessageResult<Self> {
+        Ok(ColorARGBInt::from_a8r8g8b8(u32::from_cache(reader, data, at)?).rgb())
+    }
+}
+
+impl CacheSerialize for String32 {
+    fn into_cache(&self, writer: &mut CacheTagWriter, at: usize) -> ErrorMessageResult<()> {
+        writer.data_mut()[at..at + 32].copy_from_slice(&self.bytes[..]);
+        Ok(())
+    }
+    fn from_cache(_: &CacheTagReader, data: &[u8], at: usize) -> ErrorMessageResult<Self> {
+        match data.get(at..at + 32) {
+            Some(n) => String32::from_bytes(n.try_into().unwrap()),
+            None => Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.cache.error_corrupt_cache_file")))
+        }
+    }
+}
+
+impl CacheSerialize for Matrix {
+    fn into_cache(&self, writer: &mut CacheTagWriter, at: usize) -> ErrorMessageResult<()> {
+        let vector_size = Vector3D::tag_size();
+        for i in 0..3 {
+            self.vectors[i].into_cache(writer, at + i * vector_size)?;
+        }
+        Ok(())
+    }
+    fn from_cache(reader: &CacheTagReader, data: &[u8], at: usize) -> ErrorMessageResult<Self> {
+        let vector_size = Vector3D::tag_size();
+        let mut matrix = Matrix::default();
+        for i in 0..3 {
+            matrix.vectors[i] = Vector3D::from_cache(reader, data, at + i * vector_size)?;
+        }
+        Ok(matrix)
+    }
+}
+
+impl CacheSerialize for Index {
+    fn into_cache(&self, writer: &mut CacheTagWriter, at: usize) -> ErrorMessageResult<()> {
+        self.unwrap_or(65535).into_cache(writer, at)
+    }
+    fn from_cache(reader: &CacheTagReader, data: &[u8], at: usize) -> ErrorMessageResult<Self> {
+        match u16::from_cache(reader, data, at)? {
+            65535 => Ok(None),
+            n => Ok(Some(n))
+        }
+    }
+}
+
+impl CacheSerialize for ScenarioScriptNodeValue {
+    fn into_cache(&self, writer: &mut CacheTagWriter, at: usize) -> ErrorMessageResult<()> {
+        unsafe { self.unsigned_long_int.into_cache(writer, at) }
+    }
+    fn from_cache(reader: &CacheTagReader, data: &[u8], at: usize) -> ErrorMessageResult<Self> {
+        Ok(ScenarioScriptNodeValue { unsigned_long_int: u32::from_cache(reader, data, at)? })
+    }
+}
+
+impl CacheSerialize for Data {
+    fn into_cache(&self, writer: &mut CacheTagWriter, at: usize) -> ErrorMessageResult<()> {
+        if self.is_empty() {
+            return Ok(())
+        }
+
+        let size = self.len();
+        let limit = crate::engines::h1::MAX_ARRAY_LENGTH;
+        if size > limit {
+            return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.types.serialize.error_byte_array_limit_exceeded"), size=size, limit=limit)));
+        }
+
+        let offset = writer.allocate(size)?;
+        writer.data_mut()[offset..offset + size].copy_from_slice(self);
+        (size as u32).into_cache(writer, at)?;
+        writer.write_pointer(at + 0xC, offset)
+    }
+    fn from_cache(reader: &CacheTagReader, data: &[u8], at: usize) -> ErrorMessageResult<Self> {
+        let size = u32::from_cache(reader, data, at)? as usize;
+        if size == 0 {
+            return Ok(Data::new())
+        }
+        let pointer = Pointer::from_cache(reader, data, at + 0xC)?;
+        Ok(reader.read(pointer, size)?.to_owned())
+    }
+}
+
+impl CacheSerialize for TagReference {
+    fn into_cache(&self, writer: &mut CacheTagWriter, at: usize) -> ErrorMessageResult<()> {
+        let group = self.get_group();
+        let fourcc = match group {
+            TagGroup::_None => 0xFFFFFFFF,
+            n => n.as_fourcc()
+        };
+        fourcc.into_cache(writer, at)?;
+
+        if self.is_empty() {
+            return (0xFFFFFFFFu32).into_cache(writer, at + 0xC);
+        }
+
+        (self.get_path_without_extension().len() as u32).into_cache(writer, at + 0x8)?;
+        writer.write_tag_reference(at, self);
+        Ok(())
+    }
+    fn from_cache(reader: &CacheTagReader, data: &[u8], at: usize) -> ErrorMessageResult<Self> {
+        let id = TagID::from_cache(reader, data, at + 0xC)?;
+        match reader.get_tag(id)? {
+            Some(n) => Ok(n.clone()),
+            None => {
+                let group = TagGroup::from_fourcc(u32::from_cache(reader, data, at)?).unwrap_or(TagGroup::_None);
+                TagReference::from_path_and_group("", group)
+            }
+        }
+    }
+}
+
+impl<T: TagBlockFn + TagSerialize + CacheSerialize> CacheSerialize for Reflexive<T> {
+    fn into_cache(&self, writer: &mut CacheTagWriter, at: usize) -> ErrorMessageResult<()> {
+        let size = self.blocks.len();
+        if size == 0 {
+            return Ok(())
+        }
+
+        let limit = crate::engines::h1::MAX_ARRAY_LENGTH;
+        if size > limit {
+            return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.types.serialize.error_array_limit_exceeded"), size=size, limit=limit)));
+        }
+
+        let element_size = T::tag_size();
+        let total_size = match element_size.checked_mul(size) {
+            Some(n) => n,
+            None => return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.types.serialize.error_architecture_limit_exceeded")))
+        };
+
+        // Allocate the blocks first so nested data is placed after them
+        let offset = writer.allocate(total_size)?;
+        for (i, b) in self.blocks.iter().enumerate() {
+            b.into_cache(writer, offset + i * element_size)?;
+        }
+
+        (size as u32).into_cache(writer, at)?;
+        writer.write_pointer(at + 0x4, offset)
+    }
+    fn from_cache(reader: &CacheTagReader, data: &[u8], at: usize) -> ErrorMessageResult<Self> {
+        let size = u32::from_cache(reader, data, at)? as usize;
+        if size == 0 {
+            return Ok(Reflexive { blocks: Vec::new() })
+        }
+
+        let limit = crate::engines::h1::MAX_ARRAY_LENGTH;
+        if size > limit {
+            return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.types.serialize.error_array_limit_exceeded"), size=size, limit=limit)));
+        }
+
+        let element_size = T::tag_size();
+        let total_size = match element_size.checked_mul(size) {
+            Some(n) => n,
+            None => return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.types.serialize.error_architecture_limit_exceeded")))
+        };
+
+        let pointer = Pointer::from_cache(reader, data, at + 0x4)?;
+        let block_data = reader.read(pointer, total_size)?;
+        let mut blocks = Vec::with_capacity(size);
+        for i in 0..size {
+            blocks.push(T::from_cache(reader, block_data, i * element_size)?);
+        }
+        Ok(Reflexive { blocks })
+    }
+}
+
+/// Serialize a block into cache format outside of any tag, such as for vertex data.
+///
+/// This must not be used for blocks that contain pointers or tag references, as these cannot be relocated.
+pub fn into_cache_standalone<T: TagSerialize + CacheSerialize>(blocks: &[T]) -> ErrorMessageResult<Vec<u8>> {
+    let mut writer = CacheTagWriter::new();
+    let element_size = T::tag_size();
+    let offset = writer.allocate(element_size * blocks.len())?;
+    for (i, b) in blocks.iter().enumerate() {
+        b.into_cache(&mut writer, offset + i * element_size)?;
+    }
+    debug_assert!(writer.pointers.is_empty() && writer.tag_references.is_empty() && writer.file_offsets.is_empty(), "standalone data cannot be relocated");
+    Ok(writer.data)
+}
@@ -0,0 +1,267 @@
+use crate::engines::h1::definitions::{ObjectChangeColors, ObjectChangeColorsPermutation, Projectile, Scenario, ScenarioReference, ScenarioScriptNode, ScenarioScriptNodeTable, ScenarioScriptValueType, Sound, TagCollection, TagCollectionTag, UnicodeStringList, UnicodeStringListString, Vehicle};
+use crate::engines::h1::*;
+use crate::types::String32;
+
+const TEST_ADDRESS: Pointer = 0x40440000;
+
+#[test]
+fn test_cache_tag_id_from_into_index_h1() {
+    assert_eq!(0xE7410000, tag_id_from_index(0));
+    assert_eq!(0xE7420001, tag_id_from_index(1));
+    assert_eq!(Some(1), tag_index_from_id(0xE7420001));
+    assert_eq!(None, tag_index_from_id(0xFFFFFFFF));
+}
+
+#[test]
+fn test_cache_serialize_from_into_h1() {
+    // Two tags, one referencing the other
+    let strings_reference = TagReference::from_path_and_group("ui\\strings", TagGroup::UnicodeStringList).unwrap();
+    let collection_reference = TagReference::from_path_and_group("ui\\collection", TagGroup::TagCollection).unwrap();
+
+    let mut strings = UnicodeStringList::default();
+    for s in ["hello", "world"] {
+        let mut string = UnicodeStringListString::default();
+        string.string = s.as_bytes().to_owned();
+        strings.strings.blocks.push(string);
+    }
+
+    let mut collection = TagCollection::default();
+    let mut tag = TagCollectionTag::default();
+    tag.reference = strings_reference.clone();
+    collection.tags.blocks.push(tag);
+
+    // Null references get their default group when read, like with tag files
+    let mut tag = TagCollectionTag::default();
+    tag.reference.set_group(TagGroup::TagCollection);
+    collection.tags.blocks.push(tag);
+
+    let mut index = CacheTagIndex::default();
+    assert_eq!(0, index.get_or_insert(&collection_reference));
+    assert_eq!(1, index.get_or_insert(&strings_reference));
+    assert_eq!(1, index.get_or_insert(&strings_reference));
+
+    // Write both tags and relocate them
+    let mut writer = CacheTagWriter::new();
+    let collection_offset = collection.into_cache_tag(&mut writer).unwrap();
+    let strings_offset = strings.into_cache_tag(&mut writer).unwrap();
+    assert_eq!(1, writer.tag_references().len());
+
+    let (data, file_data) = writer.finish(TEST_ADDRESS, 0, &index, &[0, 0]).unwrap();
+    assert!(file_data.is_empty());
+
+    // Read them back
+    let reader = CacheTagReader::new(&data, TEST_ADDRESS, &[], index.tags());
+    let collection_read = TagCollection::from_cache(&reader, &data, collection_offset).unwrap();
+    let strings_read = UnicodeStringList::from_cache(&reader, &data, strings_offset).unwrap();
+
+    assert!(collection == collection_read);
+    assert!(strings == strings_read);
+
+    // Out-of-bounds addresses are errors
+    assert!(reader.read(TEST_ADDRESS - 4, 4).is_err());
+    assert!(reader.read(TEST_ADDRESS, data.len() + 1).is_err());
+}
+
+#[test]
+fn test_cache_header_from_into_h1() {
+    let header = CacheFileHeader {
+        cache_file_version: 7,
+        decompressed_file_size: 0x1000,
+        compressed_padding: 0,
+        tag_data_offset: 0x800,
+        tag_data_size: 0x800,
+        name: String32::from_str("test").unwrap(),
+        build: String32::from_str("01.00.00.0564").unwrap(),
+        map_type: definitions::ScenarioType::Multiplayer,
+        crc32: 0x12345678
+    };
+
+    for layout in [HeaderLayout::Standard, HeaderLayout::GearboxDemo] {
+        let bytes = header.into_bytes(layout).unwrap();
+        assert_eq!(CACHE_FILE_HEADER_LEN, bytes.len());
+        assert!(CacheFileHeader::detect_layout(&bytes) == Some(layout));
+
+        let header_read = CacheFileHeader::from_bytes(&bytes, layout).unwrap();
+        assert_eq!(header.cache_file_version, header_read.cache_file_version);
+        assert_eq!(header.tag_data_offset, header_read.tag_data_offset);
+        assert_eq!(header.tag_data_size, header_read.tag_data_size);
+        assert_eq!(header.name, header_read.name);
+        assert_eq!(header.build, header_read.build);
+        assert_eq!(header.map_type, header_read.map_type);
+        assert_eq!(header.crc32, header_read.crc32);
+    }
+
+    assert!(CacheFileHeader::detect_layout(&[0u8; CACHE_FILE_HEADER_LEN]).is_none());
+}
+
+#[test]
+fn test_cache_read_header_compressed_h1() {
+    let header = CacheFileHeader {
+        cache_file_version: 5,
+        decompressed_file_size: 0x1000,
+        tag_data_offset: 0x800,
+        tag_data_size: 0x800,
+        name: String32::from_str("test").unwrap(),
+        build: String32::from_str("01.10.12.2276").unwrap(),
+        map_type: definitions::ScenarioType::Multiplayer,
+        ..Default::default()
+    };
+    let mut data = header.into_bytes(HeaderLayout::Standard).unwrap();
+    data.extend_from_slice(&[0u8; 0x100]);
+
+    // The engine is detected from the header even though the rest of the cache file is compressed.
+    let (header_read, engine, engine_exact_match) = CacheFile::read_header(&data).unwrap();
+    assert_eq!(header.name, header_read.name);
+    assert_eq!(0x1000, header_read.decompressed_file_size);
+    assert_eq!(Some("xbox-us"), engine.shorthand);
+    assert!(engine_exact_match);
+    assert!(engine.compressed);
+    assert!(CacheFile::from_data(data).is_err());
+}
+
+#[test]
+fn test_cache_resource_map_from_data_h1() {
+    // Header, resource data, then the resource entries and paths
+    let mut data = Vec::new();
+    data.extend_from_slice(&2u32.to_le_bytes());
+    data.extend_from_slice(&0x2Cu32.to_le_bytes());
+    data.extend_from_slice(&0x14u32.to_le_bytes());
+    data.extend_from_slice(&2u32.to_le_bytes());
+    data.extend_from_slice(&[1, 2, 3, 4]);
+
+    for (path_offset, size, data_offset) in [(0u32, 3u32, 0x10u32), (0xC, 1, 0x13)] {
+        data.extend_from_slice(&path_offset.to_le_bytes());
+        data.extend_from_slice(&size.to_le_bytes());
+        data.extend_from_slice(&data_offset.to_le_bytes());
+    }
+    data.extend_from_slice(b"sound\\first\0sound\\second\0");
+
+    let resource_map = ResourceMap::from_data(data).unwrap();
+    assert_eq!(ResourceMapType::Sounds, resource_map.resource_type());
+    assert_eq!(2, resource_map.resources().len());
+    assert_eq!("sound\\second", resource_map.resources()[1].path);
+    assert_eq!(Some(&[1u8, 2, 3][..]), resource_map.get_resource_data(0));
+    assert_eq!(Some(&[4u8][..]), resource_map.find_resource_data("sound\\second"));
+    assert!(resource_map.get_resource_data(2).is_none());
+    assert!(resource_map.read(0x13, 0x100).is_err());
+
+    // Entries pointing past the end of the file are rejected
+    assert!(ResourceMap::from_data(vec![2, 0, 0, 0, 0x10, 0, 0, 0, 0x10, 0, 0, 0, 1, 0, 0, 0]).is_err());
+}
+
+/// Get an engine without required tags, so only the scenario and the tags it refers to are built.
+fn engine_without_required_tags() -> EngineTarget {
+    EngineTarget {
+        required_tags: RequiredTags { all: &[], singleplayer: &[], multiplayer: &[], user_interface: &[], singleplayer_demo: &[], multiplayer_demo: &[], user_interface_demo: &[] },
+        ..*EngineTarget::from_shorthand("pc-custom").unwrap()
+    }
+}
+
+#[test]
+fn test_cache_build_script_tag_ids_h1() {
+    let engine = engine_without_required_tags();
+    let scenario_reference = TagReference::from_path_and_group("levels\\test\\test", TagGroup::Scenario).unwrap();
+    let sound_reference = TagReference::from_path_and_group("sound\\beep", TagGroup::Sound).unwrap();
+    let vehicle_reference = TagReference::from_path_and_group("vehicles\\warthog", TagGroup::Vehicle).unwrap();
+
+    // Cache files are named after the last part of the scenario's path.
+    assert_eq!("test", get_scenario_name(&scenario_reference));
+
+    // Scripts refer to a sound, an object definition, and no sound
+    let mut scenario = Scenario::default();
+    for reference in [&sound_reference, &vehicle_reference] {
+        scenario.references.blocks.push(ScenarioReference { reference: reference.clone() });
+    }
+    scenario.script_string_data = b"sound/beep\0vehicles\\warthog\0none\0".to_vec();
+    let mut nodes = [ScenarioScriptNode::default(); 3];
+    for (node, (_type, string_offset)) in nodes.iter_mut().zip([(ScenarioScriptValueType::Sound, 0), (ScenarioScriptValueType::ObjectDefinition, 11), (ScenarioScriptValueType::Sound, 28)]) {
+        node._type = _type;
+        node.flags.is_primitive = true;
+        node.string_offset = string_offset;
+    }
+    nodes[2].data.unsigned_long_int = u32::MAX;
+
+    let table_size = ScenarioScriptNodeTable::tag_size();
+    let node_size = ScenarioScriptNode::tag_size();
+    let table = ScenarioScriptNodeTable { count: nodes.len() as u16, ..Default::default() };
+    scenario.script_syntax_data = vec![0; table_size + node_size * nodes.len()];
+    table.into_tag(&mut scenario.script_syntax_data, 0, table_size).unwrap();
+    for (i, n) in nodes.iter().enumerate() {
+        let offset = table_size + node_size * i;
+        n.into_tag(&mut scenario.script_syntax_data, offset, offset + node_size).unwrap();
+    }
+
+    let build = |scenario: &Scenario| {
+        let scenario_data = scenario.into_tag_file().unwrap();
+        build_cache_file(&scenario_reference, &engine, &mut |reference| {
+            Ok(match reference.get_group() {
+                TagGroup::Scenario => Some(scenario_data.clone()),
+                TagGroup::Sound => Some(Sound::default().into_tag_file()?),
+                TagGroup::Vehicle => Some(Vehicle::default().into_tag_file()?),
+                _ => None
+            })
+        })
+    };
+
+    let cache_file = CacheFile::from_data(build(&scenario).unwrap().data).unwrap();
+    let scenario_read: Scenario = cache_file.get_tag(&scenario_reference).unwrap();
+    let reader = CacheTagReader::new(&[], 0, cache_file.data(), cache_file.tags());
+    let node_data = |i: usize| unsafe { ScenarioScriptNode::from_cache(&reader, &scenario_read.script_syntax_data, table_size + node_size * i).unwrap().data.id };
+    assert_eq!(tag_id_from_index(cache_file.get_tag_index(&sound_reference).unwrap()), node_data(0));
+    assert_eq!(tag_id_from_index(cache_file.get_tag_index(&vehicle_reference).unwrap()), node_data(1));
+    assert_eq!(0xFFFFFFFF, node_data(2));
+
+    // Scripts cannot refer to tags that are not in the scenario's references.
+    scenario.references.blocks.pop();
+    assert!(build(&scenario).is_err());
+}
+
+#[test]
+fn test_cache_extract_round_trip_h1() {
+    let engine = engine_without_required_tags();
+    let scenario_reference = TagReference::from_path_and_group("levels\\test\\test", TagGroup::Scenario).unwrap();
+    let sound_reference = TagReference::from_path_and_group("sound\\beep", TagGroup::Sound).unwrap();
+    let projectile_reference = TagReference::from_path_and_group("weapons\\bullet", TagGroup::Projectile).unwrap();
+
+    let mut scenario = Scenario::default();
+    for reference in [&sound_reference, &projectile_reference] {
+        scenario.references.blocks.push(ScenarioReference { reference: reference.clone() });
+    }
+
+    // Each of these is converted when built and converted back when extracted.
+    let sound = Sound { maximum_bend_per_second: 2.0, ..Default::default() };
+    let mut projectile = Projectile { initial_velocity: 90.0, final_velocity: 45.0, ..Default::default() };
+    let mut change_colors = ObjectChangeColors::default();
+    for weight in [1.0, 2.0, 0.5] {
+        change_colors.permutations.blocks.push(ObjectChangeColorsPermutation { weight, ..Default::default() });
+    }
+    projectile.base_struct.change_colors.blocks.push(change_colors);
+
+    let scenario_data = scenario.into_tag_file().unwrap();
+    let sound_data = sound.into_tag_file().unwrap();
+    let projectile_data = projectile.into_tag_file().unwrap();
+    let built = build_cache_file(&scenario_reference, &engine, &mut |reference| {
+        Ok(match reference.get_group() {
+            TagGroup::Scenario => Some(scenario_data.clone()),
+            TagGroup::Sound => Some(sound_data.clone()),
+            TagGroup::Projectile => Some(projectile_data.clone()),
+            _ => None
+        })
+    }).unwrap();
+    let cache_file = CacheFile::from_data(built.data).unwrap();
+
+    // Values are converted in the cache file.
+    let projectile_cached: Projectile = cache_file.get_tag(&projectile_reference).unwrap();
+    assert_eq!(3.0, projectile_cached.initial_velocity);
+    let weights: Vec<f32> = projectile_cached.base_struct.change_colors[0].permutations.blocks.iter().map(|p| p.weight).collect();
+    assert_eq!(vec![1.0, 3.0, 3.5], weights);
+
+    // Extracted tags have the values they were built from, other than rounding.
+    let projectile_extracted = *Projectile::from_tag_file(&extract_tag(&cache_file, &projectile_reference).unwrap()).unwrap().data;
+    assert_eq!(90.0, projectile_extracted.initial_velocity);
+    assert_eq!(45.0, projectile_extracted.final_velocity);
+    let weights: Vec<f32> = projectile_extracted.base_struct.change_colors[0].permutations.blocks.iter().map(|p| p.weight).collect();
+    assert_eq!(vec![1.0, 2.0, 0.5], weights);
+    let sound_extracted = *Sound::from_tag_file(&extract_tag(&cache_file, &sound_reference).unwrap()).unwrap().data;
+    assert!((sound_extracted.maximum_bend_per_second - 2.0).abs() < 0.0001);
+}
@@ -3,7 +3,7 @@
 use ringhopper_proc::*;
 
 use crate::bitmap::BitmapEncoding;
-use crate::engines::h1::{TagSerialize, TagFileSerializeFn, TagReference, ScenarioScriptNodeValue, Index, TagID, Pointer, TAG_FILE_HEADER_LEN, TagGroup, ParsedTagFile, TagFileHeader};
+use crate::engines::h1::{TagSerialize, TagFileSerializeFn, TagReference, ScenarioScriptNodeValue, Index, TagID, Pointer, TAG_FILE_HEADER_LEN, TagGroup, ParsedTagFile, TagFileHeader, CacheSerialize, CacheTagWriter, CacheTagReader, CacheFile, TagValidate, EngineTarget, tag_field_path, TagDependencies, TagDependency};
 use crate::error::*;
 use crate::types::*;
 use std::str::FromStr;
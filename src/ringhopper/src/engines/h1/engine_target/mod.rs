@@ -1,4 +1,5 @@
 use rat_in_a_tube::CompileTarget;
+use crate::engines::h1::definitions::ScenarioType;
 
 use ringhopper_proc::*;
 
@@ -103,6 +104,12 @@ pub struct EngineTarget {
     /// BSPs share tag space with the rest of the tag data.
     pub bsps_occupy_tag_space: bool,
 
+    /// Cache files are compressed.
+    pub compressed: bool,
+
+    /// Layout of the cache file header.
+    pub header_layout: HeaderLayout,
+
     /// Maximum tag space in bytes.
     pub max_tag_space: usize,
 
@@ -169,4 +176,13 @@ impl EngineTarget {
         // If we have a fallback, we'll return it.
         fallback
     }
+
+    /// Get the maximum cache file size in bytes for the type of scenario.
+    pub fn max_cache_file_size(&self, map_type: ScenarioType) -> usize {
+        match map_type {
+            ScenarioType::Singleplayer => self.max_cache_file_size_singleplayer,
+            ScenarioType::Multiplayer => self.max_cache_file_size_multiplayer,
+            ScenarioType::UserInterface => self.max_cache_file_size_user_interface
+        }
+    }
 }
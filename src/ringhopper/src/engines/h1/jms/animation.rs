@@ -0,0 +1,635 @@
+use std::convert::TryFrom;
+use std::f32::consts::{PI, TAU};
+
+use crate::engines::h1::definitions::*;
+use crate::error::*;
+use crate::types::{Point3D, Quaternion, String32, TagBlockFn};
+
+use ringhopper_proc::*;
+
+use super::{AnimationNode, JMA, NodeTransform};
+
+/// Maximum number of nodes an animation can have (one bit per node in the node flags).
+pub const MAX_ANIMATION_NODES: usize = 64;
+
+/// Size of a compressed rotation in frame data.
+const ROTATION_SIZE: usize = 8;
+
+/// Size of a translation in frame data.
+const TRANSLATION_SIZE: usize = 12;
+
+/// Size of a scale in frame data.
+const SCALE_SIZE: usize = 4;
+
+/// Factor for converting rotation components into 16-bit integers.
+const ROTATION_SCALE: f32 = 32767.0;
+
+/// Frame rate of animations with the 25hz PAL flag set.
+const PAL_FRAME_RATE: usize = 25;
+
+/// Frame rate of all other animations.
+const NTSC_FRAME_RATE: usize = 30;
+
+/// Type of animation source file, determined by the file extension.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum AnimationSourceType {
+    /// Base animation without any movement (JMM).
+    Base,
+
+    /// Base animation in world space (JMW).
+    WorldRelative,
+
+    /// Base animation moving along the X and Y axes (JMA).
+    DxDy,
+
+    /// Base animation moving along the X and Y axes and turning (JMT).
+    DxDyDyaw,
+
+    /// Base animation moving along all axes and turning (JMZ).
+    DxDyDzDyaw,
+
+    /// Animation that is added on top of other animations (JMO).
+    Overlay,
+
+    /// Animation that replaces other animations on the nodes it uses (JMR).
+    Replacement
+}
+
+impl AnimationSourceType {
+    /// All animation source types.
+    pub const ALL: [AnimationSourceType; 7] = [
+        AnimationSourceType::Base,
+        AnimationSourceType::WorldRelative,
+        AnimationSourceType::DxDy,
+        AnimationSourceType::DxDyDyaw,
+        AnimationSourceType::DxDyDzDyaw,
+        AnimationSourceType::Overlay,
+        AnimationSourceType::Replacement
+    ];
+
+    /// Get the file extension used for this type of animation.
+    pub fn extension(self) -> &'static str {
+        match self {
+            AnimationSourceType::Base => "jmm",
+            AnimationSourceType::WorldRelative => "jmw",
+            AnimationSourceType::DxDy => "jma",
+            AnimationSourceType::DxDyDyaw => "jmt",
+            AnimationSourceType::DxDyDzDyaw => "jmz",
+            AnimationSourceType::Overlay => "jmo",
+            AnimationSourceType::Replacement => "jmr"
+        }
+    }
+
+    /// Get the type of animation from the file extension, if it is an animation source file.
+    pub fn from_extension(extension: &str) -> Option<AnimationSourceType> {
+        AnimationSourceType::ALL.into_iter().find(|t| extension.eq_ignore_ascii_case(t.extension()))
+    }
+
+    /// Get the type of source file an animation was compiled from.
+    pub fn from_animation(animation: &ModelAnimationsAnimation) -> AnimationSourceType {
+        match animation._type {
+            AnimationType::Overlay => AnimationSourceType::Overlay,
+            AnimationType::Replacement => AnimationSourceType::Replacement,
+            AnimationType::Base if animation.flags.world_relative => AnimationSourceType::WorldRelative,
+            AnimationType::Base => match animation.frame_info_type {
+                AnimationFrameInfoType::None => AnimationSourceType::Base,
+                AnimationFrameInfoType::DxDy => AnimationSourceType::DxDy,
+                AnimationFrameInfoType::DxDyDyaw => AnimationSourceType::DxDyDyaw,
+                AnimationFrameInfoType::DxDyDzDyaw => AnimationSourceType::DxDyDzDyaw
+            }
+        }
+    }
+
+    fn animation_type(self) -> AnimationType {
+        match self {
+            AnimationSourceType::Overlay => AnimationType::Overlay,
+            AnimationSourceType::Replacement => AnimationType::Replacement,
+            _ => AnimationType::Base
+        }
+    }
+
+    fn frame_info_type(self) -> AnimationFrameInfoType {
+        match self {
+            AnimationSourceType::DxDy => AnimationFrameInfoType::DxDy,
+            AnimationSourceType::DxDyDyaw => AnimationFrameInfoType::DxDyDyaw,
+            AnimationSourceType::DxDyDzDyaw => AnimationFrameInfoType::DxDyDzDyaw,
+            _ => AnimationFrameInfoType::None
+        }
+    }
+}
+
+/// Animation source file of an animation in a model_animations tag.
+pub struct ModelAnimationJMA {
+    /// Name of the animation.
+    pub name: String,
+
+    /// Type of the animation.
+    pub source_type: AnimationSourceType,
+
+    /// JMA data.
+    pub jma: JMA
+}
+
+/// Movement of the root node that is stored in the frame info of an animation.
+#[derive(Copy, Clone)]
+struct Movement {
+    moves_z: bool,
+    turns: bool
+}
+
+impl Movement {
+    fn from_frame_info_type(frame_info_type: AnimationFrameInfoType) -> Option<Movement> {
+        match frame_info_type {
+            AnimationFrameInfoType::None => None,
+            AnimationFrameInfoType::DxDy => Some(Movement { moves_z: false, turns: false }),
+            AnimationFrameInfoType::DxDyDyaw => Some(Movement { moves_z: false, turns: true }),
+            AnimationFrameInfoType::DxDyDzDyaw => Some(Movement { moves_z: true, turns: true })
+        }
+    }
+
+    /// Size of the frame info of each frame.
+    fn size(self) -> usize {
+        (2 + self.moves_z as usize + self.turns as usize) * std::mem::size_of::<f32>()
+    }
+}
+
+fn multiply_quaternions(a: &Quaternion, b: &Quaternion) -> Quaternion {
+    Quaternion {
+        w: a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+        x: a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+        y: a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+        z: a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w
+    }
+}
+
+fn normalize_quaternion(q: &Quaternion) -> Quaternion {
+    let length = (q.x * q.x + q.y * q.y + q.z * q.z + q.w * q.w).sqrt();
+    if length == 0.0 {
+        NodeTransform::default().rotation
+    }
+    else {
+        Quaternion { x: q.x / length, y: q.y / length, z: q.z / length, w: q.w / length }
+    }
+}
+
+fn conjugate_quaternion(q: &Quaternion) -> Quaternion {
+    Quaternion { x: -q.x, y: -q.y, z: -q.z, w: q.w }
+}
+
+/// Get a rotation about the Z axis by the angle (in radians).
+pub(super) fn yaw_rotation(angle: f32) -> Quaternion {
+    Quaternion { x: 0.0, y: 0.0, z: (angle * 0.5).sin(), w: (angle * 0.5).cos() }
+}
+
+/// Get the angle about the Z axis (in radians) that the X axis faces after being rotated by the quaternion.
+pub(super) fn get_yaw(q: &Quaternion) -> f32 {
+    let q = normalize_quaternion(q);
+    (2.0 * (q.x * q.y + q.z * q.w)).atan2(q.w * q.w + q.x * q.x - q.y * q.y - q.z * q.z)
+}
+
+/// Wrap the angle to be between -pi and pi.
+fn wrap_angle(angle: f32) -> f32 {
+    angle - TAU * ((angle + PI) / TAU).floor()
+}
+
+/// Rotate the X and Y components about the Z axis by the angle (in radians).
+fn rotate_about_z(x: f32, y: f32, angle: f32) -> (f32, f32) {
+    let (sin, cos) = angle.sin_cos();
+    (x * cos - y * sin, x * sin + y * cos)
+}
+
+fn get_node_flag(flags: &[u32; 2], node: usize) -> bool {
+    flags[node / 32] & (1 << (node % 32)) != 0
+}
+
+fn set_node_flag(flags: &mut [u32; 2], node: usize) {
+    flags[node / 32] |= 1 << (node % 32);
+}
+
+fn quantize_rotation(q: &Quaternion) -> [i16; 4] {
+    let q = normalize_quaternion(q);
+    [q.x, q.y, q.z, q.w].map(|c| (c * ROTATION_SCALE).round() as i16)
+}
+
+fn write_rotation(data: &mut Vec<u8>, q: &Quaternion) {
+    for c in quantize_rotation(q) {
+        data.extend_from_slice(&c.to_be_bytes());
+    }
+}
+
+fn write_translation(data: &mut Vec<u8>, p: &Point3D) {
+    for c in [p.x, p.y, p.z] {
+        data.extend_from_slice(&c.to_be_bytes());
+    }
+}
+
+/// Reader for big endian frame data.
+struct FrameReader<'a> {
+    data: &'a [u8],
+    offset: usize
+}
+
+impl<'a> FrameReader<'a> {
+    fn new(data: &'a [u8]) -> FrameReader<'a> {
+        FrameReader { data, offset: 0 }
+    }
+
+    fn read<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let bytes = self.data.get(self.offset..self.offset + N)?.try_into().ok()?;
+        self.offset += N;
+        Some(bytes)
+    }
+
+    fn read_f32(&mut self) -> Option<f32> {
+        Some(f32::from_be_bytes(self.read()?))
+    }
+
+    fn read_rotation(&mut self) -> Option<Quaternion> {
+        let mut c = [0.0f32; 4];
+        for i in &mut c {
+            *i = i16::from_be_bytes(self.read()?) as f32 / ROTATION_SCALE;
+        }
+        Some(normalize_quaternion(&Quaternion { x: c[0], y: c[1], z: c[2], w: c[3] }))
+    }
+
+    fn read_translation(&mut self) -> Option<Point3D> {
+        Some(Point3D { x: self.read_f32()?, y: self.read_f32()?, z: self.read_f32()? })
+    }
+}
+
+/// Remove the movement of the root node from the frames, returning the frame info.
+///
+/// The last frame is only used to get the movement of the frame before it.
+fn remove_movement(frames: &mut [Vec<NodeTransform>], movement: Movement) -> Vec<u8> {
+    let frame_count = frames.len() - 1;
+    let origin = frames[0][0].position;
+
+    // Yaw of the root node on each frame, relative to the first frame
+    let mut yaw = vec![0.0f32; frames.len()];
+    if movement.turns {
+        for i in 0..frame_count {
+            yaw[i + 1] = yaw[i] + wrap_angle(get_yaw(&frames[i + 1][0].rotation) - get_yaw(&frames[i][0].rotation));
+        }
+    }
+
+    // Movement is relative to the direction the root node is facing on that frame.
+    let mut frame_info = Vec::with_capacity(frame_count * movement.size());
+    for i in 0..frame_count {
+        let from = frames[i][0].position;
+        let to = frames[i + 1][0].position;
+        let (dx, dy) = rotate_about_z(to.x - from.x, to.y - from.y, -yaw[i]);
+        frame_info.extend_from_slice(&dx.to_be_bytes());
+        frame_info.extend_from_slice(&dy.to_be_bytes());
+        if movement.moves_z {
+            frame_info.extend_from_slice(&(to.z - from.z).to_be_bytes());
+        }
+        if movement.turns {
+            frame_info.extend_from_slice(&(yaw[i + 1] - yaw[i]).to_be_bytes());
+        }
+    }
+
+    for (frame, yaw) in frames.iter_mut().zip(yaw) {
+        let root = &mut frame[0];
+        root.position.x = origin.x;
+        root.position.y = origin.y;
+        if movement.moves_z {
+            root.position.z = origin.z;
+        }
+        if movement.turns {
+            root.rotation = multiply_quaternions(&yaw_rotation(-yaw), &root.rotation);
+        }
+    }
+
+    frame_info
+}
+
+/// Add the movement of the root node in the frame info back to the frames.
+///
+/// A final frame is added with the first frame moved to where the last frame ends.
+fn add_movement(frames: &mut Vec<Vec<NodeTransform>>, frame_info: &[u8], movement: Option<Movement>) -> Option<()> {
+    let frame_count = frames.len();
+    frames.push(frames[0].clone());
+
+    let movement = match movement {
+        Some(n) => n,
+        None => return Some(())
+    };
+
+    let mut reader = FrameReader::new(frame_info);
+    let mut offset = Point3D::default();
+    let mut yaw = 0.0f32;
+    for (i, frame) in frames.iter_mut().enumerate() {
+        let root = &mut frame[0];
+        root.position.x += offset.x;
+        root.position.y += offset.y;
+        root.position.z += offset.z;
+        if movement.turns {
+            root.rotation = normalize_quaternion(&multiply_quaternions(&yaw_rotation(yaw), &root.rotation));
+        }
+
+        if i < frame_count {
+            let (dx, dy) = rotate_about_z(reader.read_f32()?, reader.read_f32()?, yaw);
+            offset.x += dx;
+            offset.y += dy;
+            if movement.moves_z {
+                offset.z += reader.read_f32()?;
+            }
+            if movement.turns {
+                yaw += reader.read_f32()?;
+            }
+        }
+    }
+
+    Some(())
+}
+
+/// Compile the animation.
+///
+/// The last frame of the JMA is not part of the animation. For animations with movement, it is used for the movement
+/// of the frame before it.
+///
+/// Frames are compressed by storing the rotation, translation, and scale of each node that does not change during the
+/// animation in the default data instead of in each frame.
+fn build_animation(animation: &ModelAnimationJMA) -> ErrorMessageResult<ModelAnimationsAnimation> {
+    let jma = &animation.jma;
+    let too_large = || ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_animation_too_large"), animation=animation.name));
+
+    let mut frames = jma.frames.clone();
+    let frame_info_type = animation.source_type.frame_info_type();
+    let frame_info = match Movement::from_frame_info_type(frame_info_type) {
+        Some(movement) => remove_movement(&mut frames, movement),
+        None => Vec::new()
+    };
+    frames.pop();
+
+    // Overlays are stored relative to their first frame.
+    if animation.source_type == AnimationSourceType::Overlay {
+        let base = frames[0].clone();
+        for frame in &mut frames {
+            for (t, b) in frame.iter_mut().zip(&base) {
+                t.position = Point3D { x: t.position.x - b.position.x, y: t.position.y - b.position.y, z: t.position.z - b.position.z };
+                t.rotation = multiply_quaternions(&normalize_quaternion(&t.rotation), &conjugate_quaternion(&normalize_quaternion(&b.rotation)));
+            }
+        }
+    }
+
+    // Find what changes during the animation.
+    let node_count = jma.nodes.len();
+    let first = &frames[0];
+    let mut rotation_flags = [0u32; 2];
+    let mut translation_flags = [0u32; 2];
+    let mut scale_flags = [0u32; 2];
+    for n in 0..node_count {
+        let rotation = quantize_rotation(&first[n].rotation);
+        if frames.iter().any(|f| quantize_rotation(&f[n].rotation) != rotation) {
+            set_node_flag(&mut rotation_flags, n);
+        }
+        if frames.iter().any(|f| f[n].position != first[n].position) {
+            set_node_flag(&mut translation_flags, n);
+        }
+        if frames.iter().any(|f| f[n].scale != first[n].scale) {
+            set_node_flag(&mut scale_flags, n);
+        }
+    }
+
+    let mut default_data = Vec::new();
+    for (n, t) in first.iter().enumerate() {
+        if !get_node_flag(&rotation_flags, n) {
+            write_rotation(&mut default_data, &t.rotation);
+        }
+        if !get_node_flag(&translation_flags, n) {
+            write_translation(&mut default_data, &t.position);
+        }
+        if !get_node_flag(&scale_flags, n) {
+            default_data.extend_from_slice(&t.scale.to_be_bytes());
+        }
+    }
+
+    let mut frame_data = Vec::new();
+    for frame in &frames {
+        for (n, t) in frame.iter().enumerate() {
+            if get_node_flag(&rotation_flags, n) {
+                write_rotation(&mut frame_data, &t.rotation);
+            }
+            if get_node_flag(&translation_flags, n) {
+                write_translation(&mut frame_data, &t.position);
+            }
+            if get_node_flag(&scale_flags, n) {
+                frame_data.extend_from_slice(&t.scale.to_be_bytes());
+            }
+        }
+    }
+
+    let mut result = ModelAnimationsAnimation {
+        name: String32::from_str(&animation.name)?,
+        _type: animation.source_type.animation_type(),
+        frame_count: u16::try_from(frames.len()).map_err(|_| too_large())?,
+        frame_size: u16::try_from(frame_data.len() / frames.len()).map_err(|_| too_large())?,
+        frame_info_type,
+        node_list_checksum: jma.node_list_checksum,
+        node_count: node_count as u16,
+        loop_frame_index: Some(0),
+        weight: 1.0,
+        frame_info,
+        node_transform_flag_data: translation_flags,
+        node_rotation_flag_data: rotation_flags,
+        node_scale_flag_data: scale_flags,
+        default_data,
+        frame_data,
+        ..Default::default()
+    };
+    result.flags.world_relative = animation.source_type == AnimationSourceType::WorldRelative;
+    result.flags._25hz_pal = jma.frame_rate == PAL_FRAME_RATE;
+
+    Ok(result)
+}
+
+/// Build a model_animations tag from the animation source files.
+///
+/// All animations must have the same nodes. Animations named with a `%` suffix (e.g. `stand rifle idle%1`) are
+/// permutations, and they are chained together with the next animation index.
+///
+/// If `existing` is set, its settings are kept, and only its nodes and animations are replaced. Animations are kept
+/// in the same order so references to them remain valid, and new animations are added to the end.
+pub fn build_model_animations(animations: &[ModelAnimationJMA], existing: Option<&ModelAnimations>) -> ErrorMessageResult<ModelAnimations> {
+    let first = match animations.first() {
+        Some(n) => &n.jma,
+        None => return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.jms.error_no_animation_files")))
+    };
+
+    if first.nodes.len() > MAX_ANIMATION_NODES {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_animation_too_many_nodes"), node_count=first.nodes.len(), max=MAX_ANIMATION_NODES)))
+    }
+
+    for (i, a) in animations.iter().enumerate() {
+        a.jma.validate()?;
+        if a.jma.nodes.len() != first.nodes.len() || a.jma.nodes.iter().zip(first.nodes.iter()).any(|(a, b)| a.name != b.name) {
+            return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_animation_node_mismatch"), animation=a.name)))
+        }
+        if a.jma.frames.len() < 2 {
+            return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_animation_too_few_frames"), animation=a.name)))
+        }
+        if animations[..i].iter().any(|b| b.name == a.name) {
+            return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_duplicate_animation"), animation=a.name)))
+        }
+    }
+
+    let mut tag = match existing {
+        Some(n) => n.clone(),
+        None => ModelAnimations::new_with_defaults()
+    };
+
+    // Nodes
+    let existing_nodes = std::mem::take(&mut tag.nodes);
+    for (n, parent) in first.nodes.iter().zip(first.get_parent_nodes()?) {
+        let name = String32::from_str(&n.name)?;
+        let existing_node = existing_nodes.blocks.iter().find(|e| e.name == name);
+        tag.nodes.blocks.push(ModelAnimationsAnimationGraphNode {
+            name,
+            next_sibling_node_index: n.sibling_node,
+            first_child_node_index: n.first_child,
+            parent_node_index: parent,
+            node_joint_flags: existing_node.map(|e| e.node_joint_flags).unwrap_or_default(),
+            base_vector: existing_node.map(|e| e.base_vector).unwrap_or_default(),
+            vector_range: existing_node.map(|e| e.vector_range).unwrap_or_default()
+        });
+    }
+
+    // Keep the existing animations in the same order.
+    let existing_animations = std::mem::take(&mut tag.animations);
+    let mut ordered: Vec<&ModelAnimationJMA> = Vec::with_capacity(animations.len());
+    for e in &existing_animations.blocks {
+        match animations.iter().find(|a| a.name == e.name.to_str()) {
+            Some(a) => ordered.push(a),
+            None => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_animation_missing"), animation=e.name)))
+        }
+    }
+    for a in animations {
+        if !ordered.iter().any(|o| o.name == a.name) {
+            ordered.push(a);
+        }
+    }
+
+    for a in ordered {
+        let mut animation = build_animation(a)?;
+        if let Some(e) = existing_animations.blocks.iter().find(|e| e.name == animation.name) {
+            if e.loop_frame_index.map(|l| l < animation.frame_count).unwrap_or(true) {
+                animation.loop_frame_index = e.loop_frame_index;
+            }
+            animation.weight = e.weight;
+            animation.key_frame_index = e.key_frame_index;
+            animation.second_key_frame_index = e.second_key_frame_index;
+            animation.sound = e.sound;
+            animation.sound_frame_index = e.sound_frame_index;
+            animation.left_foot_frame_index = e.left_foot_frame_index;
+            animation.right_foot_frame_index = e.right_foot_frame_index;
+        }
+        tag.animations.blocks.push(animation);
+    }
+
+    // Chain permutations together.
+    let permutation_base = |name: &String32| name.to_str().split('%').next().unwrap_or_default().to_owned();
+    let animation_count = tag.animations.blocks.len();
+    for i in 0..animation_count {
+        let base = permutation_base(&tag.animations[i].name);
+        let next = (i + 1..animation_count).find(|&n| permutation_base(&tag.animations[n].name) == base);
+        tag.animations[i].next_animation = match next {
+            Some(n) => Some(u16::try_from(n).map_err(|_| ErrorMessage::StaticString(get_compiled_string!("engine.h1.jms.error_too_many_animations")))?),
+            None => None
+        };
+    }
+
+    Ok(tag)
+}
+
+/// Recover the animation source file of an animation in a model_animations tag.
+///
+/// A final frame is added to the animation, as the last frame of the source file is not stored in the tag. This is
+/// the first frame, moved to where the last frame ends for animations with movement. Overlays are recovered relative
+/// to a rest pose, as the first frame they were made relative to is not stored in the tag, either.
+///
+/// Returns an [`Err`] if the animation uses compressed data or is corrupt.
+pub fn recover_animation(tag: &ModelAnimations, animation_index: usize) -> ErrorMessageResult<ModelAnimationJMA> {
+    let animation = &tag.animations[animation_index];
+    let name = animation.name.to_str().to_owned();
+    let corrupt = || ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_animation_corrupt"), animation=name));
+
+    if animation.flags.compressed_data {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_animation_compressed"), animation=name)))
+    }
+
+    let node_count = animation.node_count as usize;
+    let frame_count = animation.frame_count as usize;
+    if node_count != tag.nodes.blocks.len() || node_count > MAX_ANIMATION_NODES || frame_count == 0 {
+        return Err(corrupt())
+    }
+
+    let nodes: Vec<AnimationNode> = tag.nodes.blocks.iter().map(|n| AnimationNode {
+        name: n.name.to_str().to_owned(),
+        first_child: n.first_child_node_index,
+        sibling_node: n.next_sibling_node_index
+    }).collect();
+
+    let is_rotated = |n| get_node_flag(&animation.node_rotation_flag_data, n);
+    let is_translated = |n| get_node_flag(&animation.node_transform_flag_data, n);
+    let is_scaled = |n| get_node_flag(&animation.node_scale_flag_data, n);
+
+    // Read the values that do not change.
+    let mut reader = FrameReader::new(&animation.default_data);
+    let mut default_frame = vec![NodeTransform::default(); node_count];
+    for (n, t) in default_frame.iter_mut().enumerate() {
+        if !is_rotated(n) {
+            t.rotation = reader.read_rotation().ok_or_else(corrupt)?;
+        }
+        if !is_translated(n) {
+            t.position = reader.read_translation().ok_or_else(corrupt)?;
+        }
+        if !is_scaled(n) {
+            t.scale = reader.read_f32().ok_or_else(corrupt)?;
+        }
+    }
+
+    let frame_size: usize = (0..node_count).map(|n| {
+        is_rotated(n) as usize * ROTATION_SIZE + is_translated(n) as usize * TRANSLATION_SIZE + is_scaled(n) as usize * SCALE_SIZE
+    }).sum();
+    if animation.frame_size as usize != frame_size || animation.frame_data.len() != frame_size * frame_count {
+        return Err(corrupt())
+    }
+
+    let mut reader = FrameReader::new(&animation.frame_data);
+    let mut frames = Vec::with_capacity(frame_count + 1);
+    for _ in 0..frame_count {
+        let mut frame = default_frame.clone();
+        for (n, t) in frame.iter_mut().enumerate() {
+            if is_rotated(n) {
+                t.rotation = reader.read_rotation().ok_or_else(corrupt)?;
+            }
+            if is_translated(n) {
+                t.position = reader.read_translation().ok_or_else(corrupt)?;
+            }
+            if is_scaled(n) {
+                t.scale = reader.read_f32().ok_or_else(corrupt)?;
+            }
+        }
+        frames.push(frame);
+    }
+
+    let source_type = AnimationSourceType::from_animation(animation);
+    let movement = Movement::from_frame_info_type(animation.frame_info_type);
+    if animation.frame_info.len() != movement.map(|m| m.size()).unwrap_or(0) * frame_count {
+        return Err(corrupt())
+    }
+    add_movement(&mut frames, &animation.frame_info, movement).ok_or_else(corrupt)?;
+
+    Ok(ModelAnimationJMA {
+        name,
+        source_type,
+        jma: JMA {
+            node_list_checksum: animation.node_list_checksum,
+            frame_rate: if animation.flags._25hz_pal { PAL_FRAME_RATE } else { NTSC_FRAME_RATE },
+            actors: vec!["unnamedActor".to_owned()],
+            nodes,
+            frames
+        }
+    })
+}
@@ -0,0 +1,822 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::engines::h1::definitions::*;
+use crate::engines::h1::{ModelCompression, TagReference};
+use crate::error::*;
+use crate::types::{Bounds, Matrix, Plane3D, Point2D, Point3D, Quaternion, Reflexive, String32, TagBlockFn, Vector3D};
+
+use ringhopper_proc::*;
+
+use super::{JMS, Vertex};
+use super::collision::{BSP_LEAF_FLAG, BSP_SOLID, CollisionMaterial, CollisionTriangle, PLANE_EPSILON, build_bsp, check_degenerate_triangle, format_position, parse_material_name, position_key, triangle_normal};
+use super::model::{calculate_tangent_space, dot, resolve_material};
+
+/// Distance in front of a surface to look for the leaf containing it.
+const LEAF_OFFSET: f32 = 0.001;
+
+/// Kind of geometry made by a JMS material.
+#[derive(Copy, Clone, PartialEq)]
+enum BSPGeometryKind {
+    /// Geometry with a shader, which is rendered unless it is invisible and collidable unless it is render-only.
+    Shader,
+
+    /// Sky (`+sky`, optionally followed by the index of the sky in the scenario), which seals the world but is not
+    /// rendered.
+    Sky(u16),
+
+    /// Invisible geometry that seals the world (`+seamsealer`).
+    SeamSealer,
+
+    /// Cluster portal (`+portal` or `+exactportal`).
+    Portal,
+
+    /// Weather polyhedron (`+weatherpoly`).
+    WeatherPolyhedron,
+
+    /// Fog plane (`+fog`).
+    FogPlane
+}
+
+impl BSPGeometryKind {
+    fn from_material_name(name: &str) -> BSPGeometryKind {
+        match name {
+            "+seamsealer" => BSPGeometryKind::SeamSealer,
+            "+portal" | "+exactportal" => BSPGeometryKind::Portal,
+            "+weatherpoly" => BSPGeometryKind::WeatherPolyhedron,
+            "+fog" => BSPGeometryKind::FogPlane,
+            _ => match name.strip_prefix("+sky").map(|index| if index.is_empty() { Ok(0) } else { index.parse::<u16>() }) {
+                Some(Ok(n)) => BSPGeometryKind::Sky(n),
+                _ => BSPGeometryKind::Shader
+            }
+        }
+    }
+}
+
+/// How the triangles of a JMS material are used in the BSP.
+struct BSPMaterial {
+    kind: BSPGeometryKind,
+
+    /// Collision material and surface flags, if the triangles are collidable.
+    collision: Option<CollisionMaterial>,
+
+    /// Index of the shader used to render the triangles, if they are rendered.
+    render: Option<usize>
+}
+
+/// Welds vertices with the same position together.
+#[derive(Default)]
+struct PositionWelder {
+    indices: HashMap<[u32; 3], usize>,
+    points: Vec<Point3D>
+}
+
+impl PositionWelder {
+    fn weld(&mut self, point: &Point3D) -> usize {
+        let next_index = self.points.len();
+        *self.indices.entry(position_key(point)).or_insert_with(|| {
+            self.points.push(*point);
+            next_index
+        })
+    }
+
+    fn get_plane(&self, triangle: &[usize; 3]) -> Plane3D {
+        let normal = triangle_normal(&triangle.map(|v| Vector3D::from(self.points[v]))).normalize();
+        Plane3D { vector: normal, d: dot(&normal, &Vector3D::from(self.points[triangle[0]])) }
+    }
+}
+
+fn get_centroid(points: &[Point3D]) -> Point3D {
+    let sum = points.iter().fold(Vector3D::default(), |sum, p| sum.add_components(&Vector3D::from(*p).into_floats()));
+    Point3D::from(sum * (1.0 / points.len() as f32))
+}
+
+fn get_distance(a: &Point3D, b: &Point3D) -> f32 {
+    let difference = Vector3D::from(*a).sub_components(&Vector3D::from(*b).into_floats());
+    dot(&difference, &difference).sqrt()
+}
+
+fn get_bounds(points: &[Point3D]) -> [Bounds<f32>; 3] {
+    let mut bounds = [Bounds { lower: f32::MAX, upper: f32::MIN }; 3];
+    for p in points {
+        for (b, value) in bounds.iter_mut().zip([p.x, p.y, p.z]) {
+            b.lower = b.lower.min(value);
+            b.upper = b.upper.max(value);
+        }
+    }
+    bounds
+}
+
+fn planes_match(a: &Plane3D, b: &Plane3D) -> bool {
+    let difference = a.vector.sub_components(&b.vector.into_floats());
+    dot(&difference, &difference) < PLANE_EPSILON * PLANE_EPSILON && (a.d - b.d).abs() < PLANE_EPSILON
+}
+
+fn find_root(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+    index
+}
+
+/// Group triangles that share edges, returning the indices of the triangles in each group.
+///
+/// Two triangles sharing an edge are only put in the same group if `can_join` returns true for the triangles and the
+/// edge, with the edge's lower vertex first.
+fn group_triangles<F>(triangles: &[[usize; 3]], mut can_join: F) -> Vec<Vec<usize>> where F: FnMut(usize, usize, (usize, usize)) -> bool {
+    let mut edges: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (t, v) in triangles.iter().enumerate() {
+        for (a, b) in [(v[0], v[1]), (v[1], v[2]), (v[2], v[0])] {
+            edges.entry((a.min(b), a.max(b))).or_default().push(t);
+        }
+    }
+
+    let mut parents: Vec<usize> = (0..triangles.len()).collect();
+    for (edge, edge_triangles) in &edges {
+        for (i, &a) in edge_triangles.iter().enumerate() {
+            for &b in &edge_triangles[i + 1..] {
+                if can_join(a, b, *edge) {
+                    let (root_a, root_b) = (find_root(&mut parents, a), find_root(&mut parents, b));
+                    parents[root_a.max(root_b)] = root_a.min(root_b);
+                }
+            }
+        }
+    }
+
+    let mut group_indices: HashMap<usize, usize> = HashMap::new();
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for t in 0..triangles.len() {
+        let root = find_root(&mut parents, t);
+        let group = *group_indices.entry(root).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(t);
+    }
+    groups
+}
+
+/// Get the edges of a group of triangles that are not shared with another triangle in the group.
+fn get_outline_edges(triangles: &[[usize; 3]]) -> Vec<(usize, usize)> {
+    let edges: Vec<(usize, usize)> = triangles.iter().flat_map(|v| [(v[0], v[1]), (v[1], v[2]), (v[2], v[0])]).collect();
+    let edge_set: HashSet<(usize, usize)> = edges.iter().copied().collect();
+    edges.into_iter().filter(|(a, b)| !edge_set.contains(&(*b, *a))).collect()
+}
+
+/// Get the outline of a group of coplanar triangles as a loop of vertices, wound the same way as the triangles.
+fn get_outline(triangles: &[[usize; 3]]) -> Vec<usize> {
+    let next: HashMap<usize, usize> = get_outline_edges(triangles).into_iter().collect();
+    let mut outline = Vec::new();
+    let mut vertex = match next.keys().min() {
+        Some(n) => *n,
+        None => return outline
+    };
+    while outline.len() < next.len() {
+        outline.push(vertex);
+        vertex = next[&vertex];
+        if vertex == outline[0] {
+            break
+        }
+    }
+    outline
+}
+
+/// Find the leaf of a 3D BSP containing the point, if the point is not in solid space.
+fn find_leaf(bsp: &ModelCollisionGeometryBSP, point: &Point3D) -> Option<usize> {
+    if bsp.bsp3d_nodes.blocks.is_empty() {
+        return None
+    }
+
+    let mut child = 0u32;
+    loop {
+        if child == BSP_SOLID {
+            return None
+        }
+        if child & BSP_LEAF_FLAG != 0 {
+            return Some((child & !BSP_LEAF_FLAG) as usize)
+        }
+        let node = &bsp.bsp3d_nodes[child as usize];
+        child = if point.distance_from_plane(&bsp.planes[node.plane as usize].plane) >= 0.0 { node.front_child } else { node.back_child };
+    }
+}
+
+/// Get the parent node of each leaf of a 3D BSP, along with the planes bounding the leaf and whether the leaf is in
+/// front of each one.
+fn get_leaf_paths(bsp: &ModelCollisionGeometryBSP) -> Vec<(usize, Vec<(usize, bool)>)> {
+    let mut paths = vec![(0, Vec::new()); bsp.leaves.blocks.len()];
+    let mut nodes: Vec<(usize, Vec<(usize, bool)>)> = Vec::new();
+    if !bsp.bsp3d_nodes.blocks.is_empty() {
+        nodes.push((0, Vec::new()));
+    }
+
+    while let Some((node_index, path)) = nodes.pop() {
+        let node = &bsp.bsp3d_nodes[node_index];
+        for (child, front) in [(node.front_child, true), (node.back_child, false)] {
+            if child == BSP_SOLID {
+                continue
+            }
+            let mut child_path = path.clone();
+            child_path.push((node.plane as usize, front));
+            if child & BSP_LEAF_FLAG != 0 {
+                paths[(child & !BSP_LEAF_FLAG) as usize] = (node_index, child_path);
+            }
+            else {
+                nodes.push((child as usize, child_path));
+            }
+        }
+    }
+    paths
+}
+
+/// Split the leaves of a 3D BSP that a polygon on one of its planes passes through, so that each leaf is on one side of
+/// the polygon.
+fn split_leaves(bsp: &mut ModelCollisionGeometryBSP, plane_index: usize, points: &[Point3D]) {
+    // Move the points inwards slightly so that points on the edge of a leaf aren't counted.
+    let centroid = get_centroid(points);
+    let inset: Vec<Point3D> = points.iter()
+        .map(|p| Point3D::from(Vector3D::from(*p).add_components(&(Vector3D::from(centroid).sub_components(&Vector3D::from(*p).into_floats()) * 0.01).into_floats())))
+        .chain([centroid])
+        .collect();
+
+    for (leaf, (node, path)) in get_leaf_paths(bsp).into_iter().enumerate() {
+        if path.iter().any(|(plane, _)| *plane == plane_index) {
+            continue
+        }
+        let passes_through = inset.iter().any(|p| path.iter().all(|(plane, front)| {
+            let distance = p.distance_from_plane(&bsp.planes[*plane].plane);
+            if *front { distance > PLANE_EPSILON } else { distance < -PLANE_EPSILON }
+        }));
+        if !passes_through {
+            continue
+        }
+
+        let new_leaf = bsp.leaves.blocks.len() as u32;
+        bsp.leaves.blocks.push(bsp.leaves[leaf]);
+        let new_node = bsp.bsp3d_nodes.blocks.len() as u32;
+        bsp.bsp3d_nodes.blocks.push(ModelCollisionGeometryBSP3DNode {
+            plane: plane_index as u32,
+            front_child: leaf as u32 | BSP_LEAF_FLAG,
+            back_child: new_leaf | BSP_LEAF_FLAG
+        });
+
+        let parent = &mut bsp.bsp3d_nodes.blocks[node];
+        if parent.front_child == leaf as u32 | BSP_LEAF_FLAG {
+            parent.front_child = new_node;
+        }
+        else {
+            parent.back_child = new_node;
+        }
+    }
+}
+
+/// Build a lightmap material from the JMS triangles that use the shader, adding its surfaces.
+///
+/// Surfaces are wound in the opposite order of the JMS triangles.
+fn build_material(jms: &JMS, triangles: &[usize], shader: &ModelShaderReference, surfaces: &mut Reflexive<ScenarioStructureBSPSurface>) -> ErrorMessageResult<ScenarioStructureBSPMaterial> {
+    // Dedupe vertices by their values.
+    let vertex_key = |v: &Vertex| [
+        v.position.x.to_bits(), v.position.y.to_bits(), v.position.z.to_bits(),
+        v.normal.x.to_bits(), v.normal.y.to_bits(), v.normal.z.to_bits(),
+        v.texture_coordinates.x.to_bits(), v.texture_coordinates.y.to_bits()
+    ];
+    let mut vertex_map: HashMap<[u32; 8], u16> = HashMap::new();
+    let mut vertices: Vec<&Vertex> = Vec::new();
+    let mut local_triangles = Vec::with_capacity(triangles.len());
+    for &t in triangles {
+        let (a, b, c) = jms.triangles[t].vertices;
+        let mut local = [0u16; 3];
+        for (i, index) in [a, b, c].into_iter().enumerate() {
+            let vertex = &jms.vertices[index as usize];
+            local[i] = match vertex_map.get(&vertex_key(vertex)) {
+                Some(&n) => n,
+                None => {
+                    let new_index = u16::try_from(vertices.len()).map_err(|_| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_bsp_too_many_vertices"), shader=shader.shader)))?;
+                    vertex_map.insert(vertex_key(vertex), new_index);
+                    vertices.push(vertex);
+                    new_index
+                }
+            };
+        }
+        local_triangles.push((local[0], local[1], local[2]));
+    }
+
+    let mut material = ScenarioStructureBSPMaterial::new_with_defaults();
+    material.shader = shader.shader.clone();
+    material.shader_permutation = shader.permutation;
+    material.surfaces = surfaces.blocks.len() as i32;
+    material.surface_count = local_triangles.len() as i32;
+    for &(a, b, c) in &local_triangles {
+        surfaces.blocks.push(ScenarioStructureBSPSurface { vertex0_index: Some(a), vertex1_index: Some(c), vertex2_index: Some(b) });
+    }
+
+    let positions: Vec<Point3D> = vertices.iter().map(|v| v.position).collect();
+    material.centroid = get_centroid(&positions);
+
+    // Materials with all of their vertices on one plane are coplanar.
+    let (a, b, c) = jms.triangles[triangles[0]].vertices;
+    let normal = triangle_normal(&[a, b, c].map(|v| Vector3D::from(jms.vertices[v as usize].position))).normalize();
+    let plane = Plane3D { vector: normal, d: dot(&normal, &Vector3D::from(positions[0])) };
+    if positions.iter().all(|p| p.distance_from_plane(&plane).abs() < PLANE_EPSILON) {
+        material.flags.coplanar = true;
+        material.plane = plane;
+    }
+
+    // Vertex data is stored in little endian.
+    let tangent_space = calculate_tangent_space(&vertices, &local_triangles);
+    for (v, (normal, binormal, tangent)) in vertices.iter().zip(tangent_space) {
+        let vertex = ScenarioStructureBSPMaterialUncompressedRenderedVertex {
+            position: v.position,
+            normal,
+            binormal,
+            tangent,
+            texture_coords: Point2D { x: v.texture_coordinates.x, y: v.texture_coordinates.y }
+        };
+        for f in [vertex.position.x, vertex.position.y, vertex.position.z,
+                  vertex.normal.x, vertex.normal.y, vertex.normal.z,
+                  vertex.binormal.x, vertex.binormal.y, vertex.binormal.z,
+                  vertex.tangent.x, vertex.tangent.y, vertex.tangent.z,
+                  vertex.texture_coords.x, vertex.texture_coords.y] {
+            material.uncompressed_vertices.extend_from_slice(&f.to_le_bytes());
+        }
+
+        let compressed = vertex.compress();
+        for f in [compressed.position.x, compressed.position.y, compressed.position.z] {
+            material.compressed_vertices.extend_from_slice(&f.to_le_bytes());
+        }
+        for n in [compressed.normal, compressed.binormal, compressed.tangent] {
+            material.compressed_vertices.extend_from_slice(&n.to_le_bytes());
+        }
+        for f in [compressed.texture_coords.x, compressed.texture_coords.y] {
+            material.compressed_vertices.extend_from_slice(&f.to_le_bytes());
+        }
+    }
+    material.rendered_vertices_count = vertices.len() as u32;
+    material.lightmap_vertices_count = 0;
+
+    Ok(material)
+}
+
+/// Portal between two clusters, with its plane facing the front cluster.
+pub(super) struct ClusterPortal {
+    pub(super) front_cluster: usize,
+    pub(super) back_cluster: usize,
+    pub(super) plane: Plane3D,
+    pub(super) points: Vec<Point3D>
+}
+
+/// Find the clusters that can be seen from each cluster through the portals, returning a row of bits for each cluster.
+///
+/// A cluster can see itself and the clusters next to it. Past those, a portal can only be seen through a portal
+/// leaving the cluster if part of it is beyond that portal, and part of that portal is in front of it. This never hides
+/// a cluster that can be seen, but it does not account for walls.
+pub(super) fn get_cluster_visibility(cluster_count: usize, portals: &[ClusterPortal]) -> Vec<u8> {
+    // Each portal can be gone through from either side, with its plane facing the cluster that it goes into.
+    let mut leaving: Vec<Vec<usize>> = vec![Vec::new(); cluster_count];
+    let mut directed: Vec<(usize, Plane3D, &[Point3D])> = Vec::with_capacity(portals.len() * 2);
+    for portal in portals {
+        let flipped = Plane3D { vector: portal.plane.vector * -1.0, d: -portal.plane.d };
+        for (from, to, plane) in [(portal.back_cluster, portal.front_cluster, portal.plane), (portal.front_cluster, portal.back_cluster, flipped)] {
+            leaving[from].push(directed.len());
+            directed.push((to, plane, &portal.points));
+        }
+    }
+
+    let words = cluster_count.div_ceil(32);
+    let mut visible = vec![0u32; cluster_count * words];
+    for c in 0..cluster_count {
+        let row = &mut visible[c * words..(c + 1) * words];
+        row[c / 32] |= 1 << (c % 32);
+        for &source in &leaving[c] {
+            let (to, source_plane, source_points) = directed[source];
+            row[to / 32] |= 1 << (to % 32);
+
+            let mut visited = HashSet::from([source]);
+            let mut clusters = vec![to];
+            while let Some(cluster) = clusters.pop() {
+                for &next in &leaving[cluster] {
+                    let (to, plane, points) = directed[next];
+                    let beyond_source = points.iter().any(|p| p.distance_from_plane(&source_plane) > PLANE_EPSILON);
+                    let source_in_front = source_points.iter().any(|p| p.distance_from_plane(&plane) < -PLANE_EPSILON);
+                    if beyond_source && source_in_front && visited.insert(next) {
+                        row[to / 32] |= 1 << (to % 32);
+                        clusters.push(to);
+                    }
+                }
+            }
+        }
+    }
+
+    visible.into_iter().flat_map(u32::to_le_bytes).collect()
+}
+
+/// Build a scenario_structure_bsp tag from a JMS file.
+///
+/// Materials are named after shaders, and symbols at the end of their names set surface flags like with
+/// [`build_model_collision_geometry`](super::build_model_collision_geometry). Shaders are found by calling
+/// `resolve_shader` with the name of each material, which returns `Ok(None)` if no shader was found. The following
+/// special materials are also used:
+/// - `+sky` seals the world and sets the sky of its cluster. It can be followed by the index of the sky in the scenario.
+/// - `+seamsealer` seals the world.
+/// - `+portal` and `+exactportal` split the world into clusters. Each portal has to be coplanar, and the world has to
+///   be split along its edges.
+/// - `+weatherpoly` makes a weather polyhedron out of each closed mesh, which faces outwards.
+/// - `+fog` makes a fog plane out of each coplanar mesh, which faces into the fog.
+///
+/// The collision geometry (everything except render-only, portal, weather polyhedron, and fog plane triangles) has to
+/// be sealed, with empty space in front of its triangles and solid space behind them. Rendered geometry goes into a
+/// single lightmap without a lightmap bitmap.
+///
+/// Cluster visibility is found from the portals. BSP nodes, lens flare markers, pathfinding data, the leaf map, and
+/// sound PAS data are not generated, so they are left empty.
+///
+/// If `existing` is set, its settings are kept, including the settings of its clusters and fog regions, and only its
+/// geometry, clusters, portals, fog planes, weather polyhedra, and markers are replaced.
+pub fn build_scenario_structure_bsp<F>(jms: &JMS, existing: Option<&ScenarioStructureBSP>, mut resolve_shader: F) -> ErrorMessageResult<ScenarioStructureBSP> where F: FnMut(&str) -> ErrorMessageResult<Option<TagReference>> {
+    jms.validate()?;
+
+    let mut bsp = match existing {
+        Some(n) => n.clone(),
+        None => ScenarioStructureBSP::new_with_defaults()
+    };
+
+    // Materials
+    bsp.collision_materials = Reflexive::default();
+    let mut render_shaders: Vec<ModelShaderReference> = Vec::new();
+    let mut materials: Vec<BSPMaterial> = Vec::with_capacity(jms.materials.len());
+    for m in &jms.materials {
+        let (name, flags) = parse_material_name(&m.name);
+        let kind = BSPGeometryKind::from_material_name(name);
+        let (shader, flags, render) = match kind {
+            BSPGeometryKind::Shader => {
+                let shader = resolve_material(name, &mut resolve_shader)?;
+                let render = if flags.map(|f| f.invisible).unwrap_or(false) {
+                    None
+                }
+                else {
+                    Some(match render_shaders.iter().position(|s| s.shader == shader.shader && s.permutation == shader.permutation) {
+                        Some(n) => n,
+                        None => {
+                            render_shaders.push(shader.clone());
+                            render_shaders.len() - 1
+                        }
+                    })
+                };
+                (shader.shader, flags, render)
+            },
+            BSPGeometryKind::Sky(_) | BSPGeometryKind::SeamSealer => {
+                let flags = ModelCollisionGeometryBSPSurfaceFlags { invisible: true, ..Default::default() };
+                (TagReference::default(), Some(flags), None)
+            },
+            _ => {
+                materials.push(BSPMaterial { kind, collision: None, render: None });
+                continue
+            }
+        };
+
+        let collision = match flags {
+            Some(flags) => {
+                let material = match bsp.collision_materials.blocks.iter().position(|m| m.shader == shader) {
+                    Some(n) => n,
+                    None => {
+                        bsp.collision_materials.blocks.push(ScenarioStructureBSPCollisionMaterial { shader, ..Default::default() });
+                        bsp.collision_materials.blocks.len() - 1
+                    }
+                };
+                Some(CollisionMaterial { material: material as u16, flags })
+            },
+            None => None
+        };
+        materials.push(BSPMaterial { kind, collision, render });
+    }
+
+    // Sort the triangles by what they are used for, welding their vertices by position.
+    let mut welder = PositionWelder::default();
+    let mut welded: Vec<[usize; 3]> = Vec::with_capacity(jms.triangles.len());
+    let mut world_triangles: Vec<usize> = Vec::new();
+    let mut portal_triangles: Vec<usize> = Vec::new();
+    let mut weather_triangles: Vec<usize> = Vec::new();
+    let mut fog_triangles: Vec<usize> = Vec::new();
+    let mut collision_triangles: Vec<CollisionTriangle> = Vec::new();
+    for (index, t) in jms.triangles.iter().enumerate() {
+        let vertices = [t.vertices.0, t.vertices.1, t.vertices.2];
+        welded.push(vertices.map(|v| welder.weld(&jms.vertices[v as usize].position)));
+
+        let material = match t.shader {
+            Some(n) => &materials[n as usize],
+            None => continue
+        };
+        check_degenerate_triangle(jms, index, vertices)?;
+
+        match material.kind {
+            BSPGeometryKind::Portal => portal_triangles.push(index),
+            BSPGeometryKind::WeatherPolyhedron => weather_triangles.push(index),
+            BSPGeometryKind::FogPlane => fog_triangles.push(index),
+            _ => {
+                world_triangles.push(index);
+                if let Some(material) = material.collision {
+                    collision_triangles.push(CollisionTriangle { index, vertices, material });
+                }
+            }
+        }
+    }
+    let triangle_material = |t: usize| &materials[jms.triangles[t].shader.unwrap() as usize];
+    let triangle_points = |t: usize| welded[t].map(|v| welder.points[v]);
+
+    // Collision geometry is already in world space.
+    if collision_triangles.is_empty() {
+        return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.jms.error_bsp_no_collision")))
+    }
+    let identity = (Matrix::from(Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 }), Vector3D::default());
+    let mut collision_bsp = build_bsp(jms, &collision_triangles, &identity)?;
+
+    // Breakable surfaces
+    bsp.breakable_surfaces = Reflexive::default();
+    for (s, t) in collision_triangles.iter().enumerate().filter(|(_, t)| t.material.flags.breakable) {
+        let points = triangle_points(t.index);
+        let centroid = get_centroid(&points);
+        let index = bsp.breakable_surfaces.blocks.len();
+        collision_bsp.surfaces.blocks[s].breakable_surface = index.try_into().map_err(|_| ErrorMessage::StaticString(get_compiled_string!("engine.h1.jms.error_bsp_too_many_breakable_surfaces")))?;
+        bsp.breakable_surfaces.blocks.push(ScenarioStructureBSPBreakableSurface {
+            centroid,
+            radius: points.iter().map(|p| get_distance(p, &centroid)).fold(0.0, f32::max),
+            collision_surface_index: s as i32
+        });
+    }
+
+    // Rendered geometry
+    bsp.surfaces = Reflexive::default();
+    bsp.lightmaps = Reflexive::default();
+    let mut render_surfaces: Vec<Option<usize>> = vec![None; jms.triangles.len()];
+    let mut lightmap = ScenarioStructureBSPLightmap { bitmap: None, ..Default::default() };
+    for (r, shader) in render_shaders.iter().enumerate() {
+        let triangles: Vec<usize> = world_triangles.iter().copied().filter(|t| triangle_material(*t).render == Some(r)).collect();
+        if triangles.is_empty() {
+            continue
+        }
+        for (i, t) in triangles.iter().enumerate() {
+            render_surfaces[*t] = Some(bsp.surfaces.blocks.len() + i);
+        }
+        lightmap.materials.blocks.push(build_material(jms, &triangles, shader, &mut bsp.surfaces)?);
+    }
+    if !lightmap.materials.blocks.is_empty() {
+        bsp.lightmaps.blocks.push(lightmap);
+    }
+
+    // Portals
+    let portal_welded: Vec<[usize; 3]> = portal_triangles.iter().map(|t| welded[*t]).collect();
+    let portal_groups = group_triangles(&portal_welded, |a, b, _| planes_match(&welder.get_plane(&portal_welded[a]), &welder.get_plane(&portal_welded[b])));
+    let portal_edges: HashSet<(usize, usize)> = portal_groups.iter()
+        .flat_map(|g| get_outline_edges(&g.iter().map(|t| portal_welded[*t]).collect::<Vec<_>>()))
+        .map(|(a, b)| (a.min(b), a.max(b)))
+        .collect();
+
+    // Clusters are made of world triangles connected by edges that aren't on the edges of a portal.
+    let world_welded: Vec<[usize; 3]> = world_triangles.iter().map(|t| welded[*t]).collect();
+    let components = group_triangles(&world_welded, |_, _, edge| !portal_edges.contains(&edge));
+    let component_bounds: Vec<[Bounds<f32>; 3]> = components.iter()
+        .map(|c| get_bounds(&c.iter().flat_map(|t| triangle_points(world_triangles[*t])).collect::<Vec<_>>()))
+        .collect();
+    let volume = |c: usize| component_bounds[c].iter().map(|b| b.upper - b.lower).product::<f32>();
+    let contains = |outer: usize, inner: usize| component_bounds[outer].iter().zip(component_bounds[inner].iter()).all(|(o, i)| o.lower <= i.lower + PLANE_EPSILON && o.upper >= i.upper - PLANE_EPSILON);
+
+    // Components that don't touch a portal, such as crates or pillars in a room, are put in the cluster of the smallest
+    // component that contains them.
+    let containers: Vec<Option<usize>> = (0..components.len()).map(|c| {
+        let touches_portal = components[c].iter().any(|t| {
+            let v = world_welded[*t];
+            [(v[0], v[1]), (v[1], v[2]), (v[2], v[0])].iter().any(|(a, b)| portal_edges.contains(&((*a).min(*b), (*a).max(*b))))
+        });
+        if touches_portal {
+            return None
+        }
+        (0..components.len())
+            .filter(|o| *o != c && volume(*o) > volume(c) && contains(*o, c))
+            .min_by(|a, b| volume(*a).total_cmp(&volume(*b)))
+    }).collect();
+
+    let mut triangle_clusters: Vec<Option<usize>> = vec![None; jms.triangles.len()];
+    let mut cluster_components: HashMap<usize, usize> = HashMap::new();
+    let mut cluster_triangles: Vec<Vec<usize>> = Vec::new();
+    for (c, component) in components.iter().enumerate() {
+        let mut root = c;
+        while let Some(n) = containers[root] {
+            root = n;
+        }
+        let cluster = *cluster_components.entry(root).or_insert_with(|| {
+            cluster_triangles.push(Vec::new());
+            cluster_triangles.len() - 1
+        });
+        for t in component {
+            triangle_clusters[world_triangles[*t]] = Some(cluster);
+            cluster_triangles[cluster].push(world_triangles[*t]);
+        }
+    }
+    let cluster_count = cluster_triangles.len();
+
+    bsp.clusters = Reflexive::default();
+    for (c, triangles) in cluster_triangles.iter().enumerate() {
+        let mut cluster = existing.and_then(|e| e.clusters.blocks.get(c)).cloned().unwrap_or_default();
+        cluster.sky = triangles.iter().find_map(|t| match triangle_material(*t).kind {
+            BSPGeometryKind::Sky(n) => Some(n),
+            _ => None
+        });
+        cluster.first_lens_flare_marker_index = None;
+        cluster.lens_flare_marker_count = 0;
+        cluster.predicted_resources = Reflexive::default();
+        cluster.subclusters = Reflexive::default();
+        cluster.surface_indices = Reflexive::default();
+        cluster.mirrors = Reflexive::default();
+        cluster.portals = Reflexive::default();
+
+        let surfaces: Vec<usize> = triangles.iter().filter_map(|t| render_surfaces[*t]).collect();
+        if !surfaces.is_empty() {
+            let points: Vec<Point3D> = triangles.iter().filter(|t| render_surfaces[**t].is_some()).flat_map(|t| triangle_points(*t)).collect();
+            let [world_bounds_x, world_bounds_y, world_bounds_z] = get_bounds(&points);
+            cluster.subclusters.blocks.push(ScenarioStructureBSPSubcluster {
+                world_bounds_x,
+                world_bounds_y,
+                world_bounds_z,
+                surface_indices: Reflexive { blocks: surfaces.iter().map(|s| ScenarioStructureBSPSubclusterSurfaceIndex { index: *s as i32 }).collect() }
+            });
+            cluster.surface_indices.blocks = surfaces.iter().map(|s| ScenarioStructureBSPClusterSurfaceIndex { index: *s as i32 }).collect();
+        }
+        bsp.clusters.blocks.push(cluster);
+    }
+
+    // The clusters of a portal are found from the world triangles along its edges.
+    let mut edge_triangles: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for &t in &world_triangles {
+        let v = welded[t];
+        for (a, b) in [(v[0], v[1]), (v[1], v[2]), (v[2], v[0])] {
+            edge_triangles.entry((a.min(b), a.max(b))).or_default().push(t);
+        }
+    }
+
+    bsp.cluster_portals = Reflexive::default();
+    let mut portals: Vec<ClusterPortal> = Vec::with_capacity(portal_groups.len());
+    for group in &portal_groups {
+        let triangles: Vec<[usize; 3]> = group.iter().map(|t| portal_welded[*t]).collect();
+        let outline = get_outline(&triangles);
+        let plane = welder.get_plane(&triangles[0]);
+        let points: Vec<Point3D> = outline.iter().map(|v| welder.points[*v]).collect();
+        let centroid = get_centroid(&points);
+
+        let mut front_cluster = None;
+        let mut back_cluster = None;
+        for (i, &a) in outline.iter().enumerate() {
+            let b = outline[(i + 1) % outline.len()];
+            for &t in edge_triangles.get(&(a.min(b), a.max(b))).map(|t| t.as_slice()).unwrap_or_default() {
+                let distance = get_centroid(&triangle_points(t)).distance_from_plane(&plane);
+                if distance > PLANE_EPSILON {
+                    front_cluster.get_or_insert(triangle_clusters[t].unwrap());
+                }
+                else if distance < -PLANE_EPSILON {
+                    back_cluster.get_or_insert(triangle_clusters[t].unwrap());
+                }
+            }
+        }
+        let (front_cluster, back_cluster) = match (front_cluster, back_cluster) {
+            (Some(f), Some(b)) if f != b => (f, b),
+            _ => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_bsp_portal_clusters"), position=format_position(&centroid))))
+        };
+
+        let plane_index = match collision_bsp.planes.blocks.iter().position(|p| planes_match(&p.plane, &plane)) {
+            Some(n) => n,
+            None => {
+                collision_bsp.planes.blocks.push(ModelCollisionGeometryBSPPlane { plane });
+                collision_bsp.planes.blocks.len() - 1
+            }
+        };
+
+        split_leaves(&mut collision_bsp, plane_index, &points);
+
+        let portal_index = bsp.cluster_portals.blocks.len();
+        for c in [front_cluster, back_cluster] {
+            bsp.clusters.blocks[c].portals.blocks.push(ScenarioStructureBSPClusterPortalIndex { portal: Some(portal_index as u16) });
+        }
+        bsp.cluster_portals.blocks.push(ScenarioStructureBSPClusterPortal {
+            front_cluster: Some(front_cluster as u16),
+            back_cluster: Some(back_cluster as u16),
+            plane_index: plane_index as i32,
+            centroid,
+            bounding_radius: points.iter().map(|p| get_distance(p, &centroid)).fold(0.0, f32::max),
+            vertices: Reflexive { blocks: points.iter().map(|p| ScenarioStructureBSPClusterPortalVertex { point: *p }).collect() },
+            ..Default::default()
+        });
+        portals.push(ClusterPortal { front_cluster, back_cluster, plane, points });
+    }
+    bsp.cluster_data = get_cluster_visibility(cluster_count, &portals);
+
+    // Rendered surfaces are referenced by the leaf in front of them.
+    let leaf_paths = get_leaf_paths(&collision_bsp);
+    let mut leaf_surfaces: Vec<Vec<usize>> = vec![Vec::new(); collision_bsp.leaves.blocks.len()];
+    for &t in &world_triangles {
+        let surface = match render_surfaces[t] {
+            Some(n) => n,
+            None => continue
+        };
+        let points = triangle_points(t);
+        let normal = triangle_normal(&points.map(Vector3D::from)).normalize();
+        let point = Point3D::from(Vector3D::from(get_centroid(&points)).add_components(&(normal * LEAF_OFFSET).into_floats()));
+        if let Some(leaf) = find_leaf(&collision_bsp, &point) {
+            leaf_surfaces[leaf].push(surface);
+        }
+    }
+
+    // The cluster of a leaf is the cluster of the most collision surfaces bounding it.
+    bsp.leaves = Reflexive::default();
+    bsp.leaf_surfaces = Reflexive::default();
+    for (l, leaf) in collision_bsp.leaves.blocks.iter().enumerate() {
+        let (node, path) = &leaf_paths[l];
+        let first_reference = leaf.first_bsp2d_reference as usize;
+        let planes: Vec<usize> = collision_bsp.bsp2d_references.blocks[first_reference..first_reference + leaf.bsp2d_reference_count as usize].iter().map(|r| r.plane as usize).collect();
+
+        let mut counts = vec![0usize; cluster_count];
+        for (s, _) in collision_bsp.surfaces.blocks.iter().enumerate().filter(|(_, s)| planes.contains(&(s.plane as usize))) {
+            let t = collision_triangles[s].index;
+            let centroid = get_centroid(&triangle_points(t));
+            let inside = path.iter().all(|(plane, front)| {
+                let distance = centroid.distance_from_plane(&collision_bsp.planes[*plane].plane);
+                if *front { distance >= -PLANE_EPSILON } else { distance <= PLANE_EPSILON }
+            });
+            if inside {
+                counts[triangle_clusters[t].unwrap()] += 1;
+            }
+        }
+        let cluster = (0..cluster_count).filter(|c| counts[*c] > 0).max_by_key(|c| (counts[*c], usize::MAX - c)).unwrap_or(0);
+
+        bsp.leaves.blocks.push(ScenarioStructureBSPLeaf {
+            cluster: Some(cluster as u16),
+            surface_reference_count: leaf_surfaces[l].len() as i16,
+            surface_references: bsp.leaf_surfaces.blocks.len() as i32,
+            ..Default::default()
+        });
+        for surface in &leaf_surfaces[l] {
+            bsp.leaf_surfaces.blocks.push(ScenarioStructureBSPSurfaceReference { surface: *surface as i32, node: *node as i32 });
+        }
+    }
+    bsp.collision_bsp = Reflexive { blocks: vec![collision_bsp] };
+
+    // Fog planes
+    let fog_welded: Vec<[usize; 3]> = fog_triangles.iter().map(|t| welded[*t]).collect();
+    bsp.fog_planes = Reflexive::default();
+    bsp.fog_regions = Reflexive::default();
+    for (i, group) in group_triangles(&fog_welded, |a, b, _| planes_match(&welder.get_plane(&fog_welded[a]), &welder.get_plane(&fog_welded[b]))).iter().enumerate() {
+        let triangles: Vec<[usize; 3]> = group.iter().map(|t| fog_welded[*t]).collect();
+        bsp.fog_planes.blocks.push(ScenarioStructureBSPFogPlane {
+            front_region: Some(i as u16),
+            plane: welder.get_plane(&triangles[0]),
+            vertices: Reflexive { blocks: get_outline(&triangles).into_iter().map(|v| ScenarioStructureBSPFogPlaneVertex { point: welder.points[v] }).collect() },
+            ..Default::default()
+        });
+        bsp.fog_regions.blocks.push(existing.and_then(|e| e.fog_regions.blocks.get(i)).cloned().unwrap_or_default());
+    }
+
+    // Weather polyhedra
+    let weather_welded: Vec<[usize; 3]> = weather_triangles.iter().map(|t| welded[*t]).collect();
+    bsp.weather_polyhedra = Reflexive::default();
+    for group in group_triangles(&weather_welded, |_, _, _| true) {
+        let mut planes: Vec<Plane3D> = Vec::new();
+        for t in &group {
+            let plane = welder.get_plane(&weather_welded[*t]);
+            if !planes.iter().any(|p| planes_match(p, &plane)) {
+                planes.push(plane);
+            }
+        }
+
+        let mut vertices: Vec<usize> = group.iter().flat_map(|t| weather_welded[*t]).collect();
+        vertices.sort();
+        vertices.dedup();
+        let points: Vec<Point3D> = vertices.into_iter().map(|v| welder.points[v]).collect();
+        let center = get_centroid(&points);
+        bsp.weather_polyhedra.blocks.push(ScenarioStructureBSPWeatherPolyhedron {
+            bounding_sphere_center: center,
+            bounding_sphere_radius: points.iter().map(|p| get_distance(p, &center)).fold(0.0, f32::max),
+            planes: Reflexive { blocks: planes.into_iter().map(|plane| ScenarioStructureBSPWeatherPolyhedronPlane { plane }).collect() }
+        });
+    }
+
+    // Markers
+    bsp.markers = Reflexive::default();
+    for m in &jms.markers {
+        bsp.markers.blocks.push(ScenarioStructureBSPMarker {
+            name: String32::from_str(&m.name)?,
+            rotation: m.rotation,
+            position: m.position
+        });
+    }
+
+    let world_points: Vec<Point3D> = world_triangles.iter().flat_map(|t| triangle_points(*t)).collect();
+    [bsp.world_bounds_x, bsp.world_bounds_y, bsp.world_bounds_z] = get_bounds(&world_points);
+
+    // Anything else that depends on the geometry is no longer valid, and it isn't generated yet.
+    bsp.nodes = Reflexive::default();
+    bsp.lens_flare_markers = Reflexive::default();
+    bsp.pathfinding_surfaces = Reflexive::default();
+    bsp.pathfinding_edges = Reflexive::default();
+    bsp.leaf_map_leaves = Reflexive::default();
+    bsp.leaf_map_portals = Reflexive::default();
+    bsp.sound_pas_data = Vec::new();
+
+    Ok(bsp)
+}
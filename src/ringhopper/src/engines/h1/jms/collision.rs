@@ -0,0 +1,631 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::engines::h1::definitions::*;
+use crate::error::*;
+use crate::types::{Matrix, Plane2D, Plane3D, Point2D, Point3D, Reflexive, String32, TagBlockFn, Vector2D, Vector3D};
+
+use ringhopper_proc::*;
+
+use super::{JMS, SCALE_FACTOR};
+use super::model::{cross, dot};
+
+/// Set on a BSP child index if it refers to a leaf (3D BSP) or a surface (2D BSP) rather than a node.
+pub(super) const BSP_LEAF_FLAG: u32 = 0x80000000;
+
+/// Used for a 3D BSP child that is solid (i.e. inside of the geometry).
+pub(super) const BSP_SOLID: u32 = 0xFFFFFFFF;
+
+/// Distance from a plane within which a point is considered to be on the plane.
+pub(super) const PLANE_EPSILON: f32 = 0.0001;
+
+/// Symbols that can be added to the end of a material name to set properties of its surfaces.
+const MATERIAL_SYMBOLS: [char; 13] = ['*', '!', '@', '#', '$', '%', '^', '&', '-', '=', '.', ';', '?'];
+
+/// JMS file of a permutation of a collision model.
+pub struct CollisionPermutationJMS {
+    /// Name of the permutation.
+    pub permutation: String,
+
+    /// JMS data.
+    pub jms: JMS
+}
+
+/// Material and surface flags of a collision triangle.
+#[derive(Copy, Clone)]
+pub(super) struct CollisionMaterial {
+    pub(super) material: u16,
+    pub(super) flags: ModelCollisionGeometryBSPSurfaceFlags
+}
+
+/// Triangle of a JMS to be put into a BSP.
+pub(super) struct CollisionTriangle {
+    /// Index of the triangle in the JMS.
+    pub(super) index: usize,
+
+    /// JMS vertex indices.
+    pub(super) vertices: [u32; 3],
+
+    pub(super) material: CollisionMaterial
+}
+
+/// Split a material name into its name without symbols and the surface flags of its symbols.
+///
+/// The flags are `None` if the material is render-only (`*`), in which case it has no collision.
+pub(super) fn parse_material_name(name: &str) -> (&str, Option<ModelCollisionGeometryBSPSurfaceFlags>) {
+    let base_name = name.trim_end_matches(MATERIAL_SYMBOLS);
+    let symbols = &name[base_name.len()..];
+    if symbols.contains('*') {
+        return (base_name, None)
+    }
+
+    let flags = ModelCollisionGeometryBSPSurfaceFlags {
+        two_sided: symbols.contains('%'),
+        invisible: symbols.contains('@'),
+        climbable: symbols.contains('^'),
+        breakable: symbols.contains('-')
+    };
+    (base_name, Some(flags))
+}
+
+/// Format a position in JMS units, so that it can be found in a modeling program.
+pub(super) fn format_position(position: &Point3D) -> String {
+    let position = position.scale(SCALE_FACTOR);
+    format!("({}, {}, {})", position.x, position.y, position.z)
+}
+
+/// Get a key for the position that is the same for equal positions, for welding or deduplicating by position.
+pub(super) fn position_key(position: &Point3D) -> [u32; 3] {
+    // Adding zero makes negative zero positive zero.
+    [position.x + 0.0, position.y + 0.0, position.z + 0.0].map(f32::to_bits)
+}
+
+/// Get the normal of a triangle scaled by twice its area.
+pub(super) fn triangle_normal(points: &[Vector3D; 3]) -> Vector3D {
+    cross(&points[1].sub_components(&points[0].into_floats()), &points[2].sub_components(&points[0].into_floats()))
+}
+
+/// Get the error for a JMS triangle that has no area.
+fn degenerate_triangle_error(jms: &JMS, index: usize, vertices: [u32; 3]) -> ErrorMessage {
+    ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_degenerate_triangle"),
+        triangle=index,
+        vertex_a=vertices[0],
+        vertex_b=vertices[1],
+        vertex_c=vertices[2],
+        position=format_position(&jms.vertices[vertices[0] as usize].position)))
+}
+
+/// Return an error if a JMS triangle has no area.
+pub(super) fn check_degenerate_triangle(jms: &JMS, index: usize, vertices: [u32; 3]) -> ErrorMessageResult<()> {
+    let normal = triangle_normal(&vertices.map(|v| Vector3D::from(jms.vertices[v as usize].position)));
+    if dot(&normal, &normal).sqrt() < PLANE_EPSILON {
+        return Err(degenerate_triangle_error(jms, index, vertices))
+    }
+    Ok(())
+}
+
+/// Get the transformation of each node from node space into model space, as a rotation and translation.
+fn get_node_transforms(jms: &JMS, parents: &[Option<u16>]) -> ErrorMessageResult<Vec<(Matrix, Vector3D)>> {
+    let mut transforms: Vec<Option<(Matrix, Vector3D)>> = vec![None; jms.nodes.len()];
+    for node in 0..jms.nodes.len() {
+        // Parents need to be transformed before their children.
+        let mut chain = vec![node];
+        while let Some(parent) = parents[*chain.last().unwrap()].map(|p| p as usize).filter(|p| transforms[*p].is_none()) {
+            if chain.contains(&parent) {
+                return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.jms.error_verify_fail_infinite_loop")))
+            }
+            chain.push(parent);
+        }
+
+        for &n in chain.iter().rev() {
+            let rotation = Matrix::from(jms.nodes[n].rotation);
+            let translation = Vector3D::from(jms.nodes[n].position);
+            transforms[n] = Some(match parents[n] {
+                Some(p) => {
+                    let (parent_rotation, parent_translation) = transforms[p as usize].unwrap();
+                    (rotation * parent_rotation, translation.rotate_by_matrix(&parent_rotation).add_components(&parent_translation.into_floats()))
+                },
+                None => (rotation, translation)
+            });
+        }
+    }
+    Ok(transforms.into_iter().map(|t| t.unwrap()).collect())
+}
+
+/// Project a point on a plane into 2D by dropping the plane's largest axis.
+///
+/// The axes are swapped if the largest axis is negative so the winding order of projected surfaces is kept.
+fn project_point(normal: &Vector3D, point: &Point3D) -> Point2D {
+    let (x, y, z) = (normal.x.abs(), normal.y.abs(), normal.z.abs());
+    let (u, v, negative) = if x >= y && x >= z {
+        (point.y, point.z, normal.x < 0.0)
+    }
+    else if y >= z {
+        (point.z, point.x, normal.y < 0.0)
+    }
+    else {
+        (point.x, point.y, normal.z < 0.0)
+    };
+    if negative { Point2D { x: v, y: u } } else { Point2D { x: u, y: v } }
+}
+
+/// Builder for the BSP of a single node and permutation.
+#[derive(Default)]
+struct BSPBuilder {
+    bsp: ModelCollisionGeometryBSP,
+
+    /// BSP vertex indices of each surface.
+    surface_vertices: Vec<[usize; 3]>,
+
+    /// JMS triangle index and JMS position of the first vertex of each surface, for errors.
+    surface_triangles: Vec<(usize, Point3D)>,
+
+    /// Root 2D BSP node of each plane that has been used in a leaf.
+    bsp2d_roots: HashMap<usize, u32>
+}
+
+impl BSPBuilder {
+    /// Add the triangles, along with their edges and planes.
+    ///
+    /// Triangles must not be degenerate, and every edge must be shared by exactly two triangles with opposite winding
+    /// orders, with the exception of edges of two-sided triangles, which may be open.
+    fn add_triangles(&mut self, jms: &JMS, triangles: &[CollisionTriangle], node_transform: &(Matrix, Vector3D)) -> ErrorMessageResult<()> {
+        let (rotation, translation) = node_transform;
+        let inverse_rotation = rotation.invert_matrix();
+
+        // Put vertices into node space, welding vertices with the same position.
+        let mut vertex_map: HashMap<[u32; 3], usize> = HashMap::new();
+        let mut jms_vertices: Vec<u32> = Vec::new();
+        for t in triangles {
+            let mut indices = [0usize; 3];
+            for (i, &v) in t.vertices.iter().enumerate() {
+                let point = Point3D::from(Vector3D::from(jms.vertices[v as usize].position).sub_components(&translation.into_floats()).rotate_by_matrix(&inverse_rotation));
+                indices[i] = *vertex_map.entry(position_key(&point)).or_insert_with(|| {
+                    self.bsp.vertices.blocks.push(ModelCollisionGeometryBSPVertex { point, first_edge: 0 });
+                    jms_vertices.push(v);
+                    jms_vertices.len() - 1
+                });
+            }
+
+            let points = indices.map(|i| Vector3D::from(self.bsp.vertices[i].point));
+            let normal = triangle_normal(&points);
+            if indices[0] == indices[1] || indices[1] == indices[2] || indices[0] == indices[2] || dot(&normal, &normal).sqrt() < PLANE_EPSILON {
+                return Err(degenerate_triangle_error(jms, t.index, t.vertices))
+            }
+
+            let normal = normal.normalize();
+            let plane = Plane3D { vector: normal, d: dot(&normal, &points[0]) };
+            let plane_index = match self.bsp.planes.blocks.iter().position(|p| {
+                let difference = p.plane.vector.sub_components(&normal.into_floats());
+                dot(&difference, &difference) < PLANE_EPSILON * PLANE_EPSILON && (p.plane.d - plane.d).abs() < PLANE_EPSILON
+            }) {
+                Some(n) => n,
+                None => {
+                    self.bsp.planes.blocks.push(ModelCollisionGeometryBSPPlane { plane });
+                    self.bsp.planes.blocks.len() - 1
+                }
+            };
+
+            self.bsp.surfaces.blocks.push(ModelCollisionGeometryBSPSurface {
+                plane: plane_index as u32,
+                flags: t.material.flags,
+                breakable_surface: -1,
+                material: Some(t.material.material),
+                ..Default::default()
+            });
+            self.surface_vertices.push(indices);
+            self.surface_triangles.push((t.index, jms.vertices[t.vertices[0] as usize].position));
+        }
+
+        // Find which surface each directed edge belongs to. Two surfaces with the same directed edge overlap or are
+        // wound inconsistently.
+        let mut directed_edges: HashMap<(usize, usize), usize> = HashMap::new();
+        for (s, v) in self.surface_vertices.iter().enumerate() {
+            for (a, b) in [(v[0], v[1]), (v[1], v[2]), (v[2], v[0])] {
+                if let Some(other) = directed_edges.insert((a, b), s) {
+                    return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_duplicate_edge"),
+                               triangle_a=triangles[other].index,
+                               triangle_b=triangles[s].index,
+                               vertex_a=jms_vertices[a],
+                               vertex_b=jms_vertices[b],
+                               position_a=format_position(&jms.vertices[jms_vertices[a] as usize].position),
+                               position_b=format_position(&jms.vertices[jms_vertices[b] as usize].position))))
+                }
+            }
+        }
+
+        // Create the edges, with the surface that created it on the left.
+        let mut edge_map: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut first_edges: Vec<Option<usize>> = vec![None; jms_vertices.len()];
+        for (s, v) in self.surface_vertices.iter().enumerate() {
+            for (a, b) in [(v[0], v[1]), (v[1], v[2]), (v[2], v[0])] {
+                let key = (a.min(b), a.max(b));
+                if edge_map.contains_key(&key) {
+                    continue;
+                }
+
+                let right = match directed_edges.get(&(b, a)) {
+                    Some(&n) => n,
+                    None if self.bsp.surfaces[s].flags.two_sided => s,
+                    None => return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_open_edge"),
+                                       triangle=triangles[s].index,
+                                       vertex_a=jms_vertices[a],
+                                       vertex_b=jms_vertices[b],
+                                       position_a=format_position(&jms.vertices[jms_vertices[a] as usize].position),
+                                       position_b=format_position(&jms.vertices[jms_vertices[b] as usize].position))))
+                };
+
+                let edge_index = self.bsp.edges.blocks.len();
+                self.bsp.edges.blocks.push(ModelCollisionGeometryBSPEdge {
+                    start_vertex: a as u32,
+                    end_vertex: b as u32,
+                    left_surface: s as u32,
+                    right_surface: right as u32,
+                    ..Default::default()
+                });
+                edge_map.insert(key, edge_index);
+                first_edges[a].get_or_insert(edge_index);
+                first_edges[b].get_or_insert(edge_index);
+            }
+        }
+        for (vertex, first_edge) in self.bsp.vertices.blocks.iter_mut().zip(first_edges) {
+            vertex.first_edge = first_edge.unwrap() as u32;
+        }
+
+        // Link the edges of each surface together. Going around a surface, its edges are followed by the forward edge if
+        // the surface is on the left and by the reverse edge if it is on the right.
+        for (s, v) in self.surface_vertices.iter().enumerate() {
+            let edges = [(v[0], v[1]), (v[1], v[2]), (v[2], v[0])].map(|(a, b)| edge_map[&(a.min(b), a.max(b))]);
+            for i in 0..3 {
+                let next = edges[(i + 1) % 3] as u32;
+                let edge = &mut self.bsp.edges.blocks[edges[i]];
+                if edge.start_vertex as usize == v[i] {
+                    edge.forward_edge = next;
+
+                    // Open edges of two-sided surfaces are on both sides of the surface.
+                    if edge.right_surface as usize == s {
+                        edge.reverse_edge = edges[(i + 2) % 3] as u32;
+                    }
+                }
+                else {
+                    edge.reverse_edge = next;
+                }
+            }
+            self.bsp.surfaces.blocks[s].first_edge = edges[0] as u32;
+        }
+
+        Ok(())
+    }
+
+    /// Get the minimum and maximum distance of the surface's vertices from the plane.
+    fn get_surface_distance(&self, surface: usize, plane: &Plane3D) -> (f32, f32) {
+        let distances = self.surface_vertices[surface].map(|v| self.bsp.vertices[v].point.distance_from_plane(plane));
+        (distances.into_iter().fold(f32::MAX, f32::min), distances.into_iter().fold(f32::MIN, f32::max))
+    }
+
+    /// Split the surfaces into the surfaces in front of the plane and the surfaces behind it.
+    ///
+    /// Surfaces on the plane are left out of both, except for surfaces facing the opposite way, which are put behind it.
+    /// Surfaces crossing the plane are put in both.
+    fn split_surfaces(&self, surfaces: &[usize], plane_index: usize) -> (Vec<usize>, Vec<usize>) {
+        let plane = self.bsp.planes[plane_index].plane;
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        for &s in surfaces {
+            if self.bsp.surfaces[s].plane as usize == plane_index {
+                continue;
+            }
+            let (min, max) = self.get_surface_distance(s, &plane);
+            if max > PLANE_EPSILON {
+                front.push(s);
+            }
+            if min < -PLANE_EPSILON || (max <= PLANE_EPSILON && min >= -PLANE_EPSILON) {
+                back.push(s);
+            }
+        }
+        (front, back)
+    }
+
+    /// Build the 3D BSP of the surfaces, returning the child index of the root node.
+    ///
+    /// `front_planes` are the planes that the surfaces are in front of, along with the planes of two-sided surfaces that
+    /// they are behind. Empty space in front of a plane becomes a leaf referencing the surfaces on these planes, and
+    /// empty space behind a plane is solid unless the plane has two-sided surfaces, which can be collided with from
+    /// either side.
+    fn build_bsp3d(&mut self, surfaces: &[usize], front_planes: &mut Vec<usize>) -> ErrorMessageResult<u32> {
+        // Use the plane that splits the fewest surfaces, preferring planes with an even number of surfaces on each side.
+        let mut planes: Vec<usize> = surfaces.iter().map(|s| self.bsp.surfaces[*s].plane as usize).collect();
+        planes.sort();
+        planes.dedup();
+        let plane = planes.into_iter().min_by_key(|p| {
+            let (front, back) = self.split_surfaces(surfaces, *p);
+            let split = (front.len() + back.len()).saturating_sub(surfaces.len());
+            split * 4 + front.len().abs_diff(back.len())
+        }).unwrap();
+        let (front, back) = self.split_surfaces(surfaces, plane);
+
+        let node_index = self.bsp.bsp3d_nodes.blocks.len();
+        self.bsp.bsp3d_nodes.blocks.push(ModelCollisionGeometryBSP3DNode { plane: plane as u32, ..Default::default() });
+
+        front_planes.push(plane);
+        let front_child = if front.is_empty() { self.make_leaf(front_planes)? } else { self.build_bsp3d(&front, front_planes)? };
+        front_planes.pop();
+
+        let two_sided = surfaces.iter().any(|s| self.bsp.surfaces[*s].plane as usize == plane && self.bsp.surfaces[*s].flags.two_sided);
+        if two_sided {
+            front_planes.push(plane);
+        }
+        let back_child = match (back.is_empty(), two_sided) {
+            (true, true) => self.make_leaf(front_planes)?,
+            (true, false) => BSP_SOLID,
+            (false, _) => self.build_bsp3d(&back, front_planes)?
+        };
+        if two_sided {
+            front_planes.pop();
+        }
+
+        let node = &mut self.bsp.bsp3d_nodes.blocks[node_index];
+        node.front_child = front_child;
+        node.back_child = back_child;
+        Ok(node_index as u32)
+    }
+
+    /// Make a leaf referencing the surfaces on each plane, returning its child index.
+    fn make_leaf(&mut self, planes: &[usize]) -> ErrorMessageResult<u32> {
+        let first_reference = self.bsp.bsp2d_references.blocks.len();
+        let mut double_sided = false;
+        for &plane in planes {
+            let surfaces: Vec<usize> = (0..self.surface_vertices.len()).filter(|s| self.bsp.surfaces[*s].plane as usize == plane).collect();
+            double_sided |= surfaces.iter().any(|s| self.bsp.surfaces[*s].flags.two_sided);
+
+            let root = match self.bsp2d_roots.get(&plane) {
+                Some(&n) => n,
+                None => {
+                    let normal = self.bsp.planes[plane].plane.vector;
+                    let root = self.build_bsp2d(&surfaces, &normal)?;
+                    self.bsp2d_roots.insert(plane, root);
+                    root
+                }
+            };
+            self.bsp.bsp2d_references.blocks.push(ModelCollisionGeometryBSP2DReference { plane: plane as u32, bsp2d_node: root });
+        }
+
+        self.bsp.leaves.blocks.push(ModelCollisionGeometryBSPLeaf {
+            flags: ModelCollisionGeometryBSPLeafFlags { contains_double_sided_surfaces: double_sided },
+            bsp2d_reference_count: planes.len() as u16,
+            first_bsp2d_reference: first_reference as u32
+        });
+        Ok((self.bsp.leaves.blocks.len() - 1) as u32 | BSP_LEAF_FLAG)
+    }
+
+    /// Build the 2D BSP of surfaces on a plane with the given normal, returning the child index of the root node.
+    ///
+    /// Surfaces in front of a node's plane are on the right, and surfaces behind it are on the left. Overlapping
+    /// surfaces can't be split, so they are an error.
+    fn build_bsp2d(&mut self, surfaces: &[usize], normal: &Vector3D) -> ErrorMessageResult<u32> {
+        let projected: Vec<[Point2D; 3]> = surfaces.iter().map(|s| self.surface_vertices[*s].map(|v| project_point(normal, &self.bsp.vertices[v].point))).collect();
+        let split = |plane: &Plane2D| {
+            let mut left = Vec::new();
+            let mut right = Vec::new();
+            for (s, points) in surfaces.iter().zip(projected.iter()) {
+                let distances = points.map(|p| p.distance_from_plane(plane));
+                if distances.iter().any(|d| *d > PLANE_EPSILON) {
+                    right.push(*s);
+                }
+                if distances.iter().any(|d| *d < -PLANE_EPSILON) {
+                    left.push(*s);
+                }
+            }
+            (left, right)
+        };
+
+        // Split along the edge that splits the fewest surfaces. There has to be at least one surface entirely on each
+        // side, or else the surfaces can't be split any further.
+        let mut best: Option<(usize, Plane2D, Vec<usize>, Vec<usize>)> = None;
+        if surfaces.len() > 1 {
+            for points in &projected {
+                for i in 0..3 {
+                    let (a, b) = (points[i], points[(i + 1) % 3]);
+                    let vector = Vector2D { x: b.y - a.y, y: a.x - b.x }.normalize();
+                    let plane = Plane2D { vector, d: vector.x * a.x + vector.y * a.y };
+                    let (left, right) = split(&plane);
+                    if left.len() == surfaces.len() || right.len() == surfaces.len() {
+                        continue;
+                    }
+
+                    let score = (left.len() + right.len() - surfaces.len()) * 4 + left.len().abs_diff(right.len());
+                    if best.as_ref().map(|b| score < b.0).unwrap_or(true) {
+                        best = Some((score, plane, left, right));
+                    }
+                }
+            }
+        }
+
+        let (plane, left, right) = match best {
+            Some((_, plane, left, right)) => (plane, left, right),
+            None if surfaces.len() == 1 => return Ok(surfaces[0] as u32 | BSP_LEAF_FLAG),
+            None => {
+                let (triangle_a, position) = self.surface_triangles[surfaces[0]];
+                let (triangle_b, _) = self.surface_triangles[surfaces[1]];
+                return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_overlapping_triangles"), triangle_a=triangle_a, triangle_b=triangle_b, position=format_position(&position))))
+            }
+        };
+
+        let node_index = self.bsp.bsp2d_nodes.blocks.len();
+        self.bsp.bsp2d_nodes.blocks.push(ModelCollisionGeometryBSP2DNode { plane, ..Default::default() });
+        let left_child = self.build_bsp2d(&left, normal)?;
+        let right_child = self.build_bsp2d(&right, normal)?;
+
+        let node = &mut self.bsp.bsp2d_nodes.blocks[node_index];
+        node.left_child = left_child;
+        node.right_child = right_child;
+        Ok(node_index as u32)
+    }
+}
+
+/// Build a collision BSP from JMS triangles, which are transformed into the node's space.
+pub(super) fn build_bsp(jms: &JMS, triangles: &[CollisionTriangle], node_transform: &(Matrix, Vector3D)) -> ErrorMessageResult<ModelCollisionGeometryBSP> {
+    let mut builder = BSPBuilder::default();
+    builder.add_triangles(jms, triangles, node_transform)?;
+    if !builder.surface_vertices.is_empty() {
+        let surfaces: Vec<usize> = (0..builder.surface_vertices.len()).collect();
+        builder.build_bsp3d(&surfaces, &mut Vec::new())?;
+    }
+    Ok(builder.bsp)
+}
+
+/// Build a model_collision_geometry tag from JMS files.
+///
+/// Each node gets a BSP for each permutation of its region, made from the triangles of the node in that permutation.
+/// Triangles must be closed, not degenerate, and not overlapping. Materials are named after the JMS materials, and
+/// symbols at the end of their names set surface flags (`%` is two-sided, `@` is invisible, `^` is climbable, `-` is
+/// breakable, and `*` is render-only and ignored). Markers starting with "pathfinder" become pathfinding spheres.
+///
+/// If `existing` is set, its settings are kept, including the settings of any materials and regions with the same
+/// names, and only its geometry, materials, nodes, pathfinding spheres, and regions are replaced.
+pub fn build_model_collision_geometry(jms_files: &[CollisionPermutationJMS], existing: Option<&ModelCollisionGeometry>) -> ErrorMessageResult<ModelCollisionGeometry> {
+    let first = match jms_files.first() {
+        Some(n) => &n.jms,
+        None => return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.jms.error_no_jms_files")))
+    };
+
+    for j in jms_files {
+        j.jms.validate()?;
+        if j.jms.nodes.len() != first.nodes.len() || j.jms.nodes.iter().zip(first.nodes.iter()).any(|(a, b)| a.name != b.name) {
+            return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_collision_node_mismatch"), permutation=j.permutation)))
+        }
+    }
+
+    let mut collision = match existing {
+        Some(n) => n.clone(),
+        None => ModelCollisionGeometry::new_with_defaults()
+    };
+
+    // Nodes
+    let parents = first.get_parent_nodes()?;
+    let node_transforms = get_node_transforms(first, &parents)?;
+    collision.nodes = Reflexive::default();
+    for (n, parent) in first.nodes.iter().zip(parents) {
+        collision.nodes.blocks.push(ModelCollisionGeometryNode {
+            name: String32::from_str(&n.name)?,
+            parent_node: parent,
+            next_sibling_node: n.sibling_node,
+            first_child_node: n.first_child,
+            ..Default::default()
+        });
+    }
+
+    // Materials
+    collision.materials = Reflexive::default();
+    let mut materials: Vec<Vec<Option<CollisionMaterial>>> = Vec::with_capacity(jms_files.len());
+    for j in jms_files {
+        let mut jms_materials = Vec::with_capacity(j.jms.materials.len());
+        for m in &j.jms.materials {
+            let (name, flags) = parse_material_name(&m.name);
+            let flags = match flags {
+                Some(n) => n,
+                None => {
+                    jms_materials.push(None);
+                    continue
+                }
+            };
+            let name = String32::from_str(name)?;
+            let material = match collision.materials.blocks.iter().position(|m| m.name == name) {
+                Some(n) => n,
+                None => {
+                    let material = existing.and_then(|e| e.materials.blocks.iter().find(|m| m.name == name)).cloned();
+                    collision.materials.blocks.push(material.unwrap_or(ModelCollisionGeometryMaterial { name, ..ModelCollisionGeometryMaterial::new_with_defaults() }));
+                    collision.materials.blocks.len() - 1
+                }
+            };
+            jms_materials.push(Some(CollisionMaterial { material: material as u16, flags }));
+        }
+        materials.push(jms_materials);
+    }
+
+    // Find the triangles of each node and the region of each node.
+    let mut node_regions: Vec<Option<&str>> = vec![None; first.nodes.len()];
+    let mut node_triangles: HashMap<(usize, usize), Vec<CollisionTriangle>> = HashMap::new();
+    for (file_index, j) in jms_files.iter().enumerate() {
+        let jms = &j.jms;
+        let in_permutation = |error: ErrorMessage| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_collision_permutation"), permutation=j.permutation, error=error));
+
+        for (index, t) in jms.triangles.iter().enumerate() {
+            let (region, material) = match (t.region, t.shader.and_then(|s| materials[file_index][s as usize])) {
+                (Some(r), Some(m)) => (jms.regions[r as usize].name.as_str(), m),
+                _ => continue
+            };
+
+            let vertices = [t.vertices.0, t.vertices.1, t.vertices.2];
+            let node = jms.vertices[vertices[0] as usize].node0.unwrap_or(0) as usize;
+            if vertices.iter().any(|v| jms.vertices[*v as usize].node0.unwrap_or(0) as usize != node) {
+                return Err(in_permutation(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_collision_multiple_nodes"), triangle=index))))
+            }
+
+            match node_regions[node] {
+                Some(r) if r != region => return Err(in_permutation(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_collision_multiple_regions"), node=first.nodes[node].name, region_a=r, region_b=region)))),
+                _ => node_regions[node] = Some(region)
+            }
+
+            node_triangles.entry((file_index, node)).or_default().push(CollisionTriangle { index, vertices, material });
+        }
+    }
+
+    // Regions are sorted by name, as are their permutations.
+    let mut region_names: Vec<&str> = node_regions.iter().filter_map(|r| *r).collect();
+    region_names.sort();
+    region_names.dedup();
+
+    collision.regions = Reflexive::default();
+    let mut region_permutations: Vec<Vec<usize>> = Vec::with_capacity(region_names.len());
+    for region_name in &region_names {
+        let name = String32::from_str(region_name)?;
+        let mut region = existing.and_then(|e| e.regions.blocks.iter().find(|r| r.name == name)).cloned().unwrap_or_default();
+        region.name = name;
+        region.permutations = Reflexive::default();
+
+        let mut permutations: Vec<usize> = (0..jms_files.len())
+            .filter(|f| node_triangles.keys().any(|(file_index, node)| file_index == f && node_regions[*node] == Some(region_name)))
+            .collect();
+        permutations.sort_by_key(|f| &jms_files[*f].permutation);
+        for f in &permutations {
+            region.permutations.blocks.push(ModelCollisionGeometryPermutation { name: String32::from_str(&jms_files[*f].permutation)? });
+        }
+
+        collision.regions.blocks.push(region);
+        region_permutations.push(permutations);
+    }
+
+    // Build a BSP for each permutation of the node's region.
+    for node in 0..first.nodes.len() {
+        let region_index = match node_regions[node] {
+            Some(r) => region_names.iter().position(|n| *n == r).unwrap(),
+            None => continue
+        };
+        collision.nodes.blocks[node].region = Some(region_index as u16);
+
+        for &f in &region_permutations[region_index] {
+            let triangles = node_triangles.get(&(f, node)).map(|t| t.as_slice()).unwrap_or_default();
+            let j = &jms_files[f];
+            let bsp = build_bsp(&j.jms, triangles, &node_transforms[node])
+                .map_err(|error| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_collision_permutation"), permutation=j.permutation, error=error)))?;
+            collision.nodes.blocks[node].bsps.blocks.push(bsp);
+        }
+    }
+
+    // Pathfinding spheres. Permutations often have the same markers, so spheres on the same node and position are only
+    // added once.
+    collision.pathfinding_spheres = Reflexive::default();
+    let mut spheres_added = HashSet::new();
+    for m in jms_files.iter().flat_map(|j| j.jms.markers.iter()).filter(|m| m.name.starts_with("pathfinder")) {
+        if !spheres_added.insert((m.node, position_key(&m.position))) {
+            continue;
+        }
+        collision.pathfinding_spheres.blocks.push(ModelCollisionGeometrySphere {
+            node: m.node,
+            center: m.position,
+            radius: m.radius
+        });
+    }
+
+    Ok(collision)
+}
@@ -0,0 +1,823 @@
+use crate::types::*;
+use crate::error::*;
+
+use serde_json::{json, Value};
+
+use ringhopper_proc::*;
+
+use super::{JMS, Marker, Material, Node, SCALE_FACTOR, Vertex};
+use super::import::{ImportedJMS, JMSImporter, UNNAMED, default_node, flat_normal};
+
+/// Magic number of binary glTF files ("glTF")
+const GLB_MAGIC: u32 = 0x46546C67;
+
+/// Version of binary glTF files that are written
+const GLB_VERSION: u32 = 2;
+
+/// Chunk type of the JSON chunk ("JSON")
+const GLB_CHUNK_JSON: u32 = 0x4E4F534A;
+
+/// Chunk type of the binary chunk ("BIN\0")
+const GLB_CHUNK_BIN: u32 = 0x004E4942;
+
+const COMPONENT_TYPE_UNSIGNED_SHORT: u32 = 5123;
+const COMPONENT_TYPE_UNSIGNED_INT: u32 = 5125;
+const COMPONENT_TYPE_FLOAT: u32 = 5126;
+
+const TARGET_ARRAY_BUFFER: u32 = 34962;
+const TARGET_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// Prefix of glTF node names that are markers.
+pub const GLTF_MARKER_PREFIX: char = '#';
+
+/// Convert a JMS position to a glTF position.
+///
+/// JMS is Z-up in JMS units, while glTF is Y-up.
+pub(super) fn position_to_gltf(position: &Point3D) -> [f32; 3] {
+    let p = position.scale(SCALE_FACTOR);
+    [p.x, p.z, -p.y]
+}
+
+/// Convert a JMS normal to a glTF normal.
+pub(super) fn normal_to_gltf(normal: &Vector3D) -> [f32; 3] {
+    [normal.x, normal.z, -normal.y]
+}
+
+/// Convert a JMS rotation to a glTF rotation.
+///
+/// JMS rotations are inverted, so the conjugate is taken before converting the axes.
+pub(super) fn rotation_to_gltf(rotation: &Quaternion) -> [f32; 4] {
+    [-rotation.x, -rotation.z, rotation.y, rotation.w]
+}
+
+/// Convert a glTF position to a JMS position.
+pub(super) fn position_from_gltf(position: [f32; 3]) -> Point3D {
+    Point3D { x: position[0], y: -position[2], z: position[1] }.scale(1.0 / SCALE_FACTOR)
+}
+
+/// Convert a glTF normal to a JMS normal.
+pub(super) fn normal_from_gltf(normal: [f32; 3]) -> Vector3D {
+    Vector3D { x: normal[0], y: -normal[2], z: normal[1] }
+}
+
+/// Convert a glTF rotation to a JMS rotation.
+fn rotation_from_gltf(rotation: [f32; 4]) -> Quaternion {
+    Quaternion { x: -rotation[0], y: rotation[2], z: -rotation[1], w: rotation[3] }
+}
+
+/// Column-major 4x4 affine transformation matrix, as used by glTF.
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct Matrix4([f32; 16]);
+
+impl Matrix4 {
+    const IDENTITY: Matrix4 = Matrix4([1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+
+    fn get(&self, row: usize, column: usize) -> f32 {
+        self.0[column * 4 + row]
+    }
+
+    fn from_translation_rotation_scale(translation: [f32; 3], rotation: [f32; 4], scale: [f32; 3]) -> Matrix4 {
+        let [x, y, z, w] = rotation;
+        let length = (x * x + y * y + z * z + w * w).sqrt();
+        let (x, y, z, w) = if length > 0.0 { (x / length, y / length, z / length, w / length) } else { (0.0, 0.0, 0.0, 1.0) };
+        let rotation = [
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w)],
+            [2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w)],
+            [2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y)]
+        ];
+
+        let mut matrix = Matrix4::IDENTITY;
+        for (row, values) in rotation.iter().enumerate() {
+            for (column, value) in values.iter().enumerate() {
+                matrix.0[column * 4 + row] = value * scale[column];
+            }
+        }
+        matrix.0[12..15].copy_from_slice(&translation);
+        matrix
+    }
+
+    fn multiply(&self, other: &Matrix4) -> Matrix4 {
+        let mut matrix = [0.0; 16];
+        for column in 0..4 {
+            for row in 0..4 {
+                matrix[column * 4 + row] = (0..4).map(|i| self.get(row, i) * other.get(i, column)).sum();
+            }
+        }
+        Matrix4(matrix)
+    }
+
+    fn transform_point(&self, point: [f32; 3]) -> [f32; 3] {
+        [0, 1, 2].map(|row| self.get(row, 0) * point[0] + self.get(row, 1) * point[1] + self.get(row, 2) * point[2] + self.get(row, 3))
+    }
+
+    fn determinant(&self) -> f32 {
+        let m = |row, column| self.get(row, column);
+        m(0, 0) * (m(1, 1) * m(2, 2) - m(1, 2) * m(2, 1))
+            - m(0, 1) * (m(1, 0) * m(2, 2) - m(1, 2) * m(2, 0))
+            + m(0, 2) * (m(1, 0) * m(2, 1) - m(1, 1) * m(2, 0))
+    }
+
+    /// Get the cofactor matrix of the rotation and scale, which is the inverse transpose scaled by the determinant.
+    fn cofactors(&self) -> [[f32; 3]; 3] {
+        let m = |row: usize, column: usize| self.get(row % 3, column % 3);
+        [0, 1, 2].map(|row| [0, 1, 2].map(|column| {
+            m(row + 1, column + 1) * m(row + 2, column + 2) - m(row + 1, column + 2) * m(row + 2, column + 1)
+        }))
+    }
+
+    /// Transform a normal, which is not normalized afterwards.
+    fn transform_normal(&self, normal: [f32; 3]) -> [f32; 3] {
+        let cofactors = self.cofactors();
+        let sign = self.determinant().signum();
+        [0, 1, 2].map(|row| sign * (cofactors[row][0] * normal[0] + cofactors[row][1] * normal[1] + cofactors[row][2] * normal[2]))
+    }
+
+    fn inverse(&self) -> Matrix4 {
+        let cofactors = self.cofactors();
+        let determinant = self.determinant();
+        let mut matrix = Matrix4::IDENTITY;
+        for (column, values) in cofactors.iter().enumerate() {
+            for (row, value) in values.iter().enumerate() {
+                matrix.0[column * 4 + row] = value / determinant;
+            }
+        }
+        let translation = matrix.transform_point(self.translation());
+        matrix.0[12..15].copy_from_slice(&translation.map(|t| -t));
+        matrix
+    }
+
+    fn translation(&self) -> [f32; 3] {
+        [self.get(0, 3), self.get(1, 3), self.get(2, 3)]
+    }
+
+    /// Get the rotation, ignoring any scale.
+    fn rotation(&self) -> [f32; 4] {
+        let scale = [0, 1, 2].map(|column| (0..3).map(|row| self.get(row, column).powi(2)).sum::<f32>().sqrt());
+        let m = |row: usize, column: usize| if scale[column] > 0.0 { self.get(row, column) / scale[column] } else { 0.0 };
+
+        let trace = m(0, 0) + m(1, 1) + m(2, 2);
+        let (x, y, z, w) = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            ((m(2, 1) - m(1, 2)) / s, (m(0, 2) - m(2, 0)) / s, (m(1, 0) - m(0, 1)) / s, s / 4.0)
+        }
+        else if m(0, 0) > m(1, 1) && m(0, 0) > m(2, 2) {
+            let s = (1.0 + m(0, 0) - m(1, 1) - m(2, 2)).sqrt() * 2.0;
+            (s / 4.0, (m(0, 1) + m(1, 0)) / s, (m(0, 2) + m(2, 0)) / s, (m(2, 1) - m(1, 2)) / s)
+        }
+        else if m(1, 1) > m(2, 2) {
+            let s = (1.0 + m(1, 1) - m(0, 0) - m(2, 2)).sqrt() * 2.0;
+            ((m(0, 1) + m(1, 0)) / s, s / 4.0, (m(1, 2) + m(2, 1)) / s, (m(0, 2) - m(2, 0)) / s)
+        }
+        else {
+            let s = (1.0 + m(2, 2) - m(0, 0) - m(1, 1)).sqrt() * 2.0;
+            ((m(0, 2) + m(2, 0)) / s, (m(1, 2) + m(2, 1)) / s, s / 4.0, (m(1, 0) - m(0, 1)) / s)
+        };
+        [x, y, z, w]
+    }
+}
+
+/// Triangle indices of a region, grouped by material.
+type RegionTriangles = (Option<u16>, Vec<(Option<u16>, Vec<u32>)>);
+
+/// Binary buffer of a glTF file, along with its buffer views and accessors.
+#[derive(Default)]
+struct GLTFBuffer {
+    data: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>
+}
+
+impl GLTFBuffer {
+    /// Add an accessor with its own buffer view, returning its index.
+    fn add_accessor(&mut self, bytes: &[u8], component_type: u32, accessor_type: &str, count: usize, target: Option<u32>) -> usize {
+        // Buffer views are aligned to 4 bytes so every component type is aligned.
+        self.data.resize(self.data.len().next_multiple_of(4), 0);
+
+        let mut buffer_view = json!({ "buffer": 0, "byteOffset": self.data.len(), "byteLength": bytes.len() });
+        if let Some(target) = target {
+            buffer_view["target"] = json!(target);
+        }
+        self.data.extend_from_slice(bytes);
+        self.buffer_views.push(buffer_view);
+
+        self.accessors.push(json!({ "bufferView": self.buffer_views.len() - 1, "componentType": component_type, "count": count, "type": accessor_type }));
+        self.accessors.len() - 1
+    }
+
+    fn add_floats(&mut self, values: &[f32], accessor_type: &str, components: usize, target: Option<u32>) -> usize {
+        let bytes: Vec<u8> = values.iter().flat_map(|f| f.to_le_bytes()).collect();
+        self.add_accessor(&bytes, COMPONENT_TYPE_FLOAT, accessor_type, values.len() / components, target)
+    }
+}
+
+impl JMS {
+    /// Generate a binary glTF 2.0 (GLB) file.
+    ///
+    /// Nodes become the joints of a skin, and markers become empty nodes prefixed with `#` (see
+    /// [`GLTF_MARKER_PREFIX`]) that are parented to their node. Each region becomes a skinned mesh with a primitive for
+    /// each material used, and materials are named after the JMS materials. Positions are in JMS units, converted to
+    /// glTF's Y-up coordinate system.
+    ///
+    /// Returns an [`Err`] if the JMS is invalid.
+    pub fn into_gltf(&self) -> ErrorMessageResult<Vec<u8>> {
+        self.validate()?;
+
+        let mut buffer = GLTFBuffer::default();
+        let mut nodes: Vec<Value> = Vec::new();
+        let mut scene_nodes: Vec<usize> = Vec::new();
+
+        // Nodes are the first glTF nodes, so the index of each joint is the same as the JMS node.
+        let node_count = self.nodes.len();
+        let parents = self.get_parent_nodes()?;
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); node_count];
+        for (n, parent) in parents.iter().enumerate() {
+            match parent {
+                Some(p) => children[*p as usize].push(n),
+                None => scene_nodes.push(n)
+            }
+        }
+
+        let local_transforms: Vec<Matrix4> = self.nodes.iter().map(|n| Matrix4::from_translation_rotation_scale(position_to_gltf(&n.position), rotation_to_gltf(&n.rotation), [1.0; 3])).collect();
+        let mut world_transforms = vec![Matrix4::IDENTITY; node_count];
+        let mut queue: Vec<(usize, Matrix4)> = scene_nodes.iter().map(|n| (*n, Matrix4::IDENTITY)).collect();
+        while let Some((n, parent)) = queue.pop() {
+            world_transforms[n] = parent.multiply(&local_transforms[n]);
+            queue.extend(children[n].iter().map(|c| (*c, world_transforms[n])));
+        }
+
+        for node in &self.nodes {
+            nodes.push(json!({
+                "name": node.name,
+                "translation": position_to_gltf(&node.position),
+                "rotation": rotation_to_gltf(&node.rotation)
+            }));
+        }
+
+        // Markers are placed under their node.
+        for marker in &self.markers {
+            let index = nodes.len();
+            nodes.push(json!({
+                "name": format!("{GLTF_MARKER_PREFIX}{}", marker.name),
+                "translation": position_to_gltf(&marker.position),
+                "rotation": rotation_to_gltf(&marker.rotation),
+                "extras": {
+                    "radius": marker.radius,
+                    "region": marker.region.map(|r| self.regions[r as usize].name.clone())
+                }
+            }));
+            match marker.node {
+                Some(n) => children[n as usize].push(index),
+                None => scene_nodes.push(index)
+            }
+        }
+
+        for (n, c) in children.iter().enumerate().filter(|(_, c)| !c.is_empty()) {
+            nodes[n]["children"] = json!(c);
+        }
+
+        let mut skins = Vec::new();
+        if node_count > 0 {
+            let matrices: Vec<f32> = world_transforms.iter().flat_map(|t| t.inverse().0).collect();
+            let inverse_bind_matrices = buffer.add_floats(&matrices, "MAT4", 16, None);
+            skins.push(json!({ "joints": (0..node_count).collect::<Vec<usize>>(), "inverseBindMatrices": inverse_bind_matrices }));
+        }
+
+        let materials: Vec<Value> = self.materials.iter().map(|m| json!({ "name": m.name, "extras": { "tif_path": m.tif_path } })).collect();
+
+        // Group the triangles by region and then by material, keeping the order they first appear in.
+        let mut meshes_triangles: Vec<RegionTriangles> = Vec::new();
+        for t in &self.triangles {
+            let mesh = match meshes_triangles.iter().position(|m| m.0 == t.region) {
+                Some(n) => &mut meshes_triangles[n].1,
+                None => {
+                    meshes_triangles.push((t.region, Vec::new()));
+                    &mut meshes_triangles.last_mut().unwrap().1
+                }
+            };
+            let primitive = match mesh.iter().position(|p| p.0 == t.shader) {
+                Some(n) => &mut mesh[n].1,
+                None => {
+                    mesh.push((t.shader, Vec::new()));
+                    &mut mesh.last_mut().unwrap().1
+                }
+            };
+            primitive.extend_from_slice(&[t.vertices.0, t.vertices.1, t.vertices.2]);
+        }
+
+        let mut meshes = Vec::new();
+        if !meshes_triangles.is_empty() {
+            // All meshes share the same vertices.
+            let positions: Vec<[f32; 3]> = self.vertices.iter().map(|v| position_to_gltf(&v.position)).collect();
+            let position_accessor = buffer.add_floats(&positions.concat(), "VEC3", 3, Some(TARGET_ARRAY_BUFFER));
+            let min = [0, 1, 2].map(|i| positions.iter().map(|p| p[i]).fold(f32::INFINITY, f32::min));
+            let max = [0, 1, 2].map(|i| positions.iter().map(|p| p[i]).fold(f32::NEG_INFINITY, f32::max));
+            buffer.accessors[position_accessor]["min"] = json!(min);
+            buffer.accessors[position_accessor]["max"] = json!(max);
+
+            let normals: Vec<f32> = self.vertices.iter().flat_map(|v| normal_to_gltf(&v.normal)).collect();
+            let normal_accessor = buffer.add_floats(&normals, "VEC3", 3, Some(TARGET_ARRAY_BUFFER));
+
+            let texture_coordinates: Vec<f32> = self.vertices.iter().flat_map(|v| [v.texture_coordinates.x, v.texture_coordinates.y]).collect();
+            let texture_coordinates_accessor = buffer.add_floats(&texture_coordinates, "VEC2", 2, Some(TARGET_ARRAY_BUFFER));
+
+            let mut attributes = json!({ "POSITION": position_accessor, "NORMAL": normal_accessor, "TEXCOORD_0": texture_coordinates_accessor });
+            if node_count > 0 {
+                // Vertices without a node are weighted to the first node.
+                let mut joints: Vec<u8> = Vec::with_capacity(self.vertices.len() * 8);
+                let mut weights: Vec<f32> = Vec::with_capacity(self.vertices.len() * 4);
+                for v in &self.vertices {
+                    let (node1, node1_weight) = match (v.node0, v.node1) {
+                        (Some(_), Some(n)) => (n, v.node1_weight),
+                        _ => (0, 0.0)
+                    };
+                    for j in [v.node0.unwrap_or(0), node1, 0, 0] {
+                        joints.extend_from_slice(&j.to_le_bytes());
+                    }
+                    weights.extend_from_slice(&[1.0 - node1_weight, node1_weight, 0.0, 0.0]);
+                }
+                attributes["JOINTS_0"] = json!(buffer.add_accessor(&joints, COMPONENT_TYPE_UNSIGNED_SHORT, "VEC4", self.vertices.len(), Some(TARGET_ARRAY_BUFFER)));
+                attributes["WEIGHTS_0"] = json!(buffer.add_floats(&weights, "VEC4", 4, Some(TARGET_ARRAY_BUFFER)));
+            }
+
+            for (region, mesh_primitives) in &meshes_triangles {
+                let mut primitives = Vec::with_capacity(mesh_primitives.len());
+                for (shader, indices) in mesh_primitives {
+                    let bytes: Vec<u8> = indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+                    let mut primitive = json!({
+                        "attributes": attributes,
+                        "indices": buffer.add_accessor(&bytes, COMPONENT_TYPE_UNSIGNED_INT, "SCALAR", indices.len(), Some(TARGET_ELEMENT_ARRAY_BUFFER))
+                    });
+                    if let Some(s) = shader {
+                        primitive["material"] = json!(s);
+                    }
+                    primitives.push(primitive);
+                }
+
+                let name = match region {
+                    Some(r) => self.regions[*r as usize].name.as_str(),
+                    None => UNNAMED
+                };
+                let mut node = json!({ "name": name, "mesh": meshes.len() });
+                if node_count > 0 {
+                    node["skin"] = json!(0);
+                }
+                scene_nodes.push(nodes.len());
+                nodes.push(node);
+                meshes.push(json!({ "name": name, "primitives": primitives }));
+            }
+        }
+
+        let mut gltf = json!({
+            "asset": { "version": "2.0", "extras": { "node_list_checksum": self.node_list_checksum } },
+            "scene": 0,
+            "scenes": [{ "nodes": scene_nodes }],
+            "nodes": nodes
+        });
+        for (key, value) in [("meshes", meshes), ("materials", materials), ("skins", skins), ("accessors", buffer.accessors), ("bufferViews", buffer.buffer_views)] {
+            if !value.is_empty() {
+                gltf[key] = Value::Array(value);
+            }
+        }
+        if !buffer.data.is_empty() {
+            gltf["buffers"] = json!([{ "byteLength": buffer.data.len() }]);
+        }
+
+        // Chunks are padded to 4 bytes; spaces for JSON and zeroes for binary data.
+        let mut json_chunk = gltf.to_string().into_bytes();
+        json_chunk.resize(json_chunk.len().next_multiple_of(4), b' ');
+        let mut bin_chunk = buffer.data;
+        bin_chunk.resize(bin_chunk.len().next_multiple_of(4), 0);
+
+        let mut chunks = vec![(GLB_CHUNK_JSON, json_chunk)];
+        if !bin_chunk.is_empty() {
+            chunks.push((GLB_CHUNK_BIN, bin_chunk));
+        }
+
+        let length = 12 + chunks.iter().map(|c| 8 + c.1.len()).sum::<usize>();
+        let mut glb = Vec::with_capacity(length);
+        glb.extend_from_slice(&GLB_MAGIC.to_le_bytes());
+        glb.extend_from_slice(&GLB_VERSION.to_le_bytes());
+        glb.extend_from_slice(&(length as u32).to_le_bytes());
+        for (chunk_type, data) in chunks {
+            glb.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            glb.extend_from_slice(&chunk_type.to_le_bytes());
+            glb.extend_from_slice(&data);
+        }
+
+        Ok(glb)
+    }
+}
+
+/// Decode base64 data, as used by data URIs.
+fn decode_base64(data: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(data.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for c in data.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ => return None
+        };
+        bits = (bits << 6) | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
+            bits &= (1 << bit_count) - 1;
+        }
+    }
+    Some(bytes)
+}
+
+/// Read an array of floats from a glTF property, using the default if it is not present.
+fn read_float_array<const N: usize>(value: &Value, default: [f32; N]) -> [f32; N] {
+    match value.as_array() {
+        Some(a) if a.len() == N => std::array::from_fn(|i| a[i].as_f64().unwrap_or(default[i] as f64) as f32),
+        _ => default
+    }
+}
+
+/// Get the local transform of a glTF node.
+fn node_transform(node: &Value) -> Matrix4 {
+    if node["matrix"].is_array() {
+        Matrix4(read_float_array(&node["matrix"], Matrix4::IDENTITY.0))
+    }
+    else {
+        Matrix4::from_translation_rotation_scale(read_float_array(&node["translation"], [0.0; 3]),
+                                                 read_float_array(&node["rotation"], [0.0, 0.0, 0.0, 1.0]),
+                                                 read_float_array(&node["scale"], [1.0; 3]))
+    }
+}
+
+/// glTF file that is being read, with its buffers loaded.
+struct GLTFReader {
+    json: Value,
+    buffers: Vec<Vec<u8>>
+}
+
+impl GLTFReader {
+    fn parse(bytes: &[u8]) -> ErrorMessageResult<GLTFReader> {
+        let corrupt = || ErrorMessage::StaticString(get_compiled_string!("engine.h1.jms.error_gltf_corrupt"));
+        let read_u32 = |at: usize| bytes.get(at..at + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+
+        // Binary glTF files have a JSON chunk, optionally followed by a binary chunk. Anything else is only JSON.
+        let (json, bin) = if read_u32(0) == Some(GLB_MAGIC) {
+            let mut chunks = Vec::new();
+            let mut offset = 12;
+            while offset < bytes.len() {
+                let length = read_u32(offset).ok_or_else(corrupt)? as usize;
+                let chunk_type = read_u32(offset + 4).ok_or_else(corrupt)?;
+                chunks.push((chunk_type, bytes.get(offset + 8..offset + 8 + length).ok_or_else(corrupt)?));
+                offset += 8 + length;
+            }
+            match chunks.as_slice() {
+                [(GLB_CHUNK_JSON, json), rest @ ..] => (*json, rest.iter().find(|c| c.0 == GLB_CHUNK_BIN).map(|c| c.1)),
+                _ => return Err(corrupt())
+            }
+        }
+        else {
+            (bytes, None)
+        };
+
+        let json: Value = serde_json::from_slice(json).map_err(|e| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_could_not_parse_gltf"), error=e)))?;
+
+        // Only the binary chunk and data URIs can be read, since there is no path to find other files from.
+        let mut buffers = Vec::new();
+        for (i, buffer) in json["buffers"].as_array().map(Vec::as_slice).unwrap_or_default().iter().enumerate() {
+            let data = match buffer["uri"].as_str() {
+                Some(uri) => uri.strip_prefix("data:").and_then(|d| d.split_once(";base64,")).and_then(|(_, d)| decode_base64(d)),
+                None => bin.map(|b| b.to_vec())
+            };
+            buffers.push(data.ok_or_else(|| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_gltf_unreadable_buffer"), buffer=i)))?);
+        }
+
+        Ok(GLTFReader { json, buffers })
+    }
+
+    /// Get an element of a top-level array, such as a mesh or a skin.
+    fn get(&self, property: &str, index: &Value) -> ErrorMessageResult<&Value> {
+        index.as_u64()
+             .and_then(|i| self.json[property].get(i as usize))
+             .ok_or_else(|| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_gltf_invalid_reference"), property=property, index=index)))
+    }
+
+    /// Read the components of each element of an accessor, which must be of the given type.
+    fn read_accessor(&self, index: &Value, accessor_type: &str) -> ErrorMessageResult<Vec<f64>> {
+        let accessor = self.get("accessors", index)?;
+        let invalid = || ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_gltf_invalid_accessor"), accessor=index));
+
+        let components = match accessor["type"].as_str() {
+            Some(t) if t == accessor_type => match t {
+                "SCALAR" => 1,
+                "VEC2" => 2,
+                "VEC3" => 3,
+                "VEC4" => 4,
+                "MAT4" => 16,
+                _ => return Err(invalid())
+            },
+            _ => return Err(invalid())
+        };
+        if !accessor["sparse"].is_null() {
+            return Err(invalid());
+        }
+
+        let count = accessor["count"].as_u64().ok_or_else(invalid)? as usize;
+        let component_type = accessor["componentType"].as_u64().unwrap_or(0) as u32;
+        let component_size = match component_type {
+            5120 | 5121 => 1,
+            5122 | COMPONENT_TYPE_UNSIGNED_SHORT => 2,
+            COMPONENT_TYPE_UNSIGNED_INT | COMPONENT_TYPE_FLOAT => 4,
+            _ => return Err(invalid())
+        };
+        let normalized = accessor["normalized"].as_bool().unwrap_or(false);
+
+        // Accessors without a buffer view are all zeroes.
+        if accessor["bufferView"].is_null() {
+            return Ok(vec![0.0; count * components]);
+        }
+
+        let view = self.get("bufferViews", &accessor["bufferView"])?;
+        let buffer = view["buffer"].as_u64().and_then(|b| self.buffers.get(b as usize)).ok_or_else(invalid)?;
+        let view_offset = view["byteOffset"].as_u64().unwrap_or(0) as usize;
+        let view_length = view["byteLength"].as_u64().ok_or_else(invalid)? as usize;
+        let data = buffer.get(view_offset..view_offset + view_length).ok_or_else(invalid)?;
+        let stride = view["byteStride"].as_u64().map(|s| s as usize).unwrap_or(components * component_size);
+        let offset = accessor["byteOffset"].as_u64().unwrap_or(0) as usize;
+
+        let mut values = Vec::with_capacity(count * components);
+        for i in 0..count {
+            for c in 0..components {
+                let at = offset + i * stride + c * component_size;
+                let bytes = data.get(at..at + component_size).ok_or_else(invalid)?;
+                values.push(match (component_type, normalized) {
+                    (5120, false) => bytes[0] as i8 as f64,
+                    (5120, true) => (bytes[0] as i8 as f64 / 127.0).max(-1.0),
+                    (5121, false) => bytes[0] as f64,
+                    (5121, true) => bytes[0] as f64 / 255.0,
+                    (5122, false) => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    (5122, true) => (i16::from_le_bytes([bytes[0], bytes[1]]) as f64 / 32767.0).max(-1.0),
+                    (COMPONENT_TYPE_UNSIGNED_SHORT, false) => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    (COMPONENT_TYPE_UNSIGNED_SHORT, true) => u16::from_le_bytes([bytes[0], bytes[1]]) as f64 / 65535.0,
+                    (COMPONENT_TYPE_UNSIGNED_INT, _) => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+                    _ => f32::from_le_bytes(bytes.try_into().unwrap()) as f64
+                });
+            }
+        }
+
+        Ok(values)
+    }
+
+    /// Read an attribute of a primitive if it is present, checking it has a value for each vertex.
+    fn read_attribute(&self, attributes: &Value, name: &str, accessor_type: &str, components: usize, vertex_count: usize) -> ErrorMessageResult<Option<Vec<f64>>> {
+        let index = &attributes[name];
+        if index.is_null() {
+            return Ok(None);
+        }
+        let values = self.read_accessor(index, accessor_type)?;
+        if values.len() != vertex_count * components {
+            return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_gltf_invalid_accessor"), accessor=index)));
+        }
+        Ok(Some(values))
+    }
+}
+
+impl JMS {
+    /// Read a glTF 2.0 file, either binary (GLB) or JSON with its buffers embedded as data URIs.
+    ///
+    /// The joints of the skins become nodes. Meshes become regions, and their names can also set the permutation (see
+    /// [`IMPORTED_MESH_PERMUTATION_SEPARATOR`](super::IMPORTED_MESH_PERMUTATION_SEPARATOR)). Skinned vertices keep
+    /// their two heaviest joint weights, and other meshes are attached to the nearest joint above them. Empty nodes that
+    /// are attached to a joint or prefixed with `#` (see [`GLTF_MARKER_PREFIX`]) become markers. If there are no skins,
+    /// everything is attached to a single node.
+    ///
+    /// Each JMS is validated and optimized. Returns an [`Err`] if the file could not be read.
+    pub fn from_gltf(bytes: &[u8]) -> ErrorMessageResult<Vec<ImportedJMS>> {
+        let gltf = GLTFReader::parse(bytes)?;
+        let json = &gltf.json;
+        let gltf_nodes = json["nodes"].as_array().map(Vec::as_slice).unwrap_or_default();
+
+        let node_index = |index: &Value| index.as_u64()
+                                              .map(|i| i as usize)
+                                              .filter(|i| *i < gltf_nodes.len())
+                                              .ok_or_else(|| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_gltf_invalid_reference"), property="nodes", index=index)));
+        let node_indices = |indices: &Value| indices.as_array().map(Vec::as_slice).unwrap_or_default().iter().map(node_index).collect::<ErrorMessageResult<Vec<usize>>>();
+
+        // Find the parent and transform of each node in the scene, in depth-first order.
+        let mut children = Vec::with_capacity(gltf_nodes.len());
+        for node in gltf_nodes {
+            children.push(node_indices(&node["children"])?);
+        }
+        let roots = match json["scenes"].get(json["scene"].as_u64().unwrap_or(0) as usize) {
+            Some(scene) => node_indices(&scene["nodes"])?,
+            None => (0..gltf_nodes.len()).filter(|n| !children.iter().any(|c| c.contains(n))).collect()
+        };
+
+        let mut parents: Vec<Option<usize>> = vec![None; gltf_nodes.len()];
+        let mut world_transforms: Vec<Option<Matrix4>> = vec![None; gltf_nodes.len()];
+        let mut order = Vec::new();
+        let mut queue: Vec<(usize, Option<usize>, Matrix4)> = roots.iter().rev().map(|r| (*r, None, Matrix4::IDENTITY)).collect();
+        while let Some((n, parent, parent_transform)) = queue.pop() {
+            if world_transforms[n].is_some() {
+                continue;
+            }
+            let transform = parent_transform.multiply(&node_transform(&gltf_nodes[n]));
+            world_transforms[n] = Some(transform);
+            parents[n] = parent;
+            order.push(n);
+            queue.extend(children[n].iter().rev().map(|c| (*c, Some(n), transform)));
+        }
+        let world_transform = |n: usize| world_transforms[n].unwrap_or(Matrix4::IDENTITY);
+
+        // Joints of every skin in the scene become nodes.
+        let mut joints: Vec<usize> = Vec::new();
+        for skin in json["skins"].as_array().map(Vec::as_slice).unwrap_or_default() {
+            for j in node_indices(&skin["joints"])? {
+                if world_transforms[j].is_some() && !joints.contains(&j) {
+                    joints.push(j);
+                }
+            }
+        }
+        if joints.len() > u16::MAX as usize {
+            return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.jms.error_import_too_many_elements")));
+        }
+
+        // Get the node that a glTF node is attached to, which is the nearest joint at or above it.
+        let attached_node = |mut n: usize| loop {
+            if let Some(j) = joints.iter().position(|j| *j == n) {
+                return Some(j);
+            }
+            n = parents[n]?;
+        };
+        let relative_transform = |n: usize, node: usize| match joints.get(node) {
+            Some(j) => world_transform(*j).inverse().multiply(&world_transform(n)),
+            None => world_transform(n)
+        };
+
+        let joint_parents: Vec<Option<usize>> = joints.iter().map(|j| parents[*j].and_then(attached_node)).collect();
+        let joint_parents = &joint_parents;
+        let children_of = |parent: Option<usize>| (0..joints.len()).filter(move |c| joint_parents[*c] == parent);
+        let mut nodes = Vec::with_capacity(joints.len());
+        for (j, joint) in joints.iter().enumerate() {
+            let transform = match joint_parents[j] {
+                Some(p) => relative_transform(*joint, p),
+                None => world_transform(*joint)
+            };
+            nodes.push(Node {
+                name: gltf_nodes[*joint]["name"].as_str().unwrap_or(UNNAMED).to_owned(),
+                first_child: children_of(Some(j)).next().map(|c| c as u16),
+                sibling_node: children_of(joint_parents[j]).find(|s| *s > j).map(|s| s as u16),
+                rotation: rotation_from_gltf(transform.rotation()),
+                position: position_from_gltf(transform.translation())
+            });
+        }
+        if nodes.is_empty() {
+            nodes.push(default_node());
+        }
+
+        let node_list_checksum = json["asset"]["extras"]["node_list_checksum"].as_i64().unwrap_or(0) as i32;
+        let mut importer = JMSImporter::new(node_list_checksum, nodes);
+
+        let materials: Vec<Material> = json["materials"].as_array().map(Vec::as_slice).unwrap_or_default().iter().map(|m| Material {
+            name: m["name"].as_str().unwrap_or(UNNAMED).to_owned(),
+            tif_path: m["extras"]["tif_path"].as_str().unwrap_or("<none>").to_owned()
+        }).collect();
+
+        for &n in &order {
+            let node = &gltf_nodes[n];
+            if node["mesh"].is_null() {
+                continue;
+            }
+            let mesh = gltf.get("meshes", &node["mesh"])?;
+            let name = node["name"].as_str().or(mesh["name"].as_str()).unwrap_or(UNNAMED);
+            let world = world_transform(n);
+
+            // Skinned vertices are posed by the joint matrices of the skin, ignoring the transform of the node.
+            let skin = match node["skin"].is_null() {
+                true => None,
+                false => {
+                    let skin = gltf.get("skins", &node["skin"])?;
+                    let skin_joints = node_indices(&skin["joints"])?;
+                    let inverse_bind_matrices: Vec<Matrix4> = match skin["inverseBindMatrices"].is_null() {
+                        true => vec![Matrix4::IDENTITY; skin_joints.len()],
+                        false => gltf.read_accessor(&skin["inverseBindMatrices"], "MAT4")?.chunks(16).map(|m| Matrix4(std::array::from_fn(|i| m[i] as f32))).collect()
+                    };
+                    if inverse_bind_matrices.len() != skin_joints.len() {
+                        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_gltf_invalid_accessor"), accessor=skin["inverseBindMatrices"])));
+                    }
+                    Some(skin_joints.iter().zip(inverse_bind_matrices).map(|(j, m)| (joints.iter().position(|n| n == j), world_transform(*j).multiply(&m))).collect::<Vec<(Option<usize>, Matrix4)>>())
+                }
+            };
+            let rigid_node = attached_node(n).unwrap_or(0) as u16;
+            let flip_winding = skin.is_none() && world.determinant() < 0.0;
+
+            for primitive in mesh["primitives"].as_array().map(Vec::as_slice).unwrap_or_default() {
+                // Only triangles can be imported.
+                if primitive["mode"].as_u64().unwrap_or(4) != 4 {
+                    continue;
+                }
+
+                let attributes = &primitive["attributes"];
+                let positions = gltf.read_accessor(&attributes["POSITION"], "VEC3")?;
+                let vertex_count = positions.len() / 3;
+                let normals = gltf.read_attribute(attributes, "NORMAL", "VEC3", 3, vertex_count)?;
+                let texture_coordinates = gltf.read_attribute(attributes, "TEXCOORD_0", "VEC2", 2, vertex_count)?;
+                let vertex_joints = gltf.read_attribute(attributes, "JOINTS_0", "VEC4", 4, vertex_count)?;
+                let vertex_weights = gltf.read_attribute(attributes, "WEIGHTS_0", "VEC4", 4, vertex_count)?;
+
+                let mut vertices = Vec::with_capacity(vertex_count);
+                for v in 0..vertex_count {
+                    let (transform, node0, node1, node1_weight) = match &skin {
+                        Some(skin) => {
+                            let mut weights: Vec<(usize, f32)> = match (&vertex_joints, &vertex_weights) {
+                                (Some(j), Some(w)) => (0..4).map(|i| (j[v * 4 + i] as usize, w[v * 4 + i] as f32)).filter(|(j, w)| *w > 0.0 && *j < skin.len()).collect(),
+                                _ => Vec::new()
+                            };
+                            if weights.is_empty() {
+                                weights.push((0, 1.0));
+                            }
+                            weights.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+                            // Pose the vertex with every weight, but only keep the two heaviest.
+                            let total: f32 = weights.iter().map(|w| w.1).sum();
+                            let transform = Matrix4(std::array::from_fn(|i| weights.iter().map(|(j, w)| skin[*j].1.0[i] * w / total).sum()));
+                            let node0 = skin[weights[0].0].0.unwrap_or(0) as u16;
+                            match weights.get(1).and_then(|(j, w)| skin[*j].0.map(|n| (n as u16, *w))) {
+                                Some((node1, w1)) => (transform, node0, Some(node1), w1 / (weights[0].1 + w1)),
+                                None => (transform, node0, None, 0.0)
+                            }
+                        },
+                        None => (world, rigid_node, None, 0.0)
+                    };
+
+                    let position = transform.transform_point(std::array::from_fn(|i| positions[v * 3 + i] as f32));
+                    let normal = match &normals {
+                        Some(n) => normal_from_gltf(transform.transform_normal(std::array::from_fn(|i| n[v * 3 + i] as f32))).normalize(),
+                        None => Vector3D::default()
+                    };
+                    let texture_coordinates = match &texture_coordinates {
+                        Some(t) => Point3D { x: t[v * 2] as f32, y: t[v * 2 + 1] as f32, z: 0.0 },
+                        None => Point3D::default()
+                    };
+                    vertices.push(Vertex { node0: Some(node0), position: position_from_gltf(position), normal, node1, node1_weight, texture_coordinates });
+                }
+
+                let indices: Vec<usize> = match primitive["indices"].is_null() {
+                    true => (0..vertex_count).collect(),
+                    false => gltf.read_accessor(&primitive["indices"], "SCALAR")?.into_iter().map(|i| i as usize).collect()
+                };
+                if indices.iter().any(|i| *i >= vertex_count) {
+                    return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_gltf_invalid_accessor"), accessor=primitive["indices"])));
+                }
+                let material = match primitive["material"].is_null() {
+                    true => None,
+                    false => Some(primitive["material"].as_u64().and_then(|m| materials.get(m as usize)).ok_or_else(|| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_gltf_invalid_reference"), property="materials", index=primitive["material"])))?)
+                };
+
+                for t in indices.chunks_exact(3) {
+                    let mut triangle = [vertices[t[0]], vertices[t[1]], vertices[t[2]]];
+                    if flip_winding {
+                        triangle.swap(1, 2);
+                    }
+                    if normals.is_none() {
+                        let normal = flat_normal(&triangle.map(|v| v.position));
+                        triangle.iter_mut().for_each(|v| v.normal = normal);
+                    }
+                    importer.add_triangle(name, material, triangle)?;
+                }
+            }
+        }
+
+        // Markers are empty nodes.
+        for &n in &order {
+            let node = &gltf_nodes[n];
+            if !node["mesh"].is_null() || !children[n].is_empty() || joints.contains(&n) {
+                continue;
+            }
+            let attached = attached_node(n);
+            let name = node["name"].as_str().unwrap_or_default();
+            let name = match name.strip_prefix(GLTF_MARKER_PREFIX) {
+                Some(m) => m,
+                None if attached.is_some() => name,
+                None => continue
+            };
+
+            let marker_node = attached.unwrap_or(0);
+            let transform = relative_transform(n, marker_node);
+            importer.add_marker(Marker {
+                name: name.to_owned(),
+                region: None,
+                node: Some(marker_node as u16),
+                rotation: rotation_from_gltf(transform.rotation()),
+                position: position_from_gltf(transform.translation()),
+                radius: node["extras"]["radius"].as_f64().unwrap_or(1.0) as f32
+            }, node["extras"]["region"].as_str().map(str::to_owned));
+        }
+
+        importer.finish()
+    }
+}
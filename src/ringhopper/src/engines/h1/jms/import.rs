@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+use crate::error::*;
+use crate::types::*;
+
+use ringhopper_proc::*;
+
+use super::{JMS, Marker, Material, Node, Region, Triangle, Vertex};
+use super::collision::triangle_normal;
+
+/// Separator between the permutation and the region in the names of imported meshes (e.g. `damaged:head`).
+pub const IMPORTED_MESH_PERMUTATION_SEPARATOR: char = ':';
+
+/// Name used for regions and materials that are not named.
+pub(super) const UNNAMED: &str = "unnamed";
+
+/// JMS imported from a glTF or OBJ file.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ImportedJMS {
+    /// Permutation named by the meshes, or [`None`] if they do not name one.
+    ///
+    /// This can end with a level of detail suffix, like the names of JMS files (see
+    /// [`ModelLevelOfDetail::split_file_name`](super::ModelLevelOfDetail::split_file_name)).
+    pub permutation: Option<String>,
+
+    /// JMS data.
+    pub jms: JMS
+}
+
+/// Key for deduping vertices by their values.
+type VertexKey = [u32; 12];
+
+fn vertex_key(v: &Vertex) -> VertexKey {
+    [
+        v.node0.map(u32::from).unwrap_or(u32::MAX), v.node1.map(u32::from).unwrap_or(u32::MAX), v.node1_weight.to_bits(),
+        v.position.x.to_bits(), v.position.y.to_bits(), v.position.z.to_bits(),
+        v.normal.x.to_bits(), v.normal.y.to_bits(), v.normal.z.to_bits(),
+        v.texture_coordinates.x.to_bits(), v.texture_coordinates.y.to_bits(), v.texture_coordinates.z.to_bits()
+    ]
+}
+
+/// Get the index of the element with the name, adding it if it isn't present.
+fn find_or_add<T, F: Fn(&T) -> &str>(elements: &mut Vec<T>, name: &str, get_name: F, make: impl FnOnce() -> T) -> ErrorMessageResult<u16> {
+    let index = match elements.iter().position(|e| get_name(e) == name) {
+        Some(n) => n,
+        None => {
+            elements.push(make());
+            elements.len() - 1
+        }
+    };
+    u16::try_from(index).map_err(|_| ErrorMessage::StaticString(get_compiled_string!("engine.h1.jms.error_import_too_many_elements")))
+}
+
+/// Split a mesh name into its permutation, if any, and its region.
+fn split_mesh_name(name: &str) -> (Option<&str>, &str) {
+    match name.split_once(IMPORTED_MESH_PERMUTATION_SEPARATOR) {
+        Some((permutation, region)) => (Some(permutation), region),
+        None => (None, name)
+    }
+}
+
+/// Get the node that everything is attached to in files without nodes.
+pub(super) fn default_node() -> Node {
+    Node { name: "frame".to_owned(), rotation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 }, ..Default::default() }
+}
+
+/// Get the normal of a triangle without vertex normals.
+pub(super) fn flat_normal(positions: &[Point3D; 3]) -> Vector3D {
+    triangle_normal(&positions.map(Vector3D::from)).normalize()
+}
+
+/// Builds a JMS for each permutation from the meshes of an imported file.
+pub(super) struct JMSImporter {
+    node_list_checksum: i32,
+    nodes: Vec<Node>,
+    markers: Vec<(Marker, Option<String>)>,
+    permutations: Vec<(ImportedJMS, HashMap<VertexKey, u32>)>
+}
+
+impl JMSImporter {
+    pub fn new(node_list_checksum: i32, nodes: Vec<Node>) -> JMSImporter {
+        JMSImporter { node_list_checksum, nodes, markers: Vec::new(), permutations: Vec::new() }
+    }
+
+    /// Add a marker to every permutation, optionally in the region with the given name.
+    ///
+    /// Region names can also be mesh names, in which case the permutation is ignored.
+    pub fn add_marker(&mut self, marker: Marker, region: Option<String>) {
+        self.markers.push((marker, region));
+    }
+
+    fn get_permutation(&mut self, permutation: Option<&str>) -> &mut (ImportedJMS, HashMap<VertexKey, u32>) {
+        match self.permutations.iter().position(|p| p.0.permutation.as_deref() == permutation) {
+            Some(n) => &mut self.permutations[n],
+            None => {
+                let jms = JMS { node_list_checksum: self.node_list_checksum, nodes: self.nodes.clone(), ..Default::default() };
+                self.permutations.push((ImportedJMS { permutation: permutation.map(str::to_owned), jms }, HashMap::new()));
+                self.permutations.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Add a triangle of the mesh with the given name.
+    ///
+    /// Mesh names are split into a permutation and region with [`IMPORTED_MESH_PERMUTATION_SEPARATOR`]. Names without
+    /// it are only a region.
+    pub fn add_triangle(&mut self, mesh_name: &str, material: Option<&Material>, vertices: [Vertex; 3]) -> ErrorMessageResult<()> {
+        let (permutation, region) = split_mesh_name(mesh_name);
+
+        let (imported, vertex_map) = self.get_permutation(permutation);
+        let jms = &mut imported.jms;
+
+        let region = find_or_add(&mut jms.regions, region, |r| &r.name, || Region { name: region.to_owned() })?;
+        let shader = match material {
+            Some(m) => Some(find_or_add(&mut jms.materials, &m.name, |m| &m.name, || m.clone())?),
+            None => None
+        };
+
+        let indices = vertices.map(|v| *vertex_map.entry(vertex_key(&v)).or_insert_with(|| {
+            jms.vertices.push(v);
+            (jms.vertices.len() - 1) as u32
+        }));
+        jms.triangles.push(Triangle { region: Some(region), shader, vertices: (indices[0], indices[1], indices[2]) });
+
+        Ok(())
+    }
+
+    /// Add the markers to each permutation, then validate and optimize them.
+    pub fn finish(mut self) -> ErrorMessageResult<Vec<ImportedJMS>> {
+        if self.permutations.is_empty() {
+            self.get_permutation(None);
+        }
+
+        let mut imported = Vec::with_capacity(self.permutations.len());
+        for (mut permutation, _) in self.permutations {
+            let jms = &mut permutation.jms;
+            for (marker, region) in &self.markers {
+                let region = match region.as_deref().map(|r| split_mesh_name(r).1) {
+                    Some(r) => Some(find_or_add(&mut jms.regions, r, |r| &r.name, || Region { name: r.to_owned() })?),
+                    None => None
+                };
+                jms.markers.push(Marker { region, ..marker.clone() });
+            }
+
+            jms.validate()?;
+            jms.optimize();
+            imported.push(permutation);
+        }
+
+        Ok(imported)
+    }
+}
@@ -0,0 +1,192 @@
+use crate::types::*;
+use crate::error::*;
+
+use ringhopper_proc::*;
+
+use super::{JMSParser, SCALE_FACTOR, find_parent_nodes, get_line_and_column, parse_str_token, read_str_token};
+
+/// Version for JMA files that are written
+const JMA_VERSION: u16 = 16392;
+
+/// Oldest supported version for JMA files, which only has the names of the nodes
+const JMA_VERSION_NODE_NAMES_ONLY: u16 = 16390;
+
+/// Node of an animation.
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct AnimationNode {
+    pub name: String,
+    pub first_child: Option<u16>,
+    pub sibling_node: Option<u16>
+}
+
+/// Transform of a node on a frame of an animation.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct NodeTransform {
+    pub position: Point3D,
+    pub rotation: Quaternion,
+    pub scale: f32
+}
+
+impl Default for NodeTransform {
+    fn default() -> Self {
+        NodeTransform {
+            position: Point3D::default(),
+            rotation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
+            scale: 1.0
+        }
+    }
+}
+
+impl JMSParser for NodeTransform {
+    fn from_jms_string(string: &str, offset: &mut usize) -> ErrorMessageResult<NodeTransform> {
+        Ok(NodeTransform {
+            position: Point3D::from_jms_string(string, offset)?.scale(1.0 / SCALE_FACTOR),
+            rotation: Quaternion::from_jms_string(string, offset)?,
+            scale: f32::from_jms_string(string, offset)?
+        })
+    }
+    fn to_jms_string(&self) -> String {
+        self.position.scale(SCALE_FACTOR).to_jms_string()
+        + &self.rotation.to_jms_string()
+        + &self.scale.to_jms_string()
+    }
+}
+
+/// Animation source file.
+///
+/// This is used for all of the JMA family of formats (JMA, JMM, JMO, JMR, JMT, JMW, and JMZ). The format is the same
+/// for each of them, and the extension only determines how the animation is compiled (see [`AnimationSourceType`]).
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct JMA {
+    pub node_list_checksum: i32,
+    pub frame_rate: usize,
+    pub actors: Vec<String>,
+    pub nodes: Vec<AnimationNode>,
+
+    /// Transform of each node for each frame.
+    pub frames: Vec<Vec<NodeTransform>>
+}
+
+impl JMSParser for JMA {
+    fn from_jms_string(string: &str, offset: &mut usize) -> ErrorMessageResult<JMA> {
+        let version = parse_str_token::<u16>(string, offset)?;
+        if !(JMA_VERSION_NODE_NAMES_ONLY..=JMA_VERSION).contains(&version) {
+            return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_version_mismatch"), version_expected=JMA_VERSION, version_read=version)))
+        }
+
+        let frame_count = usize::from_jms_string(string, offset)?;
+        let frame_rate = usize::from_jms_string(string, offset)?;
+        let actors = Vec::<String>::from_jms_string(string, offset)?;
+
+        let node_count = usize::from_jms_string(string, offset)?;
+        let node_list_checksum = i32::from_jms_string(string, offset)?;
+        let mut nodes = Vec::with_capacity(node_count);
+        for _ in 0..node_count {
+            let name = String::from_jms_string(string, offset)?;
+            nodes.push(match version {
+                JMA_VERSION_NODE_NAMES_ONLY => AnimationNode { name, first_child: None, sibling_node: None },
+                _ => AnimationNode {
+                    name,
+                    first_child: Option::<u16>::from_jms_string(string, offset)?,
+                    sibling_node: Option::<u16>::from_jms_string(string, offset)?
+                }
+            });
+        }
+
+        let mut frames = Vec::with_capacity(frame_count);
+        for _ in 0..frame_count {
+            let mut frame = Vec::with_capacity(node_count);
+            for _ in 0..node_count {
+                frame.push(NodeTransform::from_jms_string(string, offset)?);
+            }
+            frames.push(frame);
+        }
+
+        if read_str_token(string, offset).is_err() {
+            Ok(JMA { node_list_checksum, frame_rate, actors, nodes, frames })
+        }
+        else {
+            Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.jms.error_unexpected_token")))
+        }
+    }
+    fn to_jms_string(&self) -> String {
+        let mut string = Some(JMA_VERSION).to_jms_string()
+            + &self.frames.len().to_jms_string()
+            + &self.frame_rate.to_jms_string()
+            + &self.actors.to_jms_string()
+            + &self.nodes.len().to_jms_string()
+            + &self.node_list_checksum.to_jms_string();
+
+        for n in &self.nodes {
+            string += &n.name.to_jms_string();
+            string += &n.first_child.to_jms_string();
+            string += &n.sibling_node.to_jms_string();
+        }
+
+        for f in &self.frames {
+            for t in f {
+                string += &t.to_jms_string();
+            }
+        }
+
+        string
+    }
+}
+
+impl JMA {
+    /// Parse the JMA file as a byte array.
+    ///
+    /// The array must be valid UTF-8.
+    ///
+    /// Returns an [`Err`] if it could not be parsed.
+    pub fn parse_bytes(bytes: &[u8]) -> ErrorMessageResult<JMA> {
+        match std::str::from_utf8(bytes) {
+            Ok(n) => JMA::parse_str(n),
+            Err(_) => Err(ErrorMessage::StaticString(get_compiled_string!("engine.types.error_string_not_valid_utf8")))
+        }
+    }
+
+    /// Parse the JMA file as a UTF-8 string.
+    ///
+    /// Returns an [`Err`] if it could not be parsed.
+    pub fn parse_str(string: &str) -> ErrorMessageResult<JMA> {
+        let mut current_offset = 0usize;
+        match JMA::from_jms_string(string, &mut current_offset) {
+            Ok(n) => Ok(n),
+            Err(e) => {
+                let (line, column) = get_line_and_column(string, current_offset);
+                Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_could_not_parse_jma"), line=line, column=column, error=e)))
+            }
+        }
+    }
+
+    /// Generate a vector of UTF-8 bytes.
+    ///
+    /// The output will use CRLF line endings.
+    pub fn into_bytes(&self) -> Vec<u8> {
+        self.to_jms_string().into_bytes()
+    }
+
+    /// Validate that the JMA node indices are valid.
+    pub fn validate(&self) -> ErrorMessageResult<()> {
+        let node_count = self.nodes.len();
+        for (i, node) in self.nodes.iter().enumerate() {
+            for n in [node.sibling_node, node.first_child].into_iter().flatten() {
+                if n as usize >= node_count {
+                    return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_verify_fail_out_of_bounds_node_for_node"),
+                               node_this=i,
+                               node=n,
+                               node_count=node_count)))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the parent of each node.
+    ///
+    /// Returns an [`Err`] if the nodes' siblings loop.
+    pub fn get_parent_nodes(&self) -> ErrorMessageResult<Vec<Option<u16>>> {
+        find_parent_nodes(self.nodes.len(), |n| (self.nodes[n].first_child, self.nodes[n].sibling_node))
+    }
+}
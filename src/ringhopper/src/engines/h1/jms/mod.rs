@@ -11,6 +11,29 @@ use ringhopper_proc::*;
 #[cfg(test)]
 mod tests;
 
+mod animation;
+pub use self::animation::*;
+
+mod bsp;
+pub use self::bsp::*;
+
+mod collision;
+pub use self::collision::*;
+
+mod gltf;
+pub use self::gltf::*;
+
+mod import;
+pub use self::import::*;
+
+mod jma;
+pub use self::jma::*;
+
+mod model;
+pub use self::model::*;
+
+mod obj;
+
 /// Delimiters used in JMS files.
 pub const JMS_DELIMITERS: [char; 3] = ['\r', '\n', '\t'];
 
@@ -70,6 +93,43 @@ fn parse_str_token<T: FromStr>(string: &str, offset: &mut usize) -> ErrorMessage
     }
 }
 
+/// Get the line and column of the offset in the string, starting at 1.
+fn get_line_and_column(string: &str, offset: usize) -> (usize, usize) {
+    let str_bytes = string.as_bytes();
+    let mut line = 1usize;
+    let mut column = 1usize;
+    for byte in &str_bytes[..offset.min(str_bytes.len())] {
+        if *byte == b'\n' {
+            line += 1;
+            column = 1;
+        }
+        else if *byte != b'\r' {
+            column += 1;
+        }
+    }
+    (line, column)
+}
+
+/// Get the parent of each node from the first child and next sibling of each node.
+///
+/// Returns an [`Err`] if the nodes' siblings loop.
+fn find_parent_nodes<F: Fn(usize) -> (Option<u16>, Option<u16>)>(node_count: usize, links: F) -> ErrorMessageResult<Vec<Option<u16>>> {
+    let mut parents = vec![None; node_count];
+    for parent in 0..node_count {
+        let mut child = links(parent).0;
+        let mut visited = 0;
+        while let Some(c) = child {
+            visited += 1;
+            if visited > node_count {
+                return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.jms.error_verify_fail_infinite_loop")))
+            }
+            parents[c as usize] = Some(parent as u16);
+            child = links(c as usize).1;
+        }
+    }
+    Ok(parents)
+}
+
 macro_rules! define_parsers {
     ($t:ty) => {
         impl JMSParser for $t {
@@ -425,19 +485,7 @@ impl JMS {
         match JMS::from_jms_string(string, &mut current_offset) {
             Ok(n) => Ok(n),
             Err(e) => {
-                let str_bytes = string.as_bytes();
-                let mut line = 1usize;
-                let mut column = 1usize;
-                for i in 0..current_offset {
-                    let byte = &str_bytes[i];
-                    if *byte == '\n' as u8 {
-                        line += 1;
-                        column = 1;
-                    }
-                    else if *byte != '\r' as u8 {
-                        column += 1;
-                    }
-                }
+                let (line, column) = get_line_and_column(string, current_offset);
                 Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_could_not_parse_jms"), line=line, column=column, error=e)))
             }
         }
@@ -455,7 +503,7 @@ impl JMS {
         // Function to safely compare if an index is out of bounds
         let is_in_bounds = |index: Option<u16>, count: usize| {
             if let Some(n) = index {
-                n as usize <= count
+                (n as usize) < count
             }
             else {
                 true
@@ -560,6 +608,13 @@ impl JMS {
         Ok(())
     }
 
+    /// Get the parent of each node.
+    ///
+    /// Returns an [`Err`] if the nodes' siblings loop.
+    pub fn get_parent_nodes(&self) -> ErrorMessageResult<Vec<Option<u16>>> {
+        find_parent_nodes(self.nodes.len(), |n| (self.nodes[n].first_child, self.nodes[n].sibling_node))
+    }
+
     /// Optimize the JMS, deduping all vertices and triangles as well as removing degenerate triangles.
     pub fn optimize(&mut self) {
         // First, copy our triangles.
@@ -0,0 +1,451 @@
+use std::collections::HashMap;
+
+use crate::engines::h1::definitions::*;
+use crate::engines::h1::{Index, TagReference};
+use crate::error::*;
+use crate::types::{Point2D, Point3D, Reflexive, String32, TagBlockFn, Vector3D};
+
+use ringhopper_proc::*;
+
+use super::{JMS, Vertex};
+
+/// Vertex indices of a JMS triangle.
+type JMSTriangleVertices = (u32, u32, u32);
+
+/// Level of detail of a model geometry.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ModelLevelOfDetail {
+    SuperHigh,
+    High,
+    Medium,
+    Low,
+    SuperLow
+}
+
+impl ModelLevelOfDetail {
+    /// All levels of detail, from highest to lowest.
+    pub const ALL: [ModelLevelOfDetail; 5] = [
+        ModelLevelOfDetail::SuperHigh,
+        ModelLevelOfDetail::High,
+        ModelLevelOfDetail::Medium,
+        ModelLevelOfDetail::Low,
+        ModelLevelOfDetail::SuperLow
+    ];
+
+    /// Get the suffix used for JMS files of this level of detail.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            ModelLevelOfDetail::SuperHigh => "superhigh",
+            ModelLevelOfDetail::High => "high",
+            ModelLevelOfDetail::Medium => "medium",
+            ModelLevelOfDetail::Low => "low",
+            ModelLevelOfDetail::SuperLow => "superlow"
+        }
+    }
+
+    /// Split a JMS file name (without the extension) into its permutation name and level of detail.
+    ///
+    /// Names without a level of detail suffix are [`ModelLevelOfDetail::SuperHigh`].
+    pub fn split_file_name(name: &str) -> (&str, ModelLevelOfDetail) {
+        for lod in ModelLevelOfDetail::ALL {
+            if let Some(permutation) = name.strip_suffix(lod.suffix()).and_then(|n| n.strip_suffix(' ')) {
+                return (permutation, lod)
+            }
+        }
+        (name, ModelLevelOfDetail::SuperHigh)
+    }
+}
+
+/// JMS file of a permutation of a model.
+pub struct ModelPermutationJMS {
+    /// Name of the permutation.
+    pub permutation: String,
+
+    /// Level of detail of the geometry.
+    pub lod: ModelLevelOfDetail,
+
+    /// JMS data.
+    pub jms: JMS
+}
+
+pub(super) fn dot(a: &Vector3D, b: &Vector3D) -> f32 {
+    a.x * b.x + a.y * b.y + a.z * b.z
+}
+
+pub(super) fn cross(a: &Vector3D, b: &Vector3D) -> Vector3D {
+    Vector3D {
+        x: a.y * b.z - a.z * b.y,
+        y: a.z * b.x - a.x * b.z,
+        z: a.x * b.y - a.y * b.x
+    }
+}
+
+/// Find a unit vector perpendicular to the normal.
+fn perpendicular(normal: &Vector3D) -> Vector3D {
+    let axis = if normal.x.abs() < 0.9 { Vector3D { x: 1.0, y: 0.0, z: 0.0 } } else { Vector3D { x: 0.0, y: 1.0, z: 0.0 } };
+    cross(normal, &axis).normalize()
+}
+
+/// Convert a list of triangles into a triangle strip.
+///
+/// Triangles are joined greedily by their shared edges, and separate strips are joined with degenerate triangles. Even
+/// triangles in the strip are wound in the opposite order of odd triangles, with the first triangle `(a, b, c)` being
+/// stored as `a, c, b`.
+pub fn make_triangle_strip(triangles: &[(u16, u16, u16)]) -> Vec<u16> {
+    // Map each directed edge to the triangles that contain it.
+    let mut edges: HashMap<(u16, u16), Vec<usize>> = HashMap::new();
+    for (i, &(a, b, c)) in triangles.iter().enumerate() {
+        for edge in [(a, b), (b, c), (c, a)] {
+            edges.entry(edge).or_default().push(i);
+        }
+    }
+
+    let mut used = vec![false; triangles.len()];
+    let mut strip: Vec<u16> = Vec::with_capacity(triangles.len() * 3);
+
+    for first in 0..triangles.len() {
+        if used[first] {
+            continue;
+        }
+        used[first] = true;
+        let (a, b, c) = triangles[first];
+
+        // Join this strip to the previous one with degenerate triangles, making sure it starts at an even position.
+        if let Some(&last) = strip.last() {
+            strip.push(last);
+            strip.push(a);
+            strip.push(a);
+            if strip.len() % 2 == 0 {
+                strip.push(a);
+            }
+        }
+        else {
+            strip.push(a);
+        }
+        strip.push(c);
+        strip.push(b);
+
+        // Continue the strip as long as there is an unused triangle sharing the last edge.
+        loop {
+            let len = strip.len();
+            let (x, y) = (strip[len - 2], strip[len - 1]);
+
+            // The next triangle is (x, z, y) if at an even position or (x, y, z) if at an odd position.
+            let edge = if (len - 2) % 2 == 0 { (y, x) } else { (x, y) };
+            let next = edges.get(&edge).and_then(|t| t.iter().copied().find(|&t| !used[t]));
+            let next = match next {
+                Some(n) => n,
+                None => break
+            };
+            used[next] = true;
+
+            let (a, b, c) = triangles[next];
+            let z = if (a, b) == edge { c } else if (b, c) == edge { a } else { b };
+            strip.push(z);
+        }
+    }
+
+    strip
+}
+
+/// Calculate the normal, binormal, and tangent of each vertex from its texture coordinates.
+pub(super) fn calculate_tangent_space(vertices: &[&Vertex], triangles: &[(u16, u16, u16)]) -> Vec<(Vector3D, Vector3D, Vector3D)> {
+    let mut tangents = vec![Vector3D::default(); vertices.len()];
+    let mut binormals = vec![Vector3D::default(); vertices.len()];
+    for &(a, b, c) in triangles {
+        let (va, vb, vc) = (vertices[a as usize], vertices[b as usize], vertices[c as usize]);
+        let edge1 = Vector3D::from(vb.position).sub_components(&Vector3D::from(va.position).into_floats());
+        let edge2 = Vector3D::from(vc.position).sub_components(&Vector3D::from(va.position).into_floats());
+        let (du1, dv1) = (vb.texture_coordinates.x - va.texture_coordinates.x, vb.texture_coordinates.y - va.texture_coordinates.y);
+        let (du2, dv2) = (vc.texture_coordinates.x - va.texture_coordinates.x, vc.texture_coordinates.y - va.texture_coordinates.y);
+        let determinant = du1 * dv2 - du2 * dv1;
+        if determinant == 0.0 {
+            continue;
+        }
+        let r = 1.0 / determinant;
+        let tangent = (edge1 * dv2).sub_components(&(edge2 * dv1).into_floats()) * r;
+        let binormal = (edge2 * du1).sub_components(&(edge1 * du2).into_floats()) * r;
+        for i in [a, b, c] {
+            tangents[i as usize] = tangents[i as usize].add_components(&tangent.into_floats());
+            binormals[i as usize] = binormals[i as usize].add_components(&binormal.into_floats());
+        }
+    }
+
+    // Orthogonalize the tangent to the normal, and make sure the binormal is on the correct side.
+    vertices.iter().enumerate().map(|(i, v)| {
+        let normal = v.normal.normalize();
+        let tangent = tangents[i].sub_components(&(normal * dot(&normal, &tangents[i])).into_floats());
+        let tangent = if dot(&tangent, &tangent) > 0.0 { tangent.normalize() } else { perpendicular(&normal) };
+        let mut binormal = cross(&normal, &tangent);
+        if dot(&binormal, &binormals[i]) < 0.0 {
+            binormal = binormal * -1.0;
+        }
+        (normal, binormal, tangent)
+    }).collect()
+}
+
+/// Build a model part from the triangles of a JMS.
+fn build_part(jms: &JMS, triangles: &[JMSTriangleVertices], shader_index: usize, u_scale: f32, v_scale: f32) -> ErrorMessageResult<GBXModelGeometryPart> {
+    // Dedupe vertices by their values.
+    let vertex_key = |v: &Vertex| [
+        v.position.x.to_bits(), v.position.y.to_bits(), v.position.z.to_bits(),
+        v.normal.x.to_bits(), v.normal.y.to_bits(), v.normal.z.to_bits(),
+        v.texture_coordinates.x.to_bits(), v.texture_coordinates.y.to_bits(),
+        v.node0.map(|n| n as u32).unwrap_or(u32::MAX), v.node1.map(|n| n as u32).unwrap_or(u32::MAX), v.node1_weight.to_bits()
+    ];
+    let mut vertex_map: HashMap<[u32; 11], u16> = HashMap::new();
+    let mut vertices: Vec<&Vertex> = Vec::new();
+    let mut local_triangles = Vec::with_capacity(triangles.len());
+
+    for &(a, b, c) in triangles {
+        let mut local = [0u16; 3];
+        for (i, index) in [a, b, c].into_iter().enumerate() {
+            let vertex = &jms.vertices[index as usize];
+            local[i] = match vertex_map.get(&vertex_key(vertex)) {
+                Some(&n) => n,
+                None => {
+                    let new_index = u16::try_from(vertices.len()).map_err(|_| ErrorMessage::StaticString(get_compiled_string!("engine.h1.jms.error_too_many_vertices")))?;
+                    vertex_map.insert(vertex_key(vertex), new_index);
+                    vertices.push(vertex);
+                    new_index
+                }
+            };
+        }
+
+        // Skip degenerate triangles.
+        if local[0] != local[1] && local[1] != local[2] && local[0] != local[2] {
+            local_triangles.push((local[0], local[1], local[2]));
+        }
+    }
+
+    let tangent_space = calculate_tangent_space(&vertices, &local_triangles);
+
+    let mut part = GBXModelGeometryPart::new_with_defaults();
+    let base = &mut part.base_struct;
+    base.shader_index = Some(shader_index as u16);
+    base.prev_filthy_part_index = u8::MAX;
+    base.next_filthy_part_index = u8::MAX;
+
+    let mut centroid = Vector3D::default();
+    base.uncompressed_vertices.blocks.reserve_exact(vertices.len());
+    for (i, v) in vertices.iter().enumerate() {
+        centroid = centroid.add_components(&Vector3D::from(v.position).into_floats());
+
+        let (normal, binormal, tangent) = tangent_space[i];
+        let (node1_index, node1_weight) = match v.node1 {
+            Some(n) => (Some(n), v.node1_weight),
+            None => (None, 0.0)
+        };
+        base.uncompressed_vertices.blocks.push(ModelVertexUncompressed {
+            position: v.position,
+            normal,
+            binormal,
+            tangent,
+            texture_coords: Point2D { x: v.texture_coordinates.x / u_scale, y: v.texture_coordinates.y / v_scale },
+            node0_index: v.node0,
+            node1_index,
+            node0_weight: 1.0 - node1_weight,
+            node1_weight
+        });
+    }
+    if !vertices.is_empty() {
+        base.centroid = Point3D::from(centroid * (1.0 / vertices.len() as f32));
+    }
+
+    // Pad the last triangle with null indices.
+    let mut indices: Vec<Index> = make_triangle_strip(&local_triangles).into_iter().map(Some).collect();
+    while indices.len() % 3 != 0 {
+        indices.push(None);
+    }
+    base.triangles.blocks = indices.chunks(3).map(|t| ModelTriangle { vertex0_index: t[0], vertex1_index: t[1], vertex2_index: t[2] }).collect();
+
+    Ok(part)
+}
+
+/// Find the shader for the material.
+///
+/// Material names may end with the permutation of the shader to use. If a shader is not found for the full name, the
+/// permutation is stripped and it is tried again.
+pub(super) fn resolve_material<F>(name: &str, resolve_shader: &mut F) -> ErrorMessageResult<ModelShaderReference> where F: FnMut(&str) -> ErrorMessageResult<Option<TagReference>> {
+    if let Some(shader) = resolve_shader(name)? {
+        return Ok(ModelShaderReference { shader, permutation: None })
+    }
+
+    let base_name = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    if base_name.len() != name.len() && !base_name.is_empty() {
+        if let (Some(shader), Ok(permutation)) = (resolve_shader(base_name)?, name[base_name.len()..].parse::<u16>()) {
+            return Ok(ModelShaderReference { shader, permutation: Some(permutation) })
+        }
+    }
+
+    Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_shader_not_found"), material=name)))
+}
+
+/// Build a gbxmodel tag from JMS files.
+///
+/// Regions are shared by every permutation, and markers are taken from the highest level of detail of each
+/// permutation. If a permutation is missing a level of detail, the next higher level of detail is used. Shaders are
+/// found by calling `resolve_shader` with the name of each material, which returns `Ok(None)` if no shader was found.
+///
+/// If `existing` is set, its settings are kept, and only its geometry, markers, nodes, and shaders are replaced.
+pub fn build_gbxmodel<F>(jms_files: &[ModelPermutationJMS], existing: Option<&GBXModel>, mut resolve_shader: F) -> ErrorMessageResult<GBXModel> where F: FnMut(&str) -> ErrorMessageResult<Option<TagReference>> {
+    let first = match jms_files.first() {
+        Some(n) => &n.jms,
+        None => return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.jms.error_no_jms_files")))
+    };
+
+    for j in jms_files {
+        j.jms.validate()?;
+        if j.jms.nodes.len() != first.nodes.len() || j.jms.nodes.iter().zip(first.nodes.iter()).any(|(a, b)| a.name != b.name) {
+            return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_node_mismatch"), permutation=j.permutation, lod=j.lod.suffix())))
+        }
+    }
+
+    let mut model = match existing {
+        Some(n) => n.clone(),
+        None => GBXModel::new_with_defaults()
+    };
+    let u_scale = if model.base_map_u_scale == 0.0 { 1.0 } else { model.base_map_u_scale };
+    let v_scale = if model.base_map_v_scale == 0.0 { 1.0 } else { model.base_map_v_scale };
+
+    // Nodes
+    model.node_list_checksum = first.node_list_checksum;
+    model.nodes = Reflexive::default();
+    for (n, parent) in first.nodes.iter().zip(first.get_parent_nodes()?) {
+        model.nodes.blocks.push(ModelNode {
+            name: String32::from_str(&n.name)?,
+            next_sibling_node_index: n.sibling_node,
+            first_child_node_index: n.first_child,
+            parent_node_index: parent,
+            default_translation: n.position,
+            default_rotation: n.rotation,
+            node_distance_from_parent: (n.position.x * n.position.x + n.position.y * n.position.y + n.position.z * n.position.z).sqrt(),
+            ..Default::default()
+        });
+    }
+
+    // Shaders
+    model.shaders = Reflexive::default();
+    let mut shader_indices: HashMap<&str, usize> = HashMap::new();
+    for j in jms_files {
+        for m in &j.jms.materials {
+            if shader_indices.contains_key(m.name.as_str()) {
+                continue;
+            }
+            let shader = resolve_material(&m.name, &mut resolve_shader)?;
+            let index = match model.shaders.blocks.iter().position(|s| s.shader == shader.shader && s.permutation == shader.permutation) {
+                Some(n) => n,
+                None => {
+                    model.shaders.blocks.push(shader);
+                    model.shaders.blocks.len() - 1
+                }
+            };
+            shader_indices.insert(&m.name, index);
+        }
+    }
+
+    // Regions are sorted by name, as are their permutations.
+    let mut region_names: Vec<&str> = jms_files.iter().flat_map(|j| j.jms.regions.iter().map(|r| r.name.as_str())).collect();
+    region_names.sort();
+    region_names.dedup();
+    let mut permutation_names: Vec<&str> = jms_files.iter().map(|j| j.permutation.as_str()).collect();
+    permutation_names.sort();
+    permutation_names.dedup();
+
+    model.regions = Reflexive::default();
+    model.geometries = Reflexive::default();
+    model.markers = Reflexive::default();
+    for region_name in &region_names {
+        let mut region = ModelRegion { name: String32::from_str(region_name)?, ..Default::default() };
+
+        for permutation_name in &permutation_names {
+            let mut permutation = ModelRegionPermutation::new_with_defaults();
+            permutation.name = String32::from_str(permutation_name)?;
+            permutation.flags.cannot_be_chosen_randomly = permutation_name.starts_with('~');
+
+            // Markers are taken from the highest level of detail this permutation has.
+            let marker_lod = ModelLevelOfDetail::ALL.into_iter().find(|l| jms_files.iter().any(|j| j.permutation == *permutation_name && j.lod == *l));
+
+            let mut geometries: [Index; 5] = [None; 5];
+            let mut has_markers = false;
+            for j in jms_files.iter().filter(|j| j.permutation == *permutation_name) {
+                let jms = &j.jms;
+                let region_index = match jms.regions.iter().position(|r| r.name == *region_name) {
+                    Some(n) => n,
+                    None => continue
+                };
+
+                // Markers without a region go into the first region.
+                if Some(j.lod) == marker_lod {
+                    for m in jms.markers.iter().filter(|m| m.region.map(|r| r as usize).unwrap_or(0) == region_index) {
+                        permutation.markers.blocks.push(ModelRegionPermutationMarker {
+                            name: String32::from_str(&m.name)?,
+                            node_index: m.node,
+                            rotation: m.rotation,
+                            translation: m.position
+                        });
+                        has_markers = true;
+                    }
+                }
+
+                // Split the triangles by shader.
+                let mut parts: Vec<(usize, Vec<JMSTriangleVertices>)> = Vec::new();
+                for t in jms.triangles.iter().filter(|t| t.region.map(|r| r as usize) == Some(region_index)) {
+                    let material = match t.shader {
+                        Some(n) => &jms.materials[n as usize].name,
+                        None => continue
+                    };
+                    let shader_index = shader_indices[material.as_str()];
+                    match parts.iter_mut().find(|(s, _)| *s == shader_index) {
+                        Some((_, triangles)) => triangles.push(t.vertices),
+                        None => parts.push((shader_index, vec![t.vertices]))
+                    }
+                }
+                if parts.is_empty() {
+                    continue;
+                }
+
+                let mut geometry = GBXModelGeometry::default();
+                for (shader_index, triangles) in parts {
+                    geometry.parts.blocks.push(build_part(jms, &triangles, shader_index, u_scale, v_scale)?);
+                }
+                let lod_index = ModelLevelOfDetail::ALL.iter().position(|l| *l == j.lod).unwrap();
+                geometries[lod_index] = Some(u16::try_from(model.geometries.blocks.len()).map_err(|_| ErrorMessage::StaticString(get_compiled_string!("engine.h1.jms.error_too_many_geometries")))?);
+                model.geometries.blocks.push(geometry);
+            }
+
+            if geometries.iter().all(|g| g.is_none()) && !has_markers {
+                continue;
+            }
+
+            // Fill in missing levels of detail with the next higher level of detail, or the next lower one if none.
+            for i in 0..geometries.len() {
+                if geometries[i].is_none() {
+                    geometries[i] = geometries[..i].iter().rev().chain(geometries[i+1..].iter()).find_map(|g| *g);
+                }
+            }
+            permutation.super_high = geometries[0];
+            permutation.high = geometries[1];
+            permutation.medium = geometries[2];
+            permutation.low = geometries[3];
+            permutation.super_low = geometries[4];
+
+            // Keep the flags of the permutation if it already existed.
+            if let Some(existing) = existing {
+                let existing_permutation = existing.regions.blocks.iter()
+                    .filter(|r| r.name == region.name)
+                    .flat_map(|r| r.permutations.blocks.iter())
+                    .find(|p| p.name == permutation.name);
+                if let Some(p) = existing_permutation {
+                    permutation.flags = p.flags;
+                }
+            }
+
+            region.permutations.blocks.push(permutation);
+        }
+
+        model.regions.blocks.push(region);
+    }
+
+    Ok(model)
+}
@@ -0,0 +1,175 @@
+use std::fmt::Write;
+use crate::error::*;
+use crate::types::*;
+
+use ringhopper_proc::*;
+
+use super::{JMS, Material, Vertex};
+use super::gltf::{normal_from_gltf, normal_to_gltf, position_from_gltf, position_to_gltf};
+use super::import::{ImportedJMS, JMSImporter, UNNAMED, default_node, flat_normal};
+
+impl JMS {
+    /// Generate a Wavefront OBJ file.
+    ///
+    /// Each region becomes an object, and materials are referenced by name with `usemtl`. Nodes, markers, and vertex
+    /// weights cannot be stored in OBJ files, so they are not exported. Coordinates are converted the same way as
+    /// [`JMS::into_gltf`].
+    ///
+    /// Returns an [`Err`] if the JMS is invalid.
+    pub fn into_obj(&self) -> ErrorMessageResult<Vec<u8>> {
+        self.validate()?;
+
+        let mut obj = String::new();
+
+        // Indices in OBJ files are 1-based, and all vertices are written first so they can be shared by every object.
+        for v in &self.vertices {
+            let [x, y, z] = position_to_gltf(&v.position);
+            writeln!(obj, "v {x} {y} {z}").unwrap();
+        }
+        for v in &self.vertices {
+            writeln!(obj, "vt {} {}", v.texture_coordinates.x, 1.0 - v.texture_coordinates.y).unwrap();
+        }
+        for v in &self.vertices {
+            let [x, y, z] = normal_to_gltf(&v.normal);
+            writeln!(obj, "vn {x} {y} {z}").unwrap();
+        }
+
+        let mut regions: Vec<Option<u16>> = Vec::new();
+        for t in &self.triangles {
+            if !regions.contains(&t.region) {
+                regions.push(t.region);
+            }
+        }
+
+        for region in regions {
+            let region_name = match region {
+                Some(r) => self.regions[r as usize].name.as_str(),
+                None => UNNAMED
+            };
+            writeln!(obj, "o {region_name}").unwrap();
+
+            let mut current_shader = None;
+            for t in self.triangles.iter().filter(|t| t.region == region) {
+                if current_shader != Some(t.shader) {
+                    current_shader = Some(t.shader);
+                    let material_name = match t.shader {
+                        Some(s) => self.materials[s as usize].name.as_str(),
+                        None => UNNAMED
+                    };
+                    writeln!(obj, "usemtl {material_name}").unwrap();
+                }
+
+                let (a, b, c) = t.vertices;
+                let [a, b, c] = [a, b, c].map(|v| v + 1);
+                writeln!(obj, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}").unwrap();
+            }
+        }
+
+        Ok(obj.into_bytes())
+    }
+}
+
+/// Parse a number on a line of an OBJ file.
+fn parse_obj_number(token: Option<&str>) -> ErrorMessageResult<f32> {
+    let token = token.unwrap_or_default();
+    token.parse().map_err(|_| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_obj_invalid_number"), token=token)))
+}
+
+/// Get the 0-based index of an OBJ index, which is 1-based or relative to the end if negative.
+fn resolve_obj_index(token: &str, count: usize) -> ErrorMessageResult<usize> {
+    let index: isize = token.parse().map_err(|_| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_obj_invalid_number"), token=token)))?;
+    let resolved = if index < 0 { count as isize + index } else { index - 1 };
+    if resolved < 0 || resolved as usize >= count {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_obj_invalid_index"), index=index)));
+    }
+    Ok(resolved as usize)
+}
+
+impl JMS {
+    /// Read a Wavefront OBJ file.
+    ///
+    /// Objects and groups are meshes, which become regions and can also set the permutation like the meshes of glTF
+    /// files (see [`JMS::from_gltf`]). Materials are referenced by name with `usemtl`, and polygons are triangulated as
+    /// fans. OBJ files cannot store nodes, so everything is attached to a single node.
+    ///
+    /// Each JMS is validated and optimized. Returns an [`Err`] if the file could not be read.
+    pub fn from_obj(bytes: &[u8]) -> ErrorMessageResult<Vec<ImportedJMS>> {
+        let string = std::str::from_utf8(bytes).map_err(|_| ErrorMessage::StaticString(get_compiled_string!("engine.types.error_string_not_valid_utf8")))?;
+
+        let mut positions: Vec<Point3D> = Vec::new();
+        let mut texture_coordinates: Vec<Point3D> = Vec::new();
+        let mut normals: Vec<Vector3D> = Vec::new();
+        let mut mesh_name = UNNAMED.to_owned();
+        let mut material: Option<Material> = None;
+        let mut importer = JMSImporter::new(0, vec![default_node()]);
+
+        for (line_index, line) in string.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut tokens = line.split_whitespace();
+            let keyword = tokens.next();
+            let rest = || line.split_whitespace().skip(1).collect::<Vec<&str>>().join(" ");
+
+            (|| -> ErrorMessageResult<()> {
+                match keyword {
+                    Some("v") => {
+                        let position = [parse_obj_number(tokens.next())?, parse_obj_number(tokens.next())?, parse_obj_number(tokens.next())?];
+                        positions.push(position_from_gltf(position));
+                    },
+                    Some("vt") => {
+                        let u = parse_obj_number(tokens.next())?;
+                        let v = match tokens.next() {
+                            Some(v) => parse_obj_number(Some(v))?,
+                            None => 0.0
+                        };
+                        texture_coordinates.push(Point3D { x: u, y: 1.0 - v, z: 0.0 });
+                    },
+                    Some("vn") => {
+                        let normal = [parse_obj_number(tokens.next())?, parse_obj_number(tokens.next())?, parse_obj_number(tokens.next())?];
+                        normals.push(normal_from_gltf(normal).normalize());
+                    },
+                    Some("o") | Some("g") => {
+                        let name = rest();
+                        mesh_name = if name.is_empty() { UNNAMED.to_owned() } else { name };
+                    },
+                    Some("usemtl") => {
+                        material = Some(Material { name: rest(), tif_path: "<none>".to_owned() });
+                    },
+                    Some("f") => {
+                        // Corners are position/texture coordinates/normal, where only the position is required.
+                        let mut corners = Vec::new();
+                        let mut has_normals = true;
+                        for corner in tokens {
+                            let mut indices = corner.split('/');
+                            let position = positions[resolve_obj_index(indices.next().unwrap_or_default(), positions.len())?];
+                            let texture_coordinates = match indices.next().filter(|t| !t.is_empty()) {
+                                Some(t) => texture_coordinates[resolve_obj_index(t, texture_coordinates.len())?],
+                                None => Point3D::default()
+                            };
+                            let normal = match indices.next().filter(|n| !n.is_empty()) {
+                                Some(n) => normals[resolve_obj_index(n, normals.len())?],
+                                None => {
+                                    has_normals = false;
+                                    Vector3D::default()
+                                }
+                            };
+                            corners.push(Vertex { node0: Some(0), position, normal, node1: None, node1_weight: 0.0, texture_coordinates });
+                        }
+
+                        for i in 2..corners.len() {
+                            let mut triangle = [corners[0], corners[i - 1], corners[i]];
+                            if !has_normals {
+                                let normal = flat_normal(&triangle.map(|v| v.position));
+                                triangle.iter_mut().for_each(|v| v.normal = normal);
+                            }
+                            importer.add_triangle(&mesh_name, material.as_ref(), triangle)?;
+                        }
+                    },
+                    _ => ()
+                }
+                Ok(())
+            })().map_err(|e| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.jms.error_could_not_parse_obj"), line=line_index + 1, error=e)))?;
+        }
+
+        importer.finish()
+    }
+}
@@ -32,3 +32,711 @@ pub fn test_parsing() {
     assert_eq!(24, test_cube.vertices.len());
     assert_eq!(12, test_cube.triangles.len());
 }
+
+/// Read the triangles back out of a triangle strip, skipping degenerate triangles.
+fn read_triangle_strip(strip: &[u16]) -> Vec<(u16, u16, u16)> {
+    let mut triangles = Vec::new();
+    for t in 0..strip.len().saturating_sub(2) {
+        let (a, b, c) = match t % 2 {
+            0 => (strip[t], strip[t + 2], strip[t + 1]),
+            _ => (strip[t], strip[t + 1], strip[t + 2])
+        };
+        if a != b && b != c && a != c {
+            triangles.push(rotate_triangle((a, b, c)));
+        }
+    }
+    triangles
+}
+
+/// Rotate the triangle so the lowest index is first without changing the winding order.
+fn rotate_triangle(t: (u16, u16, u16)) -> (u16, u16, u16) {
+    let min = t.0.min(t.1).min(t.2);
+    if t.0 == min { t } else if t.1 == min { (t.1, t.2, t.0) } else { (t.2, t.0, t.1) }
+}
+
+#[test]
+pub fn test_triangle_strip() {
+    let mut test_cube = JMS::parse_str(include_str!("test_cube.jms")).unwrap();
+    test_cube.optimize();
+
+    // Every triangle should be in the strip exactly once with the same winding order.
+    let triangles: Vec<(u16, u16, u16)> = test_cube.triangles.iter().map(|t| (t.vertices.0 as u16, t.vertices.1 as u16, t.vertices.2 as u16)).collect();
+    let mut expected: Vec<(u16, u16, u16)> = triangles.iter().map(|t| rotate_triangle(*t)).collect();
+    let mut actual = read_triangle_strip(&make_triangle_strip(&triangles));
+    expected.sort();
+    actual.sort();
+    assert_eq!(expected, actual);
+}
+
+#[test]
+pub fn test_build_gbxmodel() {
+    use crate::engines::h1::{TagGroup, TagReference};
+
+    assert_eq!(("base", ModelLevelOfDetail::SuperHigh), ModelLevelOfDetail::split_file_name("base"));
+    assert_eq!(("base", ModelLevelOfDetail::SuperHigh), ModelLevelOfDetail::split_file_name("base superhigh"));
+    assert_eq!(("damaged", ModelLevelOfDetail::Low), ModelLevelOfDetail::split_file_name("damaged low"));
+    assert_eq!(("base", ModelLevelOfDetail::SuperLow), ModelLevelOfDetail::split_file_name("base superlow"));
+
+    let test_cube = JMS::parse_str(include_str!("test_cube.jms")).unwrap();
+    let mut low_cube = test_cube.clone();
+    low_cube.triangles.truncate(2);
+    let jms_files = [
+        ModelPermutationJMS { permutation: "base".to_owned(), lod: ModelLevelOfDetail::SuperHigh, jms: test_cube.clone() },
+        ModelPermutationJMS { permutation: "base".to_owned(), lod: ModelLevelOfDetail::Low, jms: low_cube }
+    ];
+
+    // Materials are looked up by name, and a trailing number is the shader permutation.
+    let sky = TagReference::from_path_and_group("shaders\\+sky", TagGroup::ShaderModel).unwrap();
+    let model = build_gbxmodel(&jms_files, None, |name| Ok(if name == "+sky" { Some(sky.clone()) } else { None })).unwrap();
+
+    assert_eq!(1, model.nodes.blocks.len());
+    assert_eq!("frame", model.nodes[0].name.to_str());
+    assert_eq!(1, model.shaders.blocks.len());
+    assert_eq!(sky, model.shaders[0].shader);
+    assert_eq!(None, model.shaders[0].permutation);
+
+    assert_eq!(1, model.regions.blocks.len());
+    assert_eq!("unnamed", model.regions[0].name.to_str());
+    let permutation = &model.regions[0].permutations[0];
+    assert_eq!("base", permutation.name.to_str());
+
+    // Missing levels of detail use the next higher level of detail.
+    assert_eq!(2, model.geometries.blocks.len());
+    assert_eq!((Some(0), Some(0), Some(0), Some(1), Some(1)), (permutation.super_high, permutation.high, permutation.medium, permutation.low, permutation.super_low));
+
+    // The cube's vertices are deduped, and the triangles are all there.
+    let part = &model.geometries[0].parts[0].base_struct;
+    assert_eq!(24, part.uncompressed_vertices.blocks.len());
+    let strip: Vec<u16> = part.triangles.blocks.iter().flat_map(|t| [t.vertex0_index, t.vertex1_index, t.vertex2_index]).flatten().collect();
+    assert_eq!(12, read_triangle_strip(&strip).len());
+    for v in &part.uncompressed_vertices {
+        assert!(v.tangent.is_normalized());
+        assert!(v.binormal.is_normalized());
+    }
+
+    // Trailing numbers are shader permutations.
+    let mut permutation_cube = test_cube.clone();
+    permutation_cube.materials[0].name = "+sky2".to_owned();
+    let jms_files = [ModelPermutationJMS { permutation: "base".to_owned(), lod: ModelLevelOfDetail::SuperHigh, jms: permutation_cube }];
+    let model = build_gbxmodel(&jms_files, Some(&model), |name| Ok(if name == "+sky" { Some(sky.clone()) } else { None })).unwrap();
+    assert_eq!(Some(2), model.shaders[0].permutation);
+    assert_eq!(1, model.geometries.blocks.len());
+
+    // Missing shaders are errors.
+    assert!(build_gbxmodel(&jms_files, None, |_| Ok(None)).is_err());
+
+    // Markers come from the highest level of detail of each permutation, even if it isn't superhigh.
+    let mut marker_cube = test_cube.clone();
+    marker_cube.markers.push(Marker { name: "primary trigger".to_owned(), node: Some(0), ..Default::default() });
+    let mut low_marker_cube = marker_cube.clone();
+    low_marker_cube.markers[0].name = "low trigger".to_owned();
+    let jms_files = [
+        ModelPermutationJMS { permutation: "base".to_owned(), lod: ModelLevelOfDetail::SuperHigh, jms: marker_cube.clone() },
+        ModelPermutationJMS { permutation: "base".to_owned(), lod: ModelLevelOfDetail::Low, jms: low_marker_cube },
+        ModelPermutationJMS { permutation: "damaged".to_owned(), lod: ModelLevelOfDetail::Medium, jms: marker_cube }
+    ];
+    let model = build_gbxmodel(&jms_files, None, |name| Ok(if name == "+sky" { Some(sky.clone()) } else { None })).unwrap();
+    for permutation in &model.regions[0].permutations {
+        assert_eq!(1, permutation.markers.blocks.len());
+        assert_eq!("primary trigger", permutation.markers[0].name.to_str());
+    }
+}
+
+/// Find the 3D BSP child that contains the point.
+fn find_bsp3d_child(bsp: &crate::engines::h1::definitions::ModelCollisionGeometryBSP, point: &crate::types::Point3D) -> u32 {
+    let mut child = 0u32;
+    while child & 0x80000000 == 0 {
+        let node = &bsp.bsp3d_nodes[child as usize];
+        child = if point.distance_from_plane(&bsp.planes[node.plane as usize].plane) >= 0.0 { node.front_child } else { node.back_child };
+    }
+    child
+}
+
+#[test]
+pub fn test_build_model_collision_geometry() {
+    use crate::types::{Point3D, Vector3D};
+
+    let test_cube = JMS::parse_str(include_str!("test_cube.jms")).unwrap();
+    let jms_files = [CollisionPermutationJMS { permutation: "base".to_owned(), jms: test_cube.clone() }];
+    let collision = build_model_collision_geometry(&jms_files, None).unwrap();
+
+    assert_eq!(1, collision.nodes.blocks.len());
+    assert_eq!("frame", collision.nodes[0].name.to_str());
+    assert_eq!(Some(0), collision.nodes[0].region);
+    assert_eq!(1, collision.materials.blocks.len());
+    assert_eq!("+sky", collision.materials[0].name.to_str());
+    assert_eq!(1, collision.regions.blocks.len());
+    assert_eq!("unnamed", collision.regions[0].name.to_str());
+    assert_eq!("base", collision.regions[0].permutations[0].name.to_str());
+
+    // The cube's vertices are welded, and every edge is shared by two surfaces.
+    let bsp = &collision.nodes[0].bsps[0];
+    assert_eq!(8, bsp.vertices.blocks.len());
+    assert_eq!(18, bsp.edges.blocks.len());
+    assert_eq!(12, bsp.surfaces.blocks.len());
+    assert_eq!(6, bsp.planes.blocks.len());
+
+    for (s, surface) in bsp.surfaces.blocks.iter().enumerate() {
+        // Planes face outward.
+        let triangle = &test_cube.triangles[s];
+        let normal = test_cube.vertices[triangle.vertices.0 as usize].normal;
+        let plane = bsp.planes[surface.plane as usize].plane;
+        let difference = plane.vector.sub_components(&normal.into_floats());
+        assert!(difference.x.abs() < 0.001 && difference.y.abs() < 0.001 && difference.z.abs() < 0.001);
+
+        // Going around the edges of the surface gets its three vertices.
+        let mut edge = surface.first_edge;
+        let mut points = Vec::new();
+        for _ in 0..3 {
+            let e = &bsp.edges[edge as usize];
+            if e.left_surface as usize == s {
+                points.push(e.start_vertex);
+                edge = e.forward_edge;
+            }
+            else {
+                assert_eq!(s, e.right_surface as usize);
+                points.push(e.end_vertex);
+                edge = e.reverse_edge;
+            }
+        }
+        assert_eq!(surface.first_edge, edge);
+        for (point, vertex) in points.iter().zip([triangle.vertices.0, triangle.vertices.1, triangle.vertices.2]) {
+            assert_eq!(Point3D::from(Vector3D::from(test_cube.vertices[vertex as usize].position)), bsp.vertices[*point as usize].point);
+        }
+    }
+
+    // Inside of the cube is solid, and outside of it is a leaf.
+    assert_eq!(0xFFFFFFFF, find_bsp3d_child(bsp, &Point3D { x: 0.0, y: 0.0, z: 0.0 }));
+    for point in [Point3D { x: 0.05, y: 0.0, z: 0.0 }, Point3D { x: 0.0, y: -0.05, z: 0.0 }, Point3D { x: 0.0, y: 0.0, z: 0.05 }] {
+        let leaf = find_bsp3d_child(bsp, &point);
+        assert_ne!(0xFFFFFFFF, leaf);
+        assert!(((leaf & 0x7FFFFFFF) as usize) < bsp.leaves.blocks.len());
+    }
+
+    // Open edges are errors unless the surface is two-sided.
+    let mut open_cube = test_cube.clone();
+    open_cube.triangles.pop();
+    assert!(build_model_collision_geometry(&[CollisionPermutationJMS { permutation: "base".to_owned(), jms: open_cube.clone() }], None).is_err());
+    open_cube.materials[0].name = "+sky%".to_owned();
+    let collision = build_model_collision_geometry(&[CollisionPermutationJMS { permutation: "base".to_owned(), jms: open_cube }], Some(&collision)).unwrap();
+    assert_eq!("+sky", collision.materials[0].name.to_str());
+    assert!(collision.nodes[0].bsps[0].surfaces[0].flags.two_sided);
+
+    // Two-sided surfaces can be collided with from either side, so the space behind them isn't solid.
+    let mut fence = test_cube.clone();
+    fence.vertices.clear();
+    fence.triangles.clear();
+    fence.materials[0].name = "+sky%".to_owned();
+    add_quad(&mut fence, [[0.0, -1.0, -1.0], [0.0, 1.0, -1.0], [0.0, 1.0, 1.0], [0.0, -1.0, 1.0]], [1.0, 0.0, 0.0], 0);
+    let collision = build_model_collision_geometry(&[CollisionPermutationJMS { permutation: "base".to_owned(), jms: fence }], None).unwrap();
+    let bsp = &collision.nodes[0].bsps[0];
+    for x in [0.5, -0.5] {
+        let leaf = find_bsp3d_child(bsp, &Point3D { x, y: 0.0, z: 0.0 });
+        assert_ne!(0xFFFFFFFF, leaf);
+        let leaf = &bsp.leaves[(leaf & 0x7FFFFFFF) as usize];
+        assert!(leaf.flags.contains_double_sided_surfaces);
+        assert_eq!(1, leaf.bsp2d_reference_count);
+    }
+
+    // So are degenerate triangles.
+    let mut degenerate_cube = test_cube.clone();
+    degenerate_cube.triangles[0].vertices.1 = degenerate_cube.triangles[0].vertices.0;
+    assert!(build_model_collision_geometry(&[CollisionPermutationJMS { permutation: "base".to_owned(), jms: degenerate_cube }], None).is_err());
+
+    // And overlapping triangles on the same plane.
+    let mut overlapping = test_cube.clone();
+    overlapping.vertices.clear();
+    overlapping.triangles.clear();
+    overlapping.materials[0].name = "+sky%".to_owned();
+    add_quad(&mut overlapping, [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]], [0.0, 0.0, 1.0], 0);
+    add_quad(&mut overlapping, [[0.5, 0.5, 0.0], [1.5, 0.5, 0.0], [1.5, 1.5, 0.0], [0.5, 1.5, 0.0]], [0.0, 0.0, 1.0], 0);
+    assert!(build_model_collision_geometry(&[CollisionPermutationJMS { permutation: "base".to_owned(), jms: overlapping }], None).is_err());
+
+    // Pathfinding spheres that are in more than one permutation are only added once.
+    let mut pathfinder_cube = test_cube.clone();
+    pathfinder_cube.markers.push(Marker { name: "pathfinder".to_owned(), node: Some(0), radius: 0.5, ..Default::default() });
+    let mut other_pathfinder_cube = pathfinder_cube.clone();
+    other_pathfinder_cube.markers.push(Marker { name: "pathfinder".to_owned(), node: Some(0), position: Point3D { x: 0.25, y: 0.0, z: 0.0 }, radius: 0.5, ..Default::default() });
+    let jms_files = [
+        CollisionPermutationJMS { permutation: "base".to_owned(), jms: pathfinder_cube },
+        CollisionPermutationJMS { permutation: "damaged".to_owned(), jms: other_pathfinder_cube }
+    ];
+    let collision = build_model_collision_geometry(&jms_files, None).unwrap();
+    assert_eq!(2, collision.pathfinding_spheres.blocks.len());
+}
+
+/// Add a quad to the JMS facing the normal, with its corners going around it.
+fn add_quad(jms: &mut JMS, corners: [[f32; 3]; 4], normal: [f32; 3], material: u16) {
+    use crate::types::{Point3D, Vector3D};
+
+    let normal = Vector3D { x: normal[0], y: normal[1], z: normal[2] };
+    let mut points = corners.map(|c| Point3D { x: c[0], y: c[1], z: c[2] });
+    let (a, b, c) = (Vector3D::from(points[0]), Vector3D::from(points[1]), Vector3D::from(points[2]));
+    let cross = super::model::cross(&b.sub_components(&a.into_floats()), &c.sub_components(&a.into_floats()));
+    if super::model::dot(&cross, &normal) < 0.0 {
+        points.reverse();
+    }
+
+    let first = jms.vertices.len() as u32;
+    for position in points {
+        jms.vertices.push(Vertex { node0: Some(0), position, normal, ..Default::default() });
+    }
+    for (b, c) in [(1, 2), (2, 3)] {
+        jms.triangles.push(Triangle { region: Some(0), shader: Some(material), vertices: (first, first + b, first + c) });
+    }
+}
+
+#[test]
+pub fn test_build_scenario_structure_bsp() {
+    use crate::engines::h1::{TagGroup, TagReference};
+    use crate::types::Point3D;
+
+    // Turn the cube inside out to make a sealed room.
+    let mut room = JMS::parse_str(include_str!("test_cube.jms")).unwrap();
+    for t in &mut room.triangles {
+        t.vertices = (t.vertices.0, t.vertices.2, t.vertices.1);
+    }
+    for v in &mut room.vertices {
+        v.normal = v.normal * -1.0;
+    }
+    room.materials[0].name = "wall".to_owned();
+
+    let wall = TagReference::from_path_and_group("shaders\\wall", TagGroup::ShaderEnvironment).unwrap();
+    let resolve_shader = |name: &str| Ok(if name == "wall" { Some(wall.clone()) } else { None });
+    let bsp = build_scenario_structure_bsp(&room, None, resolve_shader).unwrap();
+
+    // Inside the room is empty, and outside is solid.
+    assert_eq!(1, bsp.collision_bsp.blocks.len());
+    let collision = &bsp.collision_bsp[0];
+    assert_eq!(12, collision.surfaces.blocks.len());
+    assert_eq!(6, collision.planes.blocks.len());
+    assert_ne!(0xFFFFFFFF, find_bsp3d_child(collision, &Point3D { x: 0.0, y: 0.0, z: 0.0 }));
+    assert_eq!(0xFFFFFFFF, find_bsp3d_child(collision, &Point3D { x: 0.05, y: 0.0, z: 0.0 }));
+    assert_eq!(1, bsp.collision_materials.blocks.len());
+    assert_eq!(wall, bsp.collision_materials[0].shader);
+
+    // All of the walls are rendered with the same material.
+    assert_eq!(1, bsp.lightmaps.blocks.len());
+    assert_eq!(None, bsp.lightmaps[0].bitmap);
+    let material = &bsp.lightmaps[0].materials[0];
+    assert_eq!(wall, material.shader);
+    assert_eq!(12, material.surface_count);
+    assert_eq!(12, bsp.surfaces.blocks.len());
+    assert_eq!(24, material.rendered_vertices_count);
+    assert_eq!(24 * 56, material.uncompressed_vertices.len());
+    assert_eq!(24 * 32, material.compressed_vertices.len());
+
+    // One cluster can see itself, and every leaf is in it.
+    assert_eq!(1, bsp.clusters.blocks.len());
+    assert_eq!(12, bsp.clusters[0].surface_indices.blocks.len());
+    assert_eq!(None, bsp.clusters[0].sky);
+    assert_eq!(vec![1, 0, 0, 0], bsp.cluster_data);
+    assert!(bsp.leaves.blocks.iter().all(|l| l.cluster == Some(0)));
+    assert_eq!(12, bsp.leaf_surfaces.blocks.len());
+
+    // Sky is collidable but not rendered.
+    let mut sky_room = room.clone();
+    sky_room.materials[0].name = "+sky1".to_owned();
+    let bsp = build_scenario_structure_bsp(&sky_room, Some(&bsp), resolve_shader).unwrap();
+    assert_eq!(12, bsp.collision_bsp[0].surfaces.blocks.len());
+    assert!(bsp.lightmaps.blocks.is_empty());
+    assert_eq!(Some(1), bsp.clusters[0].sky);
+
+    // A two-sided surface in the room has empty space on both sides of it, and both sides are in the room's cluster.
+    let mut fenced_room = room.clone();
+    fenced_room.materials.push(Material { name: "wall%".to_owned(), tif_path: String::new() });
+    add_quad(&mut fenced_room, [[0.0, -0.01, -0.01], [0.0, 0.01, -0.01], [0.0, 0.01, 0.01], [0.0, -0.01, 0.01]], [1.0, 0.0, 0.0], 1);
+    let bsp = build_scenario_structure_bsp(&fenced_room, None, resolve_shader).unwrap();
+    let collision = &bsp.collision_bsp[0];
+    assert_eq!(14, collision.surfaces.blocks.len());
+    assert_eq!(1, bsp.clusters.blocks.len());
+    let front = find_bsp3d_child(collision, &Point3D { x: 0.005, y: 0.0, z: 0.0 });
+    let back = find_bsp3d_child(collision, &Point3D { x: -0.005, y: 0.0, z: 0.0 });
+    assert_ne!(front, back);
+    for leaf in [front, back] {
+        assert_ne!(0xFFFFFFFF, leaf);
+        let leaf = (leaf & 0x7FFFFFFF) as usize;
+        assert!(collision.leaves[leaf].flags.contains_double_sided_surfaces);
+        assert_eq!(Some(0), bsp.leaves[leaf].cluster);
+    }
+    assert_eq!(0xFFFFFFFF, find_bsp3d_child(collision, &Point3D { x: 0.05, y: 0.0, z: 0.0 }));
+    assert_eq!(0xFFFFFFFF, find_bsp3d_child(collision, &Point3D { x: -0.05, y: 0.0, z: 0.0 }));
+
+    // Leaks and degenerate triangles are errors.
+    let mut leaking_room = room.clone();
+    leaking_room.triangles.pop();
+    assert!(build_scenario_structure_bsp(&leaking_room, None, resolve_shader).is_err());
+    let mut degenerate_room = room.clone();
+    degenerate_room.triangles[0].vertices.1 = degenerate_room.triangles[0].vertices.0;
+    assert!(build_scenario_structure_bsp(&degenerate_room, None, resolve_shader).is_err());
+
+    // Two rooms split by a portal are two clusters.
+    let mut rooms = room.clone();
+    rooms.vertices.clear();
+    rooms.triangles.clear();
+    rooms.materials.push(Material { name: "+portal".to_owned(), tif_path: String::new() });
+    add_quad(&mut rooms, [[-0.02, -0.01, -0.01], [-0.02, 0.01, -0.01], [-0.02, 0.01, 0.01], [-0.02, -0.01, 0.01]], [1.0, 0.0, 0.0], 0);
+    add_quad(&mut rooms, [[0.02, -0.01, -0.01], [0.02, 0.01, -0.01], [0.02, 0.01, 0.01], [0.02, -0.01, 0.01]], [-1.0, 0.0, 0.0], 0);
+    for (x0, x1) in [(-0.02, 0.0), (0.0, 0.02)] {
+        add_quad(&mut rooms, [[x0, -0.01, -0.01], [x1, -0.01, -0.01], [x1, -0.01, 0.01], [x0, -0.01, 0.01]], [0.0, 1.0, 0.0], 0);
+        add_quad(&mut rooms, [[x0, 0.01, -0.01], [x1, 0.01, -0.01], [x1, 0.01, 0.01], [x0, 0.01, 0.01]], [0.0, -1.0, 0.0], 0);
+        add_quad(&mut rooms, [[x0, -0.01, -0.01], [x1, -0.01, -0.01], [x1, 0.01, -0.01], [x0, 0.01, -0.01]], [0.0, 0.0, 1.0], 0);
+        add_quad(&mut rooms, [[x0, -0.01, 0.01], [x1, -0.01, 0.01], [x1, 0.01, 0.01], [x0, 0.01, 0.01]], [0.0, 0.0, -1.0], 0);
+    }
+    add_quad(&mut rooms, [[0.0, -0.01, -0.01], [0.0, 0.01, -0.01], [0.0, 0.01, 0.01], [0.0, -0.01, 0.01]], [1.0, 0.0, 0.0], 1);
+
+    let bsp = build_scenario_structure_bsp(&rooms, None, resolve_shader).unwrap();
+    assert_eq!(2, bsp.clusters.blocks.len());
+    assert_eq!(1, bsp.cluster_portals.blocks.len());
+    let portal = &bsp.cluster_portals[0];
+    assert_eq!(4, portal.vertices.blocks.len());
+    assert_ne!(portal.front_cluster, portal.back_cluster);
+    for cluster in &bsp.clusters {
+        assert_eq!(1, cluster.portals.blocks.len());
+    }
+
+    // The rooms can see each other through the portal.
+    assert_eq!(vec![3, 0, 0, 0, 3, 0, 0, 0], bsp.cluster_data);
+
+    // The leaves on each side of the portal are in different clusters.
+    let collision = &bsp.collision_bsp[0];
+    let leaf_cluster = |x: f32| bsp.leaves[(find_bsp3d_child(collision, &Point3D { x, y: 0.0, z: 0.0 }) & 0x7FFFFFFF) as usize].cluster;
+    assert_eq!(portal.front_cluster, leaf_cluster(0.01));
+    assert_eq!(portal.back_cluster, leaf_cluster(-0.01));
+
+    // Portals that don't split the world are errors.
+    let mut sealed_rooms = rooms.clone();
+    sealed_rooms.triangles.truncate(sealed_rooms.triangles.len() - 2);
+    add_quad(&mut sealed_rooms, [[0.01, -0.01, -0.01], [0.01, 0.01, -0.01], [0.01, 0.01, 0.01], [0.01, -0.01, 0.01]], [1.0, 0.0, 0.0], 1);
+    assert!(build_scenario_structure_bsp(&sealed_rooms, None, resolve_shader).is_err());
+}
+
+#[test]
+pub fn test_cluster_visibility() {
+    use crate::types::{Plane3D, Point3D, Vector3D};
+
+    let portal = |front_cluster: usize, back_cluster: usize, x: f32, facing: f32, y: f32| ClusterPortal {
+        front_cluster,
+        back_cluster,
+        plane: Plane3D { vector: Vector3D { x: facing, y: 0.0, z: 0.0 }, d: x * facing },
+        points: [(y, -1.0), (y + 2.0, -1.0), (y + 2.0, 1.0), (y, 1.0)].map(|(y, z)| Point3D { x, y, z }).to_vec()
+    };
+
+    // Cluster 0 leads into cluster 1, which leads behind cluster 0 into cluster 2 and ahead of it into cluster 3.
+    let portals = [portal(1, 0, 0.0, 1.0, -1.0), portal(2, 1, -4.0, -1.0, 2.0), portal(3, 1, 2.0, 1.0, 2.0)];
+    let visibility = get_cluster_visibility(4, &portals);
+    assert_eq!(vec![0b1011, 0, 0, 0, 0b1111, 0, 0, 0, 0b1110, 0, 0, 0, 0b1111, 0, 0, 0], visibility);
+}
+
+const TEST_JMA: &str = "16392\r\n2\r\n30\r\n1\r\nunnamedActor\r\n2\r\n12345\r\nbip01 pelvis\r\n1\r\n-1\r\nbip01 spine\r\n-1\r\n-1\r\n\
+0\t0\t50\r\n0\t0\t0\t1\r\n1\r\n0\t0\t50\r\n0\t0\t0\t1\r\n1\r\n\
+100\t0\t50\r\n0\t0\t0.7071068\t0.7071068\r\n1\r\n0\t0\t50\r\n0\t0\t0\t1\r\n1\r\n";
+
+#[test]
+pub fn test_jma_parsing() {
+    // As with JMS files, positions are chosen so multiplying/dividing by 100 doesn't cause precision errors.
+    let jma = JMA::parse_str(TEST_JMA).unwrap();
+    assert_eq!(jma, JMA::parse_bytes(&jma.into_bytes()).unwrap());
+
+    assert_eq!(12345, jma.node_list_checksum);
+    assert_eq!(30, jma.frame_rate);
+    assert_eq!(vec!["unnamedactor".to_owned()], jma.actors);
+    assert_eq!(vec![
+                   AnimationNode { name: "bip01 pelvis".to_owned(), first_child: Some(1), sibling_node: None },
+                   AnimationNode { name: "bip01 spine".to_owned(), first_child: None, sibling_node: None }
+               ],
+               jma.nodes);
+    assert_eq!(vec![None, Some(0)], jma.get_parent_nodes().unwrap());
+
+    // Positions are in centimeters.
+    assert_eq!(2, jma.frames.len());
+    assert_eq!(Point3D { x: 1.0, y: 0.0, z: 0.5 }, jma.frames[1][0].position);
+    assert_eq!(Quaternion { x: 0.0, y: 0.0, z: 0.7071068, w: 0.7071068 }, jma.frames[1][0].rotation);
+
+    // Older files only have the names of the nodes.
+    let old_jma = JMA::parse_str(&TEST_JMA.replace("16392", "16390").replace("bip01 pelvis\r\n1\r\n-1", "bip01 pelvis").replace("bip01 spine\r\n-1\r\n-1", "bip01 spine")).unwrap();
+    assert_eq!(jma.frames, old_jma.frames);
+    assert_eq!(None, old_jma.nodes[0].first_child);
+
+    assert!(JMA::parse_str(&TEST_JMA.replace("16392", "8200")).is_err());
+    assert!(JMA::parse_str(&(TEST_JMA.to_owned() + "1\r\n")).is_err());
+}
+
+/// Make an animation with a root node and a child node.
+///
+/// The root node moves along the X and Y axes and turns, and the child node rotates back and forth, ending where it
+/// started.
+fn make_animation(name: &str, source_type: AnimationSourceType, frame_count: usize) -> ModelAnimationJMA {
+    let mut frames = Vec::new();
+    for i in 0..frame_count {
+        let angle = (i as f32 / (frame_count - 1) as f32 * std::f32::consts::TAU).sin() * 0.3;
+        frames.push(vec![
+            NodeTransform {
+                position: Point3D { x: 0.1 * i as f32, y: 0.05 * i as f32, z: 0.5 },
+                rotation: super::animation::yaw_rotation(0.2 * i as f32),
+                scale: 1.0
+            },
+            NodeTransform {
+                position: Point3D { x: 0.0, y: 0.0, z: 0.25 },
+                rotation: Quaternion { x: (angle * 0.5).sin(), y: 0.0, z: 0.0, w: (angle * 0.5).cos() },
+                scale: 1.0
+            }
+        ]);
+    }
+
+    ModelAnimationJMA {
+        name: name.to_owned(),
+        source_type,
+        jma: JMA {
+            node_list_checksum: 12345,
+            frame_rate: 30,
+            actors: vec!["unnamedactor".to_owned()],
+            nodes: vec![
+                AnimationNode { name: "root".to_owned(), first_child: Some(1), sibling_node: None },
+                AnimationNode { name: "child".to_owned(), first_child: None, sibling_node: None }
+            ],
+            frames
+        }
+    }
+}
+
+fn assert_transforms_eq(expected: &NodeTransform, actual: &NodeTransform) {
+    assert!(expected.position.distance_from_point_squared(&actual.position) < 0.0001 * 0.0001, "{expected:?} != {actual:?}");
+    let dot = expected.rotation.x * actual.rotation.x + expected.rotation.y * actual.rotation.y + expected.rotation.z * actual.rotation.z + expected.rotation.w * actual.rotation.w;
+    assert!(dot.abs() > 0.9999, "{expected:?} != {actual:?}");
+    assert_eq!(expected.scale, actual.scale);
+}
+
+#[test]
+pub fn test_build_model_animations() {
+    use crate::engines::h1::definitions::{AnimationFrameInfoType, AnimationType};
+
+    // The movement of the root node is moved into the frame info, and the last frame is only used for movement.
+    let turn = make_animation("walk turn", AnimationSourceType::DxDyDyaw, 9);
+    let tag = build_model_animations(std::slice::from_ref(&turn), None).unwrap();
+    assert_eq!(2, tag.nodes.blocks.len());
+    assert_eq!(Some(0), tag.nodes[1].parent_node_index);
+
+    let animation = &tag.animations[0];
+    assert_eq!("walk turn", animation.name.to_str());
+    assert_eq!(AnimationType::Base, animation._type);
+    assert_eq!(AnimationFrameInfoType::DxDyDyaw, animation.frame_info_type);
+    assert_eq!(8, animation.frame_count);
+    assert_eq!(8 * 3 * 4, animation.frame_info.len());
+    assert_eq!(12345, animation.node_list_checksum);
+
+    // Once the movement is removed, only the rotation of the child node changes.
+    assert_eq!(([0, 0], [0b10, 0], [0, 0]), (animation.node_transform_flag_data, animation.node_rotation_flag_data, animation.node_scale_flag_data));
+    assert_eq!(8, animation.frame_size);
+    assert_eq!(8 * 8, animation.frame_data.len());
+    assert_eq!(2 * (8 + 12 + 4) - 8, animation.default_data.len());
+
+    // Recovering it adds the movement back.
+    let recovered = recover_animation(&tag, 0).unwrap();
+    assert_eq!(AnimationSourceType::DxDyDyaw, recovered.source_type);
+    assert_eq!(turn.jma.nodes, recovered.jma.nodes);
+    assert_eq!(turn.jma.frames.len(), recovered.jma.frames.len());
+    for (expected, actual) in turn.jma.frames.iter().zip(&recovered.jma.frames) {
+        for (e, a) in expected.iter().zip(actual) {
+            assert_transforms_eq(e, a);
+        }
+    }
+
+    // Each type of animation can be recovered.
+    for source_type in AnimationSourceType::ALL {
+        let animation = make_animation("test", source_type, 5);
+        let tag = build_model_animations(&[animation], None).unwrap();
+        let recovered = recover_animation(&tag, 0).unwrap();
+        assert_eq!(source_type, recovered.source_type);
+        assert_eq!(Some(source_type), AnimationSourceType::from_extension(&source_type.extension().to_uppercase()));
+    }
+
+    // Overlays are relative to the first frame.
+    let aim = make_animation("aim", AnimationSourceType::Overlay, 5);
+    let tag = build_model_animations(&[aim], None).unwrap();
+    let recovered = recover_animation(&tag, 0).unwrap();
+    for t in &recovered.jma.frames[0] {
+        assert_transforms_eq(&NodeTransform::default(), t);
+    }
+
+    // Animations that don't move are stored entirely in the default data.
+    let mut idle = make_animation("stand idle", AnimationSourceType::Base, 3);
+    let rest = idle.jma.frames[0].clone();
+    for frame in &mut idle.jma.frames {
+        frame.clone_from(&rest);
+    }
+    let mut idle_permutation = make_animation("stand idle%1", AnimationSourceType::Base, 3);
+    idle_permutation.jma.frames = idle.jma.frames.clone();
+    let tag = build_model_animations(&[idle, idle_permutation], None).unwrap();
+    assert_eq!(0, tag.animations[0].frame_size);
+    assert!(tag.animations[0].frame_data.is_empty());
+    assert_eq!(2 * (8 + 12 + 4), tag.animations[0].default_data.len());
+
+    // Permutations are chained together.
+    assert_eq!(Some(1), tag.animations[0].next_animation);
+    assert_eq!(None, tag.animations[1].next_animation);
+
+    // Existing animations keep their order and settings.
+    let mut existing = tag.clone();
+    existing.animations[1].weight = 0.5;
+    let animations = [
+        make_animation("stand idle%1", AnimationSourceType::Base, 3),
+        make_animation("stand idle", AnimationSourceType::Base, 3),
+        make_animation("aim", AnimationSourceType::Overlay, 3)
+    ];
+    let tag = build_model_animations(&animations, Some(&existing)).unwrap();
+    let names: Vec<&str> = tag.animations.blocks.iter().map(|a| a.name.to_str()).collect();
+    assert_eq!(vec!["stand idle", "stand idle%1", "aim"], names);
+    assert_eq!(0.5, tag.animations[1].weight);
+    assert_eq!(1.0, tag.animations[2].weight);
+
+    // Removing an existing animation would break references to it.
+    assert!(build_model_animations(&animations[1..], Some(&existing)).is_err());
+
+    // Nodes must match, and there must be a frame after the last frame.
+    let mut mismatched = make_animation("mismatched", AnimationSourceType::Base, 3);
+    mismatched.jma.nodes[1].name = "other".to_owned();
+    assert!(build_model_animations(&[make_animation("test", AnimationSourceType::Base, 3), mismatched], None).is_err());
+    assert!(build_model_animations(&[make_animation("test", AnimationSourceType::Base, 1)], None).is_err());
+    assert!(build_model_animations(&[], None).is_err());
+}
+
+#[test]
+pub fn test_animation_yaw() {
+    for angle in [-3.0f32, -1.0, 0.0, 0.5, 3.0] {
+        assert!((super::animation::get_yaw(&super::animation::yaw_rotation(angle)) - angle).abs() < 0.0001);
+    }
+
+    // The yaw is the direction the X axis faces.
+    let forward = crate::types::Vector3D { x: 1.0, y: 0.0, z: 0.0 }.rotate_by_quaternion(&super::animation::yaw_rotation(0.5));
+    assert!((forward.y.atan2(forward.x) - 0.5).abs() < 0.0001);
+}
+
+#[test]
+pub fn test_gltf_export() {
+    let mut test_cube = JMS::parse_str(include_str!("test_cube.jms")).unwrap();
+    test_cube.markers.push(Marker { name: "center".to_owned(), region: Some(0), node: Some(0), rotation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 }, position: Point3D::default(), radius: 1.0 });
+    let glb = test_cube.into_gltf().unwrap();
+
+    // Header: magic, version, and total length.
+    let read_u32 = |at: usize| u32::from_le_bytes(glb[at..at + 4].try_into().unwrap());
+    assert_eq!(b"glTF", &glb[0..4]);
+    assert_eq!(2, read_u32(4));
+    assert_eq!(glb.len(), read_u32(8) as usize);
+
+    // The JSON chunk is first, followed by the binary chunk.
+    let json_length = read_u32(12) as usize;
+    assert_eq!(b"JSON", &glb[16..20]);
+    let bin_at = 20 + json_length;
+    assert_eq!(b"BIN\0", &glb[bin_at + 4..bin_at + 8]);
+    assert_eq!(glb.len(), bin_at + 8 + read_u32(bin_at) as usize);
+
+    let gltf: serde_json::Value = serde_json::from_slice(&glb[20..bin_at]).unwrap();
+
+    // The node is a joint of the skin, with the marker under it, followed by a mesh for the region.
+    let nodes = gltf["nodes"].as_array().unwrap();
+    assert_eq!(3, nodes.len());
+    assert_eq!("frame", nodes[0]["name"]);
+    assert_eq!(serde_json::json!([1]), nodes[0]["children"]);
+    assert_eq!("#center", nodes[1]["name"]);
+    assert_eq!(0, nodes[2]["skin"]);
+    assert_eq!(serde_json::json!([0]), gltf["skins"][0]["joints"]);
+
+    // Every triangle of the cube is in one primitive using the material.
+    let primitive = &gltf["meshes"][0]["primitives"][0];
+    assert_eq!(0, primitive["material"]);
+    assert_eq!("+sky", gltf["materials"][0]["name"]);
+    assert_eq!(12 * 3, gltf["accessors"][primitive["indices"].as_u64().unwrap() as usize]["count"]);
+    for attribute in ["POSITION", "NORMAL", "TEXCOORD_0", "JOINTS_0", "WEIGHTS_0"] {
+        assert_eq!(test_cube.vertices.len(), gltf["accessors"][primitive["attributes"][attribute].as_u64().unwrap() as usize]["count"]);
+    }
+}
+
+#[test]
+pub fn test_obj_export() {
+    let test_cube = JMS::parse_str(include_str!("test_cube.jms")).unwrap();
+    let obj = String::from_utf8(test_cube.into_obj().unwrap()).unwrap();
+    let count_lines = |prefix: &str| obj.lines().filter(|l| l.starts_with(prefix)).count();
+
+    assert_eq!(test_cube.vertices.len(), count_lines("v "));
+    assert_eq!(test_cube.vertices.len(), count_lines("vt "));
+    assert_eq!(test_cube.vertices.len(), count_lines("vn "));
+    assert_eq!(12, count_lines("f "));
+    assert_eq!(1, count_lines("o "));
+    assert_eq!(Some("usemtl +sky"), obj.lines().find(|l| l.starts_with("usemtl ")));
+}
+
+fn assert_points_eq(expected: &Point3D, actual: &Point3D) {
+    assert!(expected.distance_from_point_squared(actual) < 0.0000000001, "{expected:?} != {actual:?}");
+}
+
+/// Check that the geometry of an imported JMS matches, allowing for precision errors and reordered vertices.
+fn assert_imported_geometry_eq(expected: &JMS, actual: &JMS) {
+    assert_eq!(expected.triangles.len(), actual.triangles.len());
+    assert_eq!(expected.vertices.len(), actual.vertices.len());
+    for (e, a) in expected.triangles.iter().zip(&actual.triangles) {
+        for (ev, av) in [(e.vertices.0, a.vertices.0), (e.vertices.1, a.vertices.1), (e.vertices.2, a.vertices.2)] {
+            let (ev, av) = (&expected.vertices[ev as usize], &actual.vertices[av as usize]);
+            assert_points_eq(&ev.position, &av.position);
+            assert_points_eq(&Point3D::from(ev.normal), &Point3D::from(av.normal));
+            assert_points_eq(&ev.texture_coordinates, &av.texture_coordinates);
+        }
+    }
+}
+
+#[test]
+pub fn test_gltf_import() {
+    let mut test_cube = JMS::parse_str(include_str!("test_cube.jms")).unwrap();
+    test_cube.optimize();
+    test_cube.markers.push(Marker { name: "center".to_owned(), region: Some(0), node: Some(0), rotation: Quaternion { x: 0.0, y: 0.0, z: 0.7071068, w: 0.7071068 }, position: Point3D { x: 0.5, y: 0.25, z: 0.0 }, radius: 2.0 });
+
+    // Exporting and importing should get the same JMS back.
+    let imported = JMS::from_gltf(&test_cube.into_gltf().unwrap()).unwrap();
+    assert_eq!(1, imported.len());
+    assert_eq!(None, imported[0].permutation);
+
+    let jms = &imported[0].jms;
+    assert_eq!(test_cube.node_list_checksum, jms.node_list_checksum);
+    assert_eq!(test_cube.materials, jms.materials);
+    assert_eq!(test_cube.regions, jms.regions);
+    assert_eq!(1, jms.nodes.len());
+    assert_eq!("frame", jms.nodes[0].name);
+    assert_imported_geometry_eq(&test_cube, jms);
+
+    assert_eq!(1, jms.markers.len());
+    let marker = &jms.markers[0];
+    assert_eq!(("center", Some(0), Some(0), 2.0), (marker.name.as_str(), marker.region, marker.node, marker.radius));
+    assert_points_eq(&test_cube.markers[0].position, &marker.position);
+    assert!((marker.rotation.z - 0.7071068).abs() < 0.00001 && (marker.rotation.w - 0.7071068).abs() < 0.00001);
+
+    // Meshes named with a permutation are split into separate JMS files.
+    test_cube.regions[0].name = "damaged:body".to_owned();
+    let imported = JMS::from_gltf(&test_cube.into_gltf().unwrap()).unwrap();
+    assert_eq!(1, imported.len());
+    assert_eq!(Some("damaged"), imported[0].permutation.as_deref());
+    assert_eq!(vec![Region { name: "body".to_owned() }], imported[0].jms.regions);
+}
+
+#[test]
+pub fn test_obj_import() {
+    let mut test_cube = JMS::parse_str(include_str!("test_cube.jms")).unwrap();
+    test_cube.optimize();
+
+    let imported = JMS::from_obj(&test_cube.into_obj().unwrap()).unwrap();
+    assert_eq!(1, imported.len());
+    assert_eq!(None, imported[0].permutation);
+    assert_eq!(test_cube.materials, imported[0].jms.materials);
+    assert_eq!(test_cube.regions, imported[0].jms.regions);
+    assert_imported_geometry_eq(&test_cube, &imported[0].jms);
+
+    // Polygons are triangulated, and faces without normals get the normal of the face.
+    let imported = JMS::from_obj(b"v 0 0 0\nv 100 0 0\nv 100 100 0\nv 0 100 0\no damaged:body\nf 1 2 3 -1\n").unwrap();
+    let jms = &imported[0].jms;
+    assert_eq!(Some("damaged"), imported[0].permutation.as_deref());
+    assert_eq!(2, jms.triangles.len());
+    assert_eq!(4, jms.vertices.len());
+    assert_points_eq(&Point3D { x: 0.0, y: -1.0, z: 0.0 }, &Point3D::from(jms.vertices[0].normal));
+    assert_points_eq(&Point3D { x: 1.0, y: 0.0, z: 1.0 }, &jms.vertices[2].position);
+
+    assert!(JMS::from_obj(b"v 0 0 0\nf 1 2 3\n").is_err());
+}
@@ -9,6 +9,9 @@ pub use self::p8::*;
 mod tag_loading;
 pub use self::tag_loading::*;
 
+mod cache;
+pub use self::cache::*;
+
 pub mod jms;
 
 pub mod definitions;
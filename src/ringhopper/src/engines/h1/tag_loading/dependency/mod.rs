@@ -0,0 +1,51 @@
+#[cfg(test)]
+mod tests;
+
+use crate::types::*;
+use crate::types::tag::TagBlockFn;
+use crate::engines::h1::{TagGroup, TagReference};
+
+/// Reference to another tag from a field of a tag.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TagDependency {
+    /// Path of the field, formatted like [`tag_field_path`](crate::engines::h1::tag_field_path).
+    pub path: String,
+
+    /// Tag being referenced.
+    pub reference: TagReference,
+
+    /// Groups the field is allowed to reference, or empty if any group is allowed.
+    pub allowed_groups: &'static [TagGroup]
+}
+
+impl TagDependency {
+    /// Return `true` if the group of the reference is allowed by the field.
+    pub fn is_group_allowed(&self) -> bool {
+        self.allowed_groups.is_empty() || self.allowed_groups.contains(&self.reference.get_group())
+    }
+}
+
+/// Functions for getting the tags referenced by tag data.
+pub trait TagDependencies {
+    /// Add each non-null tag reference in the block to `dependencies`.
+    ///
+    /// `path` is the path of this block, and it is used as the prefix of each field's path.
+    fn get_dependencies_fields(&self, path: &str, dependencies: &mut Vec<TagDependency>);
+
+    /// Get all non-null tag references in the tag data in the order they appear in the definitions.
+    ///
+    /// The same tag may be referenced more than once.
+    fn get_dependencies(&self) -> Vec<TagDependency> {
+        let mut dependencies = Vec::new();
+        self.get_dependencies_fields("", &mut dependencies);
+        dependencies
+    }
+}
+
+impl<T: TagDependencies + TagBlockFn> TagDependencies for Reflexive<T> {
+    fn get_dependencies_fields(&self, path: &str, dependencies: &mut Vec<TagDependency>) {
+        for (i, block) in self.blocks.iter().enumerate() {
+            block.get_dependencies_fields(&format!("{path}[{i}]"), dependencies);
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use crate::engines::h1::definitions::*;
+use crate::engines::h1::{TagFileSerializeFn, TagGroup, TagReference};
+use super::TagDependencies;
+
+#[test]
+fn test_dependencies_h1() {
+    // Null references are skipped.
+    assert!(Weapon::default().get_dependencies().is_empty());
+
+    // Inherited fields come first, followed by blocks.
+    let mut weapon = Weapon::default();
+    weapon.base_struct.base_struct.model = TagReference::from_full_path("weapons\\pistol\\pistol.gbxmodel").unwrap();
+    weapon.triggers.blocks.resize(2, WeaponTrigger::default());
+    weapon.triggers.blocks[1].projectile = TagReference::from_full_path("weapons\\pistol\\bullet.projectile").unwrap();
+    weapon.triggers.blocks[1].charging_effect = TagReference::from_full_path("weapons\\pistol\\bullet.projectile").unwrap();
+
+    let dependencies = weapon.get_dependencies();
+    assert_eq!(3, dependencies.len());
+    assert_eq!("model", dependencies[0].path);
+    assert_eq!(&[TagGroup::GBXModel, TagGroup::Model], dependencies[0].allowed_groups);
+    assert!(dependencies[0].is_group_allowed());
+    assert_eq!("triggers[1].charging effect", dependencies[1].path);
+    assert!(!dependencies[1].is_group_allowed());
+    assert_eq!("triggers[1].projectile", dependencies[2].path);
+    assert_eq!(weapon.triggers.blocks[1].projectile, dependencies[2].reference);
+    assert!(dependencies[2].is_group_allowed());
+
+    // Tag collections can reference anything.
+    let mut collection = TagCollection::default();
+    collection.tags.blocks.push(TagCollectionTag { reference: TagReference::from_full_path("weapons\\pistol\\pistol.weapon").unwrap() });
+    let tag: Box<dyn TagFileSerializeFn> = Box::new(collection);
+    let dependencies = tag.get_dependencies();
+    assert_eq!(1, dependencies.len());
+    assert!(dependencies[0].allowed_groups.is_empty());
+    assert!(dependencies[0].is_group_allowed());
+}
@@ -1,2 +1,8 @@
 mod serialize;
 pub use self::serialize::*;
+
+mod validate;
+pub use self::validate::*;
+
+mod dependency;
+pub use self::dependency::*;
@@ -6,6 +6,7 @@ use crate::types::*;
 use crate::types::tag::TagGroupFn;
 use crate::engines::h1::types::{TagGroup, TagReference, Index};
 use crate::types::tag::TagBlockFn;
+use crate::engines::h1::{CacheTagWriter, CacheFile, TagValidate, TagDependencies};
 use ringhopper_proc::*;
 
 use std::any::Any;
@@ -759,12 +760,18 @@ impl<T: TagSerialize> ParsedTagFile<T> {
 }
 
 /// Functions for parsing and making tag file data for a specific tag group.
-pub trait TagFileSerializeFn: Any + TagBlockFn + TagSerialize {
+pub trait TagFileSerializeFn: Any + TagBlockFn + TagSerialize + TagValidate + TagDependencies {
     /// Deserialize the data into the tag struct.
     fn from_tag_file(data: &[u8]) -> ErrorMessageResult<ParsedTagFile<Self>> where Self: Sized;
 
     /// Serialize the tag struct into a tag file.
     fn into_tag_file(&self) -> ErrorMessageResult<Vec<u8>>;
+
+    /// Serialize the tag struct into cache tag data, returning the offset of the tag struct.
+    fn into_cache_tag(&self, writer: &mut CacheTagWriter) -> ErrorMessageResult<usize>;
+
+    /// Deserialize the tag struct from a cache file.
+    fn from_cache_tag(cache_file: &CacheFile, reference: &TagReference) -> ErrorMessageResult<Self> where Self: Sized;
 }
 
 impl TagSerialize for Index {
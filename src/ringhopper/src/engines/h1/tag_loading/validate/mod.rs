@@ -0,0 +1,43 @@
+#[cfg(test)]
+mod tests;
+
+use crate::error::*;
+use crate::types::*;
+use crate::types::tag::TagBlockFn;
+use crate::engines::h1::EngineTarget;
+
+/// Functions for checking tag data against the limits in the tag definitions.
+pub trait TagValidate {
+    /// Check each field against the limits in the tag definitions, adding an error to `errors` for each invalid value.
+    ///
+    /// `path` is the path of this block, and it is used as the prefix of each field's path in error messages. Block
+    /// array limits that differ between engines use the limits of `engine` if set, or the default limits otherwise.
+    fn validate_fields(&self, path: &str, engine: Option<&EngineTarget>, errors: &mut Vec<ErrorMessage>);
+
+    /// Check the tag data against the limits in the tag definitions, returning an error for each invalid value.
+    ///
+    /// See [`validate_fields`](TagValidate::validate_fields) for more information.
+    fn validate(&self, engine: Option<&EngineTarget>) -> Vec<ErrorMessage> {
+        let mut errors = Vec::new();
+        self.validate_fields("", engine, &mut errors);
+        errors
+    }
+}
+
+impl<T: TagValidate + TagBlockFn> TagValidate for Reflexive<T> {
+    fn validate_fields(&self, path: &str, engine: Option<&EngineTarget>, errors: &mut Vec<ErrorMessage>) {
+        for (i, block) in self.blocks.iter().enumerate() {
+            block.validate_fields(&format!("{path}[{i}]"), engine, errors);
+        }
+    }
+}
+
+/// Get the path of the field named `field` in the block at `path`.
+///
+/// Paths are formatted as `triggers[0].maximum rate of fire`.
+pub fn tag_field_path(path: &str, field: &str) -> String {
+    match path {
+        "" => field.to_owned(),
+        _ => format!("{path}.{field}")
+    }
+}
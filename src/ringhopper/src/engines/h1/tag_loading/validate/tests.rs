@@ -0,0 +1,55 @@
+use crate::engines::h1::definitions::*;
+use crate::engines::h1::{ALL_TARGETS, TagFileSerializeFn, TagReference};
+use crate::types::*;
+use super::TagValidate;
+
+fn errors_to_strings(errors: Vec<crate::error::ErrorMessage>) -> Vec<String> {
+    errors.iter().map(|e| e.to_string()).collect()
+}
+
+#[test]
+fn test_validate_h1() {
+    // Defaults are valid.
+    assert!(Weapon::default().validate(None).is_empty());
+    assert!(Scenario::default().validate(None).is_empty());
+
+    // Values and bounds
+    let mut weapon = Weapon::default();
+    weapon.heat_loss_rate = -1.0;
+    weapon.triggers.blocks.resize(3, WeaponTrigger::default());
+    weapon.triggers.blocks[1].maximum_rate_of_fire = Bounds { lower: 2.0, upper: 1.0 };
+    let errors = errors_to_strings(weapon.validate(None));
+    assert_eq!(3, errors.len());
+    assert!(errors[0].starts_with("heat loss rate is -1"));
+    assert!(errors[1].starts_with("triggers has 3 block(s)"));
+    assert!(errors[2].starts_with("triggers[1].maximum rate of fire has a lower bound"));
+
+    let mut variant = ActorVariant::default();
+    variant.drop_weapon_loaded = Bounds { lower: 0.5, upper: 1.5 };
+    let errors = errors_to_strings(variant.validate(None));
+    assert_eq!(1, errors.len());
+    assert!(errors[0].starts_with("drop weapon loaded.upper is 1.5"));
+
+    // Data
+    let mut text = HUDMessageText::default();
+    text.text_data = vec![0; 65537];
+    assert_eq!(1, text.validate(None).len());
+
+    // Null references and engine-specific limits
+    let mut scenario = Scenario::default();
+    scenario.scenery_palette.blocks.push(ScenarioSceneryPalette::default());
+    scenario.scripts.blocks.push(ScenarioScript::default());
+    scenario.scripts.blocks[0].parameters.blocks.push(ScenarioScriptParameter::default());
+    let errors = errors_to_strings(scenario.validate(None));
+    assert_eq!(2, errors.len());
+    assert!(errors.iter().any(|e| e.starts_with("scenery palette[0].name must be set")));
+    assert!(errors.iter().any(|e| e.starts_with("scripts[0].parameters has 1 block(s)")));
+
+    let mcc = ALL_TARGETS.iter().find(|e| e.shorthand == Some("mcc-cea")).unwrap();
+    scenario.scenery_palette.blocks[0].name = TagReference::from_full_path("scenery\\rocks\\boulder.scenery").unwrap();
+    assert!(scenario.validate(Some(mcc)).is_empty());
+
+    // Tags can be validated without knowing the group.
+    let tag: Box<dyn TagFileSerializeFn> = Box::new(weapon);
+    assert_eq!(3, tag.validate(None).len());
+}
@@ -0,0 +1,55 @@
+use crate::engines::h1::definitions::*;
+
+/// Number of characters in each character table of a font.
+const FONT_CHARACTER_TABLE_SIZE: usize = 256;
+
+/// Trait for generating the character tables of font tags.
+pub trait FontCharacterTableGenerator {
+    /// Generate the character tables used for looking up characters by their code point.
+    ///
+    /// Each table holds the characters sharing the same upper byte, and it is indexed by the lower byte. If a character
+    /// is present more than once, the first one is used.
+    fn generate_character_tables(&mut self);
+}
+
+impl FontCharacterTableGenerator for Font {
+    fn generate_character_tables(&mut self) {
+        let table_count = self.characters.blocks.iter().map(|c| (c.character as usize >> 8) + 1).max().unwrap_or(0);
+
+        let mut tables = vec![FontCharacterTables::default(); table_count];
+        for t in &mut tables {
+            t.character_table.blocks = vec![FontCharacterIndex { character_index: None }; FONT_CHARACTER_TABLE_SIZE];
+        }
+
+        for (i, c) in self.characters.blocks.iter().enumerate() {
+            let entry = &mut tables[c.character as usize >> 8].character_table.blocks[c.character as usize & 0xFF].character_index;
+            if entry.is_none() {
+                *entry = Some(i as u16);
+            }
+        }
+
+        self.character_tables.blocks = tables;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_character_tables() {
+        let mut font = Font::default();
+        for character in [0x41, 0x20, 0x101, 0x41] {
+            font.characters.blocks.push(FontCharacter { character, ..Default::default() });
+        }
+        font.generate_character_tables();
+
+        assert_eq!(2, font.character_tables.blocks.len());
+        let index = |c: usize| font.character_tables.blocks[c >> 8].character_table.blocks[c & 0xFF].character_index;
+        assert_eq!(Some(0), index(0x41));
+        assert_eq!(Some(1), index(0x20));
+        assert_eq!(Some(2), index(0x101));
+        assert_eq!(None, index(0x42));
+        assert_eq!(None, index(0x100));
+    }
+}
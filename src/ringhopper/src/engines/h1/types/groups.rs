@@ -348,4 +348,25 @@ impl TagGroup {
         *self == TagGroup::Item ||
         *self == TagGroup::Device
     }
+
+    /// Get the group this tag group inherits, or [`TagGroup::_None`] if it does not inherit a group.
+    pub fn supergroup(&self) -> TagGroup {
+        match *self {
+            TagGroup::Biped | TagGroup::Vehicle => TagGroup::Unit,
+            TagGroup::Weapon | TagGroup::Equipment | TagGroup::Garbage => TagGroup::Item,
+            TagGroup::DeviceMachine | TagGroup::DeviceControl | TagGroup::DeviceLightFixture => TagGroup::Device,
+            TagGroup::Unit | TagGroup::Item | TagGroup::Device |
+            TagGroup::Projectile | TagGroup::Scenery | TagGroup::Placeholder | TagGroup::SoundScenery => TagGroup::Object,
+            TagGroup::ShaderEnvironment |
+            TagGroup::ShaderModel |
+            TagGroup::ShaderTransparentChicago |
+            TagGroup::ShaderTransparentChicagoExtended |
+            TagGroup::ShaderTransparentGeneric |
+            TagGroup::ShaderTransparentGlass |
+            TagGroup::ShaderTransparentMeter |
+            TagGroup::ShaderTransparentPlasma |
+            TagGroup::ShaderTransparentWater => TagGroup::Shader,
+            _ => TagGroup::_None
+        }
+    }
 }
@@ -1,6 +1,9 @@
 
 use ringhopper_proc::*;
 
+mod font;
+pub use self::font::*;
+
 mod groups;
 pub use self::groups::*;
 
@@ -16,11 +19,20 @@ pub use self::scenario::*;
 use crate::error::*;
 use crate::engines::h1::tag_loading::TagSerialize;
 use crate::types::Reflexive;
-use crate::types::TagBlockFn;
+use crate::types::{TagBlockFn, TagFieldFn, TagFieldValue, FieldReference};
 
 /// Halo: CE specific [TagReference] type.
 pub type TagReference = crate::types::TagReference<TagGroup>;
 
+impl TagFieldFn for TagReference {
+    fn as_field_value(&self) -> TagFieldValue<'_> {
+        TagFieldValue::Value(FieldReference { field: self })
+    }
+    fn as_field_value_mut(&mut self) -> TagFieldValue<'_> {
+        TagFieldValue::MutableValue(FieldReference { field: self })
+    }
+}
+
 /// Tag ID union.
 pub type TagID = u32;
 
@@ -143,6 +155,15 @@ impl PartialEq for ScenarioScriptNodeValue {
         unsafe { self.unsigned_long_int == other.unsigned_long_int }
     }
 }
+impl TagFieldFn for ScenarioScriptNodeValue {
+    // Every member is a plain 32-bit or smaller integer, so the value can always be accessed as a 32-bit integer.
+    fn as_field_value(&self) -> TagFieldValue<'_> {
+        TagFieldValue::Value(FieldReference { field: unsafe { &self.unsigned_long_int } })
+    }
+    fn as_field_value_mut(&mut self) -> TagFieldValue<'_> {
+        TagFieldValue::MutableValue(FieldReference { field: unsafe { &mut self.unsigned_long_int } })
+    }
+}
 impl TagSerialize for ScenarioScriptNodeValue {
     fn tag_size() -> usize {
         4
@@ -45,6 +45,11 @@ pub trait ModelRepair {
     fn check_for_extraction_bugs(&self) -> ErrorMessageResult<()>;
 
     /// Repair extraction bugs in the model.
+    ///
+    /// Markers are moved back into their permutations, and missing uncompressed vertices are regenerated from the
+    /// compressed vertices.
+    ///
+    /// Return [`Err`] if a marker refers to a region or permutation that does not exist.
     fn repair_extraction_bugs(&mut self) -> ErrorMessageResult<()>;
 }
 
@@ -71,7 +76,38 @@ macro_rules! generate_repair_model_marker_code {
             }
 
             fn repair_extraction_bugs(&mut self) -> ErrorMessageResult<()> {
-                todo!()
+                // Move the markers back into the permutations they belong to. Every instance is checked first so the
+                // model is left unchanged if one of them can't be moved.
+                let mut permutation_markers = Vec::new();
+                for m in &self.markers {
+                    for i in &m.instances {
+                        let region = self.regions.try_get_with_index_nonnull(Some(i.region_index as u16))?;
+                        region.permutations.try_get_with_index_nonnull(Some(i.permutation_index as u16))?;
+                        permutation_markers.push((i.region_index as usize, i.permutation_index as usize, ModelRegionPermutationMarker {
+                            name: m.name,
+                            // Instances have 8-bit indices, so their null node index is 0xFF rather than 0xFFFF.
+                            node_index: if i.node_index == u8::MAX { None } else { Some(i.node_index as u16) },
+                            rotation: i.rotation,
+                            translation: i.translation
+                        }));
+                    }
+                }
+                self.markers.blocks.clear();
+                for (region, permutation, marker) in permutation_markers {
+                    self.regions[region].permutations[permutation].markers.blocks.push(marker);
+                }
+
+                // Regenerate any missing uncompressed vertices.
+                for g in &mut self.geometries {
+                    for p in &mut g.parts {
+                        let base_model_part = p.base_model_part_mut();
+                        if base_model_part.uncompressed_vertices.blocks.is_empty() {
+                            base_model_part.uncompressed_vertices.blocks = base_model_part.compressed_vertices.blocks.iter().map(ModelVertexUncompressed::decompress).collect();
+                        }
+                    }
+                }
+
+                Ok(())
             }
         }
     }
@@ -251,6 +287,8 @@ impl ModelCompression for ScenarioStructureBSPMaterialUncompressedRenderedVertex
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn test_float_compression() {
         assert_eq!(0, compress_float!(0.0, 16));
@@ -262,6 +300,50 @@ mod tests {
         assert_eq!(32767, compress_float!(1.0, 16));
         assert_eq!(1.0, decompress_float!(32767, 16));
     }
+
+    #[test]
+    fn test_repair_extraction_bugs() {
+        let mut model = GBXModel::default();
+        model.regions.blocks.push(ModelRegion::default());
+        model.regions[0].permutations.blocks.resize(2, ModelRegionPermutation::default());
+
+        let mut marker = ModelMarker::default();
+        marker.name = String32::from_str("primary trigger").unwrap();
+        marker.instances.blocks.push(ModelMarkerInstance { region_index: 0, permutation_index: 1, node_index: 2, translation: Point3D { x: 1.0, y: 2.0, z: 3.0 }, ..Default::default() });
+        marker.instances.blocks.push(ModelMarkerInstance { region_index: 0, permutation_index: 0, node_index: u8::MAX, ..Default::default() });
+        model.markers.blocks.push(marker);
+
+        let mut part = GBXModelGeometryPart::default();
+        let vertex = ModelVertexUncompressed { normal: Vector3D { x: 0.0, y: 0.0, z: 1.0 }, node0_index: Some(1), node1_index: None, node0_weight: 1.0, ..Default::default() };
+        part.base_struct.compressed_vertices.blocks.push(vertex.compress());
+        model.geometries.blocks.push(GBXModelGeometry::default());
+        model.geometries[0].parts.blocks.push(part);
+
+        assert!(model.check_for_extraction_bugs().is_err());
+        model.repair_extraction_bugs().unwrap();
+        assert!(model.check_for_extraction_bugs().is_ok());
+
+        assert!(model.markers.blocks.is_empty());
+        assert_eq!(None, model.regions[0].permutations[0].markers[0].node_index);
+        let repaired_marker = &model.regions[0].permutations[1].markers[0];
+        assert_eq!("primary trigger", repaired_marker.name.to_str());
+        assert_eq!(Some(2), repaired_marker.node_index);
+        assert_eq!(3.0, repaired_marker.translation.z);
+
+        let repaired_vertex = &model.geometries[0].parts[0].base_struct.uncompressed_vertices[0];
+        assert_eq!(Some(1), repaired_vertex.node0_index);
+        assert_eq!(1.0, repaired_vertex.normal.z);
+
+        // Markers referring to nonexistent permutations cannot be repaired.
+        let mut marker = ModelMarker::default();
+        marker.instances.blocks.push(ModelMarkerInstance { permutation_index: 2, ..Default::default() });
+        model.markers.blocks.push(marker);
+        assert!(model.repair_extraction_bugs().is_err());
+
+        // Nothing is changed if the repair fails.
+        assert_eq!(1, model.markers.blocks.len());
+        assert_eq!(1, model.regions[0].permutations[1].markers.blocks.len());
+    }
 }
 
 /// Regenerate all compressed vertices for a model.
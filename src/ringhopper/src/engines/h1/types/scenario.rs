@@ -441,3 +441,517 @@ impl ScriptCompiler for Scenario {
         Ok(script_data.get_warnings().to_owned())
     }
 }
+
+/// Trait for updating tag references in the scripts of scenario tags.
+pub trait ScriptReferenceFn {
+    /// Replace references to `from` with `to` in the script source files and compiled script data.
+    ///
+    /// Both references must be of the same group. If the scripts are compiled, the source files are only updated if the
+    /// compiled scripts refer to `from`, since paths in source files do not specify a group. References in the
+    /// `references` array are not replaced, as they are regular tag references. Return the number of replacements made.
+    fn replace_script_references(&mut self, from: &TagReference, to: &TagReference) -> ErrorMessageResult<usize>;
+}
+
+/// Script value types that refer to tags.
+pub(crate) const SCRIPT_TAG_VALUE_TYPES: [ScenarioScriptValueType; 8] = [
+    ScenarioScriptValueType::Sound,
+    ScenarioScriptValueType::Effect,
+    ScenarioScriptValueType::Damage,
+    ScenarioScriptValueType::LoopingSound,
+    ScenarioScriptValueType::AnimationGraph,
+    ScenarioScriptValueType::ActorVariant,
+    ScenarioScriptValueType::DamageEffect,
+    ScenarioScriptValueType::ObjectDefinition
+];
+
+/// Return true if a script value of the given type can refer to a tag of the given group.
+pub(crate) fn script_value_type_refers_to_group(value_type: ScenarioScriptValueType, group: TagGroup) -> bool {
+    match value_type {
+        ScenarioScriptValueType::Sound => group == TagGroup::Sound,
+        ScenarioScriptValueType::Effect => group == TagGroup::Effect,
+        ScenarioScriptValueType::Damage | ScenarioScriptValueType::DamageEffect => group == TagGroup::DamageEffect,
+        ScenarioScriptValueType::LoopingSound => group == TagGroup::SoundLooping,
+        ScenarioScriptValueType::AnimationGraph => group == TagGroup::ModelAnimations,
+        ScenarioScriptValueType::ActorVariant => group == TagGroup::ActorVariant,
+        ScenarioScriptValueType::ObjectDefinition => group.is_object(),
+        _ => false
+    }
+}
+
+/// Return true if the script token refers to the tag path, ignoring case and the type of slashes used.
+pub(crate) fn script_token_matches_path(token: &[u8], path: &str) -> bool {
+    token.len() == path.len() && token.iter().zip(path.bytes()).all(|(&a, b)| {
+        let a = if a == b'/' { b'\\' } else { a };
+        a.to_ascii_lowercase() == b
+    })
+}
+
+/// Replace tokens in script source data that refer to `from` with `to`, returning the number of replacements.
+fn replace_source_tokens(source: &[u8], from: &str, to: &str) -> (Vec<u8>, usize) {
+    let mut output = Vec::with_capacity(source.len());
+    let mut count = 0;
+    let mut i = 0;
+
+    while i < source.len() {
+        let c = source[i];
+
+        // Comments are copied as is. Block comments start with ;* and end with *;
+        if c == b';' {
+            let end = if source.get(i + 1) == Some(&b'*') {
+                source[i + 2..].windows(2).position(|w| w == b"*;").map(|p| i + 2 + p + 2).unwrap_or(source.len())
+            }
+            else {
+                source[i..].iter().position(|&c| c == b'\n').map(|p| i + p).unwrap_or(source.len())
+            };
+            output.extend_from_slice(&source[i..end]);
+            i = end;
+            continue;
+        }
+
+        // Whitespace and parenthesis separate tokens.
+        if c.is_ascii_whitespace() || c == b'(' || c == b')' {
+            output.push(c);
+            i += 1;
+            continue;
+        }
+
+        // Otherwise, it is a token, which can be quoted.
+        let (start, end, next) = if c == b'"' {
+            let end = source[i + 1..].iter().position(|&c| c == b'"').map(|p| i + 1 + p).unwrap_or(source.len());
+            (i + 1, end, (end + 1).min(source.len()))
+        }
+        else {
+            let end = source[i..].iter().position(|&c| c.is_ascii_whitespace() || c == b'(' || c == b')' || c == b';').map(|p| i + p).unwrap_or(source.len());
+            (i, end, end)
+        };
+
+        if script_token_matches_path(&source[start..end], from) {
+            output.extend_from_slice(&source[i..start]);
+            output.extend_from_slice(to.as_bytes());
+            output.extend_from_slice(&source[end..next]);
+            count += 1;
+        }
+        else {
+            output.extend_from_slice(&source[i..next]);
+        }
+        i = next;
+    }
+
+    (output, count)
+}
+
+/// Get the number of script nodes in the syntax data from the count in its table.
+///
+/// The syntax data usually has room for more nodes than are used, but not fewer, so a count that is too big is an error.
+fn get_script_node_count(syntax_data: &[u8]) -> ErrorMessageResult<usize> {
+    let table_size = ScenarioScriptNodeTable::tag_size();
+    if syntax_data.len() < table_size {
+        return Ok(0)
+    }
+
+    let table = ScenarioScriptNodeTable::from_tag(syntax_data, 0, table_size, &mut table_size.clone())?;
+    let count = table.count as usize;
+    let capacity = (syntax_data.len() - table_size) / ScenarioScriptNode::tag_size();
+    if count > capacity {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.types.scenario.error_script_node_count_mismatch"), count=count, capacity=capacity)))
+    }
+    Ok(count)
+}
+
+impl ScriptReferenceFn for Scenario {
+    fn replace_script_references(&mut self, from: &TagReference, to: &TagReference) -> ErrorMessageResult<usize> {
+        let group = from.get_group();
+        debug_assert_eq!(group, to.get_group(), "references must be of the same group");
+
+        let from_path = from.get_path_without_extension();
+        let to_path = to.get_path_without_extension();
+        let mut count = 0;
+
+        // Only some groups can be referenced by scripts.
+        if !SCRIPT_TAG_VALUE_TYPES.iter().any(|t| script_value_type_refers_to_group(*t, group)) {
+            return Ok(0)
+        }
+
+        // First the compiled nodes. The new path is appended to the string data so existing offsets are unaffected.
+        let table_size = ScenarioScriptNodeTable::tag_size();
+        let node_size = ScenarioScriptNode::tag_size();
+        let node_count = get_script_node_count(&self.script_syntax_data)?;
+        let mut new_string_offset = None;
+        for i in 0..node_count {
+            let node_offset = table_size + i * node_size;
+            let node_end = node_offset + node_size;
+            let mut node = ScenarioScriptNode::from_tag(&self.script_syntax_data, node_offset, node_end, &mut node_end.clone())?;
+            if !node.flags.is_primitive || !script_value_type_refers_to_group(node._type, group) {
+                continue;
+            }
+
+            let string_offset = node.string_offset as usize;
+            let string = match self.script_string_data.get(string_offset..) {
+                Some(n) => &n[..n.iter().position(|&c| c == 0).unwrap_or(n.len())],
+                None => continue
+            };
+            if !script_token_matches_path(string, from_path) {
+                continue;
+            }
+
+            node.string_offset = match new_string_offset {
+                Some(n) => n,
+                None => {
+                    let offset = self.script_string_data.len() as u32;
+                    self.script_string_data.extend_from_slice(to_path.as_bytes());
+                    self.script_string_data.push(0);
+                    new_string_offset = Some(offset);
+                    offset
+                }
+            };
+            node.into_tag(&mut self.script_syntax_data, node_offset, node_end)?;
+            count += 1;
+        }
+
+        // Next, the source files. Tokens do not have a group, so if the scripts are compiled, only replace them if the
+        // compiled scripts refer to a tag of this group with the path.
+        if node_count == 0 || count > 0 {
+            for source in &mut self.source_files {
+                let (new_source, replaced) = replace_source_tokens(&source.source, from_path, to_path);
+                if replaced > 0 {
+                    source.source = new_source;
+                    count += replaced;
+                }
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+/// Trait for decompiling the scripts of scenario tags.
+pub trait ScriptDecompiler {
+    /// Decompile the globals and scripts in the compiled script data back into HSC source.
+    ///
+    /// Only the compiled script data is used, so this works for scenarios without source files, such as ones extracted
+    /// from cache files. Comments and the original formatting are not preserved.
+    fn decompile_scripts(&self) -> ErrorMessageResult<String>;
+}
+
+/// Maximum length of an expression that is kept on one line when decompiling, including indentation.
+const DECOMPILED_LINE_LENGTH: usize = 100;
+
+/// Indentation used for each level of decompiled expressions.
+const DECOMPILED_INDENT: &str = "    ";
+
+/// Expression read from the compiled script data.
+enum DecompiledExpression {
+    Token(String),
+    Call(Vec<DecompiledExpression>)
+}
+
+impl DecompiledExpression {
+    fn flat_length(&self) -> usize {
+        match self {
+            DecompiledExpression::Token(t) => t.len(),
+            DecompiledExpression::Call(e) => e.iter().map(|e| e.flat_length() + 1).sum::<usize>() + 1
+        }
+    }
+
+    fn write_flat(&self, output: &mut String) {
+        match self {
+            DecompiledExpression::Token(t) => output.push_str(t),
+            DecompiledExpression::Call(e) => {
+                output.push('(');
+                for (i, e) in e.iter().enumerate() {
+                    if i > 0 {
+                        output.push(' ');
+                    }
+                    e.write_flat(output);
+                }
+                output.push(')');
+            }
+        }
+    }
+
+    /// Write the expression, putting each argument of calls on its own line if it is too long for one line.
+    fn write(&self, indent: usize, output: &mut String) {
+        let arguments = match self {
+            DecompiledExpression::Call(e) if indent * DECOMPILED_INDENT.len() + self.flat_length() > DECOMPILED_LINE_LENGTH => e,
+            _ => return self.write_flat(output)
+        };
+
+        output.push('(');
+        for (i, e) in arguments.iter().enumerate() {
+            if i > 0 {
+                output.push('\n');
+                output.push_str(&DECOMPILED_INDENT.repeat(indent + 1));
+            }
+            e.write(indent + 1, output);
+        }
+        output.push(')');
+    }
+}
+
+/// Quote the token if it would not otherwise be read as one token.
+fn quote_script_token(token: &str) -> String {
+    if token.is_empty() || token.chars().any(|c| c.is_ascii_whitespace() || c == '(' || c == ')' || c == ';' || c == '"') {
+        format!("\"{token}\"")
+    }
+    else {
+        token.to_owned()
+    }
+}
+
+/// Get the name of the value type as it is written in scripts.
+fn script_value_type_name(value_type: ScenarioScriptValueType) -> String {
+    ScenarioScriptValueType::options_pretty()[value_type as usize].replace(' ', "_")
+}
+
+/// Reads expressions from compiled script data.
+struct ScriptNodeReader<'a> {
+    nodes: Vec<ScenarioScriptNode>,
+    string_data: &'a [u8]
+}
+
+impl<'a> ScriptNodeReader<'a> {
+    fn new(scenario: &'a Scenario) -> ErrorMessageResult<ScriptNodeReader<'a>> {
+        let syntax_data = &scenario.script_syntax_data;
+        let table_size = ScenarioScriptNodeTable::tag_size();
+        let node_size = ScenarioScriptNode::tag_size();
+
+        let node_count = get_script_node_count(syntax_data)?;
+        let mut nodes = Vec::with_capacity(node_count);
+        for i in 0..node_count {
+            let node_offset = table_size + i * node_size;
+            let node_end = node_offset + node_size;
+            nodes.push(ScenarioScriptNode::from_tag(syntax_data, node_offset, node_end, &mut node_end.clone())?);
+        }
+
+        Ok(ScriptNodeReader { nodes, string_data: &scenario.script_string_data })
+    }
+
+    fn get_node(&self, id: u32) -> ErrorMessageResult<&ScenarioScriptNode> {
+        self.nodes.get((id & 0xFFFF) as usize)
+                  .filter(|_| id != u32::MAX)
+                  .ok_or_else(|| ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.types.scenario.error_decompile_invalid_node"), node=id)))
+    }
+
+    fn get_string(&self, node: &ScenarioScriptNode) -> ErrorMessageResult<&str> {
+        let offset = node.string_offset as usize;
+        let invalid_string = || ErrorMessage::AllocatedString(format!(get_compiled_string!("engine.h1.types.scenario.error_decompile_invalid_string"), offset=offset));
+        let string = self.string_data.get(offset..).ok_or_else(invalid_string)?;
+        let string = &string[..string.iter().position(|&c| c == 0).unwrap_or(string.len())];
+        std::str::from_utf8(string).map_err(|_| invalid_string())
+    }
+
+    /// Read the expression of the node with the given ID.
+    ///
+    /// `depth` is the number of calls this is nested in, which is used to detect loops.
+    fn read_expression(&self, id: u32, depth: usize) -> ErrorMessageResult<DecompiledExpression> {
+        if depth > self.nodes.len() {
+            return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.types.scenario.error_decompile_node_loop")))
+        }
+
+        let node = self.get_node(id)?;
+
+        // Calls refer to the node with the name of the function or script, which is followed by the arguments.
+        if !node.flags.is_primitive {
+            let mut expressions = Vec::new();
+            let mut next = unsafe { node.data.unsigned_long_int };
+            while next != u32::MAX {
+                if expressions.len() > self.nodes.len() {
+                    return Err(ErrorMessage::StaticString(get_compiled_string!("engine.h1.types.scenario.error_decompile_node_loop")))
+                }
+                expressions.push(self.read_expression(next, depth + 1)?);
+                next = self.get_node(next)?.next_node;
+            }
+            return Ok(DecompiledExpression::Call(expressions))
+        }
+
+        // Globals and parameters are referred to by name, and literals use their value where it is stored in the node.
+        let token = if node.flags.is_global {
+            self.get_string(node)?.to_owned()
+        }
+        else {
+            match node._type {
+                ScenarioScriptValueType::Boolean => (if unsafe { node.data.bool_int } != 0 { "true" } else { "false" }).to_owned(),
+                ScenarioScriptValueType::Short => unsafe { node.data.short_int }.to_string(),
+                ScenarioScriptValueType::Long => unsafe { node.data.long_int }.to_string(),
+                ScenarioScriptValueType::Real => unsafe { node.data.real }.to_string(),
+                ScenarioScriptValueType::String => format!("\"{}\"", self.get_string(node)?),
+                _ => quote_script_token(self.get_string(node)?)
+            }
+        };
+        Ok(DecompiledExpression::Token(token))
+    }
+}
+
+impl ScriptDecompiler for Scenario {
+    fn decompile_scripts(&self) -> ErrorMessageResult<String> {
+        let reader = ScriptNodeReader::new(self)?;
+        let mut output = String::new();
+
+        for g in &self.globals {
+            output += &format!("(global {} {} ", script_value_type_name(g._type), g.name.to_str());
+            reader.read_expression(g.initialization_expression_index, 0)?.write(0, &mut output);
+            output += ")\n";
+        }
+
+        for s in &self.scripts {
+            if !output.is_empty() {
+                output.push('\n');
+            }
+
+            output += "(script ";
+            output += ScenarioScriptType::options()[s.script_type as usize];
+            if matches!(s.script_type, ScenarioScriptType::Static | ScenarioScriptType::Stub) {
+                output += " ";
+                output += &script_value_type_name(s.return_type);
+            }
+
+            output += " ";
+            if s.parameters.blocks.is_empty() {
+                output += s.name.to_str();
+            }
+            else {
+                output += &format!("({}", s.name.to_str());
+                for p in &s.parameters {
+                    output += &format!(" ({} {})", script_value_type_name(p.return_type), p.name.to_str());
+                }
+                output += ")";
+            }
+            output += "\n";
+
+            // The statements of scripts are compiled into a begin call.
+            let statements = match reader.read_expression(s.root_expression_index, 0)? {
+                DecompiledExpression::Call(mut e) if matches!(e.first(), Some(DecompiledExpression::Token(t)) if t == "begin") => e.split_off(1),
+                e => vec![e]
+            };
+            for e in statements {
+                output += DECOMPILED_INDENT;
+                e.write(1, &mut output);
+                output += "\n";
+            }
+            output += ")\n";
+        }
+
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_script_references() {
+        let mut scenario = Scenario::default();
+        scenario.source_files.blocks.push(ScenarioSourceFile {
+            name: String32::from_str("test").unwrap(),
+            source: b"; sound\\dialog\\a\n(script static void test\n    (sound_impulse_start \"Sound/Dialog/A\" none 1)\n    (sound_impulse_start sound\\dialog\\a none 1)\n    (sound_impulse_start \"sound\\dialog\\ab\" none 1))".to_vec()
+        });
+        let effect = |path: &str| TagReference::from_path_and_group(path, TagGroup::Effect).unwrap();
+        let sound = |path: &str| TagReference::from_path_and_group(path, TagGroup::Sound).unwrap();
+
+        // Without compiled scripts, any group that scripts can refer to is replaced, but not comments or other paths.
+        let mut uncompiled = scenario.clone();
+        assert_eq!(2, uncompiled.replace_script_references(&effect("sound\\dialog\\a"), &effect("sound\\new\\b")).unwrap());
+        assert_eq!(b"; sound\\dialog\\a\n(script static void test\n    (sound_impulse_start \"sound\\new\\b\" none 1)\n    (sound_impulse_start sound\\new\\b none 1)\n    (sound_impulse_start \"sound\\dialog\\ab\" none 1))".to_vec(), uncompiled.source_files.blocks[0].source);
+        let bitmap = |path: &str| TagReference::from_path_and_group(path, TagGroup::Bitmap).unwrap();
+        assert_eq!(0, uncompiled.replace_script_references(&bitmap("sound\\dialog\\ab"), &bitmap("sound\\new\\b")).unwrap());
+
+        // Compiled scripts with two sound nodes
+        scenario.script_string_data = b"sound\\dialog\\a\0sound\\dialog\\ab\0".to_vec();
+        let mut nodes = vec![ScenarioScriptNode::default(); 3];
+        nodes[0]._type = ScenarioScriptValueType::Sound;
+        nodes[0].flags.is_primitive = true;
+        nodes[1] = nodes[0];
+        nodes[1].string_offset = 15;
+        let table_size = ScenarioScriptNodeTable::tag_size();
+        let node_size = ScenarioScriptNode::tag_size();
+        let table = ScenarioScriptNodeTable { count: nodes.len() as u16, ..Default::default() };
+        scenario.script_syntax_data = vec![0; table_size + node_size * nodes.len()];
+        table.into_tag(&mut scenario.script_syntax_data, 0, table_size).unwrap();
+        for (i, n) in nodes.iter().enumerate() {
+            let offset = table_size + node_size * i;
+            n.into_tag(&mut scenario.script_syntax_data, offset, offset + node_size).unwrap();
+        }
+        let node_at = |scenario: &Scenario, i: usize| {
+            let offset = table_size + node_size * i;
+            ScenarioScriptNode::from_tag(&scenario.script_syntax_data, offset, offset + node_size, &mut (offset + node_size)).unwrap()
+        };
+
+        // Compiled scripts determine the group.
+        assert_eq!(0, scenario.replace_script_references(&effect("sound\\dialog\\a"), &effect("sound\\new\\b")).unwrap());
+        assert_eq!(3, scenario.replace_script_references(&sound("sound\\dialog\\a"), &sound("sound\\new\\b")).unwrap());
+        assert_eq!(uncompiled.source_files.blocks[0].source, scenario.source_files.blocks[0].source);
+        assert_eq!(31, node_at(&scenario, 0).string_offset);
+        assert_eq!(15, node_at(&scenario, 1).string_offset);
+        assert_eq!(b"sound\\new\\b\0", &scenario.script_string_data[31..]);
+        assert_eq!(0, scenario.replace_script_references(&sound("sound\\dialog\\a"), &sound("sound\\new\\b")).unwrap());
+
+        // The node count comes from the table, which can't have more nodes than the data.
+        let mut truncated = scenario.clone();
+        truncated.script_syntax_data.truncate(table_size + node_size * 2);
+        assert!(truncated.replace_script_references(&sound("sound\\new\\b"), &sound("sound\\dialog\\a")).is_err());
+    }
+
+    #[test]
+    fn test_decompile_scripts() {
+        let mut scenario = Scenario::default();
+        scenario.globals.blocks.push(ScenarioGlobal { name: String32::from_str("x").unwrap(), _type: ScenarioScriptValueType::Short, initialization_expression_index: generate_script_node_id(Some(0)) });
+        scenario.scripts.blocks.push(ScenarioScript {
+            name: String32::from_str("test").unwrap(),
+            script_type: ScenarioScriptType::Static,
+            return_type: ScenarioScriptValueType::Void,
+            root_expression_index: generate_script_node_id(Some(1)),
+            ..Default::default()
+        });
+        scenario.script_string_data = b"begin\0set\0x\0print\0hi there\0".to_vec();
+
+        // (global short x 5) and (script static void test (begin (set x 1) (print "hi there")))
+        let primitive = |_type, next: Option<usize>, string_offset, data| ScenarioScriptNode {
+            _type,
+            flags: ScenarioScriptNodeFlags { is_primitive: true, ..Default::default() },
+            next_node: generate_script_node_id(next),
+            string_offset,
+            data,
+            ..Default::default()
+        };
+        let call = |_type, next: Option<usize>, first: usize| ScenarioScriptNode {
+            _type,
+            next_node: generate_script_node_id(next),
+            data: ScenarioScriptNodeValue { unsigned_long_int: generate_script_node_id(Some(first)) },
+            ..Default::default()
+        };
+        let mut global = primitive(ScenarioScriptValueType::Short, Some(6), 10, ScenarioScriptNodeValue::default());
+        global.flags.is_global = true;
+        let nodes = [
+            primitive(ScenarioScriptValueType::Short, None, 0, ScenarioScriptNodeValue { short_int: 5 }),
+            call(ScenarioScriptValueType::Void, None, 2),
+            primitive(ScenarioScriptValueType::FunctionName, Some(3), 0, ScenarioScriptNodeValue::default()),
+            call(ScenarioScriptValueType::Short, Some(7), 4),
+            primitive(ScenarioScriptValueType::FunctionName, Some(5), 6, ScenarioScriptNodeValue::default()),
+            global,
+            primitive(ScenarioScriptValueType::Short, None, 0, ScenarioScriptNodeValue { short_int: 1 }),
+            call(ScenarioScriptValueType::Void, None, 8),
+            primitive(ScenarioScriptValueType::FunctionName, Some(9), 12, ScenarioScriptNodeValue::default()),
+            primitive(ScenarioScriptValueType::String, None, 18, ScenarioScriptNodeValue::default())
+        ];
+
+        let table_size = ScenarioScriptNodeTable::tag_size();
+        let node_size = ScenarioScriptNode::tag_size();
+        let table = ScenarioScriptNodeTable { count: nodes.len() as u16, ..Default::default() };
+        scenario.script_syntax_data = vec![0; table_size + node_size * nodes.len()];
+        table.into_tag(&mut scenario.script_syntax_data, 0, table_size).unwrap();
+        for (i, n) in nodes.iter().enumerate() {
+            let offset = table_size + node_size * i;
+            n.into_tag(&mut scenario.script_syntax_data, offset, offset + node_size).unwrap();
+        }
+
+        assert_eq!("(global short x 5)\n\n(script static void test\n    (set x 1)\n    (print \"hi there\")\n)\n", scenario.decompile_scripts().unwrap());
+
+        // Nodes that refer to themselves are an error rather than an infinite loop.
+        let mut looped = scenario.clone();
+        let mut node = nodes[2];
+        node.next_node = generate_script_node_id(Some(2));
+        node.into_tag(&mut looped.script_syntax_data, table_size + node_size * 2, table_size + node_size * 3).unwrap();
+        assert!(looped.decompile_scripts().is_err());
+    }
+}
@@ -121,6 +121,8 @@ fn iterate_recursively(base_directory: &Path, directory: &Path, recursion_limit:
 
 impl TagFile {
     /// Get all tags located in a virtual tags directory.
+    ///
+    /// If a tag exists in more than one directory, the one in the earliest directory in `tags_directories` is used.
     pub fn from_virtual_tags_directory(tags_directories: &[&Path]) -> ErrorMessageResult<Vec<TagFile>> {
         if tags_directories.len() == 0 {
             return Ok(Vec::new())
@@ -144,7 +146,7 @@ impl TagFile {
         // Go through everything!
         else {
             // First move the first directory since it doesn't need to check past directories
-            let mut final_results = results.pop().unwrap()?;
+            let mut final_results = results.remove(0)?;
 
             for i in results {
                 for p in i? {
@@ -11,3 +11,4 @@ pub mod types;
 pub mod bitmap;
 pub mod engines;
 pub mod file;
+pub mod sound;
@@ -0,0 +1,147 @@
+use crate::error::*;
+use ringhopper_proc::*;
+
+/// Number of bytes in each channel of an ADPCM block.
+pub const ADPCM_BLOCK_SIZE_PER_CHANNEL: usize = 36;
+
+/// Number of samples in each channel of an ADPCM block.
+///
+/// The header of each block stores the first sample, and the remaining 32 bytes store two samples each.
+pub const ADPCM_SAMPLES_PER_BLOCK: usize = 1 + (ADPCM_BLOCK_SIZE_PER_CHANNEL - 4) * 2;
+
+/// Change to the step index for each nibble.
+const IMA_INDEX_TABLE: [i32; 16] = [-1, -1, -1, -1, 2, 4, 6, 8, -1, -1, -1, -1, 2, 4, 6, 8];
+
+/// Quantizer step sizes.
+const IMA_STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66, 73, 80, 88, 97, 107, 118,
+    130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449, 494, 544, 598, 658, 724, 796, 876, 963, 1060,
+    1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272, 2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484,
+    7132, 7845, 8630, 9493, 10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767
+];
+
+/// State of a channel being decoded.
+struct ImaAdpcmChannel {
+    predictor: i32,
+    step_index: i32
+}
+
+impl ImaAdpcmChannel {
+    fn decode_nibble(&mut self, nibble: u8) -> i16 {
+        let step = IMA_STEP_TABLE[self.step_index as usize];
+        let mut difference = step >> 3;
+        if nibble & 1 != 0 {
+            difference += step >> 2;
+        }
+        if nibble & 2 != 0 {
+            difference += step >> 1;
+        }
+        if nibble & 4 != 0 {
+            difference += step;
+        }
+        if nibble & 8 != 0 {
+            difference = -difference;
+        }
+
+        self.predictor = (self.predictor + difference).clamp(i16::MIN as i32, i16::MAX as i32);
+        self.step_index = (self.step_index + IMA_INDEX_TABLE[nibble as usize]).clamp(0, IMA_STEP_TABLE.len() as i32 - 1);
+        self.predictor as i16
+    }
+
+    fn encode_sample(&mut self, sample: i16) -> u8 {
+        let mut step = IMA_STEP_TABLE[self.step_index as usize];
+        let mut difference = sample as i32 - self.predictor;
+        let mut nibble = 0;
+        if difference < 0 {
+            nibble = 8;
+            difference = -difference;
+        }
+        for bit in [4, 2, 1] {
+            if difference >= step {
+                nibble |= bit;
+                difference -= step;
+            }
+            step >>= 1;
+        }
+
+        // Update the state the same way the decoder will so errors do not accumulate.
+        self.decode_nibble(nibble);
+        nibble
+    }
+}
+
+/// Decode IMA ADPCM data into interleaved 16-bit PCM samples.
+///
+/// Blocks are [`ADPCM_BLOCK_SIZE_PER_CHANNEL`] bytes per channel. Each block starts with the header of each channel
+/// (the first sample and the step index), followed by groups of 4 bytes for each channel in turn, where each byte holds
+/// two samples starting with the low nibble. This is also the format of Xbox ADPCM, which is IMA ADPCM with blocks of
+/// a fixed size.
+///
+/// Returns an [`Err`] if the data does not consist of whole blocks.
+pub fn decode_ima_adpcm(data: &[u8], channel_count: usize) -> ErrorMessageResult<Vec<i16>> {
+    let block_size = ADPCM_BLOCK_SIZE_PER_CHANNEL * channel_count;
+    if channel_count == 0 || data.len() % block_size != 0 {
+        return Err(ErrorMessage::StaticString(get_compiled_string!("sound.error_adpcm_incomplete_block")));
+    }
+
+    let block_count = data.len() / block_size;
+    let mut samples = vec![0i16; block_count * ADPCM_SAMPLES_PER_BLOCK * channel_count];
+
+    for (block, output) in data.chunks_exact(block_size).zip(samples.chunks_exact_mut(ADPCM_SAMPLES_PER_BLOCK * channel_count)) {
+        let (headers, body) = block.split_at(4 * channel_count);
+
+        for (c, header) in headers.chunks_exact(4).enumerate() {
+            let predictor = i16::from_le_bytes([header[0], header[1]]);
+            let mut channel = ImaAdpcmChannel { predictor: predictor as i32, step_index: (header[2] as i32).min(IMA_STEP_TABLE.len() as i32 - 1) };
+            output[c] = predictor;
+
+            // Each group of 4 bytes holds 8 samples for one channel.
+            for (group, bytes) in body.chunks_exact(4).skip(c).step_by(channel_count).enumerate() {
+                for (b, byte) in bytes.iter().enumerate() {
+                    let frame = 1 + group * 8 + b * 2;
+                    output[frame * channel_count + c] = channel.decode_nibble(byte & 0xF);
+                    output[(frame + 1) * channel_count + c] = channel.decode_nibble(byte >> 4);
+                }
+            }
+        }
+    }
+
+    Ok(samples)
+}
+
+/// Encode interleaved 16-bit PCM samples into IMA ADPCM data.
+///
+/// The output is in the block format read by [`decode_ima_adpcm`]. If the samples do not fill the last block, the rest
+/// of it is filled with silence.
+pub fn encode_ima_adpcm(samples: &[i16], channel_count: usize) -> Vec<u8> {
+    debug_assert!(channel_count > 0);
+
+    let frames_per_block = ADPCM_SAMPLES_PER_BLOCK * channel_count;
+    let block_count = samples.len().div_ceil(frames_per_block);
+    let mut data = vec![0u8; block_count * ADPCM_BLOCK_SIZE_PER_CHANNEL * channel_count];
+    let mut channels: Vec<ImaAdpcmChannel> = (0..channel_count).map(|_| ImaAdpcmChannel { predictor: 0, step_index: 0 }).collect();
+
+    for (block_samples, block) in samples.chunks(frames_per_block).zip(data.chunks_exact_mut(ADPCM_BLOCK_SIZE_PER_CHANNEL * channel_count)) {
+        let sample_at = |frame: usize, c: usize| block_samples.get(frame * channel_count + c).copied().unwrap_or(0);
+        let (headers, body) = block.split_at_mut(4 * channel_count);
+
+        for (c, (header, channel)) in headers.chunks_exact_mut(4).zip(channels.iter_mut()).enumerate() {
+            // The first sample is stored as is, and the step index carries over from the previous block.
+            let first = sample_at(0, c);
+            channel.predictor = first as i32;
+            header[0..2].copy_from_slice(&first.to_le_bytes());
+            header[2] = channel.step_index as u8;
+
+            for (group, bytes) in body.chunks_exact_mut(4).skip(c).step_by(channel_count).enumerate() {
+                for (b, byte) in bytes.iter_mut().enumerate() {
+                    let frame = 1 + group * 8 + b * 2;
+                    let low = channel.encode_sample(sample_at(frame, c));
+                    let high = channel.encode_sample(sample_at(frame + 1, c));
+                    *byte = low | (high << 4);
+                }
+            }
+        }
+    }
+
+    data
+}
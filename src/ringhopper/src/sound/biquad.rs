@@ -0,0 +1,40 @@
+/// Second order IIR filter.
+pub(crate) struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2]
+}
+
+impl Biquad {
+    /// Make a filter from coefficients that are already normalized so that a0 is 1.
+    pub fn new(b: [f64; 3], a: [f64; 2]) -> Biquad {
+        Biquad { b, a, z: [0.0; 2] }
+    }
+
+    /// Make a high pass filter with the given cutoff frequency and Q.
+    pub fn high_pass(frequency: f64, q: f64, sample_rate: u32) -> Biquad {
+        let (cos, alpha) = Self::cos_alpha(frequency, q, sample_rate);
+        let a0 = 1.0 + alpha;
+        Biquad::new([(1.0 + cos) / 2.0 / a0, -(1.0 + cos) / a0, (1.0 + cos) / 2.0 / a0], [-2.0 * cos / a0, (1.0 - alpha) / a0])
+    }
+
+    /// Make a low pass filter with the given cutoff frequency and Q.
+    pub fn low_pass(frequency: f64, q: f64, sample_rate: u32) -> Biquad {
+        let (cos, alpha) = Self::cos_alpha(frequency, q, sample_rate);
+        let a0 = 1.0 + alpha;
+        Biquad::new([(1.0 - cos) / 2.0 / a0, (1.0 - cos) / a0, (1.0 - cos) / 2.0 / a0], [-2.0 * cos / a0, (1.0 - alpha) / a0])
+    }
+
+    /// Get the cosine of the angular frequency and the alpha term used by the filters above.
+    fn cos_alpha(frequency: f64, q: f64, sample_rate: u32) -> (f64, f64) {
+        let w0 = std::f64::consts::TAU * frequency / sample_rate as f64;
+        (w0.cos(), w0.sin() / (2.0 * q))
+    }
+
+    pub fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.z[0];
+        self.z[0] = self.b[1] * input - self.a[0] * output + self.z[1];
+        self.z[1] = self.b[2] * input - self.a[1] * output;
+        output
+    }
+}
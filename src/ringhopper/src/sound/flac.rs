@@ -0,0 +1,216 @@
+use crate::error::*;
+use ringhopper_proc::*;
+
+/// Number of samples per channel in each FLAC frame.
+const FLAC_BLOCK_SIZE: usize = 4096;
+
+/// Highest fixed predictor order.
+const FLAC_MAX_FIXED_ORDER: usize = 4;
+
+/// Highest Rice parameter that can be stored with 4-bit parameters.
+const FLAC_MAX_RICE_PARAMETER: u32 = 14;
+
+/// Writes bits most significant bit first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    accumulator: u64,
+    bit_count: u32
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u64, bits: u32) {
+        debug_assert!(bits <= 32);
+        if bits == 0 {
+            return;
+        }
+        self.accumulator = (self.accumulator << bits) | (value & ((1u64 << bits) - 1));
+        self.bit_count += bits;
+        while self.bit_count >= 8 {
+            self.bit_count -= 8;
+            self.bytes.push((self.accumulator >> self.bit_count) as u8);
+        }
+    }
+
+    fn write_signed(&mut self, value: i32, bits: u32) {
+        self.write(value as u32 as u64, bits)
+    }
+
+    fn write_unary(&mut self, zeroes: u32) {
+        let mut remaining = zeroes;
+        while remaining >= 32 {
+            self.write(0, 32);
+            remaining -= 32;
+        }
+        self.write(1, remaining + 1);
+    }
+
+    fn align(&mut self) {
+        if self.bit_count > 0 {
+            self.write(0, 8 - self.bit_count);
+        }
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Get the residuals of the fixed predictor of the given order.
+fn fixed_residuals(samples: &[i32], order: usize) -> Vec<i32> {
+    (order..samples.len()).map(|i| {
+        let s = |n: usize| samples[i - n];
+        match order {
+            0 => s(0),
+            1 => s(0) - s(1),
+            2 => s(0) - 2 * s(1) + s(2),
+            3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+            4 => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+            _ => unreachable!()
+        }
+    }).collect()
+}
+
+/// Fold a signed residual into an unsigned one so small magnitudes stay small.
+fn fold_residual(residual: i32) -> u32 {
+    ((residual << 1) ^ (residual >> 31)) as u32
+}
+
+/// Get the Rice parameter and the number of bits it takes to code the residuals.
+fn best_rice_parameter(residuals: &[i32]) -> (u32, u64) {
+    (0..=FLAC_MAX_RICE_PARAMETER)
+        .map(|k| (k, residuals.iter().map(|r| (fold_residual(*r) >> k) as u64 + 1 + k as u64).sum()))
+        .min_by_key(|(_, bits)| *bits)
+        .unwrap()
+}
+
+/// Write a subframe with the fixed predictor that takes the fewest bits, or a verbatim subframe if none are smaller.
+fn write_subframe(writer: &mut BitWriter, samples: &[i32]) {
+    let mut best: Option<(usize, u32, u64, Vec<i32>)> = None;
+    for order in 0..=FLAC_MAX_FIXED_ORDER.min(samples.len()) {
+        let residuals = fixed_residuals(samples, order);
+        let (parameter, residual_bits) = best_rice_parameter(&residuals);
+
+        // Warm-up samples, the coding method, partition order, and Rice parameter
+        let bits = order as u64 * 16 + 2 + 4 + 4 + residual_bits;
+        if best.as_ref().map(|b| bits < b.2).unwrap_or(true) {
+            best = Some((order, parameter, bits, residuals));
+        }
+    }
+
+    let (order, parameter, bits, residuals) = best.unwrap();
+    if bits >= samples.len() as u64 * 16 {
+        writer.write(0b0000_0010, 8);
+        samples.iter().for_each(|s| writer.write_signed(*s, 16));
+        return;
+    }
+
+    writer.write(0b0001_0000 | (order as u64) << 1, 8);
+    samples[..order].iter().for_each(|s| writer.write_signed(*s, 16));
+
+    // Rice coding with 4-bit parameters, and one partition
+    writer.write(0, 2);
+    writer.write(0, 4);
+    writer.write(parameter as u64, 4);
+    for r in residuals {
+        let folded = fold_residual(r);
+        writer.write_unary(folded >> parameter);
+        writer.write(folded as u64, parameter);
+    }
+}
+
+/// Write a number with the variable length coding used for frame numbers in frame headers.
+fn write_coded_number(header: &mut Vec<u8>, number: u32) {
+    if number < 0x80 {
+        header.push(number as u8);
+        return;
+    }
+
+    let continuation_bytes = match number {
+        0..=0x7FF => 1,
+        0x800..=0xFFFF => 2,
+        0x10000..=0x1FFFFF => 3,
+        0x200000..=0x3FFFFFF => 4,
+        _ => 5
+    };
+    let prefix = !(0xFFu8 >> (continuation_bytes + 1));
+    header.push(prefix | (number >> (6 * continuation_bytes)) as u8);
+    for i in (0..continuation_bytes).rev() {
+        header.push(0x80 | ((number >> (6 * i)) & 0x3F) as u8);
+    }
+}
+
+/// Encode interleaved 16-bit PCM samples into a FLAC file.
+///
+/// Each channel is encoded independently with whichever fixed predictor takes the fewest bits. This does not compress
+/// as well as encoders that use linear prediction, but it is lossless.
+///
+/// Returns an [`Err`] if there are not between 1 and 8 channels.
+pub fn encode_flac(samples: &[i16], channel_count: usize, sample_rate: u32) -> ErrorMessageResult<Vec<u8>> {
+    if !(1..=8).contains(&channel_count) {
+        return Err(ErrorMessage::StaticString(get_compiled_string!("sound.error_flac_invalid_channel_count")));
+    }
+
+    let frame_count = samples.len() / channel_count;
+    let mut flac = Vec::new();
+    flac.extend_from_slice(b"fLaC");
+
+    // STREAMINFO, which is also the last metadata block. The frame sizes and MD5 are left unknown.
+    let mut stream_info = BitWriter::default();
+    stream_info.write(FLAC_BLOCK_SIZE as u64, 16);
+    stream_info.write(FLAC_BLOCK_SIZE as u64, 16);
+    stream_info.write(0, 24);
+    stream_info.write(0, 24);
+    stream_info.write(sample_rate as u64, 20);
+    stream_info.write(channel_count as u64 - 1, 3);
+    stream_info.write(16 - 1, 5);
+    stream_info.write((frame_count as u64) >> 32, 4);
+    stream_info.write(frame_count as u64 & 0xFFFFFFFF, 32);
+    stream_info.bytes.extend_from_slice(&[0u8; 16]);
+    flac.push(0x80);
+    flac.extend_from_slice(&(stream_info.bytes.len() as u32).to_be_bytes()[1..]);
+    flac.extend_from_slice(&stream_info.bytes);
+
+    for (frame_number, block) in samples[..frame_count * channel_count].chunks(FLAC_BLOCK_SIZE * channel_count).enumerate() {
+        let block_size = block.len() / channel_count;
+
+        // Sync code, then a 16-bit block size at the end of the header, the sample rate from STREAMINFO, independent
+        // channels, and 16-bit samples
+        let mut frame = vec![0xFF, 0xF8, 0x70, ((channel_count as u8 - 1) << 4) | 0x08];
+        write_coded_number(&mut frame, frame_number as u32);
+        frame.extend_from_slice(&(block_size as u16 - 1).to_be_bytes());
+        frame.push(crc8(&frame));
+
+        let mut writer = BitWriter { bytes: frame, ..Default::default() };
+        for c in 0..channel_count {
+            let channel: Vec<i32> = block.iter().skip(c).step_by(channel_count).map(|s| *s as i32).collect();
+            write_subframe(&mut writer, &channel);
+        }
+        writer.align();
+
+        let mut frame = writer.bytes;
+        let crc = crc16(&frame);
+        frame.extend_from_slice(&crc.to_be_bytes());
+        flac.extend_from_slice(&frame);
+    }
+
+    Ok(flac)
+}
@@ -0,0 +1,139 @@
+use super::biquad::Biquad;
+
+/// Length of each gating block in seconds.
+const BLOCK_DURATION: f64 = 0.4;
+
+/// Time between the starts of gating blocks in seconds, which overlap by 75%.
+const BLOCK_STEP: f64 = 0.1;
+
+/// Blocks quieter than this (in LUFS) are not measured.
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// Blocks quieter than this relative to the loudness of the blocks above the absolute gate (in LU) are not measured.
+const RELATIVE_GATE: f64 = -10.0;
+
+/// Get the two stages of the K-weighting filter for a sample rate.
+///
+/// This is a high shelf that models the head followed by a high pass filter. The coefficients are derived from the
+/// analog filters so that sample rates other than 48 kHz can be used.
+fn k_weighting_filters(sample_rate: u32) -> [Biquad; 2] {
+    let fs = sample_rate as f64;
+
+    let f0 = 1681.974450955533;
+    let gain = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (std::f64::consts::PI * f0 / fs).tan();
+    let vh = 10.0f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0]
+    );
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (std::f64::consts::PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new([1.0, -2.0, 1.0], [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0]);
+
+    [shelf, high_pass]
+}
+
+/// Convert a mean square of K-weighted samples into LUFS.
+fn energy_to_loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+/// Measures integrated loudness as defined by ITU-R BS.1770 and EBU R 128.
+///
+/// Sounds can be added separately to measure their combined loudness. Every channel has the same weight, since sounds
+/// only have one or two channels.
+#[derive(Clone, Default)]
+pub struct LoudnessMeter {
+    block_energies: Vec<f64>
+}
+
+impl LoudnessMeter {
+    pub fn new() -> LoudnessMeter {
+        LoudnessMeter::default()
+    }
+
+    /// Add interleaved samples to the measurement.
+    ///
+    /// Sounds shorter than a gating block (400 ms) are measured as a single block.
+    pub fn add_samples(&mut self, samples: &[i16], channel_count: usize, sample_rate: u32) {
+        if channel_count == 0 || sample_rate == 0 {
+            return;
+        }
+
+        let frame_count = samples.len() / channel_count;
+        if frame_count == 0 {
+            return;
+        }
+
+        // Sum the squares of each frame's K-weighted samples, keeping a running total so blocks can be summed quickly.
+        let mut filters: Vec<[Biquad; 2]> = (0..channel_count).map(|_| k_weighting_filters(sample_rate)).collect();
+        let mut running_total = Vec::with_capacity(frame_count + 1);
+        running_total.push(0.0);
+        let mut total = 0.0;
+        for frame in samples.chunks_exact(channel_count) {
+            for (sample, [shelf, high_pass]) in frame.iter().zip(filters.iter_mut()) {
+                let filtered = high_pass.process(shelf.process(*sample as f64 / 32768.0));
+                total += filtered * filtered;
+            }
+            running_total.push(total);
+        }
+
+        let block_length = ((BLOCK_DURATION * sample_rate as f64).round() as usize).min(frame_count);
+        let block_step = ((BLOCK_STEP * sample_rate as f64).round() as usize).max(1);
+        for start in (0..=frame_count - block_length).step_by(block_step) {
+            let energy = (running_total[start + block_length] - running_total[start]) / block_length as f64;
+            self.block_energies.push(energy);
+        }
+    }
+
+    /// Get the integrated loudness in LUFS, or [`None`] if nothing was loud enough to measure.
+    pub fn integrated_loudness(&self) -> Option<f64> {
+        let mean_loudness = |energies: &[f64]| energy_to_loudness(energies.iter().sum::<f64>() / energies.len() as f64);
+
+        let above_absolute: Vec<f64> = self.block_energies.iter().copied().filter(|e| *e > 0.0 && energy_to_loudness(*e) > ABSOLUTE_GATE).collect();
+        if above_absolute.is_empty() {
+            return None;
+        }
+
+        let relative_gate = mean_loudness(&above_absolute) + RELATIVE_GATE;
+        let above_relative: Vec<f64> = above_absolute.into_iter().filter(|e| energy_to_loudness(*e) > relative_gate).collect();
+        Some(mean_loudness(&above_relative))
+    }
+}
+
+/// Peak, RMS, and loudness of a sound.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SoundLevels {
+    /// Highest absolute sample value in dBFS.
+    pub peak: f64,
+
+    /// Root mean square of the samples in dBFS.
+    pub rms: f64,
+
+    /// Integrated loudness in LUFS, or [`None`] if it is too quiet to measure.
+    pub loudness: Option<f64>
+}
+
+/// Measure the levels of interleaved samples.
+///
+/// Silent sounds have a peak and RMS of negative infinity.
+pub fn measure_levels(samples: &[i16], channel_count: usize, sample_rate: u32) -> SoundLevels {
+    let peak = samples.iter().map(|s| (*s as i32).unsigned_abs()).max().unwrap_or(0) as f64 / 32768.0;
+    let mean_square = samples.iter().map(|s| (*s as f64 / 32768.0).powi(2)).sum::<f64>() / samples.len().max(1) as f64;
+
+    let mut meter = LoudnessMeter::new();
+    meter.add_samples(samples, channel_count, sample_rate);
+
+    SoundLevels {
+        peak: 20.0 * peak.log10(),
+        rms: 10.0 * mean_square.log10(),
+        loudness: meter.integrated_loudness()
+    }
+}
@@ -0,0 +1,24 @@
+//! Functions for encoding and decoding sound data.
+
+mod adpcm;
+pub use self::adpcm::*;
+
+mod biquad;
+
+mod flac;
+pub use self::flac::*;
+
+mod loudness;
+pub use self::loudness::*;
+
+mod mouth;
+pub use self::mouth::*;
+
+mod subtitles;
+pub use self::subtitles::*;
+
+mod wav;
+pub use self::wav::*;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,122 @@
+use super::biquad::Biquad;
+
+/// Number of mouth data values per second of sound, one for each game tick.
+pub const MOUTH_DATA_RATE: u32 = 30;
+
+/// Lowest frequency of the band used for speech analysis in Hz.
+const SPEECH_BAND_LOW: f64 = 300.0;
+
+/// Highest frequency of the band used for speech analysis in Hz.
+const SPEECH_BAND_HIGH: f64 = 3400.0;
+
+/// Range in dB below the loudest tick that the mouth opens over for speech analysis.
+const SPEECH_DYNAMIC_RANGE: f64 = 30.0;
+
+/// Ticks quieter than this (in dBFS) keep the mouth closed for speech analysis.
+const SPEECH_SILENCE_THRESHOLD: f64 = -60.0;
+
+/// How mouth data is measured from a sound.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum MouthDataAnalysis {
+    /// Use the mean amplitude of each tick, scaled between the quietest tick and a bit under the loudest tick.
+    Amplitude,
+
+    /// Use the energy of each tick within the band of frequencies where most speech is (300-3400 Hz), scaled
+    /// logarithmically from the loudest tick.
+    ///
+    /// This ignores rumble and hiss that do not come from a mouth, and it follows syllables more closely.
+    #[default]
+    Speech
+}
+
+/// Options for generating mouth data.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MouthDataOptions {
+    /// How each tick is measured.
+    pub analysis: MouthDataAnalysis,
+
+    /// How slowly the mouth closes, from 0 (no smoothing) to below 1.
+    ///
+    /// The mouth always opens immediately, but while it closes, each tick keeps this fraction of the previous tick's value.
+    pub smoothing: f64
+}
+
+impl Default for MouthDataOptions {
+    fn default() -> Self {
+        MouthDataOptions { analysis: MouthDataAnalysis::default(), smoothing: 0.5 }
+    }
+}
+
+/// Generate mouth data for interleaved samples, with one byte for each tick of the sound.
+///
+/// A value of 0 means the mouth is closed and 255 means it is fully open.
+pub fn generate_mouth_data(samples: &[i16], channel_count: usize, sample_rate: u32, options: &MouthDataOptions) -> Vec<u8> {
+    if channel_count == 0 || sample_rate == 0 || samples.is_empty() {
+        return Vec::new();
+    }
+
+    let mut values = match options.analysis {
+        MouthDataAnalysis::Amplitude => amplitude_analysis(samples, channel_count, sample_rate),
+        MouthDataAnalysis::Speech => speech_analysis(samples, channel_count, sample_rate)
+    };
+
+    let smoothing = options.smoothing.clamp(0.0, 1.0);
+    let mut previous = 0.0;
+    for v in &mut values {
+        *v = v.clamp(0.0, 1.0);
+        if *v < previous {
+            *v = previous * smoothing + *v * (1.0 - smoothing);
+        }
+        previous = *v;
+    }
+
+    values.into_iter().map(|v| (v * (u8::MAX as f64) + 0.5) as u8).collect()
+}
+
+/// Measure the mean absolute amplitude of each tick.
+fn amplitude_analysis(samples: &[i16], channel_count: usize, sample_rate: u32) -> Vec<f64> {
+    let samples_per_tick = ((sample_rate as usize * channel_count) as f64 / MOUTH_DATA_RATE as f64).round().max(1.0) as usize;
+    let ticks: Vec<f64> = samples.chunks(samples_per_tick).map(|tick| {
+        tick.iter().map(|s| match *s {
+            s if s >= 0 => s as f64 / i16::MAX as f64,
+            s => -(s as f64) / -(i16::MIN as f64)
+        }).sum::<f64>() / tick.len() as f64
+    }).collect();
+
+    // Scale from the quietest tick to halfway between the average and loudest ticks.
+    let min = ticks.iter().copied().fold(1.0, f64::min);
+    let max = ticks.iter().copied().fold(0.0, f64::max);
+    let average = ticks.iter().sum::<f64>() / ticks.len() as f64;
+    let range = (max + average) / 2.0 - min;
+    if range == 0.0 {
+        return ticks;
+    }
+    ticks.into_iter().map(|t| (t - min) / range).collect()
+}
+
+/// Measure the energy of each tick in the speech band.
+fn speech_analysis(samples: &[i16], channel_count: usize, sample_rate: u32) -> Vec<f64> {
+    // Low sample rates may not have any frequencies above the band to filter out.
+    let mut high_pass = Biquad::high_pass(SPEECH_BAND_LOW, std::f64::consts::FRAC_1_SQRT_2, sample_rate);
+    let mut low_pass = (SPEECH_BAND_HIGH < sample_rate as f64 / 2.0).then(|| Biquad::low_pass(SPEECH_BAND_HIGH, std::f64::consts::FRAC_1_SQRT_2, sample_rate));
+
+    let frames_per_tick = (sample_rate as f64 / MOUTH_DATA_RATE as f64).round().max(1.0) as usize;
+    let levels: Vec<f64> = samples.chunks(frames_per_tick * channel_count).map(|tick| {
+        let mut energy = 0.0;
+        for frame in tick.chunks(channel_count) {
+            let mono = frame.iter().map(|s| *s as f64 / 32768.0).sum::<f64>() / frame.len() as f64;
+            let mut filtered = high_pass.process(mono);
+            if let Some(low_pass) = &mut low_pass {
+                filtered = low_pass.process(filtered);
+            }
+            energy += filtered * filtered;
+        }
+        10.0 * (energy / (tick.len() / channel_count).max(1) as f64).log10()
+    }).collect();
+
+    // Open the mouth over a range below the loudest tick, keeping it closed when it is silent.
+    let loudest = levels.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let floor = (loudest - SPEECH_DYNAMIC_RANGE).max(SPEECH_SILENCE_THRESHOLD);
+    let range = (loudest - floor).max(1.0);
+    levels.into_iter().map(|l| if l < SPEECH_SILENCE_THRESHOLD { 0.0 } else { (l - floor) / range }).collect()
+}
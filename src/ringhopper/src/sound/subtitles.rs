@@ -0,0 +1,92 @@
+use crate::error::*;
+use ringhopper_proc::*;
+
+/// Maximum size of a sound permutation's subtitle data in bytes.
+pub const SUBTITLE_DATA_MAXIMUM_SIZE: usize = 512;
+
+/// Text shown during part of a sound.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubtitleCue {
+    /// Time in seconds that the text is shown.
+    pub start: f64,
+
+    /// Time in seconds that the text is hidden.
+    pub end: f64,
+
+    /// Text of the cue, with each line separated by a line feed.
+    pub text: String
+}
+
+/// Parse a timestamp in an SRT file (e.g. `00:01:02,500`) into seconds.
+fn parse_srt_timestamp(timestamp: &str) -> Option<f64> {
+    let (time, milliseconds) = timestamp.split_once([',', '.'])?;
+    let mut parts = time.split(':');
+    let hours: u64 = parts.next()?.parse().ok()?;
+    let minutes: u64 = parts.next()?.parse().ok()?;
+    let seconds: u64 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() || minutes >= 60 || seconds >= 60 || milliseconds.len() != 3 {
+        return None;
+    }
+    let milliseconds: u64 = milliseconds.parse().ok()?;
+    Some((hours * 3600 + minutes * 60 + seconds) as f64 + milliseconds as f64 / 1000.0)
+}
+
+/// Parse the cues of an SRT (SubRip) file.
+///
+/// The number before each cue's timing line is optional, and any position after the timestamps is ignored.
+pub fn parse_srt(srt: &str) -> ErrorMessageResult<Vec<SubtitleCue>> {
+    let srt = srt.strip_prefix('\u{FEFF}').unwrap_or(srt);
+    let mut lines = srt.lines().enumerate().map(|(i, l)| (i + 1, l.trim_end())).peekable();
+    let mut cues = Vec::new();
+
+    loop {
+        // Cues are separated by blank lines.
+        while lines.next_if(|(_, l)| l.is_empty()).is_some() {}
+        let (mut line_number, mut timing) = match lines.next() {
+            Some(n) => n,
+            None => break
+        };
+        if !timing.contains("-->") {
+            (line_number, timing) = lines.next().filter(|(_, l)| l.contains("-->")).ok_or_else(|| ErrorMessage::AllocatedString(format!(get_compiled_string!("sound.error_srt_missing_timing"), line=line_number + 1)))?;
+        }
+
+        let (start, end) = timing.split_once("-->").unwrap();
+        let timestamp = |t: &str| parse_srt_timestamp(t).ok_or_else(|| ErrorMessage::AllocatedString(format!(get_compiled_string!("sound.error_srt_bad_timestamp"), timestamp=t, line=line_number)));
+        let start = timestamp(start.trim())?;
+        let end = timestamp(end.split_whitespace().next().unwrap_or_default())?;
+
+        let mut text = Vec::new();
+        while let Some((_, l)) = lines.next_if(|(_, l)| !l.is_empty()) {
+            text.push(l);
+        }
+
+        cues.push(SubtitleCue { start, end, text: text.join("\n") });
+    }
+
+    Ok(cues)
+}
+
+/// Encode subtitle text into subtitle data.
+///
+/// Subtitles are stored like the strings in unicode_string_list tags, as null terminated UTF-16 with CRLF line endings.
+/// Empty subtitles are stored as no data.
+pub fn encode_subtitle_data(text: &str) -> ErrorMessageResult<Vec<u8>> {
+    let text = text.strip_prefix('\u{FEFF}').unwrap_or(text).trim_end();
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let text = text.lines().collect::<Vec<&str>>().join("\r\n");
+    let data: Vec<u8> = text.encode_utf16().chain(std::iter::once(0)).flat_map(|c| c.to_le_bytes()).collect();
+    if data.len() > SUBTITLE_DATA_MAXIMUM_SIZE {
+        return Err(ErrorMessage::AllocatedString(format!(get_compiled_string!("sound.error_subtitle_data_too_large"), size=data.len(), limit=SUBTITLE_DATA_MAXIMUM_SIZE)));
+    }
+
+    Ok(data)
+}
+
+/// Decode subtitle data into text, stopping at the null terminator.
+pub fn decode_subtitle_data(data: &[u8]) -> ErrorMessageResult<String> {
+    let characters: Vec<u16> = data.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).take_while(|c| *c != 0).collect();
+    String::from_utf16(&characters).map_err(|_| ErrorMessage::StaticString(get_compiled_string!("sound.error_subtitle_data_invalid")))
+}
@@ -0,0 +1,273 @@
+use super::*;
+
+/// Decode a file with symphonia into interleaved samples, the channel count, and the sample rate.
+fn decode(file: Vec<u8>, extension: &str) -> (Vec<i16>, usize, u32) {
+    use symphonia::core::audio::SampleBuffer;
+    use symphonia::core::codecs::DecoderOptions;
+    use symphonia::core::formats::FormatOptions;
+    use symphonia::core::meta::MetadataOptions;
+    use symphonia::core::probe::Hint;
+    use symphonia::core::io::*;
+
+    let stream = MediaSourceStream::new(Box::new(std::io::Cursor::new(file)), MediaSourceStreamOptions::default());
+    let mut hint = Hint::new();
+    hint.with_extension(extension);
+    let mut probed = symphonia::default::get_probe().format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default()).unwrap();
+    let track = probed.format.default_track().unwrap().to_owned();
+    let mut decoder = symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default()).unwrap();
+
+    let mut samples = Vec::new();
+    while let Ok(packet) = probed.format.next_packet() {
+        let audio = decoder.decode(&packet).unwrap();
+        let mut buffer = SampleBuffer::<i16>::new(audio.capacity() as u64, *audio.spec());
+        buffer.copy_interleaved_ref(audio);
+        samples.extend_from_slice(buffer.samples());
+    }
+
+    (samples, track.codec_params.channels.unwrap().count(), track.codec_params.sample_rate.unwrap())
+}
+
+/// Generate a tone with some noise and a bit of silence so each kind of subframe is used.
+fn test_samples(frame_count: usize, channel_count: usize) -> Vec<i16> {
+    let mut noise = 0x1234u32;
+    let mut samples = Vec::with_capacity(frame_count * channel_count);
+    for i in 0..frame_count {
+        for c in 0..channel_count {
+            noise = noise.wrapping_mul(1103515245).wrapping_add(12345);
+            let sample = if i < 5000 {
+                0.0
+            }
+            else if i < 7000 {
+                ((noise >> 16) as i16) as f32
+            }
+            else {
+                (i as f32 * (0.05 + c as f32 * 0.01)).sin() * 20000.0 + ((noise >> 24) as f32 - 128.0)
+            };
+            samples.push(sample as i16);
+        }
+    }
+    samples
+}
+
+#[test]
+fn test_flac_round_trip() {
+    for channel_count in [1, 2] {
+        for frame_count in [0, 1, 3, 4096, 20000] {
+            let samples = test_samples(frame_count, channel_count);
+            let flac = encode_flac(&samples, channel_count, 22050).unwrap();
+            if frame_count == 0 {
+                continue;
+            }
+            assert_eq!((samples, channel_count, 22050), decode(flac, "flac"));
+        }
+    }
+
+    assert!(encode_flac(&[], 0, 22050).is_err());
+    assert!(encode_flac(&[], 9, 22050).is_err());
+}
+
+#[test]
+fn test_wav_round_trip() {
+    for channel_count in [1, 2] {
+        let samples = test_samples(10000, channel_count);
+        let wav = write_wav(&samples, channel_count, 44100).unwrap();
+        assert_eq!((samples, channel_count, 44100), decode(wav, "wav"));
+    }
+}
+
+#[test]
+fn test_decode_ima_adpcm() {
+    // A stereo block where the left channel starts at 100 and holds, and the right channel starts at -100 and rises.
+    let mut block = vec![0u8; ADPCM_BLOCK_SIZE_PER_CHANNEL * 2];
+    block[0..2].copy_from_slice(&100i16.to_le_bytes());
+    block[4..6].copy_from_slice(&(-100i16).to_le_bytes());
+    for group in block[8..].chunks_exact_mut(8) {
+        group[4..].fill(0x77);
+    }
+
+    let samples = decode_ima_adpcm(&block, 2).unwrap();
+    assert_eq!(ADPCM_SAMPLES_PER_BLOCK * 2, samples.len());
+    assert!(samples.iter().step_by(2).all(|s| *s == 100));
+
+    // The first step is 7, so the sample rises by 0 + 1 + 3 + 7 = 11, then the step index rises by 8 to a step of 16.
+    let right: Vec<i16> = samples.iter().skip(1).step_by(2).copied().collect();
+    assert_eq!([-100, -89, -59], right[..3]);
+    assert!(right.windows(2).all(|w| w[1] > w[0] || w[1] == i16::MAX));
+    assert_eq!(i16::MAX, *right.last().unwrap());
+
+    assert!(decode_ima_adpcm(&block[1..], 2).is_err());
+    assert!(decode_ima_adpcm(&block, 0).is_err());
+}
+
+#[test]
+fn test_decode_ima_adpcm_reference() {
+    // A mono block starting at -1000 with a step index of 30, and the samples it decodes to with the IMA ADPCM decoder
+    // of CPython's audioop module (with the nibbles of each byte swapped, since it reads the high nibble first). The
+    // samples hit both clamps.
+    let block = [
+        0x18, 0xFC, 0x1E, 0x00, 0x29, 0x72, 0xBB, 0x04, 0x4D, 0x96, 0xDF, 0x28, 0x71, 0xBA, 0x03, 0x4C, 0x95, 0xDE, 0x27, 0x70,
+        0xB9, 0x02, 0x4B, 0x94, 0xDD, 0x26, 0x6F, 0xB8, 0x01, 0x4A, 0x93, 0xDC, 0x25, 0x6E, 0xB7, 0x00
+    ];
+    let expected: [i16; ADPCM_SAMPLES_PER_BLOCK] = [
+        -1000, -1048, -975, -909, -728, -910, -1075, -881, -855, -1115, -802, -255, -478, -1498, -3100, -3313, -2343,
+        -1815, 588, -1129, -3314, -1326, -1068, -3180, -624, 3155, 1646, -4301, -13216, 4582, 17300, 19612, 32767, 20481,
+        -5588, 11340, 14417, -5169, 17724, 32767, 21595, -15647, -32768, 20477, 32767, -23096, 30149, 26054, -15, 10141,
+        13218, -772, 22121, 32767, 24373, 1480, -32375, 12678, 32767, -15648, 32767, 32767, 4098, 7822, 11207
+    ];
+    assert_eq!(expected.to_vec(), decode_ima_adpcm(&block, 1).unwrap());
+}
+
+#[test]
+fn test_ima_adpcm_round_trip() {
+    for channel_count in [1, 2] {
+        for frame_count in [0, 1, ADPCM_SAMPLES_PER_BLOCK, 10000] {
+            let samples = test_samples(frame_count, channel_count);
+            let encoded = encode_ima_adpcm(&samples, channel_count);
+            let decoded = decode_ima_adpcm(&encoded, channel_count).unwrap();
+
+            // The last block is padded to a whole block.
+            let block_count = frame_count.div_ceil(ADPCM_SAMPLES_PER_BLOCK);
+            assert_eq!(block_count * ADPCM_BLOCK_SIZE_PER_CHANNEL * channel_count, encoded.len());
+            assert_eq!(block_count * ADPCM_SAMPLES_PER_BLOCK * channel_count, decoded.len());
+
+            // The first sample of each block is exact, and the rest should follow the signal closely.
+            for (block, decoded_block) in samples.chunks(ADPCM_SAMPLES_PER_BLOCK * channel_count).zip(decoded.chunks(ADPCM_SAMPLES_PER_BLOCK * channel_count)) {
+                assert_eq!(block[..channel_count], decoded_block[..channel_count]);
+            }
+            let tone = 7000 * channel_count;
+            if samples.len() > tone {
+                let error: f64 = samples[tone..].iter().zip(&decoded[tone..]).map(|(a, b)| (*a as f64 - *b as f64).abs()).sum::<f64>() / (samples.len() - tone) as f64;
+                assert!(error < 500.0, "mean error {error} is too high");
+            }
+        }
+    }
+}
+
+/// Generate a 1 kHz sine wave with the given amplitude in every channel.
+fn sine(amplitude: f64, seconds: f64, channel_count: usize, sample_rate: u32) -> Vec<i16> {
+    let frame_count = (seconds * sample_rate as f64) as usize;
+    (0..frame_count * channel_count).map(|i| {
+        let t = (i / channel_count) as f64 / sample_rate as f64;
+        ((t * 1000.0 * std::f64::consts::TAU).sin() * amplitude * 32767.0).round() as i16
+    }).collect()
+}
+
+#[test]
+fn test_loudness() {
+    // A 1 kHz sine at -20 dBFS in one channel is -23 LUFS, and another channel adds 3 LU.
+    for sample_rate in [22050, 44100, 48000] {
+        let mono = measure_levels(&sine(0.1, 5.0, 1, sample_rate), 1, sample_rate);
+        assert!((mono.loudness.unwrap() + 23.01).abs() < 0.1, "{mono:?} at {sample_rate} Hz");
+        assert!((mono.peak + 20.0).abs() < 0.01);
+        assert!((mono.rms + 23.01).abs() < 0.01);
+
+        let stereo = measure_levels(&sine(0.1, 5.0, 2, sample_rate), 2, sample_rate);
+        assert!((stereo.loudness.unwrap() + 20.0).abs() < 0.1, "{stereo:?} at {sample_rate} Hz");
+    }
+
+    // Silence is gated out, both on its own and after a sound.
+    let silence = vec![0i16; 44100 * 5];
+    let silent = measure_levels(&silence, 1, 44100);
+    assert_eq!(None, silent.loudness);
+    assert_eq!(f64::NEG_INFINITY, silent.peak);
+
+    let tone = sine(0.1, 5.0, 1, 44100);
+    let mut meter = LoudnessMeter::new();
+    meter.add_samples(&tone, 1, 44100);
+    meter.add_samples(&silence, 1, 44100);
+    assert!((meter.integrated_loudness().unwrap() + 23.01).abs() < 0.1);
+
+    // Short sounds are measured as a single block.
+    let short = measure_levels(&sine(0.1, 0.1, 1, 44100), 1, 44100);
+    assert!((short.loudness.unwrap() + 23.01).abs() < 0.5);
+}
+
+/// Generate a voice-like buzz at 150 Hz with harmonics up to 3 kHz, spoken as a few syllables with pauses between them.
+fn syllables(channel_count: usize, sample_rate: u32) -> Vec<i16> {
+    // Start and end of each syllable in seconds, and its amplitude.
+    const SYLLABLES: [(f64, f64, f64); 3] = [(0.1, 0.3, 0.5), (0.4, 0.5, 0.2), (0.65, 0.9, 0.35)];
+
+    let frame_count = sample_rate as usize;
+    let mut samples = Vec::with_capacity(frame_count * channel_count);
+    for i in 0..frame_count {
+        let t = i as f64 / sample_rate as f64;
+        let envelope = SYLLABLES.iter().find(|s| t >= s.0 && t < s.1).map(|s| s.2 * (std::f64::consts::PI * (t - s.0) / (s.1 - s.0)).sin()).unwrap_or(0.0);
+        let buzz = (1..=20).map(|h| (t * 150.0 * h as f64 * std::f64::consts::TAU).sin() / h as f64).sum::<f64>() / 2.0;
+        let sample = (buzz * envelope * 32767.0).round() as i16;
+        for _ in 0..channel_count {
+            samples.push(sample);
+        }
+    }
+    samples
+}
+
+/// Add a 50 Hz hum to samples, like from a poorly grounded microphone.
+fn hum(mut samples: Vec<i16>, channel_count: usize, sample_rate: u32) -> Vec<i16> {
+    for (i, s) in samples.iter_mut().enumerate() {
+        let t = (i / channel_count) as f64 / sample_rate as f64;
+        *s = s.saturating_add(((t * 50.0 * std::f64::consts::TAU).sin() * 0.05 * 32767.0).round() as i16);
+    }
+    samples
+}
+
+#[test]
+fn test_mouth_data_corpus() {
+    let amplitude = MouthDataOptions { analysis: MouthDataAnalysis::Amplitude, smoothing: 0.0 };
+    let speech = MouthDataOptions { analysis: MouthDataAnalysis::Speech, smoothing: 0.0 };
+    let smoothed_speech = MouthDataOptions::default();
+
+    // The output must stay the same so regenerating tags does not change them.
+    let corpus: [(&str, Vec<i16>, usize, u32, MouthDataOptions, [u8; 30]); 5] = [
+        ("syllables", syllables(1, 22050), 1, 22050, amplitude,
+         [0, 0, 0, 107, 255, 255, 255, 255, 107, 0, 0, 0, 80, 160, 80, 0, 0, 0, 0, 15, 118, 216, 255, 255, 252, 171, 60, 0, 0, 0]),
+        ("syllables", syllables(1, 22050), 1, 22050, speech,
+         [0, 0, 0, 156, 228, 253, 255, 235, 177, 0, 0, 0, 134, 186, 153, 0, 0, 0, 0, 23, 160, 206, 226, 230, 222, 196, 135, 0, 0, 0]),
+        ("syllables", syllables(1, 22050), 1, 22050, smoothed_speech,
+         [0, 0, 0, 156, 228, 253, 255, 245, 211, 106, 53, 26, 134, 186, 170, 85, 42, 21, 11, 23, 160, 206, 226, 230, 226, 211, 173, 87, 43, 22]),
+        ("hum", hum(syllables(1, 22050), 1, 22050), 1, 22050, amplitude,
+         [0, 6, 0, 81, 255, 255, 255, 255, 81, 0, 6, 0, 53, 115, 54, 0, 6, 0, 0, 8, 84, 194, 248, 255, 235, 127, 38, 0, 6, 0]),
+        ("hum", hum(syllables(1, 22050), 1, 22050), 1, 22050, speech,
+         [0, 0, 0, 156, 228, 253, 255, 235, 177, 0, 0, 0, 134, 186, 153, 0, 0, 0, 0, 25, 160, 206, 226, 230, 222, 196, 135, 0, 0, 0])
+    ];
+    for (name, samples, channel_count, sample_rate, options, expected) in corpus {
+        assert_eq!(expected[..], generate_mouth_data(&samples, channel_count, sample_rate, &options), "{name} with {options:?}");
+    }
+
+    // Stereo and higher sample rates give the same result.
+    for options in [amplitude, speech, smoothed_speech] {
+        assert_eq!(generate_mouth_data(&syllables(1, 22050), 1, 22050, &options), generate_mouth_data(&syllables(2, 44100), 2, 44100, &options));
+    }
+
+    // Silence keeps the mouth closed, and there is a value for each tick, including the last partial one.
+    assert_eq!(vec![0u8; 31], generate_mouth_data(&vec![0i16; 22050 + 100], 1, 22050, &speech));
+    assert!(generate_mouth_data(&[], 1, 22050, &speech).is_empty());
+}
+
+#[test]
+fn test_parse_srt() {
+    let srt = "\u{FEFF}1\r\n00:00:00,500 --> 00:00:01,250\r\nHello there.\r\n\r\n2\r\n00:00:01,500 --> 00:00:02,000 X1:0\r\nGeneral\r\nKenobi!\r\n\r\n\r\n00:01:00.000 --> 01:00:00.000\nNo number\n";
+    assert_eq!(vec![
+        SubtitleCue { start: 0.5, end: 1.25, text: "Hello there.".to_owned() },
+        SubtitleCue { start: 1.5, end: 2.0, text: "General\nKenobi!".to_owned() },
+        SubtitleCue { start: 60.0, end: 3600.0, text: "No number".to_owned() }
+    ], parse_srt(srt).unwrap());
+
+    assert!(parse_srt("").unwrap().is_empty());
+    assert!(parse_srt("1\nHello\n").is_err());
+    assert!(parse_srt("1\n00:00:00 --> 00:00:01,000\nHello\n").is_err());
+    assert!(parse_srt("1\n00:00:61,000 --> 00:01:02,000\nHello\n").is_err());
+}
+
+#[test]
+fn test_subtitle_data() {
+    // Subtitles are null terminated UTF-16 with CRLF line endings.
+    let data = encode_subtitle_data("Hi\nthere\n").unwrap();
+    assert_eq!(b"H\0i\0\r\0\n\0t\0h\0e\0r\0e\0\0\0", data.as_slice());
+    assert_eq!("Hi\r\nthere", decode_subtitle_data(&data).unwrap());
+    assert_eq!("\u{00E9}\u{1F600}", decode_subtitle_data(&encode_subtitle_data("\u{00E9}\u{1F600}").unwrap()).unwrap());
+
+    assert!(encode_subtitle_data(" \r\n").unwrap().is_empty());
+    assert_eq!(SUBTITLE_DATA_MAXIMUM_SIZE, encode_subtitle_data(&"a".repeat(255)).unwrap().len());
+    assert!(encode_subtitle_data(&"a".repeat(256)).is_err());
+    assert!(decode_subtitle_data(&[0x00, 0xD8, 0x00, 0x00]).is_err());
+}
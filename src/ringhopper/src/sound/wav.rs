@@ -0,0 +1,34 @@
+use crate::error::*;
+use ringhopper_proc::*;
+
+/// Write interleaved 16-bit PCM samples to a RIFF WAVE file.
+///
+/// Returns an [`Err`] if the samples do not fit in a WAVE file.
+pub fn write_wav(samples: &[i16], channel_count: usize, sample_rate: u32) -> ErrorMessageResult<Vec<u8>> {
+    let overflow = || ErrorMessage::StaticString(get_compiled_string!("engine.h1.types.serialize.error_architecture_limit_exceeded"));
+    let data_size = samples.len().checked_mul(2).and_then(|s| u32::try_from(s).ok()).ok_or_else(overflow)?;
+    let riff_size = data_size.checked_add(36).ok_or_else(overflow)?;
+    let block_align = (channel_count * 2) as u16;
+
+    let mut wav = Vec::with_capacity(data_size as usize + 44);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&riff_size.to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&(channel_count as u16).to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    wav.extend_from_slice(&block_align.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    for s in samples {
+        wav.extend_from_slice(&s.to_le_bytes());
+    }
+
+    Ok(wav)
+}
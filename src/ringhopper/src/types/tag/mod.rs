@@ -47,10 +47,16 @@ impl<T: TagBlockFn + PartialEq> PartialEq for Reflexive<T> {
     }
 }
 
-impl<T: TagBlockFn> ReflexiveFn for Reflexive<T> {
+impl<T: TagBlockFn + Default> ReflexiveFn for Reflexive<T> {
     fn len(&self) -> usize {
         self.blocks.len()
     }
+    fn insert_default(&mut self, index: usize) {
+        self.blocks.insert(index, T::new_with_defaults())
+    }
+    fn remove(&mut self, index: usize) {
+        self.blocks.remove(index);
+    }
     fn block_at_index(&self, index: usize) -> &dyn TagBlockFn {
         &self.blocks[index]
     }
@@ -89,6 +95,12 @@ pub trait ReflexiveFn {
 
     /// Get the mutable block at the index or panic if out of bounds.
     fn block_at_index_mut(&mut self, index: usize) -> &mut dyn TagBlockFn;
+
+    /// Insert a default block at the index or panic if the index is greater than the length.
+    fn insert_default(&mut self, index: usize);
+
+    /// Remove the block at the index or panic if out of bounds.
+    fn remove(&mut self, index: usize);
 }
 
 /// General interface for tag group parsing.
@@ -128,6 +140,24 @@ pub enum TagFieldValue<'a> {
 
     /// Bounds (mutable)
     MutableBounds(&'a mut dyn BoundsFn),
+
+    /// Struct
+    Block(&'a dyn TagBlockFn),
+
+    /// Struct (mutable)
+    MutableBlock(&'a mut dyn TagBlockFn),
+
+    /// Enum
+    Enum(&'a dyn TagEnumValueFn),
+
+    /// Enum (mutable)
+    MutableEnum(&'a mut dyn TagEnumValueFn),
+
+    /// Bitfield
+    Bitfield(&'a dyn TagBitfieldFn),
+
+    /// Bitfield (mutable)
+    MutableBitfield(&'a mut dyn TagBitfieldFn),
 }
 
 /// Reference to a value in a tag.
@@ -187,6 +217,9 @@ impl FieldReference<&mut dyn Any> {
         attempt_downcast!(Vector2D, Vector2D);
         attempt_downcast!(Vector3D, Vector3D);
 
+        attempt_downcast!(Data, Data);
+        attempt_downcast!(Option<u16>, Index);
+
         attempt_downcast!(crate::engines::h1::TagReference, H1TagReference);
 
         unreachable!()
@@ -232,6 +265,9 @@ impl FieldReference<&dyn Any> {
         attempt_downcast!(Vector2D, Vector2D);
         attempt_downcast!(Vector3D, Vector3D);
 
+        attempt_downcast!(Data, Data);
+        attempt_downcast!(Option<u16>, Index);
+
         attempt_downcast!(crate::engines::h1::TagReference, H1TagReference);
 
         unreachable!()
@@ -278,6 +314,97 @@ pub trait TagBlockFn: Any {
 
     /// Get the mutable field at the given index. Panics if it is out of bounds.
     fn field_at_index_mut(&mut self, index: usize) -> TagField;
+
+    /// Create a new block with the default values from its definition.
+    ///
+    /// Fields without a default value are set to their [`Default`] value.
+    fn new_with_defaults() -> Self where Self: Sized + Default {
+        Self::default()
+    }
+}
+
+/// General interface for accessing a tag field of a known type as a [`TagFieldValue`] at runtime.
+pub trait TagFieldFn {
+    /// Get a reference to the field.
+    fn as_field_value(&self) -> TagFieldValue<'_>;
+
+    /// Get a mutable reference to the field.
+    fn as_field_value_mut(&mut self) -> TagFieldValue<'_>;
+}
+
+macro_rules! tag_field_fn_for_value {
+    ($($t:ty),*) => {
+        $(impl TagFieldFn for $t {
+            fn as_field_value(&self) -> TagFieldValue<'_> {
+                TagFieldValue::Value(FieldReference { field: self })
+            }
+            fn as_field_value_mut(&mut self) -> TagFieldValue<'_> {
+                TagFieldValue::MutableValue(FieldReference { field: self })
+            }
+        })*
+    };
+}
+
+tag_field_fn_for_value!(i8, i16, i32, u8, u16, u32, f32);
+tag_field_fn_for_value!(ColorAHSV, ColorARGB, ColorARGBInt, ColorHSV, ColorRGB, ColorRGBInt, Euler2D, Euler3D, Matrix, Plane2D, Plane3D,
+                        Point2D, Point2DInt, Point3D, Quaternion, Rectangle, String32, Vector2D, Vector3D);
+tag_field_fn_for_value!(Data, Option<u16>);
+
+impl<T: TagBlockFn + Default> TagFieldFn for Reflexive<T> {
+    fn as_field_value(&self) -> TagFieldValue<'_> {
+        TagFieldValue::Array(self)
+    }
+    fn as_field_value_mut(&mut self) -> TagFieldValue<'_> {
+        TagFieldValue::MutableArray(self)
+    }
+}
+
+impl<T: Any> TagFieldFn for Bounds<T> {
+    fn as_field_value(&self) -> TagFieldValue<'_> {
+        TagFieldValue::Bounds(self)
+    }
+    fn as_field_value_mut(&mut self) -> TagFieldValue<'_> {
+        TagFieldValue::MutableBounds(self)
+    }
+}
+
+/// General interface for accessing tag enums of an unknown type at runtime.
+pub trait TagEnumValueFn {
+    /// Get the numeric representation of the enum.
+    fn get_value(&self) -> u16;
+
+    /// Set the enum from its numeric representation.
+    ///
+    /// Return an [`Err`] if the value is out of bounds for the enum.
+    fn set_value(&mut self, value: u16) -> ErrorMessageResult<()>;
+
+    /// Get all options.
+    fn get_options(&self) -> &'static [&'static str];
+}
+
+impl<T: TagEnumFn + Copy> TagEnumValueFn for T {
+    fn get_value(&self) -> u16 {
+        self.into_u16()
+    }
+    fn set_value(&mut self, value: u16) -> ErrorMessageResult<()> {
+        *self = T::from_u16(value)?;
+        Ok(())
+    }
+    fn get_options(&self) -> &'static [&'static str] {
+        T::options()
+    }
+}
+
+/// General interface for accessing tag bitfields of an unknown type at runtime.
+pub trait TagBitfieldFn {
+    /// Get the names of all bits in order.
+    fn get_bits(&self) -> &'static [&'static str];
+
+    /// Get the bit at the index. Panics if it is out of bounds.
+    fn get_bit(&self, index: usize) -> bool;
+
+    /// Set the bit at the index. Panics if it is out of bounds.
+    fn set_bit(&mut self, index: usize, value: bool);
 }
 
 impl Index<usize> for &dyn ReflexiveFn {
@@ -331,6 +458,9 @@ pub enum ValueReference<'a> {
     Vector2D(&'a Vector2D),
     Vector3D(&'a Vector3D),
 
+    Data(&'a Data),
+    Index(&'a Option<u16>),
+
     H1TagReference(&'a crate::engines::h1::TagReference)
 }
 
@@ -364,6 +494,9 @@ pub enum ValueReferenceMut<'a> {
     Vector2D(&'a mut Vector2D),
     Vector3D(&'a mut Vector3D),
 
+    Data(&'a mut Data),
+    Index(&'a mut Option<u16>),
+
     H1TagReference(&'a mut crate::engines::h1::TagReference)
 }
 
@@ -247,3 +247,102 @@ fn test_tag_references() {
     assert_eq!(TagGroup::Weapon, TagReference::from_path_and_group("weapons\\pistol\\pistol", TagGroup::Weapon).unwrap().get_group());
     assert_eq!(TagGroup::Weapon, TagReference::from_full_path("weapons\\pistol\\pistol.weapon").unwrap().get_group());
 }
+
+#[test]
+fn test_access_h1_definitions() {
+    use crate::engines::h1::definitions::*;
+
+    let mut part = GBXModelGeometryPart::default();
+    part.base_struct.shader_index = Some(3);
+    part.base_struct.flags.zoner = true;
+    part.base_struct.vertex_type = VertexType::ModelCompressed;
+    part.base_struct.triangles.blocks.push(ModelTriangle { vertex0_index: Some(0), vertex1_index: Some(1), vertex2_index: None });
+    part.local_node_indices[21] = 7;
+
+    // Inherited fields come first, followed by each element of arrays.
+    let base_count = part.base_struct.field_count();
+    assert_eq!(base_count + 1 + 22, part.field_count());
+    assert_eq!("local node indices[21]", part.field_at_index(part.field_count() - 1).name);
+    match part.field_at_index(part.field_count() - 1).field {
+        TagFieldValue::Value(v) => match v.get_value() { ValueReference::UInt8(n) => assert_eq!(7, *n), _ => panic!() },
+        _ => panic!()
+    }
+
+    for i in 0..part.field_count() {
+        let field = part.field_at_index_mut(i);
+        match (field.name, field.field) {
+            ("flags", TagFieldValue::MutableBitfield(b)) => {
+                let zoner = b.get_bits().iter().position(|b| *b == "zoner").unwrap();
+                assert!(b.get_bit(zoner));
+                b.set_bit(zoner, false);
+            },
+            ("shader index", TagFieldValue::MutableValue(mut v)) => match v.get_value() { ValueReferenceMut::Index(n) => *n = Some(4), _ => panic!() },
+            ("vertex type", TagFieldValue::MutableEnum(e)) => {
+                assert_eq!("model-compressed", e.get_options()[e.get_value() as usize]);
+                e.set_value(4).unwrap();
+                assert!(e.set_value(1000).is_err());
+            },
+            ("triangles", TagFieldValue::MutableArray(a)) => {
+                assert_eq!(1, a.len());
+                match a.block_at_index(0).field_at_index(2).field {
+                    TagFieldValue::Value(v) => match v.get_value() { ValueReference::Index(n) => assert_eq!(None, *n), _ => panic!() },
+                    _ => panic!()
+                }
+                a.insert_default(0);
+                a.insert_default(2);
+                assert_eq!(3, a.len());
+                a.remove(0);
+            },
+            _ => ()
+        }
+    }
+
+    assert!(!part.base_struct.flags.zoner);
+    assert_eq!(Some(4), part.base_struct.shader_index);
+    assert_eq!(VertexType::ModelUncompressed, part.base_struct.vertex_type);
+    assert_eq!(2, part.base_struct.triangles.blocks.len());
+    assert_eq!(Some(1), part.base_struct.triangles.blocks[0].vertex1_index);
+    assert_eq!(None, part.base_struct.triangles.blocks[1].vertex0_index);
+
+    // Every value in a tag can be accessed.
+    fn visit(block: &dyn TagBlockFn) -> usize {
+        let mut count = 0;
+        for i in 0..block.field_count() {
+            match block.field_at_index(i).field {
+                TagFieldValue::Value(v) => { v.get_value(); },
+                TagFieldValue::Block(b) => count += visit(b),
+                TagFieldValue::Array(a) => for j in 0..a.len() { count += visit(a.block_at_index(j)) },
+                TagFieldValue::Bounds(b) => { b.get_lower().get_value(); b.get_upper().get_value(); },
+                TagFieldValue::Enum(_) | TagFieldValue::Bitfield(_) => (),
+                _ => panic!()
+            }
+            count += 1;
+        }
+        count
+    }
+    let mut scenario = Scenario::default();
+    scenario.scripts.blocks.push(ScenarioScript::default());
+    scenario.script_syntax_data = vec![0; 56];
+    assert!(visit(&scenario) > scenario.field_count());
+}
+
+#[test]
+fn test_h1_definition_defaults() {
+    use crate::engines::h1::definitions::*;
+
+    // Values, bounds, and colors
+    let sound = Sound::new_with_defaults();
+    assert_eq!(Bounds { lower: 1.0, upper: 1.0 }, sound.random_pitch_bounds);
+    assert_eq!(1.0, sound.outer_cone_gain);
+    assert_eq!(0.0, sound.maximum_distance);
+    assert_eq!(1.0, SoundPermutation::new_with_defaults().gain);
+    assert_eq!(ColorRGB { r: 1.0, g: 1.0, b: 1.0 }, Decal::new_with_defaults().color_lower_bounds);
+
+    // Inherited fields
+    assert_eq!(1.22173, Biped::new_with_defaults().base_struct.camera_field_of_view);
+
+    // Inserted blocks use them too.
+    let mut permutations: Reflexive<SoundPermutation> = Reflexive::default();
+    permutations.insert_default(0);
+    assert_eq!(1.0, permutations.blocks[0].gain);
+}